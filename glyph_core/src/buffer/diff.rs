@@ -0,0 +1,480 @@
+use std::ops::Range;
+
+use ropey::Rope;
+
+use crate::buffer::transaction::ChangeSet;
+
+/// A region where two sequences differ, `old` is replaced by `new`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+// past this many edits each way from both ends, a region is replaced as a
+// whole rather than searched for a shorter edit script, so that rewriting a
+// huge file doesn't take seconds
+const MAX_COST: isize = 4096;
+
+/// Myers diff between `old` and `new`, returns the regions that differ in
+/// ascending order. It finds the middle of the edit script in linear space
+/// and recurses on both halves.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let mut hunks = vec![];
+    diff_range(old, new, 0, 0, &mut hunks);
+    hunks
+}
+
+// appends the hunks between `a` and `b`, which start at `a_start` of the old
+// sequence and `b_start` of the new one
+fn diff_range<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    a_start: usize,
+    b_start: usize,
+    hunks: &mut Vec<Hunk>,
+) {
+    // common prefix and suffix don't need to go through the algorithm
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &a[prefix..a.len() - suffix];
+    let b = &b[prefix..b.len() - suffix];
+    let (a_start, b_start) = (a_start + prefix, b_start + prefix);
+
+    let split = match (a.is_empty(), b.is_empty()) {
+        (true, true) => return,
+        (true, false) | (false, true) => None,
+        (false, false) => middle_snake(a, b),
+    };
+    match split {
+        Some((x, y)) => {
+            diff_range(&a[..x], &b[..y], a_start, b_start, hunks);
+            diff_range(&a[x..], &b[y..], a_start + x, b_start + y, hunks);
+        }
+        None => {
+            let old = a_start..a_start + a.len();
+            let new = b_start..b_start + b.len();
+            match hunks.last_mut() {
+                Some(hunk) if hunk.old.end == old.start && hunk.new.end == new.start => {
+                    hunk.old.end = old.end;
+                    hunk.new.end = new.end;
+                }
+                _ => hunks.push(Hunk { old, new }),
+            }
+        }
+    }
+}
+
+// where a shortest edit script between `a` and `b` is split in two halves,
+// searched from both ends at once, `None` when it costs more than
+// `MAX_COST` each way
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = ((n + m + 1) / 2).min(MAX_COST);
+    let offset = max + 1;
+    // furthest x reached on each diagonal, counted from the end backward
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = forward.clone();
+    // only points strictly inside the grid make progress on both halves
+    let split = |x: isize, y: isize| {
+        let inside = (0..=n).contains(&x) && (0..=m).contains(&y);
+        (inside && x + y > 0 && (x, y) != (n, m)).then_some((x as usize, y as usize))
+    };
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = match k == -d || (k != d && forward[i - 1] < forward[i + 1]) {
+                true => forward[i + 1],
+                false => forward[i - 1] + 1,
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while (0..n).contains(&x) && (0..m).contains(&y) && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[i] = x;
+            let reverse = delta - k;
+            if odd
+                && (-(d - 1)..=d - 1).contains(&reverse)
+                && x + backward[(reverse + offset) as usize] >= n
+            {
+                return split(x0, y0);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = match k == -d || (k != d && backward[i - 1] < backward[i + 1]) {
+                true => backward[i + 1],
+                false => backward[i - 1] + 1,
+            };
+            let mut y = x - k;
+            while (0..n).contains(&x)
+                && (0..m).contains(&y)
+                && a[(n - x - 1) as usize] == b[(m - y - 1) as usize]
+            {
+                x += 1;
+                y += 1;
+            }
+            backward[i] = x;
+            let forward_k = delta - k;
+            if !odd
+                && (-d..=d).contains(&forward_k)
+                && x + forward[(forward_k + offset) as usize] >= n
+            {
+                return split(n - x, m - y);
+            }
+        }
+    }
+    None
+}
+
+/// splits text into lines keeping their line breaks
+pub fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// change set that turns `old` into `new`, computed line by line so positions
+/// in unchanged lines are preserved
+pub fn changes(old: &Rope, new: &str) -> ChangeSet {
+    let old_text = old.to_string();
+    let old_lines = lines(&old_text);
+    let new_lines = lines(new);
+    let mut line_starts = vec![0];
+    for line in old_lines.iter() {
+        line_starts.push(line_starts.last().unwrap() + line.chars().count());
+    }
+
+    let changes = diff(&old_lines, &new_lines).into_iter().map(|hunk| {
+        let text = new_lines[hunk.new].concat();
+        (
+            line_starts[hunk.old.start],
+            line_starts[hunk.old.end],
+            (!text.is_empty()).then_some(text),
+        )
+    });
+    ChangeSet::from_changes(old.len_chars(), changes).expect("hunks are sorted and within `old`")
+}
+
+/// unified diff between `old` and `new` with 3 lines of context around every
+/// change
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    const CONTEXT: usize = 3;
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let hunks = diff(&old_lines, &new_lines);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut text = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut i = 0;
+    while i < hunks.len() {
+        // hunks closer than twice the context share a single block
+        let mut j = i + 1;
+        while j < hunks.len() && hunks[j].old.start - hunks[j - 1].old.end <= 2 * CONTEXT {
+            j += 1;
+        }
+        let group = &hunks[i..j];
+        let old_start = group[0].old.start.saturating_sub(CONTEXT);
+        let new_start = group[0].new.start - (group[0].old.start - old_start);
+        let old_end = usize::min(group[j - i - 1].old.end + CONTEXT, old_lines.len());
+        let new_end = group[j - i - 1].new.end + (old_end - group[j - i - 1].old.end);
+
+        text.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + 1,
+            old_end - old_start,
+            new_start + 1,
+            new_end - new_start
+        ));
+        let mut pos = old_start;
+        for hunk in group {
+            push_lines(&mut text, ' ', &old_lines[pos..hunk.old.start]);
+            push_lines(&mut text, '-', &old_lines[hunk.old.clone()]);
+            push_lines(&mut text, '+', &new_lines[hunk.new.clone()]);
+            pos = hunk.old.end;
+        }
+        push_lines(&mut text, ' ', &old_lines[pos..old_end]);
+        i = j;
+    }
+    text
+}
+
+fn push_lines(text: &mut String, prefix: char, lines: &[&str]) {
+    for line in lines {
+        text.push(prefix);
+        push_line_block(text, line);
+    }
+}
+
+/// The outcome of a three way merge.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Merge {
+    pub text: String,
+    pub conflicts: usize,
+}
+
+/// merges the changes `ours` and `theirs` made to `base` line by line, regions
+/// both sides changed differently are surrounded by conflict markers
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Merge {
+    let base = lines(base);
+    let ours = lines(ours);
+    let theirs = lines(theirs);
+
+    let mut hunks = diff(&base, &ours)
+        .into_iter()
+        .map(|hunk| (Side::Ours, hunk))
+        .chain(
+            diff(&base, &theirs)
+                .into_iter()
+                .map(|hunk| (Side::Theirs, hunk)),
+        )
+        .collect::<Vec<_>>();
+    hunks.sort_by_key(|(_, hunk)| (hunk.old.start, hunk.old.end));
+
+    let mut text = String::new();
+    let mut conflicts = 0;
+    let mut pos = 0;
+    let mut i = 0;
+    while i < hunks.len() {
+        // groups every hunk touching the same region of the base
+        let start = hunks[i].1.old.start;
+        let mut end = hunks[i].1.old.end;
+        let mut j = i + 1;
+        while j < hunks.len() && hunks[j].1.old.start <= end {
+            end = end.max(hunks[j].1.old.end);
+            j += 1;
+        }
+        let group = &hunks[i..j];
+
+        text.push_str(&base[pos..start].concat());
+        let side_text = |side: Side, lines: &[&str]| {
+            let mut text = String::new();
+            let mut pos = start;
+            for (_, hunk) in group.iter().filter(|(s, _)| *s == side) {
+                text.push_str(&base[pos..hunk.old.start].concat());
+                text.push_str(&lines[hunk.new.clone()].concat());
+                pos = hunk.old.end;
+            }
+            text.push_str(&base[pos..end].concat());
+            text
+        };
+        let has_ours = group.iter().any(|(side, _)| *side == Side::Ours);
+        let has_theirs = group.iter().any(|(side, _)| *side == Side::Theirs);
+        let ours_text = side_text(Side::Ours, &ours);
+        let theirs_text = side_text(Side::Theirs, &theirs);
+
+        if !has_theirs || ours_text == theirs_text {
+            text.push_str(&ours_text);
+        } else if !has_ours {
+            text.push_str(&theirs_text);
+        } else {
+            conflicts += 1;
+            text.push_str("<<<<<<< buffer\n");
+            push_line_block(&mut text, &ours_text);
+            text.push_str("=======\n");
+            push_line_block(&mut text, &theirs_text);
+            text.push_str(">>>>>>> disk\n");
+        }
+
+        pos = end;
+        i = j;
+    }
+    text.push_str(&base[pos..].concat());
+
+    Merge { text, conflicts }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+// conflict markers need to start on their own line
+fn push_line_block(text: &mut String, block: &str) {
+    text.push_str(block);
+    if !block.is_empty() && !block.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &str, new: &str) -> String {
+        let mut rope = Rope::from_str(old);
+        changes(&rope, new).apply(&mut rope);
+        rope.to_string()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "x", "c", "d", "e"];
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Hunk {
+                    old: 1..2,
+                    new: 1..2
+                },
+                Hunk {
+                    old: 4..4,
+                    new: 4..5
+                },
+            ]
+        );
+        assert_eq!(diff(&old, &old), vec![]);
+        assert_eq!(
+            diff(&[] as &[&str], &["a"]),
+            vec![Hunk {
+                old: 0..0,
+                new: 0..1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_chars() {
+        let old = "ABCABBA".chars().collect::<Vec<_>>();
+        let new = "CBABAC".chars().collect::<Vec<_>>();
+
+        let hunks = diff(&old, &new);
+        let removed: usize = hunks.iter().map(|h| h.old.len()).sum();
+        let added: usize = hunks.iter().map(|h| h.new.len()).sum();
+
+        // the shortest edit script for this pair has 5 edits
+        assert_eq!(removed + added, 5);
+    }
+
+    // the length of the longest common subsequence, the slow way
+    fn lcs(a: &[u8], b: &[u8]) -> usize {
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                table[i + 1][j + 1] = match a[i] == b[j] {
+                    true => table[i][j] + 1,
+                    false => table[i][j + 1].max(table[i + 1][j]),
+                };
+            }
+        }
+        table[a.len()][b.len()]
+    }
+
+    #[test]
+    fn test_diff_is_shortest() {
+        let mut seed = 7u32;
+        let mut random = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    b"abc"[(seed >> 16) as usize % 3]
+                })
+                .collect::<Vec<_>>()
+        };
+        for len in 0..40 {
+            let (old, new) = (random(len), random(len / 2 + 3));
+            let hunks = diff(&old, &new);
+
+            let mut rebuilt: Vec<u8> = vec![];
+            let mut pos = 0;
+            for hunk in &hunks {
+                rebuilt.extend(&old[pos..hunk.old.start]);
+                rebuilt.extend(&new[hunk.new.clone()]);
+                pos = hunk.old.end;
+            }
+            rebuilt.extend(&old[pos..]);
+            assert_eq!(rebuilt, new);
+            let cost: usize = hunks.iter().map(|h| h.old.len() + h.new.len()).sum();
+            assert_eq!(cost, old.len() + new.len() - 2 * lcs(&old, &new));
+        }
+    }
+
+    #[test]
+    fn test_diff_replaces_huge_rewrites() {
+        let old = (0..20_000).collect::<Vec<u32>>();
+        let new = (20_000..40_000).collect::<Vec<u32>>();
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![Hunk {
+                old: 0..20_000,
+                new: 0..20_000
+            }]
+        );
+    }
+
+    #[test]
+    fn test_changes_turn_old_into_new() {
+        let cases = [
+            ("one\ntwo\nthree\n", "one\n2\nthree\nfour\n"),
+            ("", "new\n"),
+            ("gone\n", ""),
+            ("a\nb", "a\nb\nc"),
+            ("x\ny\nz\n", "z\ny\nx\n"),
+        ];
+        for (old, new) in cases {
+            assert_eq!(apply(old, new), new);
+        }
+    }
+
+    #[test]
+    fn test_unified() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13";
+
+        assert_eq!(
+            unified(old, new, "a", "b"),
+            "--- a\n+++ b\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+        assert_eq!(unified(old, old, "a", "b"), "");
+    }
+
+    #[test]
+    fn test_merge_without_conflicts() {
+        let base = "a\nb\nc\nd\n";
+        let ours = "a\nB\nc\nd\n";
+        let theirs = "a\nb\nc\nD\ne\n";
+
+        assert_eq!(
+            merge3(base, ours, theirs),
+            Merge {
+                text: "a\nB\nc\nD\ne\n".to_string(),
+                conflicts: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_merge_same_change_on_both_sides() {
+        let merge = merge3("a\nb\n", "a\nc\n", "a\nc\n");
+
+        assert_eq!(merge.text, "a\nc\n");
+        assert_eq!(merge.conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge_with_conflicts() {
+        let merge = merge3("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+
+        assert_eq!(
+            merge.text,
+            "a\n<<<<<<< buffer\nours\n=======\ntheirs\n>>>>>>> disk\nc\n"
+        );
+        assert_eq!(merge.conflicts, 1);
+    }
+}
@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// detects the encoding of `bytes` and decodes them, files that are not
+    /// valid utf-8 and have no BOM are read as latin-1 as every byte is valid
+    pub fn decode(bytes: &[u8]) -> (String, Encoding) {
+        // invalid bytes after the bom are kept as they are by latin1 below
+        let text = bytes.strip_prefix(UTF8_BOM).map(std::str::from_utf8);
+        if let Some(Ok(text)) = text {
+            return (text.to_string(), Encoding::Utf8Bom);
+        }
+        if let Some(bytes) = bytes.strip_prefix(UTF16LE_BOM) {
+            return (decode_utf16(bytes, u16::from_le_bytes), Encoding::Utf16Le);
+        }
+        if let Some(bytes) = bytes.strip_prefix(UTF16BE_BOM) {
+            return (decode_utf16(bytes, u16::from_be_bytes), Encoding::Utf16Be);
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => (text.to_string(), Encoding::Utf8),
+            Err(_) => (bytes.iter().map(|b| *b as char).collect(), Encoding::Latin1),
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> std::io::Result<Vec<u8>> {
+        let bytes = match self {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Utf8Bom => [UTF8_BOM, text.as_bytes()].concat(),
+            Encoding::Utf16Le => {
+                let mut bytes = UTF16LE_BOM.to_vec();
+                bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+                bytes
+            }
+            Encoding::Utf16Be => {
+                let mut bytes = UTF16BE_BOM.to_vec();
+                bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+                bytes
+            }
+            Encoding::Latin1 => text
+                .chars()
+                .map(|c| {
+                    u8::try_from(c).map_err(|_| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("{c:?} cannot be written as latin-1"),
+                        )
+                    })
+                })
+                .collect::<std::io::Result<Vec<u8>>>()?,
+        };
+        Ok(bytes)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Ok(Encoding::Utf8Bom),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" | "utf-16" | "utf16" => Ok(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(anyhow::anyhow!("unknown file encoding: {s}")),
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|c| to_u16([c[0], c[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    /// the style of the first line break found in `text`
+    pub fn detect(text: &str) -> LineEnding {
+        match text.find(['\n', '\r']) {
+            Some(i) if text[i..].starts_with("\r\n") => LineEnding::Crlf,
+            Some(i) if text[i..].starts_with('\r') => LineEnding::Cr,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    /// turns every line break of this style into `\n`, which is what the
+    /// buffer works with, other carriage returns are left alone
+    pub fn normalize(&self, text: &str) -> String {
+        match self {
+            LineEnding::Lf => text.to_string(),
+            _ => text.replace(self.as_str(), "\n"),
+        }
+    }
+
+    /// turns every `\n` back into this line ending
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            LineEnding::Lf => text.to_string(),
+            _ => text.replace('\n', self.as_str()),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// the vim name of the line ending
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::Crlf => "dos",
+            LineEnding::Cr => "mac",
+        }
+    }
+}
+
+impl std::fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for LineEnding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unix" | "lf" => Ok(LineEnding::Lf),
+            "dos" | "crlf" => Ok(LineEnding::Crlf),
+            "mac" | "cr" => Ok(LineEnding::Cr),
+            _ => Err(anyhow::anyhow!("unknown file format: {s}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_utf8() {
+        assert_eq!(
+            Encoding::decode("héllo".as_bytes()),
+            ("héllo".to_string(), Encoding::Utf8)
+        );
+        assert_eq!(
+            Encoding::decode(&[0xEF, 0xBB, 0xBF, b'a']),
+            ("a".to_string(), Encoding::Utf8Bom)
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8_after_bom() {
+        assert_eq!(
+            Encoding::decode(&[0xEF, 0xBB, 0xBF, b'a', 0xE9]),
+            ("ï»¿aé".to_string(), Encoding::Latin1)
+        );
+    }
+
+    #[test]
+    fn test_decode_utf16() {
+        assert_eq!(
+            Encoding::decode(&[0xFF, 0xFE, b'h', 0, 0xE9, 0]),
+            ("hé".to_string(), Encoding::Utf16Le)
+        );
+        assert_eq!(
+            Encoding::decode(&[0xFE, 0xFF, 0, b'h', 0xD8, 0x3D, 0xDE, 0x00]),
+            ("h😀".to_string(), Encoding::Utf16Be)
+        );
+    }
+
+    #[test]
+    fn test_decode_falls_back_to_latin1() {
+        assert_eq!(
+            Encoding::decode(&[b'c', b'a', b'f', 0xE9]),
+            ("café".to_string(), Encoding::Latin1)
+        );
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let text = "hé😀\n";
+        for encoding in [
+            Encoding::Utf8,
+            Encoding::Utf8Bom,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+        ] {
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(Encoding::decode(&bytes), (text.to_string(), encoding));
+        }
+        assert_eq!(Encoding::Latin1.encode("café").unwrap(), b"caf\xE9");
+        assert!(Encoding::Latin1.encode("😀").is_err());
+    }
+
+    #[test]
+    fn test_line_endings() {
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\rb"), LineEnding::Cr);
+        assert_eq!(LineEnding::detect("a\nb\r\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a"), LineEnding::Lf);
+
+        assert_eq!(LineEnding::Crlf.normalize("a\r\nb\rc\n"), "a\nb\rc\n");
+        assert_eq!(LineEnding::Cr.normalize("a\rb\r"), "a\nb\n");
+        assert_eq!(LineEnding::Lf.normalize("a\nb\r\n"), "a\nb\r\n");
+        assert_eq!(LineEnding::Crlf.apply("a\nb\n"), "a\r\nb\r\n");
+        assert_eq!("dos".parse::<LineEnding>().unwrap(), LineEnding::Crlf);
+    }
+}
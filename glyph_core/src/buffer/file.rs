@@ -0,0 +1,223 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// What the file looked like on disk the last time the buffer was read from
+/// or written to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskState {
+    pub mtime: Option<SystemTime>,
+    pub size: u64,
+    pub hash: u64,
+}
+
+impl DiskState {
+    pub fn new(content: &[u8], metadata: &fs::Metadata) -> Self {
+        Self {
+            mtime: metadata.modified().ok(),
+            size: metadata.len(),
+            hash: fnv1a(content),
+        }
+    }
+
+    /// reads the file returning its content alongside its state
+    pub fn read(path: &Path) -> io::Result<(Vec<u8>, DiskState)> {
+        let content = fs::read(path)?;
+        let metadata = fs::metadata(path)?;
+        let state = DiskState::new(&content, &metadata);
+        Ok((content, state))
+    }
+
+    pub fn same_metadata(&self, metadata: &fs::Metadata) -> bool {
+        self.mtime == metadata.modified().ok() && self.size == metadata.len()
+    }
+
+    pub fn same_content(&self, other: &DiskState) -> bool {
+        self.size == other.size && self.hash == other.hash
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskStatus {
+    Unchanged,
+    Changed,
+    Deleted,
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// writes `content` next to `path` and renames it over the original file, so
+/// the file is never left half written. `path` is expected to be canonical,
+/// that way symlinks keep pointing to the file that was just written.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let permissions = fs::metadata(path).ok().map(|m| m.permissions());
+    write_atomic_with(path, content, permissions)
+}
+
+/// `write_atomic` giving the file `permissions` instead of the ones it had
+pub fn write_atomic_with(
+    path: &Path,
+    content: &[u8],
+    permissions: Option<fs::Permissions>,
+) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let temp_path = dir.join(format!(
+        ".{}.{}.glyph~",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    let result =
+        write_temp(&temp_path, content, permissions).and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        _ = fs::remove_file(&temp_path);
+    }
+    result?;
+
+    // makes the rename itself durable, not every platform supports this
+    if let Ok(dir) = File::open(dir) {
+        _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_temp(path: &Path, content: &[u8], permissions: Option<fs::Permissions>) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// directory inside glyph's state directory where `name` files are kept
+pub fn state_dir(name: &str) -> PathBuf {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("glyph")
+        .join(name)
+}
+
+/// file inside `dir` that belongs to `path`, the whole path is encoded into
+/// the name so files with the same name in different directories don't clash
+pub fn state_file(dir: &Path, path: &Path, extension: &str) -> PathBuf {
+    let name = path
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "%");
+    dir.join(format!("{name}.{extension}"))
+}
+
+/// absolute path of `name` with every symlink resolved, files that don't
+/// exist yet are resolved relative to their parent directory
+pub fn resolve_path(name: &str) -> io::Result<PathBuf> {
+    let path = Path::new(name);
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(path),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let file_name = path.file_name().ok_or(err)?;
+            let parent = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            Ok(fs::canonicalize(parent)?.join(file_name))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("glyph_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_state_file() {
+        let path = state_file(Path::new("/state"), Path::new("/home/user/file.rs"), "swp");
+
+        assert_eq!(path, PathBuf::from("/state/%home%user%file.rs.swp"));
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_content() {
+        let dir = temp_dir("write_atomic");
+        let path = dir.join("file.txt");
+        fs::write(&path, "old content that is longer").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        // no temp files are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("write_atomic_permissions");
+        let path = dir.join("script.sh");
+        fs::write(&path, "echo").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+        write_atomic(&path, b"echo hi").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o751);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_path_follows_symlinks() {
+        let dir = temp_dir("resolve_path");
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "content").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let resolved = resolve_path(link.to_str().unwrap()).unwrap();
+        write_atomic(&resolved, b"changed").unwrap();
+
+        assert_eq!(resolved, fs::canonicalize(&target).unwrap());
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&link).unwrap(), "changed");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_path_of_new_file() {
+        let dir = temp_dir("resolve_new_path");
+        let path = dir.join("new.txt");
+
+        let resolved = resolve_path(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(resolved, fs::canonicalize(&dir).unwrap().join("new.txt"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
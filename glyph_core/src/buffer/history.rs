@@ -0,0 +1,361 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::transaction::ChangeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum HistoryJump {
+    Steps(usize),
+    Seconds(u64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Revision {
+    parent: usize,
+    // the child we came back from, used to know which branch redo follows
+    last_child: Option<usize>,
+    changes: ChangeSet,
+    inverse: ChangeSet,
+    // cursor position right before the change was made
+    cursor: usize,
+    timestamp: SystemTime,
+}
+
+/// The changes needed to move the buffer from one revision to another,
+/// alongside where the cursor should be placed after applying them.
+#[derive(Debug, PartialEq)]
+pub struct Travel {
+    pub changes: ChangeSet,
+    pub cursor: usize,
+}
+
+/// Undo tree for a buffer. Revisions are stored in the order they were
+/// created, which is also the chronological order used by `earlier` and
+/// `later`, and every revision knows its parent so undoing never loses a
+/// branch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct History {
+    revisions: Vec<Revision>,
+    current: usize,
+    // the uncommitted change alongside its inverse
+    #[serde(skip)]
+    pending: Option<(ChangeSet, ChangeSet)>,
+    #[serde(skip)]
+    pending_cursor: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            revisions: vec![Revision {
+                parent: 0,
+                last_child: None,
+                changes: ChangeSet::default(),
+                inverse: ChangeSet::default(),
+                cursor: 0,
+                timestamp: SystemTime::now(),
+            }],
+            current: 0,
+            pending: None,
+            pending_cursor: None,
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// sets where the cursor was before the pending change started, this is
+    /// ignored once the change already has edits
+    pub fn set_cursor(&mut self, cursor: usize) {
+        if self.pending.is_none() {
+            self.pending_cursor = Some(cursor);
+        }
+    }
+
+    /// adds an applied change to the pending undo step, `inverse` must be the
+    /// change set that reverts it
+    pub fn record(&mut self, changes: ChangeSet, inverse: ChangeSet) {
+        if changes.is_empty() {
+            return;
+        }
+        if self.pending.is_none() && self.pending_cursor.is_none() {
+            self.pending_cursor = changes.first_change();
+        }
+        self.pending = Some(match self.pending.take() {
+            None => (changes, inverse),
+            Some((pending, pending_inverse)) => {
+                (pending.compose(&changes), inverse.compose(&pending_inverse))
+            }
+        });
+    }
+
+    /// turns every pending edit into a single undo step
+    pub fn commit(&mut self) {
+        let cursor = self.pending_cursor.take();
+        let Some((changes, inverse)) = self.pending.take() else {
+            return;
+        };
+        self.revisions.push(Revision {
+            parent: self.current,
+            last_child: None,
+            cursor: cursor.or(changes.first_change()).unwrap_or(0),
+            changes,
+            inverse,
+            timestamp: SystemTime::now(),
+        });
+        let revision = self.revisions.len() - 1;
+        self.revisions[self.current].last_child = Some(revision);
+        self.current = revision;
+    }
+
+    pub fn undo(&mut self) -> Option<Travel> {
+        self.commit();
+        if self.current == 0 {
+            return None;
+        }
+        let revision = &self.revisions[self.current];
+        let travel = Travel {
+            changes: revision.inverse.clone(),
+            cursor: revision.cursor,
+        };
+        let parent = revision.parent;
+        self.revisions[parent].last_child = Some(self.current);
+        self.current = parent;
+        Some(travel)
+    }
+
+    pub fn redo(&mut self) -> Option<Travel> {
+        self.commit();
+        let child = self.revisions[self.current].last_child?;
+        let changes = self.revisions[child].changes.clone();
+        self.current = child;
+        Some(Travel {
+            cursor: changes.first_change().unwrap_or(0),
+            changes,
+        })
+    }
+
+    pub fn earlier(&mut self, jump: HistoryJump) -> Option<Travel> {
+        self.commit();
+        let target = match jump {
+            HistoryJump::Steps(steps) => self.current.saturating_sub(steps),
+            HistoryJump::Seconds(seconds) => {
+                let timestamp = self.revisions[self.current].timestamp;
+                let limit = timestamp
+                    .checked_sub(Duration::from_secs(seconds))
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                self.last_revision_before(limit).min(self.current)
+            }
+        };
+        self.jump_to(target)
+    }
+
+    pub fn later(&mut self, jump: HistoryJump) -> Option<Travel> {
+        self.commit();
+        let target = match jump {
+            HistoryJump::Steps(steps) => usize::min(self.current + steps, self.revisions.len() - 1),
+            HistoryJump::Seconds(seconds) => {
+                let timestamp = self.revisions[self.current].timestamp;
+                let limit = timestamp + Duration::from_secs(seconds);
+                self.last_revision_before(limit).max(self.current)
+            }
+        };
+        self.jump_to(target)
+    }
+
+    fn last_revision_before(&self, limit: SystemTime) -> usize {
+        self.revisions
+            .iter()
+            .rposition(|r| r.timestamp <= limit)
+            .unwrap_or(0)
+    }
+
+    fn ancestors(&self, revision: usize) -> Vec<usize> {
+        let mut path = vec![revision];
+        let mut revision = revision;
+        while revision != 0 {
+            revision = self.revisions[revision].parent;
+            path.push(revision);
+        }
+        path
+    }
+
+    /// walks the tree from the current revision up to the common ancestor
+    /// with `target` and then down into it
+    pub fn jump_to(&mut self, target: usize) -> Option<Travel> {
+        if target == self.current || target >= self.revisions.len() {
+            return None;
+        }
+        let down = self.ancestors(target);
+        let mut changes: Option<ChangeSet> = None;
+        let mut cursor = None;
+
+        while !down.contains(&self.current) {
+            let revision = &self.revisions[self.current];
+            changes = Some(compose(changes, &revision.inverse));
+            cursor = Some(revision.cursor);
+            let parent = revision.parent;
+            self.revisions[parent].last_child = Some(self.current);
+            self.current = parent;
+        }
+
+        let common = down.iter().position(|r| *r == self.current).unwrap();
+        for &revision in down[..common].iter().rev() {
+            self.revisions[self.current].last_child = Some(revision);
+            let revision_changes = &self.revisions[revision].changes;
+            cursor = revision_changes.first_change();
+            changes = Some(compose(changes, revision_changes));
+            self.current = revision;
+        }
+
+        Some(Travel {
+            changes: changes.unwrap_or_default(),
+            cursor: cursor.unwrap_or(0),
+        })
+    }
+}
+
+fn compose(changes: Option<ChangeSet>, next: &ChangeSet) -> ChangeSet {
+    match changes {
+        Some(changes) => changes.compose(next),
+        None => next.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    fn edit(history: &mut History, text: &mut Rope, change: (usize, usize, Option<&str>)) {
+        let (start, end, content) = change;
+        let changes =
+            ChangeSet::from_changes(text.len_chars(), [(start, end, content.map(String::from))])
+                .unwrap();
+        let inverse = changes.invert(text);
+        changes.apply(text);
+        history.record(changes, inverse);
+    }
+
+    fn travel(text: &mut Rope, travel: Option<Travel>) -> Option<usize> {
+        let travel = travel?;
+        travel.changes.apply(text);
+        Some(travel.cursor)
+    }
+
+    #[test]
+    fn test_pending_edits_are_composed() {
+        let mut history = History::new();
+        let mut text = Rope::from_str("hello");
+        edit(&mut history, &mut text, (5, 5, Some(" world")));
+        edit(&mut history, &mut text, (0, 1, Some("J")));
+        history.commit();
+
+        travel(&mut text, history.undo());
+
+        assert_eq!(text, "hello");
+        assert_eq!(history.revisions.len(), 2);
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut history = History::new();
+        let mut text = Rope::from_str("hello");
+        history.set_cursor(4);
+        edit(&mut history, &mut text, (5, 5, Some("abc")));
+        history.commit();
+
+        assert_eq!(travel(&mut text, history.undo()), Some(4));
+        assert_eq!(text, "hello");
+        assert!(history.undo().is_none());
+
+        assert_eq!(travel(&mut text, history.redo()), Some(5));
+        assert_eq!(text, "helloabc");
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn test_undo_commits_pending_edits() {
+        let mut history = History::new();
+        let mut text = Rope::from_str("");
+        edit(&mut history, &mut text, (0, 0, Some("a")));
+
+        travel(&mut text, history.undo());
+
+        assert_eq!(text, "");
+        assert_eq!(history.current(), 0);
+    }
+
+    #[test]
+    fn test_redo_follows_the_last_visited_branch() {
+        let mut history = History::new();
+        let mut text = Rope::from_str("");
+        edit(&mut history, &mut text, (0, 0, Some("a")));
+        history.commit();
+        travel(&mut text, history.undo());
+        edit(&mut history, &mut text, (0, 0, Some("b")));
+        history.commit();
+        travel(&mut text, history.undo());
+
+        travel(&mut text, history.redo());
+
+        assert_eq!(text, "b");
+    }
+
+    #[test]
+    fn test_earlier_and_later_walk_across_branches() {
+        let mut history = History::new();
+        let mut text = Rope::from_str("");
+        edit(&mut history, &mut text, (0, 0, Some("a")));
+        history.commit();
+        travel(&mut text, history.undo());
+        edit(&mut history, &mut text, (0, 0, Some("b")));
+        history.commit();
+
+        // going back one step chronologically lands on the other branch
+        travel(&mut text, history.earlier(HistoryJump::Steps(1)));
+        assert_eq!(text, "a");
+        assert_eq!(history.current(), 1);
+
+        travel(&mut text, history.later(HistoryJump::Steps(1)));
+        assert_eq!(text, "b");
+        assert_eq!(history.current(), 2);
+    }
+
+    #[test]
+    fn test_earlier_by_time() {
+        let mut history = History::new();
+        let mut text = Rope::from_str("");
+        edit(&mut history, &mut text, (0, 0, Some("a")));
+        history.commit();
+        edit(&mut history, &mut text, (1, 1, Some("b")));
+        history.commit();
+        history.revisions[1].timestamp -= Duration::from_secs(600);
+        history.revisions[0].timestamp -= Duration::from_secs(900);
+
+        travel(&mut text, history.earlier(HistoryJump::Seconds(300)));
+        assert_eq!(history.current(), 1);
+        assert_eq!(text, "a");
+
+        travel(&mut text, history.earlier(HistoryJump::Seconds(3600)));
+        assert_eq!(history.current(), 0);
+        assert_eq!(text, "");
+
+        travel(&mut text, history.later(HistoryJump::Seconds(3600)));
+        assert_eq!(history.current(), 2);
+        assert_eq!(text, "ab");
+    }
+}
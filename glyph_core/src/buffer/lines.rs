@@ -1,6 +1,8 @@
+use ropey::Rope;
+
 #[derive(Debug, PartialEq)]
 pub struct Lines<'a> {
-    pub buffer: &'a [char],
+    pub buffer: &'a Rope,
     pub start: usize,
     pub end: usize,
 }
@@ -14,13 +16,13 @@ impl<'a> Iterator for Lines<'a> {
         }
 
         let start = self.start;
-        let range = start..self.end;
-        let filtered = range.filter(|i| *i + 1 == self.end || self.buffer[*i] == '\n');
-        self.start = filtered.take(1).next().unwrap() + 1;
+        let line = self.buffer.char_to_line(start);
+        self.start = usize::min(self.buffer.line_to_char(line + 1), self.end);
 
-        let line = (start..self.start)
-            .map(|i| self.buffer[i])
-            .filter(|&c| c != '\0')
+        let line = self
+            .buffer
+            .slice(start..self.start)
+            .chars()
             .collect::<Vec<_>>();
 
         Some(line)
@@ -36,7 +38,7 @@ mod tests {
         let text =
             "The quick brown fox\njumps over the lazy dog\nThe five boxing wizards\njump quickly.";
         let mut lines = Lines {
-            buffer: &text.chars().collect::<Vec<_>>(),
+            buffer: &Rope::from_str(text),
             start: 0,
             end: text.len(),
         };
@@ -65,7 +67,7 @@ mod tests {
         let text =
             "The quick brown fox\njumps over the lazy dog\nThe five boxing wizards\njump quickly.\n";
         let mut lines = Lines {
-            buffer: &text.chars().collect::<Vec<_>>(),
+            buffer: &Rope::from_str(text),
             start: 0,
             end: text.len(),
         };
@@ -93,7 +95,7 @@ mod tests {
     fn test_return_none_with_empty_lines() {
         let text = "";
         let mut lines = Lines {
-            buffer: &text.chars().collect::<Vec<_>>(),
+            buffer: &Rope::from_str(text),
             start: 0,
             end: text.len(),
         };
@@ -107,7 +109,7 @@ mod tests {
     fn test_return_empty_string_with_only_newlines() {
         let text = "\n\n\n\n";
         let mut lines = Lines {
-            buffer: &text.chars().collect::<Vec<_>>(),
+            buffer: &Rope::from_str(text),
             start: 0,
             end: text.len(),
         };
@@ -1,4 +1,6 @@
-use super::vec_marker::VecMarker;
+use ropey::Rope;
+
+use super::tree_marker::TreeMarker;
 
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub struct Mark {
@@ -18,13 +20,15 @@ pub trait Marker: std::fmt::Debug {
     fn del_mark(&mut self, at: usize);
     fn get_by_cursor(&self, position: usize) -> Option<Mark>;
     fn get_by_line(&self, line: usize) -> Option<Mark>;
-    fn set_marks(&mut self, text: &[char]);
+    fn set_marks(&mut self, text: &Rope);
+    fn insert_text(&mut self, position: usize, text: &str);
+    fn delete_range(&mut self, start: usize, end: usize);
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
 }
 
 impl dyn Marker {
     pub fn get_marker() -> Box<Self> {
-        Box::new(VecMarker::new())
+        Box::new(TreeMarker::new())
     }
 }
@@ -0,0 +1,147 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::buffer::transaction::{Assoc, ChangeSet, Operation};
+
+/// A position that follows the text it points at as its buffer is edited.
+pub type Anchor = Rc<Cell<usize>>;
+
+pub const LAST_JUMP: char = '`';
+pub const LAST_CHANGE: char = '.';
+pub const LAST_INSERT: char = '^';
+pub const CHANGE_START: char = '[';
+pub const CHANGE_END: char = ']';
+pub const VISUAL_START: char = '<';
+pub const VISUAL_END: char = '>';
+
+const SPECIAL: [char; 8] = [
+    LAST_JUMP,
+    '\'',
+    LAST_CHANGE,
+    LAST_INSERT,
+    CHANGE_START,
+    CHANGE_END,
+    VISUAL_START,
+    VISUAL_END,
+];
+
+/// The marks of a buffer, following vim semantics:
+///
+/// - `a`-`z` are local to the buffer
+/// - `A`-`Z` are global, the editor keeps each of them in a single buffer
+/// - `` ` `` (also named `'`) is where the cursor was before the last jump
+/// - `.` is where the last change was made and `^` where insert mode was left
+/// - `[` and `]` are the first and last char of the last changed or yanked
+///   text
+/// - `<` and `>` are the first and last char of the last selection
+///
+/// Marks, and the anchors handed out to jump lists, move along with the text
+/// as the buffer is edited.
+#[derive(Debug, Default)]
+pub struct Marks {
+    marks: HashMap<char, usize>,
+    anchors: Vec<Weak<Cell<usize>>>,
+}
+
+impl Marks {
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphabetic() || SPECIAL.contains(&name)
+    }
+
+    pub fn set(&mut self, name: char, pos: usize) {
+        self.marks.insert(Self::resolve(name), pos);
+    }
+
+    pub fn get(&self, name: char) -> Option<usize> {
+        self.marks.get(&Self::resolve(name)).copied()
+    }
+
+    pub fn remove(&mut self, name: char) -> Option<usize> {
+        self.marks.remove(&Self::resolve(name))
+    }
+
+    // `'` and `` ` `` are the same mark
+    fn resolve(name: char) -> char {
+        match name {
+            '\'' => LAST_JUMP,
+            name => name,
+        }
+    }
+
+    /// anchors `pos`, it moves with the text for as long as it is kept around
+    pub fn anchor(&mut self, pos: usize) -> Anchor {
+        self.anchors.retain(|anchor| anchor.strong_count() > 0);
+        let anchor = Rc::new(Cell::new(pos));
+        self.anchors.push(Rc::downgrade(&anchor));
+        anchor
+    }
+
+    /// moves every mark and anchor through `changes`, setting `.`, `[` and `]`
+    /// to where they happened
+    pub fn map(&mut self, changes: &ChangeSet) {
+        // marks stay on their char when text is inserted right before it
+        for pos in self.marks.values_mut() {
+            *pos = changes.map_pos(*pos, Assoc::After);
+        }
+        for anchor in self.anchors.iter().filter_map(Weak::upgrade) {
+            anchor.set(changes.map_pos(anchor.get(), Assoc::After));
+        }
+
+        let mut pos = 0;
+        let mut changed = None;
+        for op in changes.operations() {
+            match op {
+                Operation::Retain(n) => pos += n,
+                Operation::Insert(text) => {
+                    let start = changed.map_or(pos, |(start, _)| start);
+                    pos += text.chars().count();
+                    changed = Some((start, pos));
+                }
+                Operation::Delete(_) => {
+                    let start = changed.map_or(pos, |(start, _)| start);
+                    changed = Some((start, pos));
+                }
+            }
+        }
+        if let Some((start, end)) = changed {
+            self.set(LAST_CHANGE, start);
+            self.set(CHANGE_START, start);
+            self.set(CHANGE_END, end.saturating_sub(1).max(start));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marks_follow_edits() {
+        let mut marks = Marks::default();
+        marks.set('a', 4);
+        marks.set('b', 10);
+        let anchor = marks.anchor(8);
+
+        // "one two three" -> "zero one three"
+        let changes =
+            ChangeSet::from_changes(13, [(0, 0, Some("zero ".into())), (4, 8, None)]).unwrap();
+        marks.map(&changes);
+
+        assert_eq!(marks.get('a'), Some(9));
+        assert_eq!(marks.get('b'), Some(11));
+        assert_eq!(anchor.get(), 9);
+        assert_eq!(marks.get(LAST_CHANGE), Some(0));
+        assert_eq!(marks.get(CHANGE_END), Some(8));
+    }
+
+    #[test]
+    fn test_last_jump_has_two_names() {
+        let mut marks = Marks::default();
+        marks.set('`', 3);
+
+        assert_eq!(marks.get('\''), Some(3));
+        assert!(Marks::is_valid('^'));
+        assert!(!Marks::is_valid('1'));
+    }
+}
@@ -1,140 +1,828 @@
+pub mod diff;
+pub mod encoding;
+pub mod file;
+pub mod history;
 mod lines;
 pub mod marker;
-mod vec_marker;
+pub mod marks;
+pub mod swap;
+pub mod text_object;
+pub mod transaction;
+mod tree_marker;
+pub mod undo;
 
 use std::io;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use anyhow::Context;
+
+use ropey::Rope;
+
+use crate::buffer::encoding::{Encoding, LineEnding};
+use crate::buffer::file::{DiskState, DiskStatus};
+use crate::buffer::history::{History, HistoryJump, Travel};
 use crate::buffer::lines::Lines;
 use crate::buffer::marker::Marker;
+use crate::buffer::marks::Marks;
+use crate::buffer::swap::Swap;
+use crate::buffer::transaction::{Change, ChangeSet, Operation, Transaction};
 use crate::editor::Action;
+use crate::options::Options;
+use crate::register::{Register, RegisterKind};
 use marker::Mark;
 
+/// Unit used to count columns when talking positions with the outside world,
+/// the LSP default is utf-16.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PositionEncoding::Utf8 => "utf-8",
+            PositionEncoding::Utf16 => "utf-16",
+            PositionEncoding::Utf32 => "utf-32",
+        }
+    }
+}
+
+impl std::str::FromStr for PositionEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf-8" => Ok(PositionEncoding::Utf8),
+            "utf-16" => Ok(PositionEncoding::Utf16),
+            "utf-32" => Ok(PositionEncoding::Utf32),
+            _ => Err(anyhow::anyhow!("unknown position encoding: {s}")),
+        }
+    }
+}
+
+// every text a buffer ever holds gets its own stamp
+static NEXT_STAMP: AtomicUsize = AtomicUsize::new(0);
+
+fn next_stamp() -> usize {
+    NEXT_STAMP.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Debug)]
 pub struct Buffer {
     pub id: usize,
-    pub buffer: Vec<char>,
+    pub buffer: Rope,
     pub marker: Box<dyn Marker>,
+    pub marks: Marks,
     pub file_name: String,
-    gap_start: usize,
-    gap_end: usize,
-    gap_size: usize,
+    // resolved when the file is opened so saving doesn't depend on the
+    // current directory
+    pub path: Option<PathBuf>,
+    // how the file is written back to disk, the buffer itself always holds
+    // utf-8 text with `\n` line breaks
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    history: History,
+    // state of the file and the content it had when we last synced with it,
+    // the content is the common ancestor when merging changes from disk
+    disk: Option<DiskState>,
+    base: Rope,
+    saved_revision: usize,
+    // where unsaved changes are journaled and how many edits happened since
+    pub swap: Option<PathBuf>,
+    swap_edits: usize,
+    // where the undo history is persisted on save, if at all
+    pub undo_file: Option<PathBuf>,
+    // what reverts the changes shown by `preview`
+    preview: Option<ChangeSet>,
+    // the changes made while they are recorded, for whoever follows many
+    // positions through a batch of edits
+    recorded: Option<Vec<ChangeSet>>,
+    // the buffer-local options, such as how wide an indentation level is
+    pub options: Options,
+    stamp: usize,
 }
 
 impl Buffer {
     pub fn new(id: usize, file_name: Option<String>) -> io::Result<Self> {
-        let lines = match file_name {
-            Some(ref name) => std::fs::read_to_string(name)?,
-            None => String::new(),
+        let (content, encoding, path, disk) = match file_name {
+            Some(ref name) => {
+                let (bytes, disk) = DiskState::read(Path::new(name))?;
+                let (content, encoding) = Encoding::decode(&bytes);
+                (
+                    content,
+                    encoding,
+                    Some(file::resolve_path(name)?),
+                    Some(disk),
+                )
+            }
+            None => (String::new(), Encoding::default(), None, None),
         };
-        let gap = 1000;
-        let mut buffer = Buffer::from_string(id, &lines, gap);
+        let line_ending = LineEnding::detect(&content);
+        let mut buffer = Buffer::from_string(id, &line_ending.normalize(&content));
         buffer.file_name = file_name.unwrap_or_default();
+        buffer.path = path;
+        buffer.encoding = encoding;
+        buffer.line_ending = line_ending;
+        buffer.disk = disk;
+        buffer.swap = buffer
+            .path
+            .as_ref()
+            .map(|path| swap::swap_path(&swap::swap_dir(), path));
         Ok(buffer)
     }
 
-    pub fn from_string(id: usize, content: &str, gap: usize) -> Self {
-        let mut buffer = vec!['\0'; gap];
-        buffer.extend(content.chars());
+    pub fn from_string(id: usize, content: &str) -> Self {
+        let buffer = Rope::from_str(content);
         let mut marker = <dyn Marker>::get_marker();
         marker.set_marks(&buffer);
 
         Buffer {
             id,
-            buffer,
-            gap_start: 0,
-            gap_size: gap,
-            gap_end: gap,
+            buffer: buffer.clone(),
             marker,
+            marks: Marks::default(),
             file_name: String::new(),
+            path: None,
+            encoding: Encoding::default(),
+            line_ending: LineEnding::default(),
+            history: History::new(),
+            disk: None,
+            base: buffer.clone(),
+            saved_revision: 0,
+            swap: None,
+            swap_edits: 0,
+            undo_file: None,
+            preview: None,
+            recorded: None,
+            options: Options::default(),
+            stamp: next_stamp(),
         }
     }
 
     pub fn insert_char(&mut self, char: char, cursor_pos: usize) {
-        self.move_gap(cursor_pos);
-        self.buffer[self.gap_start] = char;
-        self.gap_start += 1;
-        if self.gap_start == self.gap_end {
-            self.resize_gap();
+        let cursor_pos = cursor_pos.min(self.buffer.len_chars());
+        let transaction = self.transaction([(cursor_pos, cursor_pos, Some(char.to_string()))]);
+        self.apply_transaction(&transaction);
+    }
+
+    pub fn delete_char(&mut self, cursor_pos: usize) {
+        if cursor_pos == 0 || cursor_pos > self.buffer.len_chars() {
+            return;
         }
-        self.marker.set_marks(&self.buffer);
+        let transaction = self.transaction([(cursor_pos - 1, cursor_pos, None)]);
+        self.apply_transaction(&transaction);
     }
 
-    fn resize_gap(&mut self) {
-        let left = &self.buffer[0..self.gap_start];
-        let right = &self.buffer[self.gap_end..];
-        let new_size = self.buffer.len() + self.gap_size;
-        let mut new_buffer = vec!['\0'; new_size];
-        new_buffer[0..left.len()].copy_from_slice(left);
-        new_buffer[left.len() + self.gap_size..left.len() + self.gap_size + right.len()]
-            .copy_from_slice(right);
-        self.gap_end = new_buffer.len() - right.len();
-        self.buffer = new_buffer;
+    /// char ranges covered by a `kind` selection between `from` and `to`:
+    /// charwise selections end before `to`, linewise ones cover every line
+    /// they touch and blockwise ones the rectangle with both corners included
+    #[allow(clippy::single_range_in_vec_init)]
+    pub fn selection(&self, from: usize, to: usize, kind: RegisterKind) -> Vec<Range<usize>> {
+        let len = self.buffer.len_chars();
+        let (from, to) = (from.min(to).min(len), from.max(to).min(len));
+        match kind {
+            RegisterKind::Charwise => vec![from..to],
+            RegisterKind::Linewise => {
+                let (first, _) = self.char_to_line_col(from);
+                let (last, _) = self.char_to_line_col(to);
+                let mut start = self.buffer.line_to_char(first);
+                let end = self
+                    .buffer
+                    .line_to_char((last + 1).min(self.buffer.len_lines()));
+                // the last line has no line break to take, so the previous one goes
+                if end == len && end > 0 && self.buffer.char(end - 1) != '\n' {
+                    start = start.saturating_sub(1);
+                }
+                vec![start..end]
+            }
+            RegisterKind::Blockwise => {
+                let (first, first_col) = self.char_to_line_col(from);
+                let (last, last_col) = self.char_to_line_col(to);
+                let (left, right) = (first_col.min(last_col), first_col.max(last_col) + 1);
+                (first..=last)
+                    .map(|line| {
+                        let start = self.buffer.line_to_char(line);
+                        let len = self.line_len(line);
+                        start + left.min(len)..start + right.min(len)
+                    })
+                    .collect()
+            }
+        }
     }
 
-    pub fn delete_char(&mut self, cursor_pos: usize) {
-        if cursor_pos == 0 {
-            return;
+    /// the text of a `kind` selection as it would be stored in a register
+    pub fn yank(&self, from: usize, to: usize, kind: RegisterKind) -> Register {
+        let ranges = self.selection(from, to, kind);
+        let text = |range: &Range<usize>| self.buffer.slice(range.clone()).to_string();
+        match kind {
+            RegisterKind::Charwise => Register::charwise(text(&ranges[0])),
+            RegisterKind::Linewise => {
+                let (first, _) = self.char_to_line_col(from.min(to));
+                let start = self.buffer.line_to_char(first);
+                Register::linewise(text(&(start..ranges[0].end)))
+            }
+            RegisterKind::Blockwise => {
+                Register::blockwise(&ranges.iter().map(text).collect::<Vec<_>>())
+            }
         }
-        self.move_gap(cursor_pos);
-        self.gap_start -= 1;
-        self.buffer[self.gap_start] = '\0';
-        self.marker.set_marks(&self.buffer);
     }
 
-    pub fn move_gap(&mut self, cursor_pos: usize) {
-        let cursor_pos = self.translate_cursor_pos(cursor_pos);
+    /// deletes a `kind` selection returning the deleted text and where the
+    /// cursor should be placed
+    pub fn delete(&mut self, from: usize, to: usize, kind: RegisterKind) -> (Register, usize) {
+        let register = self.yank(from, to, kind);
+        let ranges = self.selection(from, to, kind);
+        let cursor = ranges[0].start;
+        let changes = ranges
+            .into_iter()
+            .map(|range| (range.start, range.end, None));
+        let transaction = self.transaction(changes).with_cursor(cursor);
+        let cursor = self.apply_transaction(&transaction).unwrap_or_default();
+        (register, cursor)
+    }
+
+    /// puts the register after or before `pos` the way vim's `p` and `P` do,
+    /// returning where the cursor should be placed
+    pub fn put(&mut self, register: &Register, pos: usize, after: bool) -> usize {
+        let (changes, cursor) = self.put_changes(register, pos, after);
+        let transaction = self.transaction(changes).with_cursor(cursor);
+        self.apply_transaction(&transaction).unwrap_or_default()
+    }
 
-        if cursor_pos >= self.gap_end {
-            for _ in self.gap_end..cursor_pos {
-                self.buffer[self.gap_start] = self.buffer[self.gap_end];
-                self.buffer[self.gap_end] = '\0';
-                self.gap_start += 1;
-                self.gap_end += 1;
+    /// the changes `put` makes, and where the cursor goes once they're made
+    pub fn put_changes(
+        &self,
+        register: &Register,
+        pos: usize,
+        after: bool,
+    ) -> (Vec<Change>, usize) {
+        let len = self.buffer.len_chars();
+        let pos = pos.min(len);
+        let (line, col) = self.char_to_line_col(pos);
+        let line_start = self.buffer.line_to_char(line);
+        let line_end = line_start + self.line_len(line);
+        let (changes, cursor) = match register.kind {
+            RegisterKind::Charwise => {
+                let at = match after && pos < line_end {
+                    true => pos + 1,
+                    false => pos,
+                };
+                let count = register.text.chars().count();
+                let cursor = at + count.saturating_sub(1);
+                (vec![(at, at, Some(register.text.clone()))], cursor)
+            }
+            RegisterKind::Linewise if !after => (
+                vec![(line_start, line_start, Some(register.text.clone()))],
+                line_start,
+            ),
+            RegisterKind::Linewise if line_end < len => {
+                let at = line_end + 1;
+                (vec![(at, at, Some(register.text.clone()))], at)
             }
-        } else {
-            for _ in (cursor_pos..self.gap_start).rev() {
-                self.gap_end -= 1;
-                self.gap_start -= 1;
-                self.buffer[self.gap_end] = self.buffer[self.gap_start];
-                self.buffer[self.gap_start] = '\0';
+            RegisterKind::Linewise => {
+                // the last line has no line break to put the text after
+                let text = format!("\n{}", register.text.trim_end_matches('\n'));
+                (vec![(line_end, line_end, Some(text))], line_end + 1)
             }
+            RegisterKind::Blockwise => {
+                let col = match after && pos < line_end {
+                    true => col + 1,
+                    false => col,
+                };
+                let mut changes = vec![];
+                let mut appended = String::new();
+                // a trailing line break doesn't start another line
+                let lines = self.buffer.len_lines()
+                    - usize::from(len > 0 && self.buffer.char(len - 1) == '\n');
+                for (i, text) in register.text.split('\n').enumerate() {
+                    let line = line + i;
+                    if line < lines {
+                        let start = self.buffer.line_to_char(line);
+                        let len = self.line_len(line);
+                        let padding = " ".repeat(col.saturating_sub(len));
+                        let at = start + col.min(len);
+                        changes.push((at, at, Some(format!("{padding}{text}"))));
+                    } else {
+                        appended.push_str(&format!("\n{}{text}", " ".repeat(col)));
+                    }
+                }
+                if !appended.is_empty() {
+                    let end = self.buffer.line_to_char(lines - 1) + self.line_len(lines - 1);
+                    changes.push((end, end, Some(appended)));
+                }
+                (changes, line_start + col)
+            }
+        };
+        (changes, cursor)
+    }
+
+    /// inserts `text` at `col` of every line in `lines`, lines shorter than
+    /// `col` are padded with spaces when `pad` is set and skipped otherwise
+    pub fn insert_at_column(
+        &mut self,
+        lines: RangeInclusive<usize>,
+        col: usize,
+        text: &str,
+        pad: bool,
+    ) -> ChangeSet {
+        let changes = lines
+            .filter(|line| *line < self.buffer.len_lines())
+            .filter_map(|line| {
+                let start = self.buffer.line_to_char(line);
+                let len = self.line_len(line);
+                match len < col {
+                    true if !pad => None,
+                    true => Some((start + len, format!("{}{text}", " ".repeat(col - len)))),
+                    false => Some((start + col, text.to_string())),
+                }
+            })
+            .map(|(at, text)| (at, at, Some(text)))
+            .collect::<Vec<_>>();
+        let transaction = self.transaction(changes);
+        self.apply_transaction(&transaction);
+        transaction.changes().clone()
+    }
+
+    /// replaces the text of every range with `f` applied to it
+    pub fn transform(&mut self, ranges: Vec<Range<usize>>, f: impl Fn(&str) -> String) {
+        let changes = ranges.into_iter().map(|range| {
+            let text = f(&self.buffer.slice(range.clone()).to_string());
+            (range.start, range.end, Some(text))
+        });
+        let transaction = self.transaction(changes.collect::<Vec<_>>());
+        self.apply_transaction(&transaction);
+    }
+
+    /// where mark `name` is, or the first non blank char of its line when
+    /// `linewise`
+    pub fn mark(&self, name: char, linewise: bool) -> Option<usize> {
+        let pos = self.marks.get(name)?.min(self.buffer.len_chars());
+        match linewise {
+            true => Some(self.first_non_blank(self.buffer.char_to_line(pos))),
+            false => Some(pos),
         }
     }
 
-    fn translate_cursor_pos(&self, cursor_pos: usize) -> usize {
-        let left = &self.buffer[0..self.gap_start];
-        match left.len().cmp(&cursor_pos) {
-            std::cmp::Ordering::Greater => cursor_pos,
-            std::cmp::Ordering::Equal => cursor_pos,
-            std::cmp::Ordering::Less => {
-                left.len() + (self.gap_end - self.gap_start) + (cursor_pos - left.len())
+    /// char offset of the first char of `line` that is not a space or a tab
+    pub fn first_non_blank(&self, line: usize) -> usize {
+        let start = self.line_col_to_char(line, 0);
+        let indent = self.indentation(line).chars().count();
+        start + indent
+    }
+
+    fn indentation(&self, line: usize) -> String {
+        self.buffer
+            .line(line)
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
+    // what `>` and `<` add and remove, `shiftwidth` columns made of tabs as
+    // far as possible unless `expandtab` is set
+    fn indent_unit(&self) -> String {
+        let width = self.options.number("shiftwidth");
+        let tabstop = self.options.number("tabstop");
+        match self.options.flag("expandtab") {
+            true => " ".repeat(width),
+            false => "\t".repeat(width / tabstop) + &" ".repeat(width % tabstop),
+        }
+    }
+
+    /// adds or removes one level of indentation from lines `first..=last`,
+    /// empty lines are left alone
+    pub fn indent(&mut self, first: usize, last: usize, dedent: bool) {
+        let unit = self.indent_unit();
+        let width = self.options.number("shiftwidth");
+        let changes = (first..=last)
+            .filter(|line| self.line_len(*line) > 0)
+            .filter_map(|line| {
+                let start = self.buffer.line_to_char(line);
+                let indentation = self.indentation(line);
+                match dedent {
+                    false => Some((start, start, Some(unit.clone()))),
+                    true if indentation.starts_with('\t') => Some((start, start + 1, None)),
+                    true => {
+                        let spaces = indentation.chars().take_while(|c| *c == ' ');
+                        let n = spaces.count().min(width);
+                        (n > 0).then_some((start, start + n, None))
+                    }
+                }
+            });
+        let transaction = self.transaction(changes.collect::<Vec<_>>());
+        self.apply_transaction(&transaction);
+    }
+
+    /// the text of `line` without its line break
+    pub fn line_text(&self, line: usize) -> String {
+        let range = self.line_span(line, line);
+        let text = self.buffer.slice(range).to_string();
+        text.strip_suffix('\n').unwrap_or(&text).to_string()
+    }
+
+    // the chars of lines `first..=last` and their line breaks, as the line
+    // index of the marker has them, its lines start at 1
+    fn line_span(&self, first: usize, last: usize) -> Range<usize> {
+        let len = self.buffer.len_chars();
+        let start = self
+            .marker
+            .get_by_line(first + 1)
+            .map_or(len, |mark| mark.start);
+        let end = self
+            .marker
+            .get_by_line(last + 1)
+            .map_or(len, |mark| mark.start + mark.size);
+        start..end
+    }
+
+    /// replaces lines `first..=last` with `lines`, given without line breaks
+    pub fn replace_lines(&mut self, first: usize, last: usize, lines: &[String]) {
+        let span = self.line_span(first, last);
+        let mut text = lines.join("\n");
+        if self.buffer.slice(span.clone()).chars().last() == Some('\n') {
+            text.push('\n');
+        }
+        let transaction = self.transaction([(span.start, span.end, Some(text))]);
+        self.apply_transaction(&transaction);
+    }
+
+    /// moves lines `first..=last` below the first `below` lines, returning
+    /// where the last moved line ends up
+    pub fn move_lines(&mut self, first: usize, last: usize, below: usize) -> anyhow::Result<usize> {
+        if below > first && below <= last {
+            anyhow::bail!("cannot move lines into themselves");
+        }
+        if below == first || below == last + 1 {
+            return Ok(last);
+        }
+        let span = self.line_span(first, last);
+        let text = self.lines_text(first, last);
+        // the last line has no line break, it takes the one before it along
+        let deleted = match self.buffer.char(span.end - 1) {
+            '\n' => span,
+            _ => span.start.saturating_sub(1)..span.end,
+        };
+        let (pos, text) = self.line_insertion(below, text);
+        let changes = match pos < deleted.start {
+            true => [(pos, pos, Some(text)), (deleted.start, deleted.end, None)],
+            false => [(deleted.start, deleted.end, None), (pos, pos, Some(text))],
+        };
+        let transaction = self.transaction(changes);
+        self.apply_transaction(&transaction);
+        match below > last {
+            true => Ok(below - 1),
+            false => Ok(below + last - first),
+        }
+    }
+
+    /// copies lines `first..=last` below the first `below` lines, returning
+    /// where the last copy ends up
+    pub fn copy_lines(&mut self, first: usize, last: usize, below: usize) -> usize {
+        let text = self.lines_text(first, last);
+        let (pos, text) = self.line_insertion(below, text);
+        let transaction = self.transaction([(pos, pos, Some(text))]);
+        self.apply_transaction(&transaction);
+        below + last - first
+    }
+
+    // the text of lines `first..=last`, each ending with a line break
+    fn lines_text(&self, first: usize, last: usize) -> String {
+        let mut text = self.buffer.slice(self.line_span(first, last)).to_string();
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text
+    }
+
+    // where lines go to end up below the first `below` lines, after a last
+    // line without a line break they start with one instead
+    fn line_insertion(&self, below: usize, mut text: String) -> (usize, String) {
+        let pos = self.line_span(below, below).start;
+        let len = self.buffer.len_chars();
+        if pos == len && len > 0 && self.buffer.char(len - 1) != '\n' {
+            text.pop();
+            text.insert(0, '\n');
+        }
+        (pos, text)
+    }
+
+    /// joins lines `first..=last` into one like `J`, leading white space of
+    /// the joined lines is replaced by a single space unless `spaces` is off,
+    /// in which case they are joined as they are
+    pub fn join_lines(&mut self, first: usize, last: usize, spaces: bool) {
+        let mut joined = self.line_text(first);
+        for line in first + 1..=last {
+            let text = self.line_text(line);
+            match spaces {
+                false => joined.push_str(&text),
+                true => {
+                    let text = text.trim_start();
+                    let space = !joined.is_empty()
+                        && !text.is_empty()
+                        && !joined.ends_with([' ', '\t'])
+                        && !text.starts_with(')');
+                    if space {
+                        joined.push(' ');
+                    }
+                    joined.push_str(text);
+                }
             }
         }
+        self.replace_lines(first, last, &[joined]);
+    }
+
+    /// indents lines `first..=last` following the brackets left open by the
+    /// line above them and by the lines themselves
+    pub fn reindent(&mut self, first: usize, last: usize) {
+        let unit = self.indent_unit();
+        let (tabstop, shiftwidth) = (
+            self.options.number("tabstop"),
+            self.options.number("shiftwidth"),
+        );
+        let width = |indentation: &str| {
+            indentation
+                .chars()
+                .map(|c| if c == '\t' { tabstop } else { 1 })
+                .sum::<usize>()
+        };
+        let mut level = (0..first)
+            .rev()
+            .find(|line| self.line_len(*line) > 0)
+            .map(|line| {
+                let text = self.buffer.line(line).to_string();
+                let opens = text.trim_end().ends_with(['{', '(', '[']);
+                width(&self.indentation(line)) / shiftwidth + usize::from(opens)
+            })
+            .unwrap_or(0);
+
+        let mut changes = vec![];
+        for line in first..=last {
+            let text = self.buffer.line(line).to_string();
+            let content = text.trim();
+            let leading_closers = content
+                .chars()
+                .take_while(|c| matches!(c, '}' | ')' | ']'))
+                .count();
+            let opens = content
+                .chars()
+                .filter(|c| matches!(c, '{' | '(' | '['))
+                .count();
+            let closes = content
+                .chars()
+                .filter(|c| matches!(c, '}' | ')' | ']'))
+                .count();
+
+            level = level.saturating_sub(leading_closers);
+            let start = self.buffer.line_to_char(line);
+            let indentation = self.indentation(line);
+            let wanted = match content.is_empty() {
+                true => String::new(),
+                false => unit.repeat(level),
+            };
+            if indentation != wanted {
+                let end = start + indentation.chars().count();
+                changes.push((start, end, Some(wanted)));
+            }
+            level = (level + opens).saturating_sub(closes - leading_closers);
+        }
+        let transaction = self.transaction(changes);
+        self.apply_transaction(&transaction);
+    }
+
+    /// makes `changes` as a single edit started with the cursor at
+    /// `cursor_pos`, returning them so other positions can follow
+    pub fn change(&mut self, changes: Vec<Change>, cursor_pos: usize) -> anyhow::Result<ChangeSet> {
+        let transaction = Transaction::change(&self.buffer, changes)?;
+        self.history.set_cursor(cursor_pos);
+        self.apply_transaction(&transaction);
+        Ok(transaction.changes().clone())
+    }
+
+    /// applies every change of the transaction as a single edit and records it
+    /// into the pending undo step, returning where the cursor should be placed,
+    /// a transaction made for a text of another length is refused
+    pub fn apply(&mut self, transaction: &Transaction) -> anyhow::Result<Option<usize>> {
+        let (len, expected) = (self.buffer.len_chars(), transaction.changes().len());
+        anyhow::ensure!(
+            len == expected,
+            "changes of a text of {expected} chars applied to one of {len}"
+        );
+        Ok(self.apply_transaction(transaction))
+    }
+
+    // a transaction of changes the buffer makes out of its own text
+    fn transaction<I>(&self, changes: I) -> Transaction
+    where
+        I: IntoIterator<Item = Change>,
+    {
+        Transaction::change(&self.buffer, changes).expect("changes within the buffer")
+    }
+
+    // `apply` for the transactions made by `transaction`
+    fn apply_transaction(&mut self, transaction: &Transaction) -> Option<usize> {
+        let changes = transaction.changes();
+        let inverse = changes.invert(&self.buffer);
+        self.apply_changes(changes);
+        self.history.record(changes.clone(), inverse);
+        transaction
+            .cursor()
+            .map(|cursor| cursor.min(self.buffer.len_chars()))
+    }
+
+    fn apply_changes(&mut self, changes: &ChangeSet) {
+        self.swap_edits += 1;
+        self.edit(changes);
+        self.marks.map(changes);
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(changes.clone());
+        }
+    }
+
+    /// keeps the changes made from now on for `take_changes`, or stops
+    pub fn record_changes(&mut self, record: bool) {
+        self.recorded = record.then(Vec::new);
+    }
+
+    /// the changes made since they were last taken, while they are recorded
+    pub fn take_changes(&mut self) -> Vec<ChangeSet> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // changes the text alone, leaving the history and the marks alone
+    fn edit(&mut self, changes: &ChangeSet) {
+        self.stamp = next_stamp();
+        let mut pos = 0;
+        for op in changes.operations() {
+            match op {
+                Operation::Retain(n) => pos += n,
+                Operation::Insert(text) => {
+                    self.buffer.insert(pos, text);
+                    self.marker.insert_text(pos, text);
+                    pos += text.chars().count();
+                }
+                Operation::Delete(n) => {
+                    self.buffer.remove(pos..pos + n);
+                    self.marker.delete_range(pos, pos + n);
+                }
+            }
+        }
+    }
+
+    /// identifies the text of the buffer, it changes with every edit and no
+    /// other text ever had it, so what is computed from the text can be kept
+    /// until it changes
+    pub fn stamp(&self) -> usize {
+        self.stamp
+    }
+
+    /// shows `changes` in the buffer until `end_preview` reverts them, they
+    /// are never recorded and marks don't move
+    pub fn preview(&mut self, changes: &ChangeSet) {
+        self.end_preview();
+        self.preview = Some(changes.invert(&self.buffer));
+        self.edit(changes);
+    }
+
+    pub fn end_preview(&mut self) {
+        if let Some(inverse) = self.preview.take() {
+            self.edit(&inverse);
+        }
+    }
+
+    /// groups every edit made since the last commit into a single undo step
+    pub fn commit(&mut self) {
+        self.history.commit();
+    }
+
+    fn travel(&mut self, travel: Option<Travel>) -> Option<usize> {
+        let travel = travel?;
+        self.apply_changes(&travel.changes);
+        Some(travel.cursor.min(self.buffer.len_chars()))
+    }
+
+    pub fn undo(&mut self) -> Option<usize> {
+        let travel = self.history.undo();
+        self.travel(travel)
+    }
+
+    pub fn redo(&mut self) -> Option<usize> {
+        let travel = self.history.redo();
+        self.travel(travel)
+    }
+
+    pub fn earlier(&mut self, jump: HistoryJump) -> Option<usize> {
+        let travel = self.history.earlier(jump);
+        self.travel(travel)
+    }
+
+    pub fn later(&mut self, jump: HistoryJump) -> Option<usize> {
+        let travel = self.history.later(jump);
+        self.travel(travel)
+    }
+
+    pub fn char_to_byte(&self, char: usize) -> usize {
+        self.buffer.char_to_byte(char.min(self.buffer.len_chars()))
+    }
+
+    pub fn byte_to_char(&self, byte: usize) -> usize {
+        self.buffer.byte_to_char(byte.min(self.buffer.len_bytes()))
+    }
+
+    pub fn char_to_utf16(&self, char: usize) -> usize {
+        self.buffer
+            .char_to_utf16_cu(char.min(self.buffer.len_chars()))
+    }
+
+    pub fn utf16_to_char(&self, utf16: usize) -> usize {
+        self.buffer
+            .utf16_cu_to_char(utf16.min(self.buffer.len_utf16_cu()))
+    }
+
+    /// zero based line and char column of a char offset
+    pub fn char_to_line_col(&self, char: usize) -> (usize, usize) {
+        let char = char.min(self.buffer.len_chars());
+        let line = self.buffer.char_to_line(char);
+        (line, char - self.buffer.line_to_char(line))
+    }
+
+    /// char offset of a zero based line and char column, columns past the end
+    /// of the line are clamped to it
+    pub fn line_col_to_char(&self, line: usize, col: usize) -> usize {
+        let line = line.min(self.buffer.len_lines() - 1);
+        let start = self.buffer.line_to_char(line);
+        start + col.min(self.line_len(line))
+    }
+
+    /// zero based line and column of a char offset, with the column counted
+    /// in the units of `encoding`
+    pub fn char_to_position(&self, char: usize, encoding: PositionEncoding) -> (usize, usize) {
+        let (line, col) = self.char_to_line_col(char);
+        let start = self.buffer.line_to_char(line);
+        let col = match encoding {
+            PositionEncoding::Utf8 => self.char_to_byte(start + col) - self.char_to_byte(start),
+            PositionEncoding::Utf16 => self.char_to_utf16(start + col) - self.char_to_utf16(start),
+            PositionEncoding::Utf32 => col,
+        };
+        (line, col)
+    }
+
+    /// char offset of a zero based line and column counted in the units of
+    /// `encoding`, positions inside a char are moved to its start
+    pub fn position_to_char(&self, line: usize, col: usize, encoding: PositionEncoding) -> usize {
+        let line = line.min(self.buffer.len_lines() - 1);
+        let start = self.buffer.line_to_char(line);
+        let end = start + self.line_len(line);
+        let char = match encoding {
+            PositionEncoding::Utf8 => self.byte_to_char(self.char_to_byte(start) + col),
+            PositionEncoding::Utf16 => self.utf16_to_char(self.char_to_utf16(start) + col),
+            PositionEncoding::Utf32 => start + col,
+        };
+        char.min(end)
+    }
+
+    // length of a line in chars without its line break
+    fn line_len(&self, line: usize) -> usize {
+        let line = self.buffer.line(line);
+        let len = line.len_chars();
+        match line.chars().last() {
+            Some('\n') => len - 1,
+            _ => len,
+        }
     }
 
     pub fn content_from(&self, line: usize, height: usize) -> String {
-        self.to_string()
-            .lines()
-            .skip(line)
-            .take(height)
-            .collect::<Vec<&str>>()
-            .join("\n")
+        let total_lines = self.buffer.len_lines();
+        let start = self.buffer.line_to_char(line.min(total_lines));
+        let end = self.buffer.line_to_char((line + height).min(total_lines));
+        let mut content = self.buffer.slice(start..end).to_string();
+        if content.ends_with('\n') {
+            content.pop();
+        }
+        content
     }
 
-    pub fn lines(&self) -> Lines {
+    pub fn lines(&self) -> Lines<'_> {
         Lines {
             buffer: &self.buffer,
             start: 0,
-            end: self.buffer.len(),
+            end: self.buffer.len_chars(),
         }
     }
 
     pub fn _line_from_mark(&self, mark: &Mark) -> String {
-        let pos = self.translate_cursor_pos(mark.start);
         let mut lines = Lines {
             buffer: &self.buffer,
-            start: pos,
-            end: self.buffer.len(),
+            start: mark.start,
+            end: self.buffer.len_chars(),
         };
         match lines.next() {
             Some(l) => l.iter().collect::<String>(),
@@ -154,24 +842,289 @@ impl Buffer {
         }
     }
 
-    fn try_save(&self) -> std::io::Result<()> {
-        if let Ok(mut path) = std::env::current_dir() {
-            path.push(&self.file_name);
-            tracing::debug!("saving file: {:?}", path);
-            std::fs::write(path, self.to_string())?;
+    /// whether the buffer changed since the last commit
+    pub fn has_pending_changes(&self) -> bool {
+        self.history.has_pending()
+    }
+
+    /// whether the buffer has changes that were not written to disk
+    pub fn is_modified(&self) -> bool {
+        self.history.has_pending() || self.history.current() != self.saved_revision
+    }
+
+    fn try_save(&mut self, force: bool) -> anyhow::Result<()> {
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("no file name"))?;
+        if !force && self.check_disk() == DiskStatus::Changed {
+            anyhow::bail!(
+                "{} changed on disk since it was read, use :w! to overwrite it",
+                path.display()
+            );
+        }
+        tracing::debug!("saving file: {:?}", path);
+        let content = self.write(&path)?;
+
+        self.history.commit();
+        self.saved_revision = self.history.current();
+        self.base = self.buffer.clone();
+        self.disk = std::fs::metadata(&path)
+            .ok()
+            .map(|metadata| DiskState::new(&content, &metadata));
+        self.remove_swap();
+        if let Err(err) = self.write_undo() {
+            tracing::error!("{err:#}");
         }
         Ok(())
     }
 
+    // writes the content to `path` with the line endings and encoding of the
+    // buffer, returning what was written
+    fn write(&self, path: &Path) -> anyhow::Result<Vec<u8>> {
+        let content = self.line_ending.apply(&self.to_string());
+        let content = self
+            .encoding
+            .encode(&content)
+            .with_context(|| format!("cannot write {} as {}", path.display(), self.encoding))?;
+        file::write_atomic(path, &content)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(content)
+    }
+
+    /// writes a copy of the buffer to the file `name`, which must not exist
+    /// unless `force` is set. A buffer without a file takes it as its own.
+    pub fn write_to(&mut self, name: &str, force: bool) -> anyhow::Result<()> {
+        let path = file::resolve_path(name)?;
+        if self.path.is_none() || self.path.as_ref() == Some(&path) {
+            return self.save_as(name, force);
+        }
+        if !force && path.exists() {
+            anyhow::bail!("{name} exists, use ! to overwrite it");
+        }
+        self.write(&path)?;
+        Ok(())
+    }
+
+    /// makes the file `name` the file of the buffer and saves it there, an
+    /// existing file is only overwritten when `force` is set
+    pub fn save_as(&mut self, name: &str, force: bool) -> anyhow::Result<()> {
+        let path = file::resolve_path(name)?;
+        if self.path.as_ref() == Some(&path) {
+            return self.try_save(force);
+        }
+        if !force && path.exists() {
+            anyhow::bail!("{name} exists, use ! to overwrite it");
+        }
+        self.remove_swap();
+        self.file_name = name.to_string();
+        self.swap = Some(swap::swap_path(&swap::swap_dir(), &path));
+        self.path = Some(path);
+        self.disk = None;
+        // the undo history of the old file doesn't belong to the new one
+        self.undo_file = None;
+        self.try_save(true)
+    }
+
+    /// puts the content of the file `name` below `line`, returning where it
+    /// starts
+    pub fn read_file(&mut self, name: &str, line: usize) -> anyhow::Result<usize> {
+        let bytes = std::fs::read(name).with_context(|| format!("cannot read {name}"))?;
+        let (content, _) = Encoding::decode(&bytes);
+        let mut text = LineEnding::detect(&content).normalize(&content);
+        let pos = self.line_col_to_char(line, 0);
+        if text.is_empty() {
+            return Ok(pos);
+        }
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        Ok(self.put(&Register::new(text, RegisterKind::Linewise), pos, true))
+    }
+
+    fn write_undo(&self) -> anyhow::Result<()> {
+        let (Some(undo_file), Some(path)) = (&self.undo_file, &self.path) else {
+            return Ok(());
+        };
+        undo::write(undo_file, path, &self.to_string(), &self.history)
+    }
+
+    /// persists the undo history in `undo_file` from now on, restoring the
+    /// history stored there if it was saved for the current content
+    pub fn load_undo(&mut self, undo_file: PathBuf) -> anyhow::Result<()> {
+        let history = match &self.path {
+            Some(path) => undo::read(&undo_file, path, &self.to_string()),
+            None => Ok(None),
+        };
+        self.undo_file = Some(undo_file);
+        if let Some(history) = history? {
+            self.saved_revision = history.current();
+            self.history = history;
+        }
+        Ok(())
+    }
+
+    /// edits made since the swap file was last written
+    pub fn swap_edits(&self) -> usize {
+        self.swap_edits
+    }
+
+    /// journals the content of a modified buffer into its swap file
+    pub fn write_swap(&mut self) -> anyhow::Result<()> {
+        let (Some(swap), Some(path)) = (&self.swap, &self.path) else {
+            return Ok(());
+        };
+        if !self.is_modified() {
+            self.remove_swap();
+            return Ok(());
+        }
+        // the text of a preview is not part of the buffer
+        if self.preview.is_some() {
+            return Ok(());
+        }
+        // another editor still running on the same file keeps its swap file
+        let swap = match swap::in_use(swap) {
+            true => swap::free_swap_path(swap)?,
+            false => swap.clone(),
+        };
+        Swap::new(path, self.to_string()).write(&swap)?;
+        self.swap = Some(swap);
+        self.swap_edits = 0;
+        Ok(())
+    }
+
+    /// removes the swap file, unless it belongs to another running editor
+    pub fn remove_swap(&mut self) {
+        self.swap_edits = 0;
+        if let Some(swap) = self.swap.as_ref().filter(|swap| !swap::in_use(swap)) {
+            if let Err(err) = std::fs::remove_file(swap) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    tracing::error!("failed to remove swap file {swap:?}: {err}");
+                }
+            }
+        }
+    }
+
+    /// swap file left behind by a previous session editing this file
+    pub fn find_swap(&self) -> Option<Swap> {
+        let swap = self.swap.as_ref().filter(|swap| swap.exists())?;
+        match Swap::read(swap) {
+            Ok(swap) => Some(swap),
+            Err(err) => {
+                tracing::error!("{err:#}");
+                None
+            }
+        }
+    }
+
+    /// replaces the content of the buffer with the one in the swap file as a
+    /// single undo step, the buffer stays modified until it is saved
+    pub fn recover_swap(&mut self) -> anyhow::Result<ChangeSet> {
+        let swap = self
+            .find_swap()
+            .ok_or_else(|| anyhow::anyhow!("no swap file found for {}", self.file_name))?;
+        Ok(self.replace_content(&swap.content))
+    }
+
+    /// compares the file on disk with the state it had when we last read or
+    /// wrote it, the content is only hashed when the metadata changed
+    pub fn check_disk(&mut self) -> DiskStatus {
+        let Some(path) = &self.path else {
+            return DiskStatus::Unchanged;
+        };
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return match self.disk.take() {
+                Some(_) => DiskStatus::Deleted,
+                None => DiskStatus::Unchanged,
+            };
+        };
+        if let Some(disk) = &self.disk {
+            if disk.same_metadata(&metadata) {
+                return DiskStatus::Unchanged;
+            }
+        }
+        let Ok((_, state)) = DiskState::read(path) else {
+            return DiskStatus::Unchanged;
+        };
+        match &self.disk {
+            // only the metadata changed, e.g. the file was touched
+            Some(disk) if disk.same_content(&state) => {
+                self.disk = Some(state);
+                DiskStatus::Unchanged
+            }
+            _ => DiskStatus::Changed,
+        }
+    }
+
+    fn read_disk(&self) -> anyhow::Result<(String, Encoding, DiskState)> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("no file name"))?;
+        let (bytes, disk) =
+            DiskState::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let (content, encoding) = Encoding::decode(&bytes);
+        Ok((content, encoding, disk))
+    }
+
+    /// replaces the content of the buffer with the file on disk as a single
+    /// undo step, the returned changes can be used to map positions
+    pub fn reload(&mut self) -> anyhow::Result<ChangeSet> {
+        let (content, encoding, disk) = self.read_disk()?;
+        self.line_ending = LineEnding::detect(&content);
+        self.encoding = encoding;
+        let content = self.line_ending.normalize(&content);
+        let changes = self.replace_content(&content);
+
+        self.saved_revision = self.history.current();
+        self.base = self.buffer.clone();
+        self.disk = Some(disk);
+        Ok(changes)
+    }
+
+    /// merges the changes made on disk into the buffer using the content we
+    /// last synced with as the common ancestor, returns the applied changes
+    /// and how many conflicts were marked
+    pub fn merge_disk(&mut self) -> anyhow::Result<(ChangeSet, usize)> {
+        let (content, _, disk) = self.read_disk()?;
+        let theirs = LineEnding::detect(&content).normalize(&content);
+        let merge = diff::merge3(&self.base.to_string(), &self.to_string(), &theirs);
+        let changes = self.replace_content(&merge.text);
+
+        self.base = Rope::from_str(&theirs);
+        self.disk = Some(disk);
+        Ok((changes, merge.conflicts))
+    }
+
+    /// keeps the buffer as it is, accepting that saving will overwrite the
+    /// changes made on disk
+    pub fn keep_buffer(&mut self) -> anyhow::Result<()> {
+        let (content, _, disk) = self.read_disk()?;
+        self.base = Rope::from_str(&LineEnding::detect(&content).normalize(&content));
+        self.disk = Some(disk);
+        Ok(())
+    }
+
+    fn replace_content(&mut self, content: &str) -> ChangeSet {
+        let changes = diff::changes(&self.buffer, content);
+        self.history.commit();
+        self.apply_transaction(&Transaction::new(changes.clone()));
+        self.history.commit();
+        changes
+    }
+
     pub fn handle_action(&mut self, action: &Action, cursor_pos: usize) -> anyhow::Result<()> {
+        self.history.set_cursor(cursor_pos);
         match action {
             Action::InsertChar(c) => self.insert_char(*c, cursor_pos),
             Action::DeletePreviousChar => self.delete_char(cursor_pos),
-            Action::DeleteCurrentChar => self.delete_char(cursor_pos + 1),
-            Action::SaveBuffer => self.try_save()?,
+            Action::SaveBuffer => self.try_save(false)?,
+            Action::ForceSaveBuffer => self.try_save(true)?,
             Action::InsertLine => self.insert_char('\n', cursor_pos),
             Action::InsertLineAbove => self.insert_line_above(cursor_pos),
             Action::InsertLineBelow => self.insert_line_below(cursor_pos),
+            Action::SetFileFormat(line_ending) => self.line_ending = *line_ending,
+            Action::SetFileEncoding(encoding) => self.encoding = *encoding,
             _ => (),
         };
         Ok(())
@@ -180,12 +1133,7 @@ impl Buffer {
 
 impl std::fmt::Display for Buffer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let lines = self
-            .buffer
-            .iter()
-            .filter(|c| **c != '\0')
-            .collect::<String>();
-        write!(f, "{}", lines)
+        write!(f, "{}", self.buffer)
     }
 }
 
@@ -193,62 +1141,38 @@ impl std::fmt::Display for Buffer {
 mod tests {
     use super::*;
     use crate::buffer::marker::Mark;
+    use crate::buffer::transaction::Assoc;
+    use crate::options::{Scopes, Setting};
 
     #[test]
     fn test_buffer_initialization() {
-        let gap = 5;
-        let buffer = Buffer::from_string(1, "Hello, World!", gap);
-        let first_needle = &"Hello, World!".chars().collect::<Vec<_>>();
-
-        assert_eq!(buffer.gap_end - buffer.gap_start, gap);
-        assert!(buffer.buffer[gap..].starts_with(first_needle));
-    }
-
-    #[test]
-    fn test_move_gap() {
-        let gap = 5;
-        let mut buffer = Buffer::from_string(1, "Hello, World!", gap);
-        let first_needle = &"Hello".chars().collect::<Vec<_>>();
-
-        buffer.move_gap(5);
+        let buffer = Buffer::from_string(1, "Hello, World!");
 
-        assert_eq!(buffer.gap_start, 5);
-        assert_eq!(buffer.gap_end, buffer.gap_start + gap);
-        assert!(buffer.buffer[0..buffer.gap_start].starts_with(first_needle));
+        assert_eq!(buffer.buffer.len_chars(), 13);
+        assert_eq!(buffer.to_string(), "Hello, World!");
     }
 
     #[test]
-    fn test_move_gap_left() {
-        let gap = 5;
-        let mut buffer = Buffer::from_string(1, "Hello, World!", gap);
+    fn test_insert_in_the_middle() {
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
 
-        buffer.move_gap(13);
-
-        assert_eq!(buffer.gap_start, 13);
-        assert_eq!(buffer.gap_end, buffer.gap_start + gap);
-
-        buffer.move_gap(5);
+        buffer.insert_char('!', 5);
 
-        assert_eq!(buffer.gap_start, 5);
-        assert_eq!(buffer.gap_end, buffer.gap_start + gap);
+        assert_eq!(buffer.to_string(), "Hello!, World!");
     }
 
     #[test]
-    fn test_insert_into_gap() {
-        let mut buffer = Buffer::from_string(1, "Hello, World!", 5);
-        let first_needle = &"Hello!".chars().collect::<Vec<_>>();
+    fn test_insert_past_the_end_appends() {
+        let mut buffer = Buffer::from_string(1, "Hello");
 
-        buffer.insert_char('!', 5);
+        buffer.insert_char('!', 100);
 
-        assert_eq!(buffer.gap_start, 6);
-        assert!(buffer.buffer[0..buffer.gap_start].starts_with(first_needle));
-        assert_eq!(buffer.gap_end - buffer.gap_start, 4);
+        assert_eq!(buffer.to_string(), "Hello!");
     }
 
     #[test]
-    fn test_delete_from_gap() {
-        let mut buffer = Buffer::from_string(1, "Hello, World!", 5);
-        let first_needle = &"Hell".chars().collect::<Vec<_>>();
+    fn test_delete_from_the_middle() {
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
         let insert = "\nanother string\n";
 
         for (i, c) in insert.chars().enumerate() {
@@ -260,67 +1184,66 @@ mod tests {
         buffer.delete_char(5);
 
         assert_eq!(buffer.to_string(), "Hell\nanother string\n, World!");
-        assert_eq!(buffer.gap_start, 4);
-        assert!(buffer.buffer[0..buffer.gap_start].starts_with(first_needle));
-        assert_eq!(buffer.gap_end - buffer.gap_start, 5);
     }
 
     #[test]
     fn test_delete_everything_to_the_left() {
-        let mut buffer = Buffer::from_string(1, "Hello, World!", 5);
-        let first_needle = &"".chars().collect::<Vec<_>>();
-
-        // this moves the gap to the right by 5
-        buffer.delete_char(5);
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
 
         // then this removes more chars than there are
+        let mut pos = 5;
         for _ in 0..100 {
-            buffer.delete_char(buffer.gap_start);
+            buffer.delete_char(pos);
+            pos = pos.saturating_sub(1);
         }
 
-        assert_eq!(buffer.gap_start, 0);
-        assert!(buffer.buffer[0..buffer.gap_start].starts_with(first_needle));
-        assert_eq!(buffer.gap_end - buffer.gap_start, 10);
+        assert_eq!(buffer.to_string(), ", World!");
     }
 
     #[test]
-    fn test_should_resize_gap() {
-        let gap = 5;
-        let mut buffer = Buffer::from_string(1, "Hello, World!", gap);
+    fn test_many_inserts_at_the_same_position() {
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
 
         buffer.insert_char('_', 5);
-        buffer.insert_char('_', buffer.gap_start);
-        buffer.insert_char('_', buffer.gap_start);
-        buffer.insert_char('_', buffer.gap_start);
-        buffer.insert_char('_', buffer.gap_start);
-
-        assert_eq!(buffer.gap_end - buffer.gap_end, 0);
-        assert_eq!(buffer.gap_start, 10);
-        assert_eq!(buffer.gap_end, 15);
-        assert_eq!(buffer.buffer.len(), 23);
-
-        buffer.insert_char('!', buffer.gap_start);
-        let first_needle = "Hello_____!".chars().collect::<Vec<_>>();
-
-        assert_eq!(buffer.buffer[0..buffer.gap_start], first_needle);
-        assert_eq!(buffer.gap_start, 11);
-        assert_eq!(buffer.gap_end - buffer.gap_start, 4);
-        assert_eq!(buffer.gap_end, 15);
-        assert_eq!(buffer.buffer.len(), 23);
-        assert_eq!(
-            buffer.buffer[buffer.gap_end..],
-            ", World!".chars().collect::<Vec<_>>()
-        );
-        assert_eq!(
-            buffer.buffer[0..buffer.gap_start],
-            "Hello_____!".chars().collect::<Vec<_>>()
-        );
+        buffer.insert_char('_', 6);
+        buffer.insert_char('_', 7);
+        buffer.insert_char('_', 8);
+        buffer.insert_char('_', 9);
+        buffer.insert_char('!', 10);
+
+        assert_eq!(buffer.to_string(), "Hello_____!, World!");
+    }
+
+    #[test]
+    fn test_keeps_nul_characters() {
+        let mut buffer = Buffer::from_string(1, "a\0b");
+
+        buffer.insert_char('\0', 0);
+
+        assert_eq!(buffer.to_string(), "\0a\0b");
+        assert_eq!(buffer.marker.get_by_line(1).unwrap(), Mark::new(0, 1, 4));
+    }
+
+    #[test]
+    fn test_content_from() {
+        let buffer = Buffer::from_string(1, "one\ntwo\nthree\nfour\n");
+
+        assert_eq!(buffer.content_from(0, 2), "one\ntwo");
+        assert_eq!(buffer.content_from(2, 10), "three\nfour");
+        assert_eq!(buffer.content_from(10, 10), "");
+    }
+
+    #[test]
+    fn test_only_line_feeds_break_lines() {
+        let buffer = Buffer::from_string(1, "a\u{c}b\u{2028}c\rd\ne");
+
+        assert_eq!(buffer.buffer.len_lines(), 2);
+        assert_eq!(buffer.marker.len(), 2);
     }
 
     #[test]
     fn test_marks_initialization() {
-        let gap = 5;
-        let buffer = Buffer::from_string(1, "Hello, World!", gap);
+        let buffer = Buffer::from_string(1, "Hello, World!");
 
         assert_eq!(buffer.marker.len(), 1);
         assert_eq!(buffer.marker.get_by_line(0).unwrap(), Mark::new(0, 1, 13));
@@ -328,8 +1251,7 @@ mod tests {
 
     #[test]
     fn test_return_line_from_mark() {
-        let gap = 5;
-        let buffer = Buffer::from_string(1, "Hello, World!", gap);
+        let buffer = Buffer::from_string(1, "Hello, World!");
         let mark = Mark {
             size: 13,
             line: 1,
@@ -342,12 +1264,11 @@ mod tests {
 
     #[test]
     fn test_return_empty_line_from_invalid_mark() {
-        let gap = 5;
-        let buffer = Buffer::from_string(1, "Hello, World!", gap);
+        let buffer = Buffer::from_string(1, "Hello, World!");
         let mark = Mark {
             size: 10,
             line: 2,
-            start: 14 + gap,
+            start: 14,
         };
 
         let line = buffer._line_from_mark(&mark);
@@ -356,25 +1277,19 @@ mod tests {
 
     #[test]
     fn test_insert_char_through_command() {
-        let mut buffer = Buffer::from_string(1, "Hello, World!", 5);
-        let first_needle = &"Hello!".chars().collect::<Vec<_>>();
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
 
         _ = buffer.handle_action(&Action::InsertChar('!'), 5);
 
-        assert_eq!(buffer.gap_start, 6);
-        assert!(buffer.buffer[0..buffer.gap_start].starts_with(first_needle));
-        assert_eq!(buffer.gap_end - buffer.gap_start, 4);
+        assert_eq!(buffer.to_string(), "Hello!, World!");
     }
 
     #[test]
     fn test_initialization_with_empty_filename() {
-        let gap = 1000;
         let buffer = Buffer::new(1, None).unwrap();
 
-        assert_eq!(buffer.buffer.len(), gap);
-        assert_eq!(buffer.gap_start, 0);
-        assert_eq!(buffer.gap_end - buffer.gap_start, gap);
-        assert_eq!(buffer.gap_end, gap);
+        assert_eq!(buffer.buffer.len_chars(), 0);
+        assert_eq!(buffer.marker.len(), 1);
     }
 
     #[test]
@@ -386,27 +1301,508 @@ mod tests {
 
     #[test]
     fn test_delete_char_through_command() {
-        let mut buffer = Buffer::from_string(1, "Hello, World!", 5);
-        let first_needle = &"Hell".chars().collect::<Vec<_>>();
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
 
         let _ = buffer.handle_action(&Action::DeletePreviousChar, 5);
 
-        assert_eq!(buffer.gap_start, 4);
-        assert!(buffer.buffer[0..buffer.gap_start].starts_with(first_needle));
-        assert_eq!(buffer.gap_end - buffer.gap_start, 6);
+        assert_eq!(buffer.to_string(), "Hell, World!");
+    }
+
+    #[test]
+    fn test_undo_restores_content_and_cursor() {
+        let mut buffer = Buffer::from_string(1, "Hello");
+
+        for (i, c) in ", World".chars().enumerate() {
+            _ = buffer.handle_action(&Action::InsertChar(c), i + 5);
+        }
+        _ = buffer.handle_action(&Action::DeletePreviousChar, 12);
+        buffer.commit();
+
+        assert_eq!(buffer.to_string(), "Hello, Worl");
+        assert_eq!(buffer.undo(), Some(5));
+        assert_eq!(buffer.to_string(), "Hello");
+        assert_eq!(buffer.marker.get_by_line(1).unwrap(), Mark::new(0, 1, 5));
+        assert_eq!(buffer.undo(), None);
+
+        assert_eq!(buffer.redo(), Some(5));
+        assert_eq!(buffer.to_string(), "Hello, Worl");
+    }
+
+    #[test]
+    fn test_earlier_and_later() {
+        let mut buffer = Buffer::from_string(1, "");
+
+        buffer.insert_char('a', 0);
+        buffer.commit();
+        buffer.insert_char('b', 1);
+        buffer.commit();
+
+        buffer.earlier(HistoryJump::Steps(2));
+        assert_eq!(buffer.to_string(), "");
+
+        buffer.later(HistoryJump::Steps(1));
+        assert_eq!(buffer.to_string(), "a");
+    }
+
+    #[test]
+    fn test_apply_transaction() {
+        let mut buffer = Buffer::from_string(1, "one\ntwo\nthree");
+        let transaction = Transaction::change(
+            &buffer.buffer,
+            [
+                (0, 3, Some("1\n1".to_string())),
+                (4, 8, None),
+                (13, 13, Some("\nfour".to_string())),
+            ],
+        )
+        .unwrap()
+        .with_cursor(100);
+
+        assert!(buffer
+            .apply(&Transaction::delete(&Rope::from("on"), 0..1).unwrap())
+            .is_err());
+        assert_eq!(buffer.to_string(), "one\ntwo\nthree");
+        assert_eq!(buffer.apply(&transaction).unwrap(), Some(14));
+        assert_eq!(buffer.to_string(), "1\n1\nthree\nfour");
+        assert_eq!(buffer.marker.len(), 4);
+        let mark = buffer.marker.get_by_line(3).unwrap();
+        assert_eq!(buffer._line_from_mark(&mark), "three\n");
+
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "one\ntwo\nthree");
+        assert_eq!(buffer.marker.len(), 3);
     }
 
     #[test]
     fn test_insert_newline_through_command() {
-        let mut buffer = Buffer::from_string(1, "Hello, World!", 5);
-        let first_needle = &"Hello".chars().collect::<Vec<_>>();
-        let second_needle = &", World!".chars().collect::<Vec<_>>();
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
 
         let _ = buffer.handle_action(&Action::InsertLine, 5);
 
-        assert_eq!(buffer.gap_start, 6);
-        assert!(buffer.buffer[0..buffer.gap_start].starts_with(first_needle));
-        assert!(buffer.buffer[buffer.gap_end..].starts_with(second_needle));
-        assert_eq!(buffer.gap_end - buffer.gap_start, 4);
+        assert_eq!(buffer.to_string(), "Hello\n, World!");
+        assert_eq!(buffer.marker.len(), 2);
+    }
+
+    #[test]
+    fn test_offset_conversions() {
+        let buffer = Buffer::from_string(1, "aé😀b\nx");
+
+        assert_eq!(buffer.char_to_byte(3), 7);
+        assert_eq!(buffer.byte_to_char(7), 3);
+        // bytes inside a char map to the char itself
+        assert_eq!(buffer.byte_to_char(4), 2);
+        assert_eq!(buffer.char_to_utf16(3), 4);
+        assert_eq!(buffer.utf16_to_char(4), 3);
+        assert_eq!(buffer.char_to_byte(100), 10);
+    }
+
+    #[test]
+    fn test_line_col_conversions() {
+        let buffer = Buffer::from_string(1, "aé😀b\nx");
+
+        assert_eq!(buffer.char_to_line_col(3), (0, 3));
+        assert_eq!(buffer.char_to_line_col(6), (1, 1));
+        assert_eq!(buffer.line_col_to_char(1, 0), 5);
+        assert_eq!(buffer.line_col_to_char(0, 100), 4);
+        assert_eq!(buffer.line_col_to_char(100, 0), 5);
+    }
+
+    #[test]
+    fn test_position_encodings() {
+        let buffer = Buffer::from_string(1, "aé😀b\nx");
+
+        assert_eq!(buffer.char_to_position(3, PositionEncoding::Utf8), (0, 7));
+        assert_eq!(buffer.char_to_position(3, PositionEncoding::Utf16), (0, 4));
+        assert_eq!(buffer.char_to_position(3, PositionEncoding::Utf32), (0, 3));
+        assert_eq!(buffer.char_to_position(6, PositionEncoding::Utf16), (1, 1));
+
+        assert_eq!(buffer.position_to_char(0, 7, PositionEncoding::Utf8), 3);
+        assert_eq!(buffer.position_to_char(0, 4, PositionEncoding::Utf16), 3);
+        assert_eq!(buffer.position_to_char(0, 3, PositionEncoding::Utf32), 3);
+        assert_eq!(buffer.position_to_char(0, 50, PositionEncoding::Utf16), 4);
+        assert_eq!(buffer.position_to_char(1, 1, PositionEncoding::Utf8), 6);
+    }
+
+    #[test]
+    fn test_parse_position_encoding() {
+        assert_eq!(
+            "utf-8".parse::<PositionEncoding>().unwrap(),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(PositionEncoding::Utf16.as_str(), "utf-16");
+        assert!("utf-7".parse::<PositionEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_keeps_line_ending_and_encoding_on_save() {
+        let path = std::env::temp_dir().join("glyph_test_line_endings.txt");
+        std::fs::write(&path, b"caf\xE9\r\nline\r\n").unwrap();
+
+        let mut buffer = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+        assert_eq!(buffer.to_string(), "café\nline\n");
+        assert_eq!(buffer.encoding, Encoding::Latin1);
+        assert_eq!(buffer.line_ending, LineEnding::Crlf);
+
+        buffer.insert_char('!', 4);
+        buffer.try_save(false).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"caf\xE9!\r\nline\r\n");
+
+        buffer
+            .handle_action(&Action::SetFileFormat(LineEnding::Lf), 0)
+            .unwrap();
+        buffer
+            .handle_action(&Action::SetFileEncoding(Encoding::Utf8), 0)
+            .unwrap();
+        buffer.try_save(false).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "café!\nline\n");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_save_without_a_path_fails() {
+        let mut buffer = Buffer::from_string(1, "content");
+
+        let result = buffer.handle_action(&Action::SaveBuffer, 0);
+
+        assert_eq!(result.unwrap_err().to_string(), "no file name");
+    }
+
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("glyph_{name}_{}", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_line_count_of_lf_terminated_file() {
+        let path = temp_file("line_count", "a\nb\n");
+
+        let buffer = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+
+        assert_eq!(buffer.marker.len(), 2);
+        assert_eq!(buffer.marker.get_by_line(3), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_clean_buffer_after_external_change() {
+        let path = temp_file("reload", "one\ntwo\n");
+        let mut buffer = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+        assert_eq!(buffer.check_disk(), DiskStatus::Unchanged);
+
+        std::fs::write(&path, "zero\none\ntwo\n").unwrap();
+        assert_eq!(buffer.check_disk(), DiskStatus::Changed);
+        assert!(!buffer.is_modified());
+
+        let changes = buffer.reload().unwrap();
+        assert_eq!(buffer.to_string(), "zero\none\ntwo\n");
+        // a cursor on `two` stays on it
+        assert_eq!(changes.map_pos(4, Assoc::Before), 9);
+        assert_eq!(buffer.check_disk(), DiskStatus::Unchanged);
+        assert!(!buffer.is_modified());
+
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "one\ntwo\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_save_refuses_to_overwrite_disk_changes() {
+        let path = temp_file("overwrite", "content\n");
+        let mut buffer = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+        buffer.insert_char('!', 0);
+        assert!(buffer.is_modified());
+
+        std::fs::write(&path, "newer content\n").unwrap();
+        assert!(buffer.handle_action(&Action::SaveBuffer, 0).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "newer content\n");
+
+        buffer.handle_action(&Action::ForceSaveBuffer, 0).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "!content\n");
+        assert!(!buffer.is_modified());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_write_copy_save_as_and_read() {
+        let path = temp_file("write_to", "one\n");
+        let name = path.to_string_lossy().to_string();
+        let copy = format!("{name}.copy");
+        let mut buffer = Buffer::new(1, Some(name.clone())).unwrap();
+        buffer.insert_char('!', 0);
+
+        buffer.write_to(&copy, false).unwrap();
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), "!one\n");
+        assert!(buffer.write_to(&copy, false).is_err());
+        assert_eq!(buffer.file_name, name);
+        assert!(buffer.is_modified());
+
+        buffer.save_as(&copy, true).unwrap();
+        assert_eq!(buffer.file_name, copy);
+        assert!(!buffer.is_modified());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\n");
+
+        let pos = buffer.read_file(&name, 0).unwrap();
+        assert_eq!(buffer.to_string(), "!one\none\n");
+        assert_eq!(pos, 5);
+        assert!(buffer.read_file("/nonexistent/glyph", 0).is_err());
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(copy).unwrap();
+    }
+
+    #[test]
+    fn test_merge_disk_changes() {
+        let path = temp_file("merge", "a\nb\nc\n");
+        let mut buffer = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+        buffer.insert_char('A', 0);
+        buffer.delete_char(2);
+
+        std::fs::write(&path, "a\nb\nc\nd\n").unwrap();
+        let (_, conflicts) = buffer.merge_disk().unwrap();
+
+        assert_eq!(conflicts, 0);
+        assert_eq!(buffer.to_string(), "A\nb\nc\nd\n");
+        assert!(buffer.is_modified());
+        assert_eq!(buffer.check_disk(), DiskStatus::Unchanged);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_from_swap() {
+        let path = temp_file("swap_recover", "saved\n");
+        let swap = std::env::temp_dir().join(format!("glyph_recover_{}.swp", std::process::id()));
+        let mut buffer = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+        buffer.swap = Some(swap.clone());
+
+        buffer.insert_char('!', 5);
+        assert_eq!(buffer.swap_edits(), 1);
+        buffer.write_swap().unwrap();
+        assert_eq!(buffer.swap_edits(), 0);
+
+        let mut reopened = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+        reopened.swap = Some(swap.clone());
+        assert_eq!(reopened.find_swap().unwrap().content, "saved!\n");
+        reopened.recover_swap().unwrap();
+        assert_eq!(reopened.to_string(), "saved!\n");
+        assert!(reopened.is_modified());
+
+        reopened.handle_action(&Action::SaveBuffer, 0).unwrap();
+        assert!(!swap.exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keep_the_swap_of_a_running_editor() {
+        let path = temp_file("swap_shared", "saved\n");
+        let swap = std::env::temp_dir().join(format!("glyph_shared_{}.swp", std::process::id()));
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let mut other = Swap::new(&path, "theirs\n".into());
+        other.pid = child.id();
+        other.write(&swap).unwrap();
+        let mut buffer = Buffer::new(1, Some(path.to_string_lossy().to_string())).unwrap();
+        buffer.swap = Some(swap.clone());
+
+        buffer.insert_char('!', 5);
+        buffer.write_swap().unwrap();
+        assert_eq!(Swap::read(&swap).unwrap(), other);
+        let own = swap.with_extension("swo");
+        assert_eq!(buffer.swap, Some(own.clone()));
+        assert_eq!(Swap::read(&own).unwrap().content, "saved!\n");
+
+        buffer.swap = Some(swap.clone());
+        buffer.remove_swap();
+        assert!(swap.exists());
+        buffer.swap = Some(own.clone());
+        buffer.remove_swap();
+        assert!(!own.exists());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        std::fs::remove_file(swap).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_undo_history_survives_reopening() {
+        let path = temp_file("undo_persist", "one\n");
+        let undo_file =
+            std::env::temp_dir().join(format!("glyph_persist_{}.undo", std::process::id()));
+        let name = path.to_string_lossy().to_string();
+        let mut buffer = Buffer::new(1, Some(name.clone())).unwrap();
+        buffer.load_undo(undo_file.clone()).unwrap();
+        buffer.insert_char('!', 3);
+        buffer.handle_action(&Action::SaveBuffer, 0).unwrap();
+
+        let mut reopened = Buffer::new(1, Some(name.clone())).unwrap();
+        reopened.load_undo(undo_file.clone()).unwrap();
+        assert!(!reopened.is_modified());
+        reopened.undo();
+        assert_eq!(reopened.to_string(), "one\n");
+
+        // the history doesn't apply once the file changed behind our back
+        std::fs::write(&path, "other\n").unwrap();
+        let mut changed = Buffer::new(1, Some(name)).unwrap();
+        changed.load_undo(undo_file.clone()).unwrap();
+        assert_eq!(changed.undo(), None);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(undo_file).unwrap();
+    }
+
+    #[test]
+    fn test_yank_and_delete_selections() {
+        let mut buffer = Buffer::from_string(1, "one two\nthree\nfour");
+
+        assert_eq!(
+            buffer.yank(0, 3, RegisterKind::Charwise),
+            Register::charwise("one")
+        );
+        assert_eq!(
+            buffer.yank(9, 9, RegisterKind::Linewise),
+            Register::linewise("three")
+        );
+        assert_eq!(
+            buffer.yank(1, 10, RegisterKind::Blockwise),
+            Register::blockwise(&["ne".into(), "hr".into()])
+        );
+
+        // the last line takes the line break before it
+        let (register, cursor) = buffer.delete(15, 15, RegisterKind::Linewise);
+        assert_eq!(register, Register::linewise("four"));
+        assert_eq!(buffer.to_string(), "one two\nthree");
+        assert_eq!(cursor, 13);
+    }
+
+    #[test]
+    fn test_put() {
+        let mut buffer = Buffer::from_string(1, "ab\ncd");
+
+        assert_eq!(buffer.put(&Register::charwise("xy"), 0, true), 2);
+        assert_eq!(buffer.to_string(), "axyb\ncd");
+
+        assert_eq!(buffer.put(&Register::linewise("new"), 0, true), 5);
+        assert_eq!(buffer.to_string(), "axyb\nnew\ncd");
+
+        assert_eq!(buffer.put(&Register::linewise("last"), 10, true), 12);
+        assert_eq!(buffer.to_string(), "axyb\nnew\ncd\nlast");
+
+        assert_eq!(buffer.put(&Register::linewise("top"), 2, false), 0);
+        assert_eq!(buffer.to_string(), "top\naxyb\nnew\ncd\nlast");
+    }
+
+    #[test]
+    fn test_put_block() {
+        let mut buffer = Buffer::from_string(1, "abc\nd\n");
+
+        buffer.put(
+            &Register::blockwise(&["1".into(), "2".into(), "3".into()]),
+            1,
+            false,
+        );
+
+        assert_eq!(buffer.to_string(), "a1bc\nd2\n 3\n");
+    }
+
+    #[test]
+    fn test_insert_at_column() {
+        let mut buffer = Buffer::from_string(1, "abc\nd\nefg\n");
+
+        buffer.insert_at_column(0..=2, 2, "X", false);
+        assert_eq!(buffer.to_string(), "abXc\nd\nefXg\n");
+
+        buffer.insert_at_column(1..=1, 3, "Y", true);
+        assert_eq!(buffer.to_string(), "abXc\nd  Y\nefXg\n");
+    }
+
+    #[test]
+    fn test_indent_and_dedent() {
+        let mut buffer = Buffer::from_string(1, "a\n\n\tb\n      c\n");
+
+        buffer.indent(0, 1, false);
+        assert_eq!(buffer.to_string(), "    a\n\n\tb\n      c\n");
+
+        buffer.indent(0, 3, true);
+        assert_eq!(buffer.to_string(), "a\n\nb\n  c\n");
+    }
+
+    #[test]
+    fn test_indent_follows_options() {
+        let mut buffer = Buffer::from_string(1, "a\n\tb\n");
+        let mut window = Options::default();
+        let mut global = Options::default();
+        let mut scopes = Scopes {
+            global: &mut global,
+            window: &mut window,
+            buffer: &mut buffer.options,
+        };
+        for arg in ["ts=8", "sw=8", "noet"] {
+            let setting = Setting::parse(arg).unwrap();
+            scopes.apply(&setting, true, &Options::default()).unwrap();
+        }
+
+        buffer.indent(0, 0, false);
+        assert_eq!(buffer.to_string(), "\ta\n\tb\n");
+        buffer.reindent(1, 1);
+        assert_eq!(buffer.to_string(), "\ta\n\tb\n");
+    }
+
+    #[test]
+    fn test_move_and_copy_lines() {
+        let mut buffer = Buffer::from_string(1, "a\nb\nc");
+
+        assert_eq!(buffer.move_lines(0, 0, 3).unwrap(), 2);
+        assert_eq!(buffer.to_string(), "b\nc\na");
+        assert_eq!(buffer.move_lines(2, 2, 0).unwrap(), 0);
+        assert_eq!(buffer.to_string(), "a\nb\nc");
+        assert!(buffer.move_lines(0, 1, 1).is_err());
+
+        assert_eq!(buffer.copy_lines(0, 1, 3), 4);
+        assert_eq!(buffer.to_string(), "a\nb\nc\na\nb");
+        assert_eq!(buffer.copy_lines(2, 2, 0), 0);
+        assert_eq!(buffer.to_string(), "c\na\nb\nc\na\nb");
+    }
+
+    #[test]
+    fn test_join_and_replace_lines() {
+        let mut buffer = Buffer::from_string(1, "a\n  b\n)\nc\n");
+
+        buffer.join_lines(0, 2, true);
+        assert_eq!(buffer.to_string(), "a b)\nc\n");
+        buffer.replace_lines(0, 1, &["x".into(), "y".into(), "z".into()]);
+        assert_eq!(buffer.to_string(), "x\ny\nz\n");
+        buffer.join_lines(1, 2, false);
+        assert_eq!(buffer.to_string(), "x\nyz\n");
+    }
+
+    #[test]
+    fn test_preview_is_not_recorded() {
+        let mut buffer = Buffer::from_string(1, "one two\n");
+        buffer.marks.set('a', 4);
+
+        let changes = ChangeSet::from_changes(8, [(0, 3, Some("three".into()))]).unwrap();
+        buffer.preview(&changes);
+        assert_eq!(buffer.to_string(), "three two\n");
+        assert_eq!(buffer.marks.get('a'), Some(4));
+        assert!(!buffer.has_pending_changes());
+
+        buffer.end_preview();
+        assert_eq!(buffer.to_string(), "one two\n");
+        assert_eq!(buffer.undo(), None);
+    }
+
+    #[test]
+    fn test_reindent_follows_brackets() {
+        let mut buffer = Buffer::from_string(1, "fn main() {\nif x {\ny();\n}\n\n  }\n");
+
+        buffer.reindent(1, 5);
+
+        assert_eq!(
+            buffer.to_string(),
+            "fn main() {\n    if x {\n        y();\n    }\n\n}\n"
+        );
     }
 }
@@ -0,0 +1,199 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::buffer::file;
+
+const HEADER: &str = "glyph swap";
+
+/// The content of a swap file, written while a buffer has unsaved changes so
+/// they can be recovered after a crash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Swap {
+    pub file_path: PathBuf,
+    pub pid: u32,
+    pub content: String,
+}
+
+/// directory every swap file is written to
+pub fn swap_dir() -> PathBuf {
+    file::state_dir("swap")
+}
+
+pub fn swap_path(dir: &Path, path: &Path) -> PathBuf {
+    file::state_file(dir, path, "swp")
+}
+
+/// whether the swap file belongs to another editor that is still running,
+/// which must be left alone
+pub fn in_use(swap_path: &Path) -> bool {
+    Swap::read(swap_path).is_ok_and(|swap| swap.pid != std::process::id() && swap.is_running())
+}
+
+/// the first of `.swp`, `.swo`, `.swn` down to `.swa` next to `swap_path`
+/// that no other running editor uses, like vim does for a file opened twice
+pub fn free_swap_path(swap_path: &Path) -> anyhow::Result<PathBuf> {
+    ('a'..='p')
+        .rev()
+        .map(|c| swap_path.with_extension(format!("sw{c}")))
+        .find(|swap| !in_use(swap))
+        .ok_or_else(|| anyhow::anyhow!("too many swap files for {}", swap_path.display()))
+}
+
+impl Swap {
+    pub fn new(file_path: &Path, content: String) -> Self {
+        Self {
+            file_path: file_path.to_path_buf(),
+            pid: std::process::id(),
+            content,
+        }
+    }
+
+    /// whether the process that wrote the swap file is still running, and
+    /// may still be editing the file
+    pub fn is_running(&self) -> bool {
+        process_running(self.pid)
+    }
+
+    /// writes the swap file readable by no one the file itself isn't
+    pub fn write(&self, swap_path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = swap_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let content = format!(
+            "{HEADER}\n{}\n{}\n{}",
+            self.file_path.to_string_lossy(),
+            self.pid,
+            self.content
+        );
+        let permissions = std::fs::metadata(&self.file_path)
+            .ok()
+            .map(|m| m.permissions());
+        file::write_atomic_with(swap_path, content.as_bytes(), permissions)
+            .with_context(|| format!("failed to write swap file {}", swap_path.display()))
+    }
+
+    pub fn read(swap_path: &Path) -> anyhow::Result<Swap> {
+        let content = std::fs::read_to_string(swap_path)
+            .with_context(|| format!("failed to read swap file {}", swap_path.display()))?;
+        let mut parts = content.splitn(4, '\n');
+        let (Some(HEADER), Some(file_path), Some(pid), Some(content)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("{} is not a swap file", swap_path.display());
+        };
+        Ok(Swap {
+            file_path: PathBuf::from(file_path),
+            pid: pid.parse()?,
+            content: content.to_string(),
+        })
+    }
+}
+
+// signal 0 only checks the process exists, it fails with EPERM when the
+// process belongs to someone else
+#[cfg(unix)]
+fn process_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    pid > 0
+        && (unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+#[cfg(not(unix))]
+fn process_running(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = std::env::temp_dir().join(format!("glyph_swap_{}", std::process::id()));
+        let swap_path = swap_path(&dir, Path::new("/some/file.txt"));
+        let swap = Swap::new(Path::new("/some/file.txt"), "first\nsecond\n".into());
+
+        swap.write(&swap_path).unwrap();
+
+        assert_eq!(Swap::read(&swap_path).unwrap(), swap);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_with_the_permissions_of_the_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("glyph_swap_mode_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("secret.txt");
+        std::fs::write(&file_path, "secret").unwrap();
+        std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let swap_path = swap_path(&dir, &file_path);
+
+        Swap::new(&file_path, "more secret".into())
+            .write(&swap_path)
+            .unwrap();
+
+        let mode = std::fs::metadata(&swap_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_running() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let mut swap = Swap::new(Path::new("/some/file.txt"), String::new());
+
+        assert!(swap.is_running());
+        swap.pid = pid;
+        assert!(!swap.is_running());
+        swap.pid = 0;
+        assert!(!swap.is_running());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_skip_swap_files_of_running_editors() {
+        let dir = std::env::temp_dir().join(format!("glyph_swap_free_{}", std::process::id()));
+        let swap_path = swap_path(&dir, Path::new("/some/file.txt"));
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let mut other = Swap::new(Path::new("/some/file.txt"), String::new());
+        other.pid = child.id();
+        other.write(&swap_path).unwrap();
+
+        assert!(in_use(&swap_path));
+        let free = free_swap_path(&swap_path).unwrap();
+        assert_eq!(free, swap_path.with_extension("swo"));
+        Swap::new(Path::new("/some/file.txt"), String::new())
+            .write(&free)
+            .unwrap();
+        assert!(!in_use(&free));
+        assert_eq!(free_swap_path(&free).unwrap(), free);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!in_use(&swap_path));
+        assert_eq!(free_swap_path(&free).unwrap(), swap_path);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("glyph_not_swap_{}", std::process::id()));
+        std::fs::write(&path, "just some text\n").unwrap();
+
+        assert!(Swap::read(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::Buffer;
+use crate::cursor::MotionKind;
+
+/// Regions of text selected around the cursor, such as `w` in `diw`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    Word,
+    // words separated only by whitespace
+    BigWord,
+    Quote(char),
+    // given by its opening bracket
+    Bracket(char),
+    Paragraph,
+    Tag,
+}
+
+/// range covered by `count` times the text object around `pos`, `around`
+/// includes the surrounding whitespace, quotes, brackets or tags
+pub fn select(
+    buffer: &Buffer,
+    pos: usize,
+    object: TextObject,
+    around: bool,
+    count: usize,
+) -> Option<(Range<usize>, MotionKind)> {
+    let count = count.max(1);
+    let range = match object {
+        TextObject::Word => word(buffer, pos, false, around, count)?,
+        TextObject::BigWord => word(buffer, pos, true, around, count)?,
+        TextObject::Quote(quote) => self::quote(buffer, pos, quote, around)?,
+        TextObject::Bracket(open) => return bracket(buffer, pos, open, around, count),
+        TextObject::Paragraph => {
+            return Some((paragraph(buffer, pos, around, count), MotionKind::Linewise))
+        }
+        TextObject::Tag => tag(buffer, pos, around, count)?,
+    };
+    Some((range, MotionKind::Exclusive))
+}
+
+fn line_bounds(buffer: &Buffer, pos: usize) -> (usize, usize) {
+    let (line, _) = buffer.char_to_line_col(pos);
+    (
+        buffer.line_col_to_char(line, 0),
+        buffer.line_col_to_char(line, usize::MAX),
+    )
+}
+
+// whitespace, word chars and punctuation form separate words, big words
+// only stop at whitespace
+fn class(c: char, big: bool) -> u8 {
+    match c {
+        c if c.is_whitespace() => 0,
+        _ if big => 1,
+        c if c.is_alphanumeric() || c == '_' => 1,
+        _ => 2,
+    }
+}
+
+fn word(
+    buffer: &Buffer,
+    pos: usize,
+    big: bool,
+    around: bool,
+    count: usize,
+) -> Option<Range<usize>> {
+    let (line_start, line_end) = line_bounds(buffer, pos);
+    if line_start == line_end {
+        return None;
+    }
+    let pos = pos.min(line_end - 1);
+    let class = |i: usize| class(buffer.buffer.char(i), big);
+    let run_end = |start: usize| {
+        let mut end = start;
+        while end < line_end && class(end) == class(start) {
+            end += 1;
+        }
+        end
+    };
+
+    let mut start = pos;
+    while start > line_start && class(start - 1) == class(pos) {
+        start -= 1;
+    }
+    let mut end = run_end(start);
+    if !around {
+        for _ in 1..count {
+            if end < line_end {
+                end = run_end(end);
+            }
+        }
+        return Some(start..end);
+    }
+
+    // every word takes the whitespace after it, or before it when there is
+    // none after the last one
+    let on_space = class(pos) == 0;
+    for i in 0..count {
+        if i > 0 && end < line_end {
+            end = run_end(end);
+        }
+        if end < line_end && (on_space || class(end) == 0) {
+            end = run_end(end);
+        }
+    }
+    if !on_space && (end == line_end || class(end - 1) != 0) {
+        while start > line_start && class(start - 1) == 0 {
+            start -= 1;
+        }
+    }
+    Some(start..end)
+}
+
+fn quote(buffer: &Buffer, pos: usize, quote: char, around: bool) -> Option<Range<usize>> {
+    let (line_start, line_end) = line_bounds(buffer, pos);
+    let text = &buffer.buffer;
+    let quotes = (line_start..line_end)
+        .filter(|i| text.char(*i) == quote && (*i == line_start || text.char(i - 1) != '\\'))
+        .collect::<Vec<_>>();
+    // the pair around the cursor, or the first one after it
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, close)| pos <= *close)?;
+
+    if !around {
+        return Some(open + 1..close);
+    }
+    let mut start = open;
+    let mut end = close + 1;
+    while end < line_end && text.char(end).is_whitespace() {
+        end += 1;
+    }
+    if end == close + 1 {
+        while start > line_start && text.char(start - 1).is_whitespace() {
+            start -= 1;
+        }
+    }
+    Some(start..end)
+}
+
+fn closing(open: char) -> Option<char> {
+    match open {
+        '(' => Some(')'),
+        '{' => Some('}'),
+        '[' => Some(']'),
+        '<' => Some('>'),
+        _ => None,
+    }
+}
+
+fn bracket(
+    buffer: &Buffer,
+    pos: usize,
+    open: char,
+    around: bool,
+    count: usize,
+) -> Option<(Range<usize>, MotionKind)> {
+    let close = closing(open)?;
+    let text = &buffer.buffer;
+    let len = text.len_chars();
+    if len == 0 {
+        return None;
+    }
+    let pos = pos.min(len - 1);
+
+    // the `count`th bracket left open before the cursor, a closing bracket
+    // under the cursor belongs to the pair
+    let mut depth = 0;
+    let mut found = 0;
+    let mut start = None;
+    for i in (0..=pos).rev() {
+        match text.char(i) {
+            c if c == close && i != pos => depth += 1,
+            c if c == open && depth > 0 => depth -= 1,
+            c if c == open => {
+                found += 1;
+                if found == count {
+                    start = Some(i);
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    let start = start?;
+
+    let mut depth = 0;
+    let end = (start + 1..len).find(|i| match text.char(*i) {
+        c if c == open => {
+            depth += 1;
+            false
+        }
+        c if c == close && depth > 0 => {
+            depth -= 1;
+            false
+        }
+        c => c == close,
+    })?;
+
+    if around {
+        return Some((start..end + 1, MotionKind::Exclusive));
+    }
+    // a block spanning whole lines only selects the lines in between
+    let (start_line, _) = buffer.char_to_line_col(start);
+    let (end_line, end_col) = buffer.char_to_line_col(end);
+    let closes_own_line = end - end_col == buffer.first_non_blank(end_line);
+    if text.char(start + 1) == '\n' && closes_own_line && end_line > start_line + 1 {
+        let first = buffer.line_col_to_char(start_line + 1, 0);
+        let last = buffer.line_col_to_char(end_line - 1, 0);
+        return Some((first..last, MotionKind::Linewise));
+    }
+    Some((start + 1..end, MotionKind::Exclusive))
+}
+
+fn paragraph(buffer: &Buffer, pos: usize, around: bool, count: usize) -> Range<usize> {
+    let text = &buffer.buffer;
+    let len = text.len_chars();
+    // a trailing line break doesn't start another line
+    let lines = text.len_lines() - usize::from(len > 0 && text.char(len - 1) == '\n');
+    let blank = |line: usize| text.line(line).chars().all(char::is_whitespace);
+    let block_end = |line: usize| {
+        let mut last = line;
+        while last + 1 < lines && blank(last + 1) == blank(line) {
+            last += 1;
+        }
+        last
+    };
+
+    let (line, _) = buffer.char_to_line_col(pos);
+    let mut first = line;
+    while first > 0 && blank(first - 1) == blank(line) {
+        first -= 1;
+    }
+    let mut last = block_end(line);
+    for _ in 1..count {
+        if last + 1 < lines {
+            last = block_end(last + 1);
+        }
+    }
+    if around {
+        if last + 1 < lines {
+            last = block_end(last + 1);
+        } else {
+            while first > 0 && blank(first - 1) != blank(line) {
+                first -= 1;
+            }
+        }
+    }
+    buffer.line_col_to_char(first, 0)..buffer.line_col_to_char(last, 0)
+}
+
+fn tag(buffer: &Buffer, pos: usize, around: bool, count: usize) -> Option<Range<usize>> {
+    let chars = buffer.buffer.chars().collect::<Vec<_>>();
+    let mut stack: Vec<(String, usize, usize)> = vec![];
+    let mut pairs = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '<' {
+            i += 1;
+            continue;
+        }
+        let Some(end) = chars[i..].iter().position(|c| *c == '>').map(|end| i + end) else {
+            break;
+        };
+        let content = chars[i + 1..end].iter().collect::<String>();
+        if let Some(name) = content.strip_prefix('/') {
+            let name = name.trim();
+            // unclosed tags in between are dropped
+            if let Some(open) = stack.iter().rposition(|(open, _, _)| open == name) {
+                let (_, open_start, open_end) = stack[open].clone();
+                stack.truncate(open);
+                pairs.push((open_start, open_end, i, end + 1));
+            }
+        } else if !content.ends_with('/') && !content.starts_with(['!', '?']) {
+            let name = content.split_whitespace().next().unwrap_or_default();
+            stack.push((name.to_string(), i, end + 1));
+        }
+        i = end + 1;
+    }
+
+    pairs.retain(|(open_start, _, _, close_end)| *open_start <= pos && pos < *close_end);
+    pairs.sort_by_key(|(open_start, _, _, close_end)| close_end - open_start);
+    let (open_start, open_end, close_start, close_end) = *pairs.get(count - 1)?;
+    match around {
+        true => Some(open_start..close_end),
+        false => Some(open_end..close_start),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(text: &str, pos: usize, object: TextObject, around: bool, count: usize) -> String {
+        let buffer = Buffer::from_string(1, text);
+        let (range, _) = select(&buffer, pos, object, around, count).unwrap();
+        buffer.buffer.slice(range).to_string()
+    }
+
+    #[test]
+    fn test_words() {
+        let text = "foo.bar  baz qux";
+
+        assert_eq!(selected(text, 1, TextObject::Word, false, 1), "foo");
+        assert_eq!(selected(text, 1, TextObject::Word, false, 3), "foo.bar");
+        assert_eq!(selected(text, 5, TextObject::Word, true, 1), "bar  ");
+        assert_eq!(selected(text, 1, TextObject::BigWord, false, 1), "foo.bar");
+        // the last word takes the whitespace before it
+        assert_eq!(selected(text, 14, TextObject::Word, true, 1), " qux");
+    }
+
+    #[test]
+    fn test_quotes() {
+        let text = r#"let s = "a \"b\" c" + 'x';"#;
+
+        assert_eq!(
+            selected(text, 10, TextObject::Quote('"'), false, 1),
+            r#"a \"b\" c"#
+        );
+        assert_eq!(selected(text, 0, TextObject::Quote('\''), false, 1), "x");
+        assert_eq!(
+            selected(text, 10, TextObject::Quote('"'), true, 1),
+            r#""a \"b\" c" "#
+        );
+    }
+
+    #[test]
+    fn test_nested_brackets() {
+        let text = "f(a, g(b), [c])";
+
+        assert_eq!(selected(text, 7, TextObject::Bracket('('), false, 1), "b");
+        assert_eq!(
+            selected(text, 7, TextObject::Bracket('('), false, 2),
+            "a, g(b), [c]"
+        );
+        assert_eq!(selected(text, 8, TextObject::Bracket('('), true, 1), "(b)");
+        assert_eq!(
+            selected(text, 3, TextObject::Bracket('('), true, 1),
+            text[1..].to_string()
+        );
+    }
+
+    #[test]
+    fn test_multiline_block() {
+        let text = "fn f() {\n    a;\n    b;\n}\n";
+        let buffer = Buffer::from_string(1, text);
+
+        let (range, kind) = select(&buffer, 15, TextObject::Bracket('{'), false, 1).unwrap();
+
+        assert_eq!(kind, MotionKind::Linewise);
+        assert_eq!(
+            buffer
+                .yank(
+                    range.start,
+                    range.end,
+                    crate::register::RegisterKind::Linewise
+                )
+                .text,
+            "    a;\n    b;\n"
+        );
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let text = "a\nb\n\n\nc\n";
+        let buffer = Buffer::from_string(1, text);
+
+        let (range, _) = select(&buffer, 0, TextObject::Paragraph, false, 1).unwrap();
+        assert_eq!((range.start, range.end), (0, 2));
+
+        let (range, _) = select(&buffer, 0, TextObject::Paragraph, true, 1).unwrap();
+        assert_eq!((range.start, range.end), (0, 5));
+
+        // the last paragraph takes the blank lines before it
+        let (range, _) = select(&buffer, 6, TextObject::Paragraph, true, 1).unwrap();
+        assert_eq!((range.start, range.end), (4, 6));
+    }
+
+    #[test]
+    fn test_tags() {
+        let text = "<div class=\"x\"><p>hi <b>there</b></p><br/></div>";
+
+        assert_eq!(selected(text, 25, TextObject::Tag, false, 1), "there");
+        assert_eq!(
+            selected(text, 20, TextObject::Tag, true, 1),
+            "<p>hi <b>there</b></p>"
+        );
+        assert_eq!(
+            selected(text, 25, TextObject::Tag, false, 3),
+            "<p>hi <b>there</b></p><br/>"
+        );
+    }
+}
@@ -0,0 +1,495 @@
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operation {
+    Retain(usize),
+    Insert(String),
+    Delete(usize),
+}
+
+/// Which side of an insertion a position sticks to when mapped through a
+/// change set that inserts exactly at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Before,
+    After,
+}
+
+/// A replacement of the chars in `start..end` by an optional text, all
+/// offsets are char offsets into the document before the change.
+pub type Change = (usize, usize, Option<String>);
+
+/// A list of operations that walk the whole document, keeping, inserting or
+/// deleting chars. Every offset is measured in chars.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ChangeSet {
+    operations: Vec<Operation>,
+    // length of the document before and after applying the change set
+    len: usize,
+    len_after: usize,
+}
+
+impl ChangeSet {
+    /// a change set that keeps all the `len` chars of the document
+    pub fn identity(len: usize) -> Self {
+        let mut changes = Self::default();
+        changes.retain(len);
+        changes
+    }
+
+    /// builds a change set out of sorted, non overlapping changes over a
+    /// document of `len` chars, failing on any other change
+    pub fn from_changes<I>(len: usize, changes: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = Change>,
+    {
+        let mut change_set = Self::default();
+        let mut last = 0;
+        for (start, end, text) in changes {
+            anyhow::ensure!(
+                start >= last && start <= end && end <= len,
+                "invalid change {start}..{end} of a text of {len} chars"
+            );
+            change_set.retain(start - last);
+            if let Some(text) = text {
+                change_set.insert(&text);
+            }
+            change_set.delete(end - start);
+            last = end;
+        }
+        change_set.retain(len - last);
+        Ok(change_set)
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn len_after(&self) -> usize {
+        self.len_after
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations
+            .iter()
+            .all(|op| matches!(op, Operation::Retain(_)))
+    }
+
+    pub fn retain(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.len += n;
+        self.len_after += n;
+        match self.operations.last_mut() {
+            Some(Operation::Retain(count)) => *count += n,
+            _ => self.operations.push(Operation::Retain(n)),
+        }
+    }
+
+    pub fn delete(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.len += n;
+        match self.operations.last_mut() {
+            Some(Operation::Delete(count)) => *count += n,
+            _ => self.operations.push(Operation::Delete(n)),
+        }
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.len_after += text.chars().count();
+        // insertions always come before deletions at the same position, so
+        // equivalent change sets always have the same operations
+        let at = match self.operations.last() {
+            Some(Operation::Delete(_)) => self.operations.len() - 1,
+            _ => self.operations.len(),
+        };
+        match at.checked_sub(1).map(|i| &mut self.operations[i]) {
+            Some(Operation::Insert(existing)) => existing.push_str(text),
+            _ => self.operations.insert(at, Operation::Insert(text.into())),
+        }
+    }
+
+    /// position of the first char touched by this change set
+    pub fn first_change(&self) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        match self.operations.first()? {
+            Operation::Retain(n) => Some(*n),
+            _ => Some(0),
+        }
+    }
+
+    /// position right after the last char touched by this change set, in
+    /// the text it applies to
+    pub fn last_change_end(&self) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        let retained = match self.operations.last()? {
+            Operation::Retain(n) => *n,
+            _ => 0,
+        };
+        Some(self.len - retained)
+    }
+
+    pub fn apply(&self, text: &mut Rope) {
+        debug_assert_eq!(self.len, text.len_chars());
+        let mut pos = 0;
+        for op in self.operations.iter() {
+            match op {
+                Operation::Retain(n) => pos += n,
+                Operation::Insert(s) => {
+                    text.insert(pos, s);
+                    pos += s.chars().count();
+                }
+                Operation::Delete(n) => text.remove(pos..pos + n),
+            }
+        }
+    }
+
+    /// returns the change set that reverts this one, `original` is the
+    /// document before this change set was applied
+    pub fn invert(&self, original: &Rope) -> ChangeSet {
+        let mut inverted = ChangeSet::default();
+        let mut pos = 0;
+        for op in self.operations.iter() {
+            match op {
+                Operation::Retain(n) => {
+                    inverted.retain(*n);
+                    pos += n;
+                }
+                Operation::Insert(s) => inverted.delete(s.chars().count()),
+                Operation::Delete(n) => {
+                    inverted.insert(&original.slice(pos..pos + n).to_string());
+                    pos += n;
+                }
+            }
+        }
+        inverted
+    }
+
+    /// combines two change sets into one that has the same effect as applying
+    /// `self` and then `other`
+    pub fn compose(&self, other: &ChangeSet) -> ChangeSet {
+        assert_eq!(self.len_after, other.len, "change sets are not sequential");
+        let mut composed = ChangeSet::default();
+        let mut first = self.operations.iter().cloned();
+        let mut second = other.operations.iter().cloned();
+        let mut a = first.next();
+        let mut b = second.next();
+
+        loop {
+            match (a.take(), b.take()) {
+                (None, None) => break,
+                (Some(Operation::Delete(n)), next) => {
+                    composed.delete(n);
+                    a = first.next();
+                    b = next;
+                }
+                (prev, Some(Operation::Insert(s))) => {
+                    composed.insert(&s);
+                    a = prev;
+                    b = second.next();
+                }
+                (Some(Operation::Retain(i)), Some(Operation::Retain(j))) => {
+                    let n = i.min(j);
+                    composed.retain(n);
+                    a = remaining_retain(i - n).or_else(|| first.next());
+                    b = remaining_retain(j - n).or_else(|| second.next());
+                }
+                (Some(Operation::Retain(i)), Some(Operation::Delete(j))) => {
+                    let n = i.min(j);
+                    composed.delete(n);
+                    a = remaining_retain(i - n).or_else(|| first.next());
+                    b = remaining_delete(j - n).or_else(|| second.next());
+                }
+                (Some(Operation::Insert(s)), Some(Operation::Retain(j))) => {
+                    let len = s.chars().count();
+                    if len <= j {
+                        composed.insert(&s);
+                        a = first.next();
+                        b = remaining_retain(j - len).or_else(|| second.next());
+                    } else {
+                        let (kept, rest) = split_at_char(&s, j);
+                        composed.insert(kept);
+                        a = Some(Operation::Insert(rest.into()));
+                        b = second.next();
+                    }
+                }
+                (Some(Operation::Insert(s)), Some(Operation::Delete(j))) => {
+                    let len = s.chars().count();
+                    if len <= j {
+                        a = first.next();
+                        b = remaining_delete(j - len).or_else(|| second.next());
+                    } else {
+                        let (_, rest) = split_at_char(&s, j);
+                        a = Some(Operation::Insert(rest.into()));
+                        b = second.next();
+                    }
+                }
+                (None, Some(_)) | (Some(_), None) => unreachable!("change sets length mismatch"),
+            }
+        }
+
+        composed
+    }
+
+    /// maps a position in the document before the change to where it ends up
+    /// after the change is applied
+    pub fn map_pos(&self, pos: usize, assoc: Assoc) -> usize {
+        let mut old_pos = 0;
+        let mut new_pos = 0;
+        for op in self.operations.iter() {
+            match op {
+                Operation::Retain(n) => {
+                    if pos < old_pos + n {
+                        return new_pos + pos - old_pos;
+                    }
+                    old_pos += n;
+                    new_pos += n;
+                }
+                Operation::Delete(n) => {
+                    if pos < old_pos + n {
+                        return new_pos;
+                    }
+                    old_pos += n;
+                }
+                Operation::Insert(s) => {
+                    if pos == old_pos && assoc == Assoc::Before {
+                        return new_pos;
+                    }
+                    new_pos += s.chars().count();
+                }
+            }
+        }
+        new_pos + pos.saturating_sub(old_pos)
+    }
+}
+
+fn remaining_retain(n: usize) -> Option<Operation> {
+    (n > 0).then_some(Operation::Retain(n))
+}
+
+fn remaining_delete(n: usize) -> Option<Operation> {
+    (n > 0).then_some(Operation::Delete(n))
+}
+
+fn split_at_char(text: &str, at: usize) -> (&str, &str) {
+    let idx = text
+        .char_indices()
+        .nth(at)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len());
+    text.split_at(idx)
+}
+
+/// A change set applied as a single unit to a buffer, optionally telling
+/// where the cursor should be after it is applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    changes: ChangeSet,
+    cursor: Option<usize>,
+}
+
+impl Transaction {
+    pub fn new(changes: ChangeSet) -> Self {
+        Self {
+            changes,
+            cursor: None,
+        }
+    }
+
+    pub fn change<I>(text: &Rope, changes: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = Change>,
+    {
+        Ok(Self::new(ChangeSet::from_changes(
+            text.len_chars(),
+            changes,
+        )?))
+    }
+
+    pub fn insert(text: &Rope, at: usize, content: &str) -> anyhow::Result<Self> {
+        Self::change(text, [(at, at, Some(content.to_string()))])
+    }
+
+    pub fn delete(text: &Rope, range: std::ops::Range<usize>) -> anyhow::Result<Self> {
+        Self::change(text, [(range.start, range.end, None)])
+    }
+
+    pub fn with_cursor(mut self, cursor: usize) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    pub fn changes(&self) -> &ChangeSet {
+        &self.changes
+    }
+
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(text: &str, changes: &ChangeSet) -> String {
+        let mut rope = Rope::from_str(text);
+        changes.apply(&mut rope);
+        rope.to_string()
+    }
+
+    #[test]
+    fn test_from_changes() {
+        let changes = ChangeSet::from_changes(
+            11,
+            [(0, 5, Some("Howdy".into())), (6, 6, Some("big ".into()))],
+        )
+        .unwrap();
+
+        assert_eq!(
+            changes.operations(),
+            &[
+                Operation::Insert("Howdy".into()),
+                Operation::Delete(5),
+                Operation::Retain(1),
+                Operation::Insert("big ".into()),
+                Operation::Retain(5),
+            ]
+        );
+        assert_eq!(apply("hello world", &changes), "Howdy big world");
+        assert_eq!(changes.len(), 11);
+        assert_eq!(changes.len_after(), 15);
+    }
+
+    #[test]
+    fn test_from_invalid_changes() {
+        assert!(ChangeSet::from_changes(5, [(2, 6, None)]).is_err());
+        assert!(ChangeSet::from_changes(5, [(3, 2, None)]).is_err());
+        assert!(ChangeSet::from_changes(5, [(2, 3, None), (1, 1, None)]).is_err());
+    }
+
+    #[test]
+    fn test_insert_is_placed_before_delete() {
+        let mut a = ChangeSet::default();
+        a.delete(2);
+        a.insert("x");
+        let mut b = ChangeSet::default();
+        b.insert("x");
+        b.delete(2);
+
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_invert() {
+        let original = Rope::from_str("hello world");
+        let changes =
+            ChangeSet::from_changes(11, [(0, 5, Some("bye".into())), (10, 11, None)]).unwrap();
+
+        let inverted = changes.invert(&original);
+
+        assert_eq!(apply("bye worl", &inverted), "hello world");
+    }
+
+    #[test]
+    fn test_compose() {
+        let text = "hello world";
+        let first = ChangeSet::from_changes(11, [(5, 5, Some(", big".into()))]).unwrap();
+        let second =
+            ChangeSet::from_changes(16, [(0, 1, Some("J".into())), (7, 11, None)]).unwrap();
+
+        let composed = first.compose(&second);
+
+        assert_eq!(apply(&apply(text, &first), &second), "Jello, world");
+        assert_eq!(apply(text, &composed), "Jello, world");
+    }
+
+    #[test]
+    fn test_compose_deleting_inserted_text() {
+        let first = ChangeSet::from_changes(3, [(1, 1, Some("abc".into()))]).unwrap();
+        let second = ChangeSet::from_changes(6, [(2, 5, None)]).unwrap();
+
+        let composed = first.compose(&second);
+
+        assert_eq!(apply("123", &composed), "1a3");
+        assert_eq!(composed.len(), 3);
+        assert_eq!(composed.len_after(), 3);
+    }
+
+    #[test]
+    fn test_map_pos() {
+        let changes = ChangeSet::from_changes(
+            11,
+            [
+                (0, 0, Some("> ".into())),
+                (2, 5, None),
+                (6, 6, Some("!".into())),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(changes.map_pos(0, Assoc::Before), 0);
+        assert_eq!(changes.map_pos(0, Assoc::After), 2);
+        assert_eq!(changes.map_pos(3, Assoc::After), 4);
+        assert_eq!(changes.map_pos(6, Assoc::Before), 5);
+        assert_eq!(changes.map_pos(6, Assoc::After), 6);
+        assert_eq!(changes.map_pos(11, Assoc::After), 11);
+    }
+
+    #[test]
+    fn test_first_change() {
+        assert_eq!(ChangeSet::identity(10).first_change(), None);
+        assert_eq!(
+            ChangeSet::from_changes(10, [(4, 6, None)])
+                .unwrap()
+                .first_change(),
+            Some(4)
+        );
+        assert_eq!(
+            ChangeSet::from_changes(10, [(0, 0, Some("a".into()))])
+                .unwrap()
+                .first_change(),
+            Some(0)
+        );
+        assert_eq!(ChangeSet::identity(10).last_change_end(), None);
+        assert_eq!(
+            ChangeSet::from_changes(10, [(1, 2, None), (4, 6, None)])
+                .unwrap()
+                .last_change_end(),
+            Some(6)
+        );
+        assert_eq!(
+            ChangeSet::from_changes(10, [(10, 10, Some("a".into()))])
+                .unwrap()
+                .last_change_end(),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn test_transaction_helpers() {
+        let text = Rope::from_str("hello");
+
+        let insert = Transaction::insert(&text, 5, "!").unwrap().with_cursor(6);
+        let delete = Transaction::delete(&text, 0..1).unwrap();
+
+        assert_eq!(apply("hello", insert.changes()), "hello!");
+        assert_eq!(insert.cursor(), Some(6));
+        assert_eq!(apply("hello", delete.changes()), "ello");
+    }
+}
@@ -0,0 +1,504 @@
+use ropey::Rope;
+
+use crate::buffer::marker::{Mark, Marker};
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    size: usize,
+    priority: u64,
+    left: Option<usize>,
+    right: Option<usize>,
+    // number of lines in this subtree
+    count: usize,
+    // number of chars in this subtree
+    sum: usize,
+}
+
+/// Line index backed by an implicit treap. Every node is a line, ordered by
+/// its position in the buffer, and each subtree keeps the amount of lines and
+/// chars under it, so lookups by line or by char offset are logarithmic and
+/// edits only touch the lines they change. Like the rope, the tree keeps an
+/// empty line after a final line break, which is hidden from the lookups.
+#[derive(Debug)]
+pub struct TreeMarker {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    seed: u64,
+}
+
+impl TreeMarker {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        // xorshift, we only need the priorities to be well distributed
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+
+    fn alloc(&mut self, size: usize) -> usize {
+        let node = Node {
+            size,
+            priority: self.next_priority(),
+            left: None,
+            right: None,
+            count: 1,
+            sum: size,
+        };
+        match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, node: Option<usize>) {
+        let mut stack = vec![];
+        stack.extend(node);
+        while let Some(idx) = stack.pop() {
+            stack.extend(self.nodes[idx].left);
+            stack.extend(self.nodes[idx].right);
+            self.free.push(idx);
+        }
+    }
+
+    fn count(&self, node: Option<usize>) -> usize {
+        node.map(|n| self.nodes[n].count).unwrap_or(0)
+    }
+
+    fn sum(&self, node: Option<usize>) -> usize {
+        node.map(|n| self.nodes[n].sum).unwrap_or(0)
+    }
+
+    fn update(&mut self, idx: usize) {
+        let Node { left, right, .. } = self.nodes[idx];
+        let count = self.count(left) + self.count(right) + 1;
+        let sum = self.sum(left) + self.sum(right) + self.nodes[idx].size;
+        self.nodes[idx].count = count;
+        self.nodes[idx].sum = sum;
+    }
+
+    fn merge(&mut self, left: Option<usize>, right: Option<usize>) -> Option<usize> {
+        match (left, right) {
+            (None, r) => r,
+            (l, None) => l,
+            (Some(l), Some(r)) => {
+                if self.nodes[l].priority > self.nodes[r].priority {
+                    let merged = self.merge(self.nodes[l].right, Some(r));
+                    self.nodes[l].right = merged;
+                    self.update(l);
+                    Some(l)
+                } else {
+                    let merged = self.merge(Some(l), self.nodes[r].left);
+                    self.nodes[r].left = merged;
+                    self.update(r);
+                    Some(r)
+                }
+            }
+        }
+    }
+
+    /// splits the tree so the left side has the first `at` lines
+    fn split(&mut self, node: Option<usize>, at: usize) -> (Option<usize>, Option<usize>) {
+        let Some(idx) = node else {
+            return (None, None);
+        };
+        let left_count = self.count(self.nodes[idx].left);
+        if at <= left_count {
+            let (l, r) = self.split(self.nodes[idx].left, at);
+            self.nodes[idx].left = r;
+            self.update(idx);
+            (l, Some(idx))
+        } else {
+            let (l, r) = self.split(self.nodes[idx].right, at - left_count - 1);
+            self.nodes[idx].right = l;
+            self.update(idx);
+            (Some(idx), r)
+        }
+    }
+
+    /// replaces `remove` lines starting at line `at` (0 indexed) with lines of
+    /// the given sizes
+    fn splice(&mut self, at: usize, remove: usize, sizes: &[usize]) {
+        let (left, rest) = self.split(self.root, at);
+        let (removed, right) = self.split(rest, remove);
+        self.release(removed);
+
+        let mut middle = None;
+        for &size in sizes {
+            let node = self.alloc(size);
+            middle = self.merge(middle, Some(node));
+        }
+
+        let left = self.merge(left, middle);
+        self.root = self.merge(left, right);
+    }
+
+    /// returns the 0 indexed line containing `position` alongside its start,
+    /// a position equal to the length of the buffer belongs to the last line
+    fn find_by_position(&self, position: usize) -> Option<(usize, usize, usize)> {
+        let total = self.sum(self.root);
+        if position > total || self.root.is_none() {
+            return None;
+        }
+        if position == total {
+            let line = self.count(self.root) - 1;
+            let (start, size) = self.find_by_line(line)?;
+            return Some((line, start, size));
+        }
+
+        let mut node = self.root;
+        let mut position = position;
+        let (mut line, mut start) = (0, 0);
+        while let Some(idx) = node {
+            let Node {
+                left, right, size, ..
+            } = self.nodes[idx];
+            let left_sum = self.sum(left);
+            if position < left_sum {
+                node = left;
+            } else if position < left_sum + size {
+                return Some((line + self.count(left), start + left_sum, size));
+            } else {
+                position -= left_sum + size;
+                line += self.count(left) + 1;
+                start += left_sum + size;
+                node = right;
+            }
+        }
+        None
+    }
+
+    // the empty line after a final line break, the only line of an empty
+    // buffer is not one of them
+    fn has_trailing_line(&self) -> bool {
+        let count = self.count(self.root);
+        count > 1
+            && self
+                .find_by_line(count - 1)
+                .is_some_and(|(_, size)| size == 0)
+    }
+
+    /// returns the start and size of the 0 indexed `line`
+    fn find_by_line(&self, line: usize) -> Option<(usize, usize)> {
+        let mut node = self.root;
+        let mut line = line;
+        let mut start = 0;
+        while let Some(idx) = node {
+            let Node {
+                left, right, size, ..
+            } = self.nodes[idx];
+            let left_count = self.count(left);
+            match line.cmp(&left_count) {
+                std::cmp::Ordering::Less => node = left,
+                std::cmp::Ordering::Equal => return Some((start + self.sum(left), size)),
+                std::cmp::Ordering::Greater => {
+                    line -= left_count + 1;
+                    start += self.sum(left) + size;
+                    node = right;
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Marker for TreeMarker {
+    fn len(&self) -> usize {
+        self.count(self.root) - usize::from(self.has_trailing_line())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    fn add_mark(&mut self, mark: Mark, at: usize) {
+        self.splice(at, 0, &[mark.size]);
+    }
+
+    fn del_mark(&mut self, at: usize) {
+        self.splice(at, 1, &[]);
+    }
+
+    fn get_by_cursor(&self, position: usize) -> Option<Mark> {
+        let (line, start, size) = self.find_by_position(position)?;
+        // the end of the buffer belongs to the last line that is shown
+        match line == self.len() {
+            true => self.get_by_line(line),
+            false => Some(Mark::new(start, line + 1, size)),
+        }
+    }
+
+    fn get_by_line(&self, line: usize) -> Option<Mark> {
+        let line = line.saturating_sub(1);
+        if line >= self.len() {
+            return None;
+        }
+        self.find_by_line(line)
+            .map(|(start, size)| Mark::new(start, line + 1, size))
+    }
+
+    fn set_marks(&mut self, buffer: &Rope) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = None;
+        let sizes = buffer.lines().map(|l| l.len_chars()).collect::<Vec<_>>();
+        self.splice(0, 0, &sizes);
+    }
+
+    fn insert_text(&mut self, position: usize, text: &str) {
+        let Some((line, start, size)) = self.find_by_position(position) else {
+            return;
+        };
+        let offset = position - start;
+        let mut segments = text.split('\n').map(|s| s.chars().count());
+        let first = segments.next().unwrap_or(0);
+        let mut sizes = segments.collect::<Vec<_>>();
+
+        match sizes.pop() {
+            None => self.splice(line, 1, &[size + first]),
+            Some(last) => {
+                let mut new_sizes = vec![offset + first + 1];
+                new_sizes.extend(sizes.iter().map(|s| s + 1));
+                new_sizes.push(last + size - offset);
+                self.splice(line, 1, &new_sizes);
+            }
+        }
+    }
+
+    fn delete_range(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let (Some(first), Some(last)) = (self.find_by_position(start), self.find_by_position(end))
+        else {
+            return;
+        };
+        let (first_line, first_start, _) = first;
+        let (last_line, last_start, last_size) = last;
+        let size = (start - first_start) + (last_start + last_size - end);
+        self.splice(first_line, last_line - first_line + 1, &[size]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::marker::Mark;
+
+    fn marks(marker: &TreeMarker) -> Vec<Mark> {
+        (1..=marker.len())
+            .map(|l| marker.get_by_line(l).unwrap())
+            .collect()
+    }
+
+    fn marker_from(text: &str) -> TreeMarker {
+        let mut marker = TreeMarker::new();
+        marker.set_marks(&Rope::from_str(text));
+        marker
+    }
+
+    #[test]
+    fn test_add_mark() {
+        let mut marker = TreeMarker::new();
+        marker.add_mark(Mark::new(0, 1, 10), 0);
+        marker.add_mark(Mark::new(1, 2, 20), 1);
+        marker.add_mark(Mark::new(0, 1, 30), 0);
+
+        assert_eq!(marker.len(), 3);
+        assert_eq!(
+            marks(&marker),
+            vec![
+                Mark::new(0, 1, 30),
+                Mark::new(30, 2, 10),
+                Mark::new(40, 3, 20)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_del_mark() {
+        let mut marker = TreeMarker::new();
+        marker.add_mark(Mark::new(0, 1, 10), 0);
+        marker.add_mark(Mark::new(1, 2, 20), 1);
+        marker.add_mark(Mark::new(0, 1, 30), 0);
+
+        marker.del_mark(1);
+
+        assert_eq!(marker.len(), 2);
+        assert_eq!(
+            marks(&marker),
+            vec![Mark::new(0, 1, 30), Mark::new(30, 2, 20)]
+        );
+    }
+
+    #[test]
+    fn test_get_by_cursor() {
+        let mut marker = TreeMarker::new();
+        marker.add_mark(Mark::new(0, 1, 10), 0);
+        marker.add_mark(Mark::new(1, 2, 20), 1);
+        marker.add_mark(Mark::new(0, 1, 30), 0);
+
+        let mark = marker.get_by_cursor(36).unwrap();
+
+        assert_eq!(mark, Mark::new(30, 2, 10));
+    }
+
+    #[test]
+    fn test_get_by_cursor_on_line_boundaries() {
+        let marker = marker_from("Hello\nWorld\n");
+
+        assert_eq!(marker.get_by_cursor(5).unwrap(), Mark::new(0, 1, 6));
+        assert_eq!(marker.get_by_cursor(6).unwrap(), Mark::new(6, 2, 6));
+        assert_eq!(marker.get_by_cursor(12).unwrap(), Mark::new(6, 2, 6));
+        assert_eq!(marker.get_by_cursor(13), None);
+    }
+
+    #[test]
+    fn test_get_by_line() {
+        let mut marker = TreeMarker::new();
+        marker.add_mark(Mark::new(0, 1, 10), 0);
+        marker.add_mark(Mark::new(1, 2, 20), 1);
+        marker.add_mark(Mark::new(0, 1, 30), 0);
+
+        let mark = marker.get_by_line(2).unwrap();
+
+        assert_eq!(mark, Mark::new(30, 2, 10));
+    }
+
+    #[test]
+    fn should_return_none_if_line_is_invalid() {
+        let marker = TreeMarker::new();
+
+        let mark = marker.get_by_line(10);
+
+        assert_eq!(mark, None);
+    }
+
+    #[test]
+    fn should_return_none_if_cursor_is_invalid() {
+        let marker = TreeMarker::new();
+
+        let mark = marker.get_by_cursor(10);
+
+        assert_eq!(mark, None);
+    }
+
+    #[test]
+    fn test_insert_text_without_newlines() {
+        let mut marker = marker_from("Hello\nWorld");
+
+        marker.insert_text(8, "!!");
+
+        assert_eq!(marks(&marker), marks(&marker_from("Hello\nWo!!rld")));
+    }
+
+    #[test]
+    fn test_insert_text_splitting_lines() {
+        let mut marker = marker_from("Hello\nWorld");
+
+        marker.insert_text(2, "y\nthere\nfr");
+
+        assert_eq!(
+            marks(&marker),
+            marks(&marker_from("Hey\nthere\nfrllo\nWorld"))
+        );
+    }
+
+    #[test]
+    fn test_insert_newline_at_the_end() {
+        let mut marker = marker_from("Hello");
+
+        marker.insert_text(5, "\n");
+
+        assert_eq!(marks(&marker), vec![Mark::new(0, 1, 6)]);
+        assert_eq!(marker.get_by_line(2), None);
+
+        marker.insert_text(6, "\n");
+
+        assert_eq!(marks(&marker), vec![Mark::new(0, 1, 6), Mark::new(6, 2, 1)]);
+    }
+
+    #[test]
+    fn test_insert_text_with_other_line_breaks() {
+        let mut marker = marker_from("a\nb\nc");
+        let mut rope = Rope::from_str("a\nb\nc");
+
+        for text in ["\u{2028}", "\u{c}\u{b}", "\r", "\u{85}\u{2029}"] {
+            marker.insert_text(0, text);
+            rope.insert(0, text);
+        }
+
+        assert_eq!(marker.len(), 3);
+        assert_eq!(marks(&marker), marks(&marker_from(&rope.to_string())));
+    }
+
+    #[test]
+    fn test_delete_range_joining_lines() {
+        let mut marker = marker_from("one\ntwo\nthree\nfour");
+
+        marker.delete_range(2, 10);
+
+        assert_eq!(marks(&marker), marks(&marker_from("onree\nfour")));
+    }
+
+    #[test]
+    fn test_delete_range_inside_line() {
+        let mut marker = marker_from("one\ntwo\nthree");
+
+        marker.delete_range(4, 6);
+
+        assert_eq!(marks(&marker), marks(&marker_from("one\no\nthree")));
+    }
+
+    #[test]
+    fn test_no_line_after_final_line_break() {
+        assert_eq!(marker_from("a\nb\n").len(), 2);
+        assert_eq!(marker_from("a\nb").len(), 2);
+        assert_eq!(marker_from("a\n\n").len(), 2);
+        assert_eq!(marker_from("\n").len(), 1);
+        assert_eq!(marker_from("").len(), 1);
+    }
+
+    #[test]
+    fn test_delete_trailing_newline() {
+        let mut marker = marker_from("one\n");
+
+        marker.delete_range(3, 4);
+
+        assert_eq!(marks(&marker), vec![Mark::new(0, 1, 3)]);
+    }
+
+    #[test]
+    fn test_many_lines_stay_consistent() {
+        let mut text = String::new();
+        let mut marker = marker_from("");
+
+        for i in 0..2000 {
+            let line = format!("line {i}\n");
+            marker.insert_text(text.chars().count(), &line);
+            text.push_str(&line);
+        }
+
+        assert_eq!(marker.len(), 2000);
+        assert_eq!(marks(&marker), marks(&marker_from(&text)));
+        assert_eq!(
+            marker.get_by_cursor(text.find("line 1500").unwrap()),
+            marker.get_by_line(1501)
+        );
+    }
+}
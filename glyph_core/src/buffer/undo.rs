@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::file;
+use crate::buffer::history::History;
+
+/// An undo history saved to disk alongside what the file looked like when
+/// it was saved, the history is only valid for that exact content.
+#[derive(Debug, Serialize, Deserialize)]
+struct UndoFile<H> {
+    path: PathBuf,
+    hash: u64,
+    history: H,
+}
+
+/// directory every undo history is written to
+pub fn undo_dir() -> PathBuf {
+    file::state_dir("undo")
+}
+
+pub fn undo_path(dir: &Path, path: &Path) -> PathBuf {
+    file::state_file(dir, path, "undo")
+}
+
+/// writes the committed part of `history`, `content` must be the content of
+/// the buffer at the current revision
+pub fn write(
+    undo_path: &Path,
+    path: &Path,
+    content: &str,
+    history: &History,
+) -> anyhow::Result<()> {
+    if let Some(dir) = undo_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let undo = UndoFile {
+        path: path.to_path_buf(),
+        hash: file::fnv1a(content.as_bytes()),
+        history,
+    };
+    file::write_atomic(undo_path, serde_json::to_string(&undo)?.as_bytes())
+        .with_context(|| format!("failed to write undo file {}", undo_path.display()))
+}
+
+/// reads the history saved for `path`, histories saved for a different
+/// content than `content` are ignored as they can't be applied
+pub fn read(undo_path: &Path, path: &Path, content: &str) -> anyhow::Result<Option<History>> {
+    if !undo_path.exists() {
+        return Ok(None);
+    }
+    let undo = std::fs::read_to_string(undo_path)
+        .with_context(|| format!("failed to read undo file {}", undo_path.display()))?;
+    let undo: UndoFile<History> = serde_json::from_str(&undo)
+        .with_context(|| format!("invalid undo file {}", undo_path.display()))?;
+    if undo.path != path || undo.hash != file::fnv1a(content.as_bytes()) {
+        return Ok(None);
+    }
+    Ok(Some(undo.history))
+}
+
+/// deletes undo files older than `max_age`, then the least recently written
+/// ones until the directory fits in `max_size` bytes
+pub fn prune(dir: &Path, max_size: u64, max_age: Duration) -> std::io::Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    let now = SystemTime::now();
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata.modified().unwrap_or(now);
+        let age = now.duration_since(modified).unwrap_or_default();
+        if age > max_age {
+            std::fs::remove_file(entry.path())?;
+            continue;
+        }
+        files.push((modified, metadata.len(), entry.path()));
+    }
+
+    files.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+    let mut size = 0;
+    for (_, len, path) in files {
+        size += len;
+        if size > max_size {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+    use crate::buffer::transaction::ChangeSet;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("glyph_{name}_{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = temp_dir("undo");
+        let undo_path = undo_path(&dir, Path::new("/file.txt"));
+        let mut history = History::new();
+        let mut text = Rope::from_str("a");
+        let changes = ChangeSet::from_changes(1, [(1, 1, Some("b".to_string()))]).unwrap();
+        let inverse = changes.invert(&text);
+        changes.apply(&mut text);
+        history.record(changes, inverse);
+        history.commit();
+
+        write(&undo_path, Path::new("/file.txt"), "ab", &history).unwrap();
+
+        let mut read = read(&undo_path, Path::new("/file.txt"), "ab")
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.current(), 1);
+        read.undo().unwrap().changes.apply(&mut text);
+        assert_eq!(text, "a");
+
+        // the file changed since the history was written
+        assert!(super::read(&undo_path, Path::new("/file.txt"), "abc")
+            .unwrap()
+            .is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune_by_size() {
+        let dir = temp_dir("undo_prune");
+        std::fs::write(dir.join("old.undo"), "x".repeat(10)).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join("old.undo"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        std::fs::write(dir.join("new.undo"), "x".repeat(10)).unwrap();
+
+        prune(&dir, 15, Duration::from_secs(3600)).unwrap();
+
+        assert!(!dir.join("old.undo").exists());
+        assert!(dir.join("new.undo").exists());
+
+        prune(&dir, 15, Duration::from_secs(0)).unwrap();
+        assert!(!dir.join("new.undo").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crossterm::ExecutableCommand;
+use serde::{Deserialize, Serialize};
+
+/// Which of the system selections a register talks to, `+` is the clipboard
+/// and `*` the primary selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardKind {
+    Clipboard,
+    Selection,
+}
+
+impl ClipboardKind {
+    pub fn from_register(name: char) -> Option<Self> {
+        match name {
+            '+' => Some(ClipboardKind::Clipboard),
+            '*' => Some(ClipboardKind::Selection),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps the unnamed register in sync with one of the clipboard registers,
+/// like vim's `clipboard` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardSync {
+    Unnamed,
+    UnnamedPlus,
+}
+
+impl ClipboardSync {
+    pub fn register(&self) -> char {
+        match self {
+            ClipboardSync::Unnamed => '*',
+            ClipboardSync::UnnamedPlus => '+',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipboardProviderName {
+    Osc52,
+    WlCopy,
+    Xclip,
+    Xsel,
+}
+
+pub trait ClipboardProvider: std::fmt::Debug {
+    fn name(&self) -> &str;
+    fn set(&mut self, kind: ClipboardKind, text: &str) -> anyhow::Result<()>;
+    /// content of the selection, `None` when the provider can't read it
+    fn get(&mut self, kind: ClipboardKind) -> anyhow::Result<Option<String>>;
+}
+
+/// picks the provider named in the config, or the first tool available for
+/// the current display server, falling back to OSC 52 which works over ssh
+/// and inside tmux
+pub fn provider(
+    name: Option<ClipboardProviderName>,
+    out: Box<dyn Write>,
+) -> Box<dyn ClipboardProvider> {
+    let name = name.unwrap_or_else(|| {
+        let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+        let x11 = std::env::var_os("DISPLAY").is_some();
+        if wayland && in_path("wl-copy") {
+            ClipboardProviderName::WlCopy
+        } else if x11 && in_path("xclip") {
+            ClipboardProviderName::Xclip
+        } else if x11 && in_path("xsel") {
+            ClipboardProviderName::Xsel
+        } else {
+            ClipboardProviderName::Osc52
+        }
+    });
+    tracing::debug!("using clipboard provider: {name:?}");
+    match name {
+        ClipboardProviderName::Osc52 => Box::new(Osc52::new(out)),
+        ClipboardProviderName::WlCopy => Box::new(CommandProvider::wl_copy()),
+        ClipboardProviderName::Xclip => Box::new(CommandProvider::xclip()),
+        ClipboardProviderName::Xsel => Box::new(CommandProvider::xsel()),
+    }
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Sets the clipboard of the terminal with an OSC 52 escape sequence, the
+/// terminal can't be asked for it back so reading is not supported.
+pub struct Osc52 {
+    out: Box<dyn Write>,
+    tmux: bool,
+}
+
+impl std::fmt::Debug for Osc52 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Osc52").field("tmux", &self.tmux).finish()
+    }
+}
+
+impl Osc52 {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            tmux: std::env::var_os("TMUX").is_some(),
+        }
+    }
+}
+
+impl ClipboardProvider for Osc52 {
+    fn name(&self) -> &str {
+        "osc52"
+    }
+
+    fn set(&mut self, kind: ClipboardKind, text: &str) -> anyhow::Result<()> {
+        self.out.execute(SetClipboard {
+            kind,
+            text,
+            tmux: self.tmux,
+        })?;
+        Ok(())
+    }
+
+    fn get(&mut self, _: ClipboardKind) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// The OSC 52 sequence setting the clipboard to `text`.
+pub struct SetClipboard<'a> {
+    pub kind: ClipboardKind,
+    pub text: &'a str,
+    // tmux only forwards the sequence to the outer terminal when wrapped
+    pub tmux: bool,
+}
+
+impl crossterm::Command for SetClipboard<'_> {
+    fn write_ansi(&self, f: &mut impl std::fmt::Write) -> std::fmt::Result {
+        let selection = match self.kind {
+            ClipboardKind::Clipboard => 'c',
+            ClipboardKind::Selection => 'p',
+        };
+        let sequence = format!("\x1b]52;{selection};{}\x07", base64(self.text.as_bytes()));
+        match self.tmux {
+            true => write!(
+                f,
+                "\x1bPtmux;{}\x1b\\",
+                sequence.replace('\x1b', "\x1b\x1b")
+            ),
+            false => f.write_str(&sequence),
+        }
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i)) & 0x3f] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+/// Talks to the clipboard through an external tool, the arguments are given
+/// for the clipboard and the primary selection respectively.
+#[derive(Debug)]
+pub struct CommandProvider {
+    name: &'static str,
+    copy: [&'static [&'static str]; 2],
+    paste: [&'static [&'static str]; 2],
+}
+
+impl CommandProvider {
+    pub fn wl_copy() -> Self {
+        Self {
+            name: "wl-copy",
+            copy: [&["wl-copy"], &["wl-copy", "--primary"]],
+            paste: [
+                &["wl-paste", "--no-newline"],
+                &["wl-paste", "--no-newline", "--primary"],
+            ],
+        }
+    }
+
+    pub fn xclip() -> Self {
+        Self {
+            name: "xclip",
+            copy: [
+                &["xclip", "-selection", "clipboard"],
+                &["xclip", "-selection", "primary"],
+            ],
+            paste: [
+                &["xclip", "-selection", "clipboard", "-o"],
+                &["xclip", "-selection", "primary", "-o"],
+            ],
+        }
+    }
+
+    pub fn xsel() -> Self {
+        Self {
+            name: "xsel",
+            copy: [
+                &["xsel", "--clipboard", "--input"],
+                &["xsel", "--primary", "--input"],
+            ],
+            paste: [
+                &["xsel", "--clipboard", "--output"],
+                &["xsel", "--primary", "--output"],
+            ],
+        }
+    }
+
+    fn command(args: &[&str]) -> Command {
+        let mut command = Command::new(args[0]);
+        command.args(&args[1..]).stderr(Stdio::null());
+        command
+    }
+}
+
+impl ClipboardProvider for CommandProvider {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn set(&mut self, kind: ClipboardKind, text: &str) -> anyhow::Result<()> {
+        let args = self.copy[kind as usize];
+        let mut child = Self::command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|err| anyhow::anyhow!("failed to run {}: {err}", args[0]))?;
+        child.stdin.take().unwrap().write_all(text.as_bytes())?;
+        let status = child.wait()?;
+        if !status.success() {
+            anyhow::bail!("{} exited with {status}", args[0]);
+        }
+        Ok(())
+    }
+
+    fn get(&mut self, kind: ClipboardKind) -> anyhow::Result<Option<String>> {
+        let args = self.paste[kind as usize];
+        let output = Self::command(args)
+            .output()
+            .map_err(|err| anyhow::anyhow!("failed to run {}: {err}", args[0]))?;
+        if !output.status.success() {
+            anyhow::bail!("{} exited with {}", args[0], output.status);
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("héllo\n".as_bytes()), "aMOpbGxvCg==");
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        let mut sequence = String::new();
+        let command = SetClipboard {
+            kind: ClipboardKind::Clipboard,
+            text: "foo",
+            tmux: false,
+        };
+        crossterm::Command::write_ansi(&command, &mut sequence).unwrap();
+        assert_eq!(sequence, "\x1b]52;c;Zm9v\x07");
+
+        let mut sequence = String::new();
+        let command = SetClipboard {
+            kind: ClipboardKind::Selection,
+            text: "foo",
+            tmux: true,
+        };
+        crossterm::Command::write_ansi(&command, &mut sequence).unwrap();
+        assert_eq!(sequence, "\x1bPtmux;\x1b\x1b]52;p;Zm9v\x07\x1b\\");
+    }
+}
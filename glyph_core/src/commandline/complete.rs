@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use crate::{ex, options};
+
+/// What completions come from besides the editor itself.
+pub struct Sources<'a> {
+    pub buffers: &'a [String],
+    pub themes: &'a Path,
+}
+
+/// The candidates of the word before the cursor, which starts at byte
+/// `start` of the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+/// The candidates of a completion shown in the wildmenu, `Tab` and `S-Tab`
+/// select them in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wildmenu {
+    completion: Completion,
+    selected: Option<usize>,
+}
+
+impl Wildmenu {
+    pub fn new(completion: Completion) -> Self {
+        Self {
+            completion,
+            selected: None,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.completion.start
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        &self.completion.candidates
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// selects the next candidate, or the previous one, wrapping around
+    pub fn select(&mut self, forward: bool) -> &str {
+        let len = self.completion.candidates.len();
+        let selected = match (self.selected, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(selected), true) => (selected + 1) % len,
+            (Some(selected), false) => (selected + len - 1) % len,
+        };
+        self.selected = Some(selected);
+        &self.completion.candidates[selected]
+    }
+}
+
+/// what the word at the end of `line`, the command line up to the cursor,
+/// completes to: command names, then the files, buffers, options or themes
+/// the command takes
+pub fn complete(line: &str, sources: &Sources) -> Option<Completion> {
+    let (_, name, rest) = ex::split(line.trim_start()).ok()?;
+    let (word, mut candidates) = match rest.strip_prefix('!').unwrap_or(rest) {
+        "" if rest.is_empty() => (
+            name,
+            ex::complete(name).into_iter().map(String::from).collect(),
+        ),
+        args if args.starts_with(' ') => {
+            let word = &args[args.rfind(' ').unwrap() + 1..];
+            (word, arguments(ex::full_name(name)?, word, sources)?)
+        }
+        _ => return None,
+    };
+    candidates.sort();
+    Some(Completion {
+        start: line.len() - word.len(),
+        candidates,
+    })
+}
+
+// the candidates of the argument `word` of the command `name`
+fn arguments(name: &str, word: &str, sources: &Sources) -> Option<Vec<String>> {
+    let candidates = match name {
+        "edit" | "write" | "saveas" | "read" => files(word),
+        "buffer" => sources
+            .buffers
+            .iter()
+            .filter(|buffer| buffer.contains(word))
+            .cloned()
+            .collect(),
+        "set" | "setlocal" => settings(word),
+        "colorscheme" => entries(sources.themes, word)
+            .into_iter()
+            .filter(|(_, dir)| !dir)
+            .map(|(theme, _)| theme)
+            .collect(),
+        _ => return None,
+    };
+    Some(candidates)
+}
+
+/// the longest prefix the candidates share
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let Some(first) = candidates.first() else {
+        return "";
+    };
+    candidates
+        .iter()
+        .skip(1)
+        .fold(first.as_str(), |common, candidate| {
+            let len = common
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(candidate.len()), |((i, _), _)| i);
+            &common[..len]
+        })
+}
+
+// the paths of the entries of the directory of `word` starting with its file
+// name, directories end with a slash
+fn files(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => word.split_at(slash + 1),
+        None => ("", word),
+    };
+    let path = match dir {
+        "" => Path::new("."),
+        dir => Path::new(dir),
+    };
+    entries(path, prefix)
+        .into_iter()
+        .map(|(name, is_dir)| match is_dir {
+            true => format!("{dir}{name}/"),
+            false => format!("{dir}{name}"),
+        })
+        .collect()
+}
+
+// the names of the entries of `dir` starting with `prefix` and whether they
+// are directories, hidden ones only when `prefix` starts with a dot
+fn entries(dir: &Path, prefix: &str) -> Vec<(String, bool)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            Some((name, is_dir))
+        })
+        .collect()
+}
+
+// option names, keeping the `no` or `inv` they are typed after, values
+// aren't completed
+fn settings(word: &str) -> Vec<String> {
+    if word.contains(['=', ':']) {
+        return vec![];
+    }
+    ["", "no", "inv"]
+        .into_iter()
+        .find_map(|prefix| {
+            let names = options::complete(word.strip_prefix(prefix)?);
+            let names = names.into_iter().map(|name| format!("{prefix}{name}"));
+            Some(names.collect::<Vec<_>>()).filter(|names| !names.is_empty())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(line: &str, sources: &Sources) -> Vec<String> {
+        complete(line, sources).map_or(vec![], |completion| completion.candidates)
+    }
+
+    #[test]
+    fn test_complete_by_context() {
+        let dir = std::env::temp_dir().join(format!("glyph_complete_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("dark.toml"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        let buffers = ["src/main.rs".to_string(), "README.md".to_string()];
+        let sources = Sources {
+            buffers: &buffers,
+            themes: &dir,
+        };
+
+        assert_eq!(candidates("no", &sources), ["nohlsearch", "normal"]);
+        assert_eq!(complete("'<,'>so", &sources).unwrap().start, 5);
+        assert_eq!(
+            candidates("set nonu", &sources),
+            ["nonumber", "nonumberwidth"]
+        );
+        assert_eq!(candidates("setl invr", &sources), ["invrelativenumber"]);
+        assert_eq!(candidates("set ts=", &sources), Vec::<String>::new());
+        assert_eq!(candidates("b main", &sources), ["src/main.rs"]);
+        assert_eq!(candidates("colo ", &sources), ["dark.toml"]);
+        let path = format!("{}/", dir.display());
+        assert_eq!(
+            candidates(&format!("e! {path}"), &sources),
+            [format!("{path}dark.toml"), format!("{path}src/")]
+        );
+        assert_eq!(
+            candidates(&format!("w {path}."), &sources),
+            [format!("{path}.hidden")]
+        );
+        let completion = complete(&format!("e {path}s"), &sources).unwrap();
+        assert_eq!(completion.start, 2);
+        assert!(complete("s/a/b", &sources).is_none());
+        assert!(complete("q now", &sources).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_walk_wildmenu() {
+        let candidates = vec!["number".to_string(), "numberwidth".to_string()];
+        let mut menu = Wildmenu::new(Completion {
+            start: 4,
+            candidates: candidates.clone(),
+        });
+
+        assert_eq!(common_prefix(&candidates), "number");
+        assert_eq!(common_prefix(&["ab".into(), "ac".into()]), "a");
+        assert_eq!(menu.select(false), "numberwidth");
+        assert_eq!(menu.select(true), "number");
+        assert_eq!(menu.select(true), "numberwidth");
+        assert_eq!(menu.selected(), Some(1));
+    }
+}
@@ -0,0 +1,160 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::file;
+
+// entries past it are dropped, oldest first
+const MAX_ENTRIES: usize = 200;
+
+/// Lines executed on the command line, oldest first, each only once.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    /// adds `entry` as the newest one, moving it there if it was already in
+    pub fn add(&mut self, entry: &str) {
+        if entry.trim().is_empty() {
+            return;
+        }
+        self.entries.retain(|old| old != entry);
+        self.entries.push(entry.to_string());
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+}
+
+/// A walk through a history with `Up` and `Down`, it only goes through the
+/// entries that start with what was typed before it started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Browsing {
+    typed: String,
+    // the entry shown, none while the typed text is
+    index: Option<usize>,
+}
+
+impl Browsing {
+    pub fn new(typed: &str) -> Self {
+        Self {
+            typed: typed.to_string(),
+            index: None,
+        }
+    }
+
+    /// the next older or newer entry to show, going newer than the newest one
+    /// shows the typed text again, there's none past either end
+    pub fn browse(&mut self, history: &History, older: bool) -> Option<String> {
+        let entries = history.entries();
+        let matches = |i: &usize| entries[*i].starts_with(&self.typed);
+        let next = match (self.index, older) {
+            (None, false) => return None,
+            (None, true) => (0..entries.len()).rev().find(matches),
+            (Some(index), true) => (0..index).rev().find(matches),
+            (Some(index), false) => (index + 1..entries.len()).find(matches),
+        };
+        match (next, older) {
+            (None, true) => None,
+            (next, _) => {
+                self.index = next;
+                Some(next.map_or(self.typed.clone(), |i| entries[i].clone()))
+            }
+        }
+    }
+}
+
+/// The histories of `:` and of `/` and `?`, kept between sessions.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Histories {
+    pub commands: History,
+    pub searches: History,
+}
+
+impl Histories {
+    pub fn path() -> PathBuf {
+        file::state_dir("history").join("commandline.json")
+    }
+
+    /// the histories saved at `path`, empty ones if nothing was saved yet
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let histories = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read history file {}", path.display()))?;
+        serde_json::from_str(&histories)
+            .with_context(|| format!("invalid history file {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        file::write_atomic(path, serde_json::to_string(self)?.as_bytes())
+            .with_context(|| format!("failed to write history file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::default();
+        for entry in entries {
+            history.add(entry);
+        }
+        history
+    }
+
+    #[test]
+    fn test_add_entries() {
+        let history = history(&["w", "", "set nu", "w", "  "]);
+        assert_eq!(history.entries(), ["set nu", "w"]);
+
+        let mut history = History::default();
+        for i in 0..MAX_ENTRIES + 5 {
+            history.add(&i.to_string());
+        }
+        assert_eq!(history.entries().len(), MAX_ENTRIES);
+        assert_eq!(history.entries()[0], "5");
+    }
+
+    #[test]
+    fn test_browse_with_prefix() {
+        let history = history(&["set nu", "w", "set ts=2", "e file"]);
+        let mut browsing = Browsing::new("set");
+
+        assert_eq!(browsing.browse(&history, false), None);
+        assert_eq!(browsing.browse(&history, true).unwrap(), "set ts=2");
+        assert_eq!(browsing.browse(&history, true).unwrap(), "set nu");
+        assert_eq!(browsing.browse(&history, true), None);
+        assert_eq!(browsing.browse(&history, false).unwrap(), "set ts=2");
+        assert_eq!(browsing.browse(&history, false).unwrap(), "set");
+        assert_eq!(browsing.browse(&history, false), None);
+
+        let mut browsing = Browsing::new("");
+        assert_eq!(browsing.browse(&history, true).unwrap(), "e file");
+    }
+
+    #[test]
+    fn test_persist_histories() {
+        let dir = std::env::temp_dir().join(format!("glyph_history_{}", std::process::id()));
+        let path = dir.join("commandline.json");
+        let histories = Histories {
+            commands: history(&["w"]),
+            searches: history(&["foo", "bar"]),
+        };
+
+        assert_eq!(Histories::read(&path).unwrap(), Histories::default());
+        histories.write(&path).unwrap();
+        assert_eq!(Histories::read(&path).unwrap(), histories);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+/// Edits of the text typed on the command line that the keys bound in
+/// command and search mode make, besides typing and `Backspace`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Start,
+    End,
+    // `Delete`, `C-w` and `C-u`
+    DeleteForward,
+    DeleteWord,
+    DeleteToStart,
+}
+
+/// What is typed on the command line, the cursor counts chars.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Input {
+    text: String,
+    cursor: usize,
+}
+
+#[derive(PartialEq, Eq)]
+enum Class {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> Class {
+    match c {
+        c if c.is_whitespace() => Class::Space,
+        c if c.is_alphanumeric() || c == '_' => Class::Word,
+        _ => Class::Punctuation,
+    }
+}
+
+impl Input {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// replaces the text, leaving the cursor at its end
+    pub fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = text.chars().count();
+    }
+
+    pub fn clear(&mut self) {
+        self.set("");
+    }
+
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.byte(self.cursor)]
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        let at = self.byte(self.cursor);
+        self.text.insert_str(at, text);
+        self.cursor += text.chars().count();
+    }
+
+    /// deletes the char before the cursor, there's none at the start
+    pub fn delete_back(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.delete(self.cursor - 1, self.cursor);
+        true
+    }
+
+    /// replaces what follows byte `start` up to the cursor, with a completion
+    pub fn complete(&mut self, start: usize, text: &str) {
+        let end = self.byte(self.cursor);
+        self.text.replace_range(start..end, text);
+        self.cursor = self.text[..start + text.len()].chars().count();
+    }
+
+    pub fn edit(&mut self, edit: Edit) {
+        let len = self.text.chars().count();
+        match edit {
+            Edit::Left => self.cursor = self.cursor.saturating_sub(1),
+            Edit::Right => self.cursor = (self.cursor + 1).min(len),
+            Edit::WordLeft => self.cursor = self.word_start(),
+            Edit::WordRight => self.cursor = self.word_end(),
+            Edit::Start => self.cursor = 0,
+            Edit::End => self.cursor = len,
+            Edit::DeleteForward if self.cursor < len => self.delete(self.cursor, self.cursor + 1),
+            Edit::DeleteForward => (),
+            Edit::DeleteWord => self.delete(self.word_start(), self.cursor),
+            Edit::DeleteToStart => self.delete(0, self.cursor),
+        }
+    }
+
+    // deletes the chars from `start` to `end`, leaving the cursor at `start`
+    fn delete(&mut self, start: usize, end: usize) {
+        let range = self.byte(start)..self.byte(end);
+        self.text.replace_range(range, "");
+        self.cursor = start;
+    }
+
+    fn byte(&self, char_idx: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(char_idx)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    // the start of the word before the cursor, after the spaces before it
+    fn word_start(&self) -> usize {
+        let chars = self.text.chars().take(self.cursor).collect::<Vec<_>>();
+        let mut start = chars.len();
+        while start > 0 && class(chars[start - 1]) == Class::Space {
+            start -= 1;
+        }
+        if let Some(word) = start.checked_sub(1).map(|last| class(chars[last])) {
+            while start > 0 && class(chars[start - 1]) == word {
+                start -= 1;
+            }
+        }
+        start
+    }
+
+    // the start of the next word, past the spaces after the current one
+    fn word_end(&self) -> usize {
+        let chars = self.text.chars().collect::<Vec<_>>();
+        let mut end = self.cursor;
+        if let Some(word) = chars.get(end).map(|c| class(*c)) {
+            while end < chars.len() && class(chars[end]) == word {
+                end += 1;
+            }
+        }
+        while end < chars.len() && class(chars[end]) == Class::Space {
+            end += 1;
+        }
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> Input {
+        let mut input = Input::default();
+        input.set(text);
+        input
+    }
+
+    #[test]
+    fn test_move_and_insert() {
+        let mut input = typed("e fö.rs");
+
+        input.edit(Edit::Left);
+        input.edit(Edit::Left);
+        input.insert("x");
+        assert_eq!(input.text(), "e fö.xrs");
+        assert_eq!(input.before_cursor(), "e fö.x");
+        input.edit(Edit::WordLeft);
+        assert_eq!(input.cursor(), 5);
+        input.edit(Edit::WordLeft);
+        assert_eq!(input.cursor(), 4);
+        input.edit(Edit::WordRight);
+        assert_eq!(input.cursor(), 5);
+        input.edit(Edit::Start);
+        input.edit(Edit::DeleteForward);
+        assert_eq!(input.text(), " fö.xrs");
+        assert!(!input.delete_back());
+        input.edit(Edit::End);
+        assert!(input.delete_back());
+        assert_eq!(input.text(), " fö.xr");
+    }
+
+    #[test]
+    fn test_delete_word_and_line() {
+        let mut input = typed("s/foo/bar  ");
+
+        input.edit(Edit::DeleteWord);
+        assert_eq!(input.text(), "s/foo/");
+        input.edit(Edit::DeleteWord);
+        assert_eq!(input.text(), "s/foo");
+        input.edit(Edit::Left);
+        input.edit(Edit::DeleteToStart);
+        assert_eq!(input.text(), "o");
+        assert_eq!(input.cursor(), 0);
+
+        let mut input = typed("set nonu");
+        input.complete(4, "nonumber");
+        assert_eq!(input.text(), "set nonumber");
+        assert_eq!(input.cursor(), 12);
+    }
+}
@@ -0,0 +1,55 @@
+pub mod complete;
+pub mod history;
+pub mod input;
+
+use crate::editor::Rect;
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Info,
+    Error,
+}
+
+/// Feedback shown to the user on the commandline until it gets replaced or
+/// cleared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: MessageKind,
+    pub text: String,
+}
+
+impl Message {
+    pub fn info(text: impl Into<String>) -> Self {
+        Self {
+            kind: MessageKind::Info,
+            text: text.into(),
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            kind: MessageKind::Error,
+            text: text.into(),
+        }
+    }
+}
+
+pub trait Commandline<'a> {
+    fn new(area: Rect, theme: &'a Theme) -> Self;
+    fn render(&mut self) -> anyhow::Result<()>;
+    fn set_message(&mut self, message: Message);
+    fn clear_message(&mut self);
+    /// shows the `text` typed after `prompt`, such as `:` or `/`, in place of
+    /// the message until it is cleared, `cursor` counts the chars before it
+    fn set_input(&mut self, prompt: char, text: &str, cursor: usize);
+    fn clear_input(&mut self);
+    /// where the terminal cursor goes while input is shown
+    fn input_cursor(&self) -> Option<(u16, u16)>;
+    /// shows the completions of the word being typed above the input, with
+    /// the `selected` one highlighted
+    fn set_wildmenu(&mut self, items: &[String], selected: Option<usize>);
+    fn clear_wildmenu(&mut self);
+    /// themes picked with `:colorscheme` are loaded once and never freed
+    fn set_theme(&mut self, theme: &'static Theme);
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::PathBuf};
 
+use crate::clipboard::{ClipboardProviderName, ClipboardSync};
 use crate::editor::KeyAction;
 
 const fn default_true() -> bool {
@@ -34,6 +35,44 @@ pub struct Config {
     pub empty_line_char: char,
     #[serde(default = "default_true")]
     pub show_diagnostics: bool,
+    #[serde(default)]
+    pub undo: UndoConfig,
+    // mirrors the unnamed register into the system clipboard
+    #[serde(default)]
+    pub clipboard: Option<ClipboardSync>,
+    // picked from the environment when not set
+    #[serde(default)]
+    pub clipboard_provider: Option<ClipboardProviderName>,
+}
+
+/// Persistent undo, histories older than `max_age_days` or past the total
+/// size of `max_size_mb` are pruned on startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoConfig {
+    #[serde(default = "default_true")]
+    pub persistent: bool,
+    #[serde(default = "default_undo_max_size_mb")]
+    pub max_size_mb: u64,
+    #[serde(default = "default_undo_max_age_days")]
+    pub max_age_days: u64,
+}
+
+const fn default_undo_max_size_mb() -> u64 {
+    100
+}
+
+const fn default_undo_max_age_days() -> u64 {
+    90
+}
+
+impl Default for UndoConfig {
+    fn default() -> Self {
+        Self {
+            persistent: true,
+            max_size_mb: default_undo_max_size_mb(),
+            max_age_days: default_undo_max_age_days(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -44,6 +83,14 @@ pub struct Keys {
     pub insert: HashMap<String, KeyAction>,
     #[serde(default)]
     pub command: HashMap<String, KeyAction>,
+    #[serde(default)]
+    pub search: HashMap<String, KeyAction>,
+    // looked up before `normal` while an operator waits for its motion
+    #[serde(default)]
+    pub operator: HashMap<String, KeyAction>,
+    // looked up before `operator` and `normal` in the visual modes
+    #[serde(default)]
+    pub visual: HashMap<String, KeyAction>,
 }
 
 impl Config {
@@ -57,3 +104,17 @@ impl Config {
         config_path.join("themes")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        let toml = include_str!("../../config/glyph.toml");
+
+        let config = toml::from_str::<Config>(toml);
+
+        assert!(config.is_ok(), "{:?}", config.err());
+    }
+}
@@ -1,17 +1,47 @@
 use crate::buffer::Buffer;
 use crate::editor::Action;
 use crate::editor::Mode;
+use crate::ex;
+use crate::register::RegisterKind;
 
 use crate::editor::Position;
 
-#[derive(Debug, Default)]
+/// How an operator treats the text between the cursor and the position a
+/// motion takes it to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    // the char under the target is left out, e.g. `w`
+    Exclusive,
+    // the char under the target is included, e.g. `$`
+    Inclusive,
+    // every line between both positions, e.g. `j`
+    Linewise,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Cursor {
     pub absolute_position: usize,
     pub row: usize,
     pub col: usize,
+    // where the selection started in visual mode, the cursor is its other end
+    pub anchor: Option<usize>,
 }
 
 impl Cursor {
+    /// the text selected in visual `mode`, as the bounds and kind taken by
+    /// `Buffer::selection`
+    pub fn selection(&self, mode: &Mode) -> Option<(usize, usize, RegisterKind)> {
+        let anchor = self.anchor?;
+        let pos = self.absolute_position;
+        match mode {
+            // both ends are included
+            Mode::Visual => Some((anchor.min(pos), anchor.max(pos) + 1, RegisterKind::Charwise)),
+            Mode::VisualLine => Some((anchor, pos, RegisterKind::Linewise)),
+            Mode::VisualBlock => Some((anchor, pos, RegisterKind::Blockwise)),
+            _ => None,
+        }
+    }
+
     pub fn handle_action(&mut self, action: &Action, buffer: &mut Buffer, mode: &Mode) {
         tracing::debug!(
             "cursor: {}:{} handling action: {action:?}",
@@ -28,15 +58,81 @@ impl Cursor {
             Action::MoveToLineStart => self.move_to_line_start(buffer),
             Action::MoveToLineEnd => self.move_to_line_end(buffer),
             Action::NextWord => self.move_to_next_word(buffer),
+            Action::PreviousWord => self.move_to_previous_word(buffer),
             Action::InsertChar(_) => self.insert_char(),
             Action::DeletePreviousChar => self.delete_prev_char(buffer),
             Action::InsertLineBelow => self.insert_line_below(buffer),
             Action::InsertLineAbove => self.insert_line_above(buffer),
             Action::InsertLine => self.insert_line(),
+            Action::GoToLine(address) => {
+                // lines past the end go to the last one, like vim
+                let line = address
+                    .line(buffer, self.row)
+                    .unwrap_or_else(|_| ex::last_line(buffer));
+                self.move_to(buffer.first_non_blank(line), buffer);
+            }
+            Action::GoToMark(name) | Action::GoToMarkLine(name) => {
+                let linewise = matches!(action, Action::GoToMarkLine(_));
+                if let Some(pos) = buffer.mark(*name, linewise) {
+                    self.move_to(pos, buffer);
+                }
+            }
             _ => (),
         }
     }
 
+    /// where `motion` repeated `count` times would take the cursor and how the
+    /// text up to there is covered, `None` if the action is not a motion
+    pub fn motion(
+        &self,
+        motion: &Action,
+        buffer: &mut Buffer,
+        count: usize,
+    ) -> Option<(usize, MotionKind)> {
+        let kind = match motion {
+            Action::MoveLeft
+            | Action::MoveRight
+            | Action::MoveToLineStart
+            | Action::NextWord
+            | Action::PreviousWord => MotionKind::Exclusive,
+            Action::MoveToLineEnd => MotionKind::Inclusive,
+            Action::MoveUp
+            | Action::MoveDown
+            | Action::MoveToTop
+            | Action::MoveToBottom
+            | Action::CurrentLine => MotionKind::Linewise,
+            // marks of other buffers can't be operated on
+            Action::GoToMark(name) => {
+                buffer.marks.get(*name)?;
+                MotionKind::Exclusive
+            }
+            Action::GoToMarkLine(name) => {
+                buffer.marks.get(*name)?;
+                MotionKind::Linewise
+            }
+            _ => return None,
+        };
+        let mut cursor = self.clone();
+        let count = match motion {
+            Action::CurrentLine => count - 1,
+            Action::MoveToLineStart
+            | Action::MoveToTop
+            | Action::MoveToBottom
+            | Action::GoToMark(_)
+            | Action::GoToMarkLine(_) => 1,
+            _ => count,
+        };
+        let motion = match motion {
+            Action::CurrentLine => &Action::MoveDown,
+            motion => motion,
+        };
+        for _ in 0..count {
+            // operators can reach the line break, like insert mode
+            cursor.handle_action(motion, buffer, &Mode::Insert);
+        }
+        Some((cursor.absolute_position, kind))
+    }
+
     fn insert_char(&mut self) {
         self.absolute_position += 1;
         self.col += 1;
@@ -77,6 +173,28 @@ impl Cursor {
         }
     }
 
+    /// places the cursor at the given absolute position, clamped to the buffer
+    pub fn move_to(&mut self, position: usize, buffer: &Buffer) {
+        let position = position.min(buffer.buffer.len_chars());
+        if let Some(mark) = buffer.marker.get_by_cursor(position) {
+            self.row = mark.line - 1;
+            self.col = position - mark.start;
+            self.absolute_position = position;
+        }
+    }
+
+    /// normal mode never leaves the cursor on a line break
+    pub fn clamp(&mut self, buffer: &Buffer, mode: &Mode) {
+        let pos = self.absolute_position;
+        let (line, col) = buffer.char_to_line_col(pos);
+        if (matches!(mode, Mode::Normal) || mode.is_visual())
+            && col > 0
+            && pos == buffer.line_col_to_char(line, usize::MAX)
+        {
+            self.move_to(pos - 1, buffer);
+        }
+    }
+
     pub fn get_readable_position(&self) -> Position {
         Position {
             row: self.row + 1,
@@ -115,7 +233,9 @@ impl Cursor {
     fn move_right(&mut self, buffer: &mut Buffer, mode: &Mode) {
         if let Some(mark) = buffer.marker.get_by_line(self.row + 1) {
             let limit = match mode {
-                Mode::Normal => mark.size.saturating_sub(2),
+                Mode::Normal | Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                    mark.size.saturating_sub(2)
+                }
                 _ => mark.size.saturating_sub(1),
             };
             self.col = usize::min(self.col + 1, limit);
@@ -129,7 +249,9 @@ impl Cursor {
             Some(mark) => {
                 self.row += 1;
                 let limit = match mode {
-                    Mode::Normal => mark.size.saturating_sub(2),
+                    Mode::Normal | Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                        mark.size.saturating_sub(2)
+                    }
                     _ => mark.size.saturating_sub(1),
                 };
 
@@ -144,7 +266,9 @@ impl Cursor {
                     .get_by_line(self.row + 1)
                     .expect("current line should never be none");
                 let limit = match mode {
-                    Mode::Normal => mark.size.saturating_sub(2),
+                    Mode::Normal | Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                        mark.size.saturating_sub(2)
+                    }
                     _ => mark.size.saturating_sub(1),
                 };
                 self.col = limit;
@@ -190,16 +314,15 @@ impl Cursor {
     // FIXME: this is not behaving as vim would, currently we are not as
     // smart as vim W
     fn move_to_next_word(&mut self, buffer: &mut Buffer) {
-        let content = buffer.to_string();
+        let content = &buffer.buffer;
         let mut pos = self.absolute_position;
 
-        let starting_char = content
-            .chars()
-            .nth(pos)
-            .expect("current position should never be out of bounds");
+        let Some(starting_char) = content.get_char(pos) else {
+            return;
+        };
         let starting_on_separator = self.is_separator(starting_char);
 
-        for char in content[pos..].chars() {
+        for char in content.chars_at(pos) {
             if starting_on_separator {
                 // we should skip repeated separators, such as :: or //
                 if !char.is_whitespace() && char != starting_char {
@@ -217,7 +340,7 @@ impl Cursor {
 
             // if it is a separator, and a whitespace, we skip until the next non whitespace
             if char.is_whitespace() {
-                while let Some(c) = content[pos..].chars().nth(0) {
+                while let Some(c) = content.get_char(pos) {
                     if !c.is_whitespace() {
                         break;
                     }
@@ -235,6 +358,26 @@ impl Cursor {
         }
     }
 
+    fn move_to_previous_word(&mut self, buffer: &Buffer) {
+        let text = &buffer.buffer;
+        let mut pos = self.absolute_position;
+
+        while pos > 0 && text.char(pos - 1).is_whitespace() {
+            pos -= 1;
+        }
+        if pos > 0 && self.is_separator(text.char(pos - 1)) {
+            pos -= 1;
+        } else {
+            while pos > 0
+                && !self.is_separator(text.char(pos - 1))
+                && !text.char(pos - 1).is_whitespace()
+            {
+                pos -= 1;
+            }
+        }
+        self.move_to(pos, buffer);
+    }
+
     fn is_separator(&self, c: char) -> bool {
         matches!(c, ' ' | ':' | '-' | '}' | ')' | ']' | ',' | '(' | '>')
     }
@@ -245,16 +388,15 @@ mod tests {
     use super::*;
     #[test]
     fn test_cursor_move_up() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone");
         cursor.row = 1;
 
         cursor.handle_action(&Action::MoveUp, &mut buffer, &Mode::Normal);
 
         assert_eq!(cursor.col, 0);
         assert_eq!(cursor.absolute_position, 0);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], 'H');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), 'H');
     }
 
     #[test]
@@ -272,10 +414,8 @@ mod tests {
 
     #[test]
     fn move_to_line_start() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer =
-            Buffer::from_string(1, "Hello World! This is a big line\n this isn't\n", gap);
+        let mut buffer = Buffer::from_string(1, "Hello World! This is a big line\n this isn't\n");
 
         for _ in 0..20 {
             cursor.handle_action(&Action::MoveRight, &mut buffer, &Mode::Normal);
@@ -289,10 +429,8 @@ mod tests {
 
     #[test]
     fn move_to_line_end() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer =
-            Buffer::from_string(1, "Hello World! This is a big line\n this isn't\n", gap);
+        let mut buffer = Buffer::from_string(1, "Hello World! This is a big line\n this isn't\n");
 
         cursor.handle_action(&Action::MoveToLineEnd, &mut buffer, &Mode::Normal);
 
@@ -302,36 +440,45 @@ mod tests {
 
     #[test]
     fn test_cursor_move_down() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone");
         cursor.row = 0;
 
         cursor.handle_action(&Action::MoveDown, &mut buffer, &Mode::Normal);
 
         assert_eq!(cursor.col, 0);
         assert_eq!(cursor.absolute_position, 6);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], 'W');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), 'W');
+    }
+
+    #[test]
+    fn test_cursor_move_down_on_last_line() {
+        let mut cursor = Cursor::default();
+        let mut buffer = Buffer::from_string(1, "a\nb\n");
+        cursor.handle_action(&Action::MoveDown, &mut buffer, &Mode::Normal);
+
+        cursor.handle_action(&Action::MoveDown, &mut buffer, &Mode::Normal);
+
+        assert_eq!(cursor.row, 1);
+        assert_eq!(cursor.absolute_position, 2);
     }
 
     #[test]
     fn test_cursor_move_right() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone");
 
         cursor.handle_action(&Action::MoveRight, &mut buffer, &Mode::Normal);
 
         assert_eq!(cursor.col, 1);
         assert_eq!(cursor.absolute_position, 1);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], 'e');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), 'e');
     }
 
     #[test]
     fn test_cursor_move_left() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld\nEveryone");
         cursor.col = 1;
         cursor.absolute_position = 1;
 
@@ -339,15 +486,13 @@ mod tests {
 
         assert_eq!(cursor.col, 0);
         assert_eq!(cursor.absolute_position, 0);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], 'H');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), 'H');
     }
 
     #[test]
     fn test_moving_down_into_shorter_line() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer =
-            Buffer::from_string(1, "Hello World! This is a big line\n this isn't\n", gap);
+        let mut buffer = Buffer::from_string(1, "Hello World! This is a big line\n this isn't\n");
 
         for _ in 0..20 {
             cursor.handle_action(&Action::MoveRight, &mut buffer, &Mode::Normal);
@@ -364,15 +509,13 @@ mod tests {
 
         assert_eq!(cursor.col, 20);
         assert_eq!(cursor.absolute_position, mark.start + mark.size - 2);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], 't');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), 't');
     }
 
     #[test]
     fn test_moving_up_into_shorter_line() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer =
-            Buffer::from_string(1, "Hello\nWorld! This is a big line we got here", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld! This is a big line we got here");
         cursor.handle_action(&Action::MoveDown, &mut buffer, &Mode::Normal);
         cursor.col = 20;
         cursor.absolute_position += 20;
@@ -389,14 +532,13 @@ mod tests {
 
         assert_eq!(cursor.col, 20);
         assert_eq!(cursor.absolute_position, mark.start + mark.size - 2);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], 'o');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), 'o');
     }
 
     #[test]
     fn test_moving_up_into_longer_line() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello World! This is a big line\nThis isn't", gap);
+        let mut buffer = Buffer::from_string(1, "Hello World! This is a big line\nThis isn't");
         cursor.handle_action(&Action::MoveDown, &mut buffer, &Mode::Normal);
         cursor.col = 5;
         cursor.absolute_position += 5;
@@ -414,14 +556,13 @@ mod tests {
         assert_eq!(cursor.col, 5);
         assert_eq!(cursor.absolute_position, 5);
         assert_eq!(mark.start, 0);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], ' ');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), ' ');
     }
 
     #[test]
     fn test_moving_down_into_longer_line() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld! This is a big line", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld! This is a big line");
         cursor.row = 0;
         cursor.col = 5;
         cursor.absolute_position = 5;
@@ -436,14 +577,13 @@ mod tests {
         assert_eq!(cursor.col, 5);
         assert_eq!(cursor.absolute_position, 11);
         assert_eq!(mark.start, 6);
-        assert_eq!(buffer.buffer[cursor.absolute_position + gap], '!');
+        assert_eq!(buffer.buffer.char(cursor.absolute_position), '!');
     }
 
     #[test]
     fn test_should_not_go_left_when_at_start_of_file() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld!");
 
         cursor.handle_action(&Action::MoveLeft, &mut buffer, &Mode::Normal);
 
@@ -454,9 +594,8 @@ mod tests {
 
     #[test]
     fn test_should_go_to_line_start_when_moving_up_from_start_of_file() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello World!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello World!");
         cursor.absolute_position = 5;
         cursor.col = 5;
 
@@ -469,9 +608,8 @@ mod tests {
 
     #[test]
     fn test_should_go_to_line_end_when_moving_down_from_end_of_file() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello World!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello World!");
 
         cursor.handle_action(&Action::MoveDown, &mut buffer, &Mode::Normal);
 
@@ -482,9 +620,8 @@ mod tests {
 
     #[test]
     fn test_should_not_go_right_when_at_end_of_file() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello World!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello World!");
         cursor.absolute_position = 11;
         cursor.col = 11;
 
@@ -497,9 +634,8 @@ mod tests {
 
     #[test]
     fn test_move_to_top() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Random\nmultiline\nstring\ntext\nbuffer", gap);
+        let mut buffer = Buffer::from_string(1, "Random\nmultiline\nstring\ntext\nbuffer");
 
         for _ in 0..5 {
             cursor.handle_action(&Action::MoveDown, &mut buffer, &Mode::Normal);
@@ -518,9 +654,8 @@ mod tests {
 
     #[test]
     fn test_move_to_bottom() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Random\nmultiline\nstring\ntext\nbuffer", gap);
+        let mut buffer = Buffer::from_string(1, "Random\nmultiline\nstring\ntext\nbuffer");
 
         cursor.handle_action(&Action::MoveToBottom, &mut buffer, &Mode::Normal);
 
@@ -529,11 +664,21 @@ mod tests {
         assert_eq!(cursor.absolute_position, 33);
     }
 
+    #[test]
+    fn test_move_to_bottom_after_final_line_break() {
+        let mut cursor = Cursor::default();
+        let mut buffer = Buffer::from_string(1, "a\nb\n");
+
+        cursor.handle_action(&Action::MoveToBottom, &mut buffer, &Mode::Normal);
+
+        assert_eq!(cursor.row, 1);
+        assert_eq!(cursor.absolute_position, 2);
+    }
+
     #[test]
     fn test_insert_char() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello, World!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
 
         cursor.handle_action(&Action::InsertChar('.'), &mut buffer, &Mode::Normal);
 
@@ -544,9 +689,8 @@ mod tests {
 
     #[test]
     fn test_delete_prev_char() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello, World!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello, World!");
         cursor.col = 3;
         cursor.absolute_position = 3;
 
@@ -558,9 +702,8 @@ mod tests {
 
     #[test]
     fn test_insert_line_below() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld!");
         cursor.col = 3;
         cursor.absolute_position = 3;
 
@@ -573,9 +716,8 @@ mod tests {
 
     #[test]
     fn test_insert_line_above() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld!");
         cursor.col = 3;
         cursor.row = 1;
         cursor.absolute_position = 9;
@@ -587,11 +729,22 @@ mod tests {
         assert_eq!(cursor.row, 1);
     }
 
+    #[test]
+    fn test_move_to() {
+        let mut cursor = Cursor::default();
+        let buffer = Buffer::from_string(1, "Hello\nWorld!");
+
+        cursor.move_to(8, &buffer);
+
+        assert_eq!(cursor.row, 1);
+        assert_eq!(cursor.col, 2);
+        assert_eq!(cursor.absolute_position, 8);
+    }
+
     #[test]
     fn test_insert_line() {
-        let gap = 5;
         let mut cursor = Cursor::default();
-        let mut buffer = Buffer::from_string(1, "Hello\nWorld!", gap);
+        let mut buffer = Buffer::from_string(1, "Hello\nWorld!");
         cursor.col = 3;
         cursor.row = 0;
         cursor.absolute_position = 6;
@@ -1,14 +1,31 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::buffer::encoding::{Encoding, LineEnding};
+use crate::buffer::history::HistoryJump;
+use crate::buffer::text_object::TextObject;
+use crate::commandline::input::Edit;
+use crate::ex::{Address, LineRange, Sort};
+use crate::options::Setting;
+use crate::search::Direction;
+use crate::substitute::{Confirm, Substitute};
 use crate::theme::Style;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Mode {
     Normal,
     Insert,
     Command,
     Search,
+    Visual,
+    VisualLine,
+    VisualBlock,
+}
+
+impl Mode {
+    pub fn is_visual(&self) -> bool {
+        matches!(self, Mode::Visual | Mode::VisualLine | Mode::VisualBlock)
+    }
 }
 
 impl std::fmt::Display for Mode {
@@ -18,6 +35,9 @@ impl std::fmt::Display for Mode {
             Self::Insert => f.write_str("INSERT"),
             Self::Normal => f.write_str("NORMAL"),
             Self::Command => f.write_str("COMMAND"),
+            Self::Visual => f.write_str("VISUAL"),
+            Self::VisualLine => f.write_str("V-LINE"),
+            Self::VisualBlock => f.write_str("V-BLOCK"),
         }
     }
 }
@@ -30,23 +50,174 @@ pub enum KeyAction {
     Complex(HashMap<String, KeyAction>),
 }
 
+/// Edits that act on the text covered by a motion, e.g. `d` in `dw`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+    Lowercase,
+    Uppercase,
+    Format,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Action {
     EnterMode(Mode),
+    // `:q` refuses to quit while a buffer has unsaved changes, `:q!` doesn't
     Quit,
+    ForceQuit,
+    // `:wq` and `:x`, which only writes a modified buffer, the flag forces
+    // the write like `:w!`
+    WriteQuit(bool),
+    Exit(bool),
     Undo,
+    Redo,
+    Earlier(HistoryJump),
+    Later(HistoryJump),
     InsertLine,
     InsertLineBelow,
     InsertLineAbove,
     PasteBelow,
+    PasteAbove,
+    YankLine,
+    YankWord,
+    YankUntilEOL,
+    // waits for the name of a register, selecting it for the next yank,
+    // delete or put in normal mode and inserting its content otherwise
+    AwaitRegister,
+    SelectRegister(char),
+    InsertRegister(char),
+    // `q` waits for the register to record a macro into, or stops recording,
+    // and `@` waits for the register of the macro to play
+    ToggleRecording,
+    StartRecording(char),
+    StoreMacro(char, String),
+    AwaitMacro,
+    PlayMacro(char),
+    // `m` waits for the name of the mark to set, `` ` `` and `'` for the mark
+    // to go to, exactly or to the first non blank char of its line
+    AwaitSetMark,
+    SetMark(char),
+    AwaitMark,
+    AwaitMarkLine,
+    GoToMark(char),
+    GoToMarkLine(char),
+    // walks the jump list, `C-o` and `C-i`
+    JumpBack,
+    JumpForward,
+
+    // waits for a motion to apply the operator to
+    Operator(Operator),
+    OperatorMotion {
+        operator: Operator,
+        motion: Box<Action>,
+        count: usize,
+    },
+    // the motion of a doubled operator such as `dd`, covering `count` lines
+    // starting at the cursor
+    CurrentLine,
+    // replays the last change, `.`
+    RepeatChange,
+    // text objects, the motion of an operator or what visual mode selects
+    Inner(TextObject),
+    Around(TextObject),
+    // visual mode, `gv` and `o`
+    Reselect,
+    SwapSelectionEnds,
+    // `I` and `A` on a selection, replicated on every line of a block
+    VisualInsert,
+    VisualAppend,
+    Repeat(usize, Box<Action>),
+    // a count before a key running several actions, a key entering insert
+    // mode inserts what is typed that many times instead, like `3o`
+    RepeatActions(usize, Vec<Action>),
+    // `/` and `?`, `*` and `#` search the word under the cursor
+    Search(Direction),
+    SearchWord(Direction),
     FindNext,
     FindPrevious,
+    // cursors besides the main one that edits and motions also apply to, at
+    // the next match of the word under the cursor, on every line of a block
+    // or at the matches of the last search in a selection
+    AddCursorAtNextMatch,
+    AddCursorsToSelection,
+    ClearCursors,
+    // `:nohlsearch`, hides the matches until the next search
+    ClearSearch,
+    // `:s`, and the answer to its confirmation prompt with the `c` flag
+    Substitute(Substitute),
+    ConfirmSubstitute(Confirm),
     CenterLine,
     InsertTab,
     InsertChar(char),
     InsertCommand(char),
     ExecuteCommand,
     SaveBuffer,
+    ForceSaveBuffer,
+    ReloadBuffer,
+    // `:e file`, opening it unless a buffer already holds it
+    EditFile(String),
+    // `:b name`, the open buffer whose name contains `name`
+    SwitchBuffer(String),
+    // `:w file` writes a copy of the buffer, `:saveas file` makes it the file
+    // of the buffer, both only overwrite an existing file when forced
+    WriteFile {
+        path: String,
+        force: bool,
+    },
+    SaveAs {
+        path: String,
+        force: bool,
+    },
+    // `:r file`, puts the file below the line
+    ReadFile(String, Address),
+    // `:{line}`
+    GoToLine(Address),
+    // line commands, `:d` and `:y` into a register, `:m` and `:t` below an
+    // address, `:j` joining the lines as they are with `!`, `:>` and `:<`
+    // repeated for more levels
+    DeleteLines(LineRange, Option<char>),
+    YankLines(LineRange, Option<char>),
+    MoveLines(LineRange, Address),
+    CopyLines(LineRange, Address),
+    JoinLines(LineRange, bool),
+    IndentLines {
+        range: LineRange,
+        levels: usize,
+        dedent: bool,
+    },
+    SortLines(LineRange, Sort),
+    // `:normal keys`, typed on every line of the range if there is one
+    Normal(Option<LineRange>, String),
+    // `:g/pattern/command` runs the command on every line of the range that
+    // matches, `:v` on every line that doesn't
+    Global {
+        range: LineRange,
+        pattern: String,
+        invert: bool,
+        command: String,
+    },
+    MergeBuffer,
+    KeepBuffer,
+    RecoverSwap,
+    DiscardSwap,
+    DiffSwap,
+    SetFileFormat(LineEnding),
+    SetFileEncoding(Encoding),
+    // `:set` and `:setlocal`, which leaves the global values alone
+    Set(Vec<Setting>, bool),
+    // `:colorscheme name`, loads the theme file `name`
+    ColorScheme(String),
+    // edits of the command line, `Tab` and `S-Tab` walk the completions of
+    // the word before the cursor, `Up` and `Down` the history
+    EditCommand(Edit),
+    CompleteCommand,
+    CompletePrevious,
+    OlderHistory,
+    NewerHistory,
     DeleteUntilEOL,
     Resize(u16, u16),
 
@@ -73,6 +244,40 @@ pub enum Action {
     Hover,
 }
 
+impl Action {
+    /// the operator and motion that shorthand actions such as `x` or `D` stand
+    /// for, so they support counts and registers like any other operator
+    pub fn as_operator(&self) -> Option<(Operator, Action)> {
+        let (operator, motion) = match self {
+            Action::DeleteCurrentChar => (Operator::Delete, Action::MoveRight),
+            Action::DeleteWord => (Operator::Delete, Action::NextWord),
+            Action::DeleteBack => (Operator::Delete, Action::PreviousWord),
+            Action::DeleteLine => (Operator::Delete, Action::CurrentLine),
+            Action::DeleteUntilEOL => (Operator::Delete, Action::MoveToLineEnd),
+            Action::YankWord => (Operator::Yank, Action::NextWord),
+            Action::YankLine => (Operator::Yank, Action::CurrentLine),
+            Action::YankUntilEOL => (Operator::Yank, Action::MoveToLineEnd),
+            _ => return None,
+        };
+        Some((operator, motion))
+    }
+
+    /// large motions, the place they start from is remembered in the jump list
+    pub fn is_jump(&self) -> bool {
+        matches!(
+            self,
+            Action::MoveToTop
+                | Action::MoveToBottom
+                | Action::FindNext
+                | Action::FindPrevious
+                | Action::GoToDefinition
+                | Action::GoToMark(_)
+                | Action::GoToMarkLine(_)
+                | Action::GoToLine(_)
+        )
+    }
+}
+
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Size {
     pub height: usize,
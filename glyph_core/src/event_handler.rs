@@ -6,4 +6,10 @@ use crossterm::event::Event;
 pub trait EventHandler {
     // TODO: once we start looking into GUI, this would have to be our own event
     fn poll(&mut self, event: &Event, mode: &Mode) -> Option<KeyAction>;
+
+    /// polls a key replayed from a macro rather than typed, so it isn't
+    /// recorded again
+    fn poll_replayed(&mut self, event: &Event, mode: &Mode) -> Option<KeyAction> {
+        self.poll(event, mode)
+    }
 }
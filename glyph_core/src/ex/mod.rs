@@ -0,0 +1,657 @@
+mod range;
+mod sort;
+
+use crate::buffer::history::HistoryJump;
+use crate::editor::Action;
+use crate::options::Setting;
+use crate::register::Registers;
+use crate::substitute::Substitute;
+
+use range::parse_range;
+pub use range::{last_line, Address, LineRange};
+pub use sort::Sort;
+
+/// What follows the name of a command.
+struct Args<'a> {
+    range: Option<LineRange>,
+    bang: bool,
+    text: &'a str,
+}
+
+impl Args<'_> {
+    fn range_or_current(&mut self) -> LineRange {
+        self.range.take().unwrap_or_else(LineRange::current)
+    }
+
+    // `:d x` and `:y x` name a register
+    fn register(&self) -> anyhow::Result<Option<char>> {
+        let mut chars = self.text.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => Ok(None),
+            (Some(name), None) if Registers::is_valid(name) => Ok(Some(name)),
+            _ => Err(anyhow::anyhow!("invalid register: {}", self.text)),
+        }
+    }
+
+    fn address(&self) -> anyhow::Result<Address> {
+        let (range, rest) = parse_range(self.text)?;
+        match (range, rest.trim()) {
+            (Some(range), "") => Ok(range.end),
+            _ => Err(anyhow::anyhow!("invalid address: {}", self.text)),
+        }
+    }
+
+    fn file_name(&self) -> anyhow::Result<String> {
+        match self.text {
+            "" => Err(anyhow::anyhow!("expected a file name")),
+            text => Ok(text.to_string()),
+        }
+    }
+}
+
+/// An ex command, it can be abbreviated down to its first `min` letters like
+/// `:e` for `:edit`. When several commands share an abbreviation, the first
+/// one wins.
+struct Command {
+    name: &'static str,
+    min: usize,
+    range: bool,
+    bang: bool,
+    parse: fn(Args) -> anyhow::Result<Action>,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "substitute",
+        min: 1,
+        range: true,
+        bang: false,
+        parse: |args| {
+            let range = args.range.unwrap_or(LineRange::current());
+            Ok(Action::Substitute(Substitute::parse(range, args.text)?))
+        },
+    },
+    Command {
+        name: "delete",
+        min: 1,
+        range: true,
+        bang: false,
+        parse: |mut args| {
+            let register = args.register()?;
+            Ok(Action::DeleteLines(args.range_or_current(), register))
+        },
+    },
+    Command {
+        name: "yank",
+        min: 1,
+        range: true,
+        bang: false,
+        parse: |mut args| {
+            let register = args.register()?;
+            Ok(Action::YankLines(args.range_or_current(), register))
+        },
+    },
+    Command {
+        name: "move",
+        min: 1,
+        range: true,
+        bang: false,
+        parse: |mut args| {
+            let address = args.address()?;
+            Ok(Action::MoveLines(args.range_or_current(), address))
+        },
+    },
+    Command {
+        name: "t",
+        min: 1,
+        range: true,
+        bang: false,
+        parse: |mut args| {
+            let address = args.address()?;
+            Ok(Action::CopyLines(args.range_or_current(), address))
+        },
+    },
+    Command {
+        name: "copy",
+        min: 2,
+        range: true,
+        bang: false,
+        parse: |mut args| {
+            let address = args.address()?;
+            Ok(Action::CopyLines(args.range_or_current(), address))
+        },
+    },
+    Command {
+        name: "join",
+        min: 1,
+        range: true,
+        bang: true,
+        parse: |mut args| {
+            // a single line is joined with the next one
+            let range = match args.range.take() {
+                Some(range) if range.start != range.end => range,
+                range => {
+                    let start = range.map_or(Address::Current, |range| range.start);
+                    let end = Address::Offset(Box::new(start.clone()), 1);
+                    LineRange { start, end }
+                }
+            };
+            Ok(Action::JoinLines(range, !args.bang))
+        },
+    },
+    Command {
+        name: "sort",
+        min: 3,
+        range: true,
+        bang: true,
+        parse: |mut args| {
+            let range = args.range.take().unwrap_or_else(LineRange::all);
+            Ok(Action::SortLines(range, Sort::parse(args.text, args.bang)?))
+        },
+    },
+    Command {
+        name: "normal",
+        min: 4,
+        range: true,
+        bang: true,
+        parse: |args| match args.text {
+            "" => Err(anyhow::anyhow!("expected keys")),
+            keys => Ok(Action::Normal(args.range, keys.to_string())),
+        },
+    },
+    Command {
+        name: "global",
+        min: 1,
+        range: true,
+        bang: true,
+        parse: |args| parse_global(args, false),
+    },
+    Command {
+        name: "vglobal",
+        min: 1,
+        range: true,
+        bang: false,
+        parse: |args| parse_global(args, true),
+    },
+    Command {
+        name: "write",
+        min: 1,
+        range: false,
+        bang: true,
+        parse: |args| match (args.text, args.bang) {
+            ("", false) => Ok(Action::SaveBuffer),
+            ("", true) => Ok(Action::ForceSaveBuffer),
+            (path, force) => Ok(Action::WriteFile {
+                path: path.to_string(),
+                force,
+            }),
+        },
+    },
+    Command {
+        name: "wq",
+        min: 2,
+        range: false,
+        bang: true,
+        parse: |args| Ok(Action::WriteQuit(args.bang)),
+    },
+    Command {
+        name: "xit",
+        min: 1,
+        range: false,
+        bang: true,
+        parse: |args| Ok(Action::Exit(args.bang)),
+    },
+    Command {
+        name: "exit",
+        min: 3,
+        range: false,
+        bang: true,
+        parse: |args| Ok(Action::Exit(args.bang)),
+    },
+    Command {
+        name: "quit",
+        min: 1,
+        range: false,
+        bang: true,
+        parse: |args| match args.bang {
+            true => Ok(Action::ForceQuit),
+            false => Ok(Action::Quit),
+        },
+    },
+    Command {
+        name: "edit",
+        min: 1,
+        range: false,
+        bang: true,
+        parse: |args| match (args.text, args.bang) {
+            ("", true) => Ok(Action::ReloadBuffer),
+            _ => Ok(Action::EditFile(args.file_name()?)),
+        },
+    },
+    Command {
+        name: "saveas",
+        min: 3,
+        range: false,
+        bang: true,
+        parse: |args| {
+            Ok(Action::SaveAs {
+                path: args.file_name()?,
+                force: args.bang,
+            })
+        },
+    },
+    Command {
+        name: "buffer",
+        min: 1,
+        range: false,
+        bang: false,
+        parse: |args| match args.text {
+            "" => Err(anyhow::anyhow!("expected a buffer name")),
+            name => Ok(Action::SwitchBuffer(name.to_string())),
+        },
+    },
+    Command {
+        name: "read",
+        min: 1,
+        range: true,
+        bang: false,
+        parse: |args| {
+            let path = args.file_name()?;
+            let line = args.range.map_or(Address::Current, |range| range.end);
+            Ok(Action::ReadFile(path, line))
+        },
+    },
+    Command {
+        name: "earlier",
+        min: 2,
+        range: false,
+        bang: false,
+        parse: |args| Ok(Action::Earlier(parse_history_jump(args.text)?)),
+    },
+    Command {
+        name: "later",
+        min: 3,
+        range: false,
+        bang: false,
+        parse: |args| Ok(Action::Later(parse_history_jump(args.text)?)),
+    },
+    Command {
+        name: "set",
+        min: 2,
+        range: false,
+        bang: false,
+        parse: |args| parse_set(args.text, false),
+    },
+    Command {
+        name: "setlocal",
+        min: 4,
+        range: false,
+        bang: false,
+        parse: |args| parse_set(args.text, true),
+    },
+    Command {
+        name: "colorscheme",
+        min: 4,
+        range: false,
+        bang: false,
+        parse: |args| match args.text {
+            "" => Err(anyhow::anyhow!("expected a theme name")),
+            name => Ok(Action::ColorScheme(name.to_string())),
+        },
+    },
+    Command {
+        name: "nohlsearch",
+        min: 3,
+        range: false,
+        bang: false,
+        parse: |_| Ok(Action::ClearSearch),
+    },
+];
+
+pub fn parse(command: &str) -> anyhow::Result<Action> {
+    let (range, name, rest) = split(command.trim())?;
+    let (bang, text) = match rest.strip_prefix('!') {
+        Some(text) => (true, text.trim()),
+        None => (false, rest.trim()),
+    };
+
+    if name.is_empty() {
+        if let Some(action) = parse_indent(range.clone(), rest) {
+            return Ok(action);
+        }
+    }
+    // `:{range}` alone goes to the last line of the range
+    if let (Some(range), "", false, "") = (&range, name, bang, text) {
+        return Ok(Action::GoToLine(range.end.clone()));
+    }
+    let found =
+        find(name).ok_or_else(|| anyhow::anyhow!("not an editor command: {}", command.trim()))?;
+    if range.is_some() && !found.range {
+        anyhow::bail!("no range allowed: {}", command.trim());
+    }
+    if bang && !found.bang {
+        anyhow::bail!("no ! allowed: {}", command.trim());
+    }
+    (found.parse)(Args { range, bang, text })
+}
+
+/// the range of `command`, the name that follows it and the rest
+pub(crate) fn split(command: &str) -> anyhow::Result<(Option<LineRange>, &str, &str)> {
+    let (range, rest) = parse_range(command)?;
+    // the name is made of letters, `s/a/b/` has no space after it
+    let name_len = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    Ok((range, &rest[..name_len], &rest[name_len..]))
+}
+
+fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .iter()
+        .find(|command| name.len() >= command.min && command.name.starts_with(name))
+}
+
+/// the full name of the command `name` stands for
+pub(crate) fn full_name(name: &str) -> Option<&'static str> {
+    find(name).map(|command| command.name)
+}
+
+/// the names of the commands starting with `prefix`
+pub(crate) fn complete(prefix: &str) -> Vec<&'static str> {
+    COMMANDS
+        .iter()
+        .map(|command| command.name)
+        .filter(|name| name.starts_with(prefix))
+        .collect()
+}
+
+// `:>` and `:<` aren't letters, each repeat is another level
+fn parse_indent(range: Option<LineRange>, rest: &str) -> Option<Action> {
+    let dedent = match rest.chars().next()? {
+        '>' => false,
+        '<' => true,
+        _ => return None,
+    };
+    let marker = rest.chars().next()?;
+    let levels = rest.chars().take_while(|c| *c == marker).count();
+    if !rest[levels..].trim().is_empty() {
+        return None;
+    }
+    Some(Action::IndentLines {
+        range: range.unwrap_or_else(LineRange::current),
+        levels,
+        dedent,
+    })
+}
+
+// `:g/pattern/command`, any delimiter works like for `:s` and `:g!` is `:v`
+fn parse_global(args: Args, invert: bool) -> anyhow::Result<Action> {
+    let mut chars = args.text.chars();
+    let delimiter = chars
+        .next()
+        .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && *c != '\\')
+        .ok_or_else(|| anyhow::anyhow!("expected /pattern/: {}", args.text))?;
+    let rest = chars.as_str();
+    let (pattern, command) = rest.split_once(delimiter).unwrap_or((rest, ""));
+    if pattern.is_empty() || command.trim().is_empty() {
+        anyhow::bail!("expected /pattern/command: {}", args.text);
+    }
+    Ok(Action::Global {
+        range: args.range.unwrap_or_else(LineRange::all),
+        pattern: pattern.to_string(),
+        invert: invert || args.bang,
+        command: command.trim().to_string(),
+    })
+}
+
+// the file format and encoding belong to the buffer, they aren't options
+fn parse_set(args: &str, local: bool) -> anyhow::Result<Action> {
+    if let Some((option, value)) = args.split_once('=') {
+        match option.trim() {
+            "fileformat" | "ff" => return Ok(Action::SetFileFormat(value.trim().parse()?)),
+            "fileencoding" | "fenc" => return Ok(Action::SetFileEncoding(value.trim().parse()?)),
+            _ => (),
+        }
+    }
+    let settings = args
+        .split_whitespace()
+        .map(Setting::parse)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if settings.is_empty() {
+        anyhow::bail!("expected an option");
+    }
+    Ok(Action::Set(settings, local))
+}
+
+fn parse_history_jump(args: &str) -> anyhow::Result<HistoryJump> {
+    if args.is_empty() {
+        return Ok(HistoryJump::Steps(1));
+    }
+    let unit = args.chars().last().unwrap();
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 60 * 60 * 24,
+        _ => {
+            let steps = args
+                .parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("invalid argument: {args}"))?;
+            return Ok(HistoryJump::Steps(steps));
+        }
+    };
+    let amount = args[..args.len() - 1]
+        .parse::<u64>()
+        .map_err(|_| anyhow::anyhow!("invalid argument: {args}"))?;
+    Ok(HistoryJump::Seconds(amount * multiplier))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::encoding::{Encoding, LineEnding};
+
+    #[test]
+    fn test_parse_earlier_and_later() {
+        assert!(matches!(
+            parse("earlier").unwrap(),
+            Action::Earlier(HistoryJump::Steps(1))
+        ));
+        assert!(matches!(
+            parse("earlier 3").unwrap(),
+            Action::Earlier(HistoryJump::Steps(3))
+        ));
+        assert!(matches!(
+            parse("earlier 5m").unwrap(),
+            Action::Earlier(HistoryJump::Seconds(300))
+        ));
+        assert!(matches!(
+            parse(" later 2h ").unwrap(),
+            Action::Later(HistoryJump::Seconds(7200))
+        ));
+    }
+
+    #[test]
+    fn test_parse_set() {
+        assert!(matches!(
+            parse("set fileformat=dos").unwrap(),
+            Action::SetFileFormat(LineEnding::Crlf)
+        ));
+        assert!(matches!(
+            parse("se fenc=utf-16le").unwrap(),
+            Action::SetFileEncoding(Encoding::Utf16Le)
+        ));
+        assert!(parse("set ff=amiga").is_err());
+        assert!(parse("set nonsense=1").is_err());
+        assert!(parse("set ff").is_err());
+        assert!(parse("set").is_err());
+        assert!(matches!(
+            parse("set nu ts=2").unwrap(),
+            Action::Set(settings, false) if settings.len() == 2 && settings[1].name == "tabstop"
+        ));
+        assert!(matches!(parse("setl rnu").unwrap(), Action::Set(_, true)));
+    }
+
+    #[test]
+    fn test_parse_write_and_reload() {
+        assert!(matches!(parse("w").unwrap(), Action::SaveBuffer));
+        assert!(matches!(parse("write!").unwrap(), Action::ForceSaveBuffer));
+        assert!(matches!(parse("e!").unwrap(), Action::ReloadBuffer));
+        assert!(
+            matches!(parse("e src/main.rs").unwrap(), Action::EditFile(f) if f == "src/main.rs")
+        );
+        assert!(parse("edit").is_err());
+        assert!(matches!(parse("noh").unwrap(), Action::ClearSearch));
+        assert!(matches!(parse("b main").unwrap(), Action::SwitchBuffer(b) if b == "main"));
+        assert!(matches!(parse("co 3").unwrap(), Action::CopyLines(..)));
+        assert!(matches!(parse("colo dark").unwrap(), Action::ColorScheme(t) if t == "dark"));
+        assert!(parse("colorscheme").is_err());
+    }
+
+    #[test]
+    fn test_dispatch_abbreviations() {
+        assert!(matches!(parse("q").unwrap(), Action::Quit));
+        assert!(matches!(parse("qui!").unwrap(), Action::ForceQuit));
+        assert!(matches!(parse("wq").unwrap(), Action::WriteQuit(false)));
+        assert!(matches!(parse("x!").unwrap(), Action::Exit(true)));
+        assert!(matches!(parse("exi").unwrap(), Action::Exit(false)));
+        assert!(matches!(
+            parse("ea 2").unwrap(),
+            Action::Earlier(HistoryJump::Steps(2))
+        ));
+        assert!(
+            matches!(parse("w! out.txt").unwrap(), Action::WriteFile { path, force: true } if path == "out.txt")
+        );
+        assert!(
+            matches!(parse("sav new.txt").unwrap(), Action::SaveAs { path, force: false } if path == "new.txt")
+        );
+        assert!(
+            matches!(parse("$r notes").unwrap(), Action::ReadFile(path, Address::Last) if path == "notes")
+        );
+        assert!(parse("sa x").is_err());
+        assert!(parse("noh!").is_err());
+        assert!(parse("r").is_err());
+    }
+
+    #[test]
+    fn test_parse_line_numbers() {
+        assert!(matches!(
+            parse("42").unwrap(),
+            Action::GoToLine(Address::Line(42))
+        ));
+        assert!(matches!(
+            parse("$").unwrap(),
+            Action::GoToLine(Address::Last)
+        ));
+        assert!(matches!(
+            parse("1,7").unwrap(),
+            Action::GoToLine(Address::Line(7))
+        ));
+    }
+
+    #[test]
+    fn test_parse_substitute_ranges() {
+        let range = |command: &str| match parse(command).unwrap() {
+            Action::Substitute(substitute) => substitute.range,
+            action => panic!("not a substitution: {action:?}"),
+        };
+
+        assert_eq!(range("s/a/b/"), LineRange::current());
+        assert_eq!(range("%s/a/b/g"), LineRange::all());
+        assert_eq!(
+            range("2,$s#a#b#"),
+            LineRange {
+                start: Address::Line(2),
+                end: Address::Last
+            }
+        );
+        assert_eq!(
+            range(".substitute/a/b/"),
+            LineRange {
+                start: Address::Current,
+                end: Address::Current
+            }
+        );
+        assert!(parse("1,s/a/b/").is_err());
+        assert!(parse("%w").is_err());
+    }
+
+    #[test]
+    fn test_parse_line_commands() {
+        assert!(matches!(
+            parse("d").unwrap(),
+            Action::DeleteLines(range, None) if range == LineRange::current()
+        ));
+        assert!(matches!(
+            parse("%y a").unwrap(),
+            Action::YankLines(_, Some('a'))
+        ));
+        assert!(matches!(
+            parse("1,3m$").unwrap(),
+            Action::MoveLines(_, Address::Last)
+        ));
+        assert!(matches!(
+            parse("t.").unwrap(),
+            Action::CopyLines(_, Address::Current)
+        ));
+        assert!(matches!(
+            parse("co 0").unwrap(),
+            Action::CopyLines(_, Address::Line(0))
+        ));
+        assert!(matches!(
+            parse("'<,'>>>").unwrap(),
+            Action::IndentLines { levels: 2, dedent: false, range } if range.start == Address::Mark('<')
+        ));
+        assert!(matches!(
+            parse("<").unwrap(),
+            Action::IndentLines {
+                levels: 1,
+                dedent: true,
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse("j!").unwrap(),
+            Action::JoinLines(range, false) if range.end == Address::Offset(Box::new(Address::Current), 1)
+        ));
+        assert!(matches!(
+            parse("sort! u").unwrap(),
+            Action::SortLines(range, Sort { reverse: true, unique: true, .. }) if range == LineRange::all()
+        ));
+        assert!(parse("m").is_err());
+        assert!(parse("d xy").is_err());
+        assert!(parse(">x").is_err());
+    }
+
+    #[test]
+    fn test_parse_global_and_normal() {
+        assert!(matches!(
+            parse("g/a b/s/a/c/").unwrap(),
+            Action::Global { pattern, invert: false, command, .. } if pattern == "a b" && command == "s/a/c/"
+        ));
+        assert!(matches!(
+            parse("g!#x#d").unwrap(),
+            Action::Global { invert: true, .. }
+        ));
+        assert!(matches!(
+            parse("1,5v/x/normal Ay").unwrap(),
+            Action::Global { invert: true, range, .. } if range.start == Address::Line(1)
+        ));
+        assert!(matches!(
+            parse("%norm dw").unwrap(),
+            Action::Normal(Some(_), keys) if keys == "dw"
+        ));
+        assert!(matches!(
+            parse("normal! x").unwrap(),
+            Action::Normal(None, _)
+        ));
+        assert!(parse("g/x/").is_err());
+        assert!(parse("normal").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("earlier 5x").is_err());
+        assert!(parse("nonsense").is_err());
+    }
+}
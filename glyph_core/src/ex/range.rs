@@ -0,0 +1,270 @@
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::marks::Marks;
+use crate::buffer::Buffer;
+use crate::search::{Direction, Search};
+
+/// A line of an ex range:
+///
+/// - `.` the cursor line, `$` the last one or a line number
+/// - `'a` the line of a mark
+/// - `/pattern/` the next line matching the pattern, `?pattern?` the previous
+///   one
+/// - any of those followed by `+N` or `-N`, a lone offset is relative to the
+///   cursor line
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Address {
+    Current,
+    Last,
+    Line(usize),
+    Mark(char),
+    Search(String, Direction),
+    Offset(Box<Address>, isize),
+}
+
+/// The lines an ex command acts on, e.g. `%` or `3,$`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl LineRange {
+    pub fn current() -> Self {
+        Self {
+            start: Address::Current,
+            end: Address::Current,
+        }
+    }
+
+    pub fn all() -> Self {
+        Self {
+            start: Address::Line(1),
+            end: Address::Last,
+        }
+    }
+
+    /// the lines of `buffer` in the range, `current` being the cursor line,
+    /// a backward range is swapped
+    pub fn resolve(
+        &self,
+        buffer: &Buffer,
+        current: usize,
+    ) -> anyhow::Result<RangeInclusive<usize>> {
+        let (start, end) = (
+            self.start.line(buffer, current)?,
+            self.end.line(buffer, current)?,
+        );
+        Ok(start.min(end)..=start.max(end))
+    }
+}
+
+impl Address {
+    /// the line of `buffer` the address points at, `current` being the
+    /// cursor line
+    pub fn line(&self, buffer: &Buffer, current: usize) -> anyhow::Result<usize> {
+        let last = last_line(buffer);
+        let text = &buffer.buffer;
+        match self {
+            Address::Current => Ok(current),
+            Address::Last => Ok(last),
+            Address::Line(line) if *line <= last + 1 => Ok(line.saturating_sub(1)),
+            Address::Line(_) => Err(anyhow::anyhow!("invalid range")),
+            Address::Mark(name) => {
+                let pos = buffer
+                    .mark(*name, false)
+                    .ok_or_else(|| anyhow::anyhow!("mark not set: {name}"))?;
+                Ok(text.char_to_line(pos))
+            }
+            Address::Search(pattern, direction) => {
+                if pattern.is_empty() {
+                    anyhow::bail!("no previous search");
+                }
+                let search = Search::new(pattern)?;
+                // the search starts on the line after the cursor line, or
+                // before it going backward
+                let from = match direction {
+                    Direction::Forward => text.line_to_char(current + 1).saturating_sub(1),
+                    Direction::Backward => text.line_to_char(current),
+                };
+                let (pos, _) = search
+                    .find(buffer, from, *direction)
+                    .ok_or_else(|| anyhow::anyhow!("pattern not found: {pattern}"))?;
+                Ok(text.char_to_line(pos))
+            }
+            Address::Offset(address, offset) => {
+                let line = address.line(buffer, current)?;
+                line.checked_add_signed(*offset)
+                    .filter(|line| *line <= last)
+                    .ok_or_else(|| anyhow::anyhow!("invalid range"))
+            }
+        }
+    }
+
+    /// how many lines come before the place right below the address, `0`
+    /// being above the first line, for `:m` and `:t`
+    pub fn below(&self, buffer: &Buffer, current: usize) -> anyhow::Result<usize> {
+        match self {
+            Address::Line(0) => Ok(0),
+            address => Ok(address.line(buffer, current)? + 1),
+        }
+    }
+}
+
+/// the index of the last line of `buffer`, a trailing line break doesn't
+/// start another line
+pub fn last_line(buffer: &Buffer) -> usize {
+    let text = &buffer.buffer;
+    match text.len_chars() > 0 && text.char(text.len_chars() - 1) == '\n' {
+        true => text.len_lines().saturating_sub(2),
+        false => text.len_lines() - 1,
+    }
+}
+
+/// the range the command starts with, if any, and the rest of it
+pub(super) fn parse_range(command: &str) -> anyhow::Result<(Option<LineRange>, &str)> {
+    if let Some(command) = command.strip_prefix('%') {
+        return Ok((Some(LineRange::all()), command));
+    }
+    let Some((start, command)) = parse_address(command)? else {
+        return Ok((None, command));
+    };
+    let Some(command) = command.strip_prefix(',') else {
+        let end = start.clone();
+        return Ok((Some(LineRange { start, end }), command));
+    };
+    match parse_address(command)? {
+        Some((end, command)) => Ok((Some(LineRange { start, end }), command)),
+        None => Err(anyhow::anyhow!("invalid range: {command}")),
+    }
+}
+
+/// the address `text` starts with, if any, and the rest of it
+pub(super) fn parse_address(text: &str) -> anyhow::Result<Option<(Address, &str)>> {
+    let mut chars = text.chars();
+    let (address, mut rest) = match chars.next() {
+        Some('.') => (Address::Current, chars.as_str()),
+        Some('$') => (Address::Last, chars.as_str()),
+        Some('0'..='9') => {
+            let (line, rest) = parse_number(text);
+            let line = line.ok_or_else(|| anyhow::anyhow!("invalid range: {text}"))?;
+            (Address::Line(line), rest)
+        }
+        Some('\'') => match chars.next() {
+            Some(name) if Marks::is_valid(name) => (Address::Mark(name), chars.as_str()),
+            _ => anyhow::bail!("invalid mark: {text}"),
+        },
+        Some(delimiter @ ('/' | '?')) => {
+            let rest = chars.as_str();
+            // the closing delimiter can be left out at the end
+            let (pattern, rest) = match rest.find(delimiter) {
+                Some(end) => (&rest[..end], &rest[end + 1..]),
+                None => (rest, ""),
+            };
+            let direction = match delimiter {
+                '/' => Direction::Forward,
+                _ => Direction::Backward,
+            };
+            (Address::Search(pattern.to_string(), direction), rest)
+        }
+        Some('+' | '-') => (Address::Current, text),
+        _ => return Ok(None),
+    };
+
+    let mut offset = 0isize;
+    while let Some(sign @ ('+' | '-')) = rest.chars().next() {
+        let (n, after) = parse_number(&rest[1..]);
+        let n = n.unwrap_or(1) as isize;
+        offset += if sign == '+' { n } else { -n };
+        rest = after;
+    }
+    match offset {
+        0 => Ok(Some((address, rest))),
+        offset => Ok(Some((Address::Offset(Box::new(address), offset), rest))),
+    }
+}
+
+// the number `text` starts with, if any, and the rest of it
+fn parse_number(text: &str) -> (Option<usize>, &str) {
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    (text[..digits].parse().ok(), &text[digits..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(address: &str, buffer: &Buffer, current: usize) -> anyhow::Result<usize> {
+        let (address, rest) = parse_address(address)?.unwrap();
+        assert_eq!(rest, "");
+        address.line(buffer, current)
+    }
+
+    #[test]
+    fn test_resolve_range() {
+        let buffer = Buffer::from_string(1, "one\ntwo\nthree\n");
+
+        assert_eq!(LineRange::all().resolve(&buffer, 1).unwrap(), 0..=2);
+        assert_eq!(LineRange::current().resolve(&buffer, 1).unwrap(), 1..=1);
+        let backward = LineRange {
+            start: Address::Last,
+            end: Address::Line(2),
+        };
+        assert_eq!(backward.resolve(&buffer, 0).unwrap(), 1..=2);
+        let past_the_end = LineRange {
+            start: Address::Line(1),
+            end: Address::Line(5),
+        };
+        assert!(past_the_end.resolve(&buffer, 0).is_err());
+    }
+
+    #[test]
+    fn test_addresses() {
+        let mut buffer = Buffer::from_string(1, "one\ntwo\nthree\nfour\n");
+        buffer.marks.set('a', 9);
+
+        assert_eq!(line("'a", &buffer, 0).unwrap(), 2);
+        assert_eq!(line("'a-1", &buffer, 0).unwrap(), 1);
+        assert_eq!(line("+2", &buffer, 1).unwrap(), 3);
+        assert_eq!(line(".-", &buffer, 1).unwrap(), 0);
+        assert_eq!(line("$-1+0", &buffer, 0).unwrap(), 2);
+        assert!(line("$+1", &buffer, 0).is_err());
+        assert!(line("'b", &buffer, 0).is_err());
+    }
+
+    #[test]
+    fn test_search_addresses() {
+        let buffer = Buffer::from_string(1, "one\ntwo\nthree\none\n");
+
+        assert_eq!(line("/one/", &buffer, 0).unwrap(), 3);
+        // searches wrap around the buffer
+        assert_eq!(line("/t", &buffer, 3).unwrap(), 1);
+        assert_eq!(line("?one?", &buffer, 3).unwrap(), 0);
+        assert_eq!(line("/three/+1", &buffer, 0).unwrap(), 3);
+        assert!(line("/five/", &buffer, 0).is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let (range, rest) = parse_range("'<,'>s/a/b/").unwrap();
+        assert_eq!(
+            range,
+            Some(LineRange {
+                start: Address::Mark('<'),
+                end: Address::Mark('>'),
+            })
+        );
+        assert_eq!(rest, "s/a/b/");
+        let (range, rest) = parse_range("/a/,/b/d").unwrap();
+        assert_eq!(
+            range.unwrap().end,
+            Address::Search("b".into(), Direction::Forward)
+        );
+        assert_eq!(rest, "d");
+        assert_eq!(parse_range("d").unwrap(), (None, "d"));
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use crate::search::Search;
+
+/// The options of `:sort`:
+///
+/// - `!` reverses the order
+/// - `u` keeps only the first of lines that compare equal
+/// - `n` sorts on the first number of each line, lines without one come first
+/// - `i` ignores case
+/// - `/pattern/` sorts on what follows the first match of the pattern, or on
+///   the match itself with `r`, lines without a match come first
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Sort {
+    pub reverse: bool,
+    pub unique: bool,
+    pub numeric: bool,
+    pub ignore_case: bool,
+    pub pattern: Option<String>,
+    pub on_match: bool,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    Missing,
+    Number(i64),
+    Text(String),
+}
+
+impl Sort {
+    pub fn parse(args: &str, reverse: bool) -> anyhow::Result<Self> {
+        let mut sort = Self {
+            reverse,
+            ..Self::default()
+        };
+        let mut chars = args.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                'u' => sort.unique = true,
+                'n' => sort.numeric = true,
+                'i' => sort.ignore_case = true,
+                'r' => sort.on_match = true,
+                '/' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('/')
+                        .ok_or_else(|| anyhow::anyhow!("missing / after the pattern: {args}"))?;
+                    sort.pattern = Some(rest[..end].to_string());
+                    chars = rest[end + 1..].chars();
+                }
+                c if c.is_whitespace() => {}
+                c => anyhow::bail!("invalid argument: {c}"),
+            }
+        }
+        Ok(sort)
+    }
+
+    /// sorts `lines`, the sort is stable so equal lines keep their order
+    pub fn sort(&self, lines: Vec<String>) -> anyhow::Result<Vec<String>> {
+        let search = match self.pattern.as_deref() {
+            Some("") => anyhow::bail!("no previous search"),
+            Some(pattern) => Some(Search::new(pattern)?),
+            None => None,
+        };
+        let mut keyed = lines
+            .into_iter()
+            .map(|line| (self.key(&line, search.as_ref()), line))
+            .collect::<Vec<_>>();
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        if self.unique {
+            keyed.dedup_by(|(a, _), (b, _)| a == b);
+        }
+        let mut lines = keyed.into_iter().map(|(_, line)| line).collect::<Vec<_>>();
+        if self.reverse {
+            lines.reverse();
+        }
+        Ok(lines)
+    }
+
+    fn key(&self, line: &str, search: Option<&Search>) -> Key {
+        let text = match search {
+            Some(search) => match search.regex().find(line) {
+                Some(found) if self.on_match => found.as_str(),
+                Some(found) => &line[found.end()..],
+                None => return Key::Missing,
+            },
+            None => line,
+        };
+        if self.numeric {
+            return number(text).map_or(Key::Missing, Key::Number);
+        }
+        match self.ignore_case {
+            true => Key::Text(text.to_lowercase()),
+            false => Key::Text(text.to_string()),
+        }
+    }
+}
+
+// the first decimal number of `text`, with its minus sign
+fn number(text: &str) -> Option<i64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let digits = text[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(&text[start..], |end| &text[start..start + end]);
+    let number = digits.parse::<i64>().ok()?;
+    match text[..start].ends_with('-') {
+        true => Some(-number),
+        false => Some(number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(args: &str, lines: &[&str]) -> Vec<String> {
+        let lines = lines.iter().map(|line| line.to_string()).collect();
+        Sort::parse(args, false).unwrap().sort(lines).unwrap()
+    }
+
+    #[test]
+    fn test_sort_options() {
+        assert_eq!(sort("", &["b", "B", "a"]), ["B", "a", "b"]);
+        assert_eq!(sort("i", &["b", "B", "a"]), ["a", "b", "B"]);
+        assert_eq!(sort("u", &["b", "a", "b"]), ["a", "b"]);
+        assert_eq!(
+            sort("n", &["x10", "x-2", "y", "x9"]),
+            ["y", "x-2", "x9", "x10"]
+        );
+        assert_eq!(sort("/,/", &["a,2", "b,1", "c"]), ["c", "b,1", "a,2"]);
+        assert_eq!(sort("/\\d/ r", &["b2", "a3", "c1"]), ["c1", "b2", "a3"]);
+        assert!(Sort::parse("x", false).is_err());
+        assert!(Sort::parse("/a", false).is_err());
+    }
+
+    #[test]
+    fn test_reverse_sort() {
+        let lines = vec!["a".to_string(), "c".to_string(), "b".to_string()];
+        let sort = Sort::parse("", true).unwrap();
+
+        assert_eq!(sort.sort(lines).unwrap(), ["c", "b", "a"]);
+    }
+}
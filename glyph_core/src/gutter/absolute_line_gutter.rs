@@ -1,4 +1,4 @@
-use crate::gutter::Gutter;
+use crate::gutter::{gutter_line, Gutter};
 
 #[derive(Debug, Clone)]
 pub struct AbsoluteLineGutter {
@@ -29,17 +29,12 @@ impl Gutter for AbsoluteLineGutter {
 
         for _ in 0..total_lines {
             scroll += 1;
-            let mut line = scroll.to_string();
-            line = " ".repeat(self.offset - 1 - line.len()) + &line;
-            line.push(' ');
-            lines.push(line);
+            lines.push(gutter_line(&scroll.to_string(), self.offset));
         }
 
         if total_lines < height {
             for _ in total_lines..height {
-                let mut line = " ".repeat(self.offset - 2);
-                line.push(self.empty_line_char);
-                line.push(' ');
+                let line = gutter_line(&self.empty_line_char.to_string(), self.offset);
                 lines.push(line);
             }
         }
@@ -64,4 +59,13 @@ mod tests {
         let absolute_gutter = AbsoluteLineGutter::new('~', 6);
         absolute_gutter.get_lines(400, 0, 103, 10);
     }
+
+    #[test]
+    fn test_draw_narrower_than_line_numbers() {
+        let absolute_gutter = AbsoluteLineGutter::new('~', 3);
+
+        let lines = absolute_gutter.get_lines(2, 0, 99, 3);
+
+        assert_eq!(lines, vec!["100", "101", " ~ "]);
+    }
 }
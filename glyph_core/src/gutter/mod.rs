@@ -2,6 +2,11 @@ pub mod absolute_line_gutter;
 pub mod noop_line_gutter;
 pub mod relative_line_gutter;
 
+use crate::config::LineNumbers;
+use absolute_line_gutter::AbsoluteLineGutter;
+use noop_line_gutter::NoopLineGutter;
+use relative_line_gutter::RelativeLineGutter;
+
 pub trait Gutter: std::fmt::Debug {
     fn get_lines(
         &self,
@@ -11,3 +16,28 @@ pub trait Gutter: std::fmt::Debug {
         height: usize,
     ) -> Vec<String>;
 }
+
+// `text` right aligned in a gutter `width` columns wide whose last column is
+// blank, cut when the window is too narrow for it
+fn gutter_line(text: &str, width: usize) -> String {
+    let line = format!("{text:>0$} ", width.saturating_sub(1));
+    line.chars().take(width).collect()
+}
+
+/// the gutter showing `line_numbers` in `width` columns, the lines past the
+/// end of the buffer are marked with `empty_line_char`
+pub fn line_gutter(
+    line_numbers: LineNumbers,
+    empty_line_char: char,
+    width: usize,
+) -> Box<dyn Gutter> {
+    match line_numbers {
+        LineNumbers::Absolute => Box::new(AbsoluteLineGutter::new(empty_line_char, width)),
+        LineNumbers::Relative | LineNumbers::RelativeNumbered => Box::new(RelativeLineGutter::new(
+            empty_line_char,
+            width,
+            line_numbers,
+        )),
+        LineNumbers::None => Box::<NoopLineGutter>::default(),
+    }
+}
@@ -1,5 +1,5 @@
 use crate::config::LineNumbers;
-use crate::gutter::Gutter;
+use crate::gutter::{gutter_line, Gutter};
 
 #[derive(Debug)]
 pub struct RelativeLineGutter {
@@ -42,16 +42,11 @@ impl Gutter for RelativeLineGutter {
                 }
             }
 
-            line = " ".repeat(self.offset - 1 - line.len()) + &line;
-            line.push(' ');
-            lines.push(line);
+            lines.push(gutter_line(&line, self.offset));
         }
 
         if total_lines < height {
-            let mut line = " ".repeat(self.offset - 2);
-            line.push(self.empty_line_char);
-            line.push(' ');
-            lines.push(line);
+            lines.push(gutter_line(&self.empty_line_char.to_string(), self.offset));
         }
 
         lines
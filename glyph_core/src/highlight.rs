@@ -1,3 +1,4 @@
+use ropey::Rope;
 use tree_sitter::{Parser, Query, QueryCursor};
 use tree_sitter_rust::{language, HIGHLIGHT_QUERY};
 
@@ -9,6 +10,7 @@ pub struct Highlight<'a> {
     theme: &'a Theme,
 }
 
+/// A styled range of the highlighted text, `start` and `end` are char offsets.
 #[derive(Debug)]
 pub struct ColorInfo<'a> {
     pub start: usize,
@@ -30,8 +32,14 @@ impl<'a> Highlight<'a> {
         }
     }
 
-    pub fn colors(&mut self, buffer: &str) -> Vec<ColorInfo> {
+    pub fn set_theme(&mut self, theme: &'a Theme) {
+        self.theme = theme;
+    }
+
+    pub fn colors(&mut self, buffer: &str) -> Vec<ColorInfo<'_>> {
         let tree = self.parser.parse(buffer, None).unwrap();
+        // tree-sitter works with byte offsets
+        let text = Rope::from_str(buffer);
 
         let mut colors = Vec::new();
         let mut cursor = QueryCursor::new();
@@ -40,8 +48,8 @@ impl<'a> Highlight<'a> {
         for m in matches {
             for cap in m.captures {
                 let node = cap.node;
-                let start = node.start_byte();
-                let end = node.end_byte();
+                let start = text.byte_to_char(node.start_byte());
+                let end = text.byte_to_char(node.end_byte());
                 let capture_name = self.query.capture_names()[cap.index as usize].as_str();
                 if let Some(style) = self.theme.tokens.get(capture_name) {
                     colors.push(ColorInfo { start, end, style });
@@ -67,4 +75,16 @@ mod tests {
 
         assert_eq!(colors.len(), 12);
     }
+
+    #[test]
+    fn test_colors_use_char_offsets() {
+        let theme = Theme::default();
+        let mut hl = Highlight::new(&theme);
+
+        let buffer = "let é = \"😀\"; fn main() {}";
+        let colors = hl.colors(buffer);
+
+        let keyword = colors.iter().find(|c| c.start == 13).unwrap();
+        assert_eq!(keyword.end, 15);
+    }
 }
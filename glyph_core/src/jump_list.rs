@@ -0,0 +1,103 @@
+use crate::buffer::marks::Anchor;
+
+// how many jumps are remembered, like vim
+const MAX_JUMPS: usize = 100;
+
+/// A place jumped from, in the buffer with id `buffer`.
+#[derive(Debug, Clone)]
+pub struct Jump {
+    pub buffer: usize,
+    pub anchor: Anchor,
+}
+
+impl Jump {
+    fn same_place(&self, other: &Jump) -> bool {
+        self.buffer == other.buffer && self.anchor.get() == other.anchor.get()
+    }
+}
+
+/// Where the large motions of a window started, walked with `C-o` and `C-i`.
+/// Like in vim, jumping from the middle of the list keeps the newer jumps.
+#[derive(Debug, Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    // index of the jump we are at, the length of the list when we are past
+    // the newest one
+    current: usize,
+}
+
+impl JumpList {
+    /// remembers `jump` as the newest one, forgetting older jumps to the same
+    /// place
+    pub fn push(&mut self, jump: Jump) {
+        self.jumps.retain(|old| !old.same_place(&jump));
+        self.jumps.push(jump);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.current = self.jumps.len();
+    }
+
+    /// goes to the jump before the current one, `from` is where the cursor is
+    /// and is remembered the first time so `C-i` can come back to it
+    pub fn back(&mut self, from: Jump) -> Option<&Jump> {
+        if self.current == self.jumps.len() {
+            self.push(from);
+            self.current = self.jumps.len() - 1;
+        }
+        self.current = self.current.checked_sub(1)?;
+        self.jumps.get(self.current)
+    }
+
+    pub fn forward(&mut self) -> Option<&Jump> {
+        if self.current + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.current += 1;
+        self.jumps.get(self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn jump(buffer: usize, pos: usize) -> Jump {
+        Jump {
+            buffer,
+            anchor: Rc::new(Cell::new(pos)),
+        }
+    }
+
+    fn position(jump: Option<&Jump>) -> Option<(usize, usize)> {
+        jump.map(|jump| (jump.buffer, jump.anchor.get()))
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut jumps = JumpList::default();
+        jumps.push(jump(1, 10));
+        jumps.push(jump(2, 20));
+
+        assert_eq!(position(jumps.back(jump(1, 30))), Some((2, 20)));
+        assert_eq!(position(jumps.back(jump(2, 20))), Some((1, 10)));
+        assert_eq!(position(jumps.back(jump(1, 10))), None);
+        assert_eq!(position(jumps.forward()), Some((2, 20)));
+        assert_eq!(position(jumps.forward()), Some((1, 30)));
+        assert_eq!(position(jumps.forward()), None);
+    }
+
+    #[test]
+    fn test_jumping_to_the_same_place_again_moves_it_last() {
+        let mut jumps = JumpList::default();
+        jumps.push(jump(1, 10));
+        jumps.push(jump(1, 20));
+        jumps.push(jump(1, 10));
+
+        assert_eq!(position(jumps.back(jump(1, 0))), Some((1, 10)));
+        assert_eq!(position(jumps.back(jump(1, 10))), Some((1, 20)));
+        assert_eq!(position(jumps.back(jump(1, 20))), None);
+    }
+}
@@ -0,0 +1,145 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// names of the keys that aren't written as themselves
+const NAMES: [(KeyCode, &str); 15] = [
+    (KeyCode::Esc, "Esc"),
+    (KeyCode::Enter, "CR"),
+    (KeyCode::Backspace, "BS"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::BackTab, "S-Tab"),
+    (KeyCode::Delete, "Del"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+];
+
+/// writes a key the way vim does, plain chars as themselves and everything
+/// else between angle brackets, e.g. `<Esc>` or `<C-r>`
+pub fn encode(key: &KeyEvent) -> Option<String> {
+    let name = match key.code {
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{n}"),
+        code => NAMES
+            .iter()
+            .find(|(named, _)| *named == code)
+            .map(|(_, name)| name.to_string())?,
+    };
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("A-");
+    }
+    match key.code {
+        KeyCode::Char(c) if prefix.is_empty() && c != '<' => Some(name),
+        _ => Some(format!("<{prefix}{name}>")),
+    }
+}
+
+/// reads back keys written by `encode`, anything between angle brackets that
+/// isn't a key name is taken literally
+pub fn decode(keys: &str) -> Vec<KeyEvent> {
+    let mut events = vec![];
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        let named = rest
+            .strip_prefix('<')
+            .and_then(|tail| tail.split_once('>'))
+            .and_then(|(name, tail)| Some((decode_name(name)?, tail)));
+        match named {
+            Some((event, tail)) => {
+                events.push(event);
+                rest = tail;
+            }
+            None => {
+                events.push(char_event(c, KeyModifiers::NONE));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    events
+}
+
+fn decode_name(name: &str) -> Option<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = name;
+    loop {
+        if let Some(rest) = name.strip_prefix("C-") {
+            modifiers |= KeyModifiers::CONTROL;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("A-") {
+            modifiers |= KeyModifiers::ALT;
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if modifiers != KeyModifiers::NONE => {
+            return Some(char_event(c, modifiers))
+        }
+        _ if name == "lt" => return Some(char_event('<', modifiers)),
+        _ => (),
+    }
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse().ok()) {
+        return Some(KeyEvent::new(KeyCode::F(n), modifiers));
+    }
+    let (code, _) = NAMES.iter().find(|(_, named)| *named == name)?;
+    let modifiers = match code {
+        KeyCode::BackTab => modifiers | KeyModifiers::SHIFT,
+        _ => modifiers,
+    };
+    Some(KeyEvent::new(*code, modifiers))
+}
+
+// terminals report uppercase letters with shift held
+fn char_event(c: char, modifiers: KeyModifiers) -> KeyEvent {
+    match c.is_uppercase() {
+        true => KeyEvent::new(KeyCode::Char(c), modifiers | KeyModifiers::SHIFT),
+        false => KeyEvent::new(KeyCode::Char(c), modifiers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let keys = [
+            KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('W'), KeyModifiers::SHIFT),
+            KeyEvent::new(KeyCode::Char('<'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL),
+            KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+        ];
+
+        let encoded = keys.iter().filter_map(encode).collect::<String>();
+
+        assert_eq!(encoded, "dW<lt><C-r><Esc><CR>");
+        assert_eq!(decode(&encoded), keys);
+    }
+
+    #[test]
+    fn test_decode_takes_unknown_names_literally() {
+        assert_eq!(decode("<foo>").len(), 5);
+        assert_eq!(
+            decode("<x<lt>"),
+            [
+                KeyEvent::new(KeyCode::Char('<'), KeyModifiers::NONE),
+                KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE),
+                KeyEvent::new(KeyCode::Char('<'), KeyModifiers::NONE),
+            ]
+        );
+    }
+}
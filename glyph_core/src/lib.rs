@@ -1,13 +1,22 @@
 pub mod buffer;
+pub mod clipboard;
 pub mod commandline;
 pub mod config;
 pub mod cursor;
 pub mod editor;
 pub mod event_handler;
+pub mod ex;
 pub mod gutter;
 pub mod highlight;
+pub mod jump_list;
+pub mod keys;
 pub mod lsp;
+pub mod options;
+pub mod prompt;
+pub mod register;
+pub mod search;
 pub mod statusline;
+pub mod substitute;
 pub mod tab;
 pub mod theme;
 pub mod ui;
@@ -6,6 +6,8 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+
+use crate::buffer::{Buffer, PositionEncoding};
 use tokio::{
     io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
     process::{ChildStdin, Command},
@@ -205,6 +207,7 @@ async fn lsp_start() -> anyhow::Result<LspClient> {
         request_tx,
         response_rx,
         pending_responses: HashMap::new(),
+        position_encoding: PositionEncoding::default(),
     })
 }
 
@@ -381,6 +384,8 @@ pub struct LspClient {
     pub request_tx: mpsc::Sender<OutgoingMessage>,
     pub response_rx: mpsc::Receiver<IncomingMessage>,
     pub pending_responses: HashMap<i64, String>,
+    // negotiated on initialize, servers that don't answer use utf-16
+    pub position_encoding: PositionEncoding,
 }
 
 impl LspClient {
@@ -417,6 +422,9 @@ impl LspClient {
             Ok(msg) => {
                 if let IncomingMessage::Message(msg) = &msg {
                     if let Some(method) = self.pending_responses.remove(&msg.id) {
+                        if method == "initialize" {
+                            self.handle_initialize(&msg.result);
+                        }
                         return Ok(Some((IncomingMessage::Message(msg.clone()), Some(method))));
                     }
                 }
@@ -427,21 +435,43 @@ impl LspClient {
         }
     }
 
-    pub async fn request_hover(
-        &mut self,
-        file_path: &str,
-        row: usize,
-        col: usize,
-    ) -> anyhow::Result<i64> {
+    fn handle_initialize(&mut self, result: &Value) {
+        let encoding = &result["capabilities"]["positionEncoding"];
+        self.position_encoding = match encoding.as_str().map(str::parse) {
+            Some(Ok(encoding)) => encoding,
+            Some(Err(err)) => {
+                tracing::error!("[LSP] {err}, falling back to utf-16");
+                PositionEncoding::Utf16
+            }
+            None => PositionEncoding::Utf16,
+        };
+        tracing::debug!("[LSP] using position encoding {:?}", self.position_encoding);
+    }
+
+    /// converts a char offset into an LSP position using the negotiated encoding
+    pub fn position(&self, buffer: &Buffer, char: usize) -> Value {
+        let (line, character) = buffer.char_to_position(char, self.position_encoding);
+        json!({
+            "line": line,
+            "character": character,
+        })
+    }
+
+    /// converts an LSP position back into a char offset of the buffer
+    pub fn position_to_char(&self, buffer: &Buffer, position: &Value) -> Option<usize> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        Some(buffer.position_to_char(line, character, self.position_encoding))
+    }
+
+    /// `position` is expected to come from [`LspClient::position`]
+    pub async fn request_hover(&mut self, file_path: &str, position: Value) -> anyhow::Result<i64> {
         let file_path = std::fs::canonicalize(file_path)?;
         let params = json!({
             "textDocument": {
                 "uri": format!("file://{}", file_path.to_string_lossy()),
             },
-            "position": {
-                "line": row,
-                "character": col
-            }
+            "position": position,
         });
 
         self.send_request("textDocument/hover", params).await
@@ -457,6 +487,13 @@ impl LspClient {
                     "version": "0.1.0",
                 },
                 "capabilities": {
+                    "general": {
+                        "positionEncodings": [
+                            PositionEncoding::Utf8.as_str(),
+                            PositionEncoding::Utf32.as_str(),
+                            PositionEncoding::Utf16.as_str(),
+                        ],
+                    },
                     "textDocument": {
                         "hover": {
                             "dynamicRegistration": true,
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, LineNumbers};
+
+/// Where the value of an option lives, `:set` changes the global value along
+/// with the local one so new windows and buffers get it too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Window,
+    Buffer,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Number(usize),
+    Char(char),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(value) => write!(f, "{value}"),
+            Value::Char(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// An option `:set` knows about, numbers can't go below `min`.
+#[derive(Debug)]
+pub struct Info {
+    pub name: &'static str,
+    pub short: &'static str,
+    pub scope: Scope,
+    pub default: Value,
+    min: usize,
+}
+
+pub const OPTIONS: &[Info] = &[
+    Info {
+        name: "wrapscan",
+        short: "ws",
+        scope: Scope::Global,
+        default: Value::Bool(true),
+        min: 0,
+    },
+    Info {
+        name: "number",
+        short: "nu",
+        scope: Scope::Window,
+        default: Value::Bool(true),
+        min: 0,
+    },
+    Info {
+        name: "relativenumber",
+        short: "rnu",
+        scope: Scope::Window,
+        default: Value::Bool(false),
+        min: 0,
+    },
+    // the gutter is one column wider than the line numbers it shows
+    Info {
+        name: "numberwidth",
+        short: "nuw",
+        scope: Scope::Window,
+        default: Value::Number(6),
+        min: 3,
+    },
+    Info {
+        name: "emptylinechar",
+        short: "elc",
+        scope: Scope::Window,
+        default: Value::Char('~'),
+        min: 0,
+    },
+    Info {
+        name: "tabstop",
+        short: "ts",
+        scope: Scope::Buffer,
+        default: Value::Number(4),
+        min: 1,
+    },
+    Info {
+        name: "shiftwidth",
+        short: "sw",
+        scope: Scope::Buffer,
+        default: Value::Number(4),
+        min: 1,
+    },
+    Info {
+        name: "expandtab",
+        short: "et",
+        scope: Scope::Buffer,
+        default: Value::Bool(true),
+        min: 0,
+    },
+];
+
+/// the option named `name` or abbreviated to it
+pub fn info(name: &str) -> anyhow::Result<&'static Info> {
+    OPTIONS
+        .iter()
+        .find(|info| info.name == name || info.short == name)
+        .ok_or_else(|| anyhow::anyhow!("unknown option: {name}"))
+}
+
+/// the names of the options starting with `prefix`
+pub fn complete(prefix: &str) -> Vec<&'static str> {
+    OPTIONS
+        .iter()
+        .map(|info| info.name)
+        .filter(|name| name.starts_with(prefix))
+        .collect()
+}
+
+/// The values of the options of a scope, those never set have their default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    values: HashMap<&'static str, Value>,
+}
+
+impl Options {
+    /// the global values, the defaults of new windows and buffers, as the
+    /// config sets them
+    pub fn from_config(config: &Config) -> Self {
+        let mut options = Self::default();
+        let (number, relative) = match config.line_numbers {
+            LineNumbers::Absolute => (true, false),
+            LineNumbers::Relative => (false, true),
+            LineNumbers::RelativeNumbered => (true, true),
+            LineNumbers::None => (false, false),
+        };
+        options.values.insert("number", Value::Bool(number));
+        options
+            .values
+            .insert("relativenumber", Value::Bool(relative));
+        options
+            .values
+            .insert("numberwidth", Value::Number(config.gutter_width));
+        options
+            .values
+            .insert("emptylinechar", Value::Char(config.empty_line_char));
+        options
+    }
+
+    /// the values of the options of `scope`, to start a window or a buffer with
+    pub fn scope(&self, scope: Scope) -> Self {
+        let values = self
+            .values
+            .iter()
+            .filter(|(name, _)| info(name).is_ok_and(|info| info.scope == scope))
+            .map(|(name, value)| (*name, *value))
+            .collect();
+        Self { values }
+    }
+
+    pub fn get(&self, name: &str) -> Value {
+        let info = info(name).expect("options are looked up by their name");
+        self.values.get(info.name).copied().unwrap_or(info.default)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        matches!(self.get(name), Value::Bool(true))
+    }
+
+    pub fn number(&self, name: &str) -> usize {
+        match self.get(name) {
+            Value::Number(value) => value,
+            _ => 0,
+        }
+    }
+
+    pub fn char(&self, name: &str) -> char {
+        match self.get(name) {
+            Value::Char(value) => value,
+            _ => ' ',
+        }
+    }
+
+    fn set(&mut self, info: &'static Info, value: Value) {
+        self.values.insert(info.name, value);
+    }
+
+    /// which line numbers `number` and `relativenumber` show
+    pub fn line_numbers(&self) -> LineNumbers {
+        match (self.flag("number"), self.flag("relativenumber")) {
+            (true, false) => LineNumbers::Absolute,
+            (false, true) => LineNumbers::Relative,
+            (true, true) => LineNumbers::RelativeNumbered,
+            (false, false) => LineNumbers::None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    // `nu?`, or a non boolean option named alone
+    Show,
+    Enable,
+    // `nonu`
+    Disable,
+    // `invnu` and `nu!`
+    Toggle,
+    // `ts=4` or `ts:4`
+    Set(Value),
+    // `nu&`, back to what the config set
+    Reset,
+}
+
+/// An argument of `:set`, naming its option in full.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub name: String,
+    pub change: Change,
+}
+
+impl Setting {
+    pub fn parse(arg: &str) -> anyhow::Result<Self> {
+        if let Some((name, value)) = arg.split_once(['=', ':']) {
+            let info = info(name)?;
+            let value = match info.default {
+                Value::Number(_) => Value::Number(
+                    value
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("number required: {arg}"))?,
+                ),
+                Value::Char(_) => {
+                    let mut chars = value.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Value::Char(c),
+                        _ => anyhow::bail!("a single char required: {arg}"),
+                    }
+                }
+                Value::Bool(_) => anyhow::bail!("invalid argument: {arg}"),
+            };
+            return Ok(Self::new(info, Change::Set(value)));
+        }
+        let (name, change) = match arg {
+            arg if arg.ends_with('?') => (&arg[..arg.len() - 1], Change::Show),
+            arg if arg.ends_with('&') => (&arg[..arg.len() - 1], Change::Reset),
+            arg if arg.ends_with('!') => (&arg[..arg.len() - 1], Change::Toggle),
+            arg => match (info(arg), arg.strip_prefix("no"), arg.strip_prefix("inv")) {
+                (Ok(_), _, _) => (arg, Change::Enable),
+                (_, Some(name), _) => (name, Change::Disable),
+                (_, _, Some(name)) => (name, Change::Toggle),
+                _ => (arg, Change::Enable),
+            },
+        };
+        let info = info(name)?;
+        let change = match (change, info.default) {
+            (Change::Enable, Value::Bool(_)) => Change::Enable,
+            (Change::Enable, _) => Change::Show,
+            (Change::Disable | Change::Toggle, Value::Bool(_)) => change,
+            (Change::Disable | Change::Toggle, _) => anyhow::bail!("invalid argument: {arg}"),
+            (change, _) => change,
+        };
+        Ok(Self::new(info, change))
+    }
+
+    fn new(info: &Info, change: Change) -> Self {
+        Self {
+            name: info.name.to_string(),
+            change,
+        }
+    }
+}
+
+/// The options of every scope a setting can change, the window and buffer
+/// ones being those of the current window and buffer.
+pub struct Scopes<'a> {
+    pub global: &'a mut Options,
+    pub window: &'a mut Options,
+    pub buffer: &'a mut Options,
+}
+
+impl Scopes<'_> {
+    /// applies `setting`, leaving the global value alone when `local` is set
+    /// like `:setlocal`, returning what it shows if anything
+    pub fn apply(
+        &mut self,
+        setting: &Setting,
+        local: bool,
+        defaults: &Options,
+    ) -> anyhow::Result<Option<String>> {
+        let info = info(&setting.name)?;
+        let options = match info.scope {
+            Scope::Global => &mut *self.global,
+            Scope::Window => &mut *self.window,
+            Scope::Buffer => &mut *self.buffer,
+        };
+        let current = options.get(info.name);
+        let value = match setting.change {
+            Change::Show => {
+                return Ok(Some(match current {
+                    Value::Bool(true) => info.name.to_string(),
+                    Value::Bool(false) => format!("no{}", info.name),
+                    value => format!("{}={value}", info.name),
+                }))
+            }
+            Change::Enable => Value::Bool(true),
+            Change::Disable => Value::Bool(false),
+            Change::Toggle => Value::Bool(current != Value::Bool(true)),
+            Change::Set(value) => value,
+            Change::Reset => defaults.get(info.name),
+        };
+        if let Value::Number(number) = value {
+            if number < info.min {
+                anyhow::bail!("{} can't be less than {}", info.name, info.min);
+            }
+        }
+        options.set(info, value);
+        if !local && info.scope != Scope::Global {
+            self.global.set(info, value);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings() {
+        let change = |arg: &str| Setting::parse(arg).unwrap().change;
+
+        assert_eq!(Setting::parse("nu").unwrap().name, "number");
+        assert_eq!(change("number"), Change::Enable);
+        assert_eq!(change("nonu"), Change::Disable);
+        assert_eq!(change("invrnu"), Change::Toggle);
+        assert_eq!(change("et!"), Change::Toggle);
+        assert_eq!(change("ts"), Change::Show);
+        assert_eq!(change("nu?"), Change::Show);
+        assert_eq!(change("sw&"), Change::Reset);
+        assert_eq!(change("ts=8"), Change::Set(Value::Number(8)));
+        assert_eq!(change("elc:."), Change::Set(Value::Char('.')));
+        assert!(Setting::parse("ts=x").is_err());
+        assert!(Setting::parse("nu=1").is_err());
+        assert!(Setting::parse("nots").is_err());
+        assert!(Setting::parse("nonsense").is_err());
+        assert_eq!(complete("n"), ["number", "numberwidth"]);
+    }
+
+    #[test]
+    fn test_scopes() {
+        let defaults = Options::default();
+        let mut global = defaults.clone();
+        let (mut window, mut buffer) = (Options::default(), Options::default());
+        let mut scopes = Scopes {
+            global: &mut global,
+            window: &mut window,
+            buffer: &mut buffer,
+        };
+        let mut set = |arg: &str, local: bool| {
+            let setting = Setting::parse(arg).unwrap();
+            scopes.apply(&setting, local, &defaults)
+        };
+
+        set("ts=2", true).unwrap();
+        set("nonu", false).unwrap();
+        set("nows", true).unwrap();
+        assert_eq!(set("ts?", false).unwrap().as_deref(), Some("tabstop=2"));
+        assert_eq!(set("nu?", false).unwrap().as_deref(), Some("nonumber"));
+        assert!(set("nuw=2", false).is_err());
+        assert_eq!(buffer.number("tabstop"), 2);
+        assert!(!window.flag("number"));
+        assert_eq!(global.number("tabstop"), 4);
+        assert!(!global.flag("number"));
+        assert!(!global.flag("wrapscan"));
+        assert_eq!(
+            global.scope(Scope::Window).line_numbers(),
+            LineNumbers::None
+        );
+    }
+}
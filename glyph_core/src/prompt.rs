@@ -0,0 +1,96 @@
+use crate::editor::Action;
+
+#[derive(Debug, Clone)]
+pub struct Choice {
+    pub key: char,
+    pub label: String,
+    pub action: Action,
+}
+
+/// A question shown on the commandline where each answer is a single key
+/// that maps to an action.
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub message: String,
+    pub choices: Vec<Choice>,
+    // what to do when the prompt is dismissed without an answer
+    pub cancel: Option<Action>,
+}
+
+impl Prompt {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            choices: vec![],
+            cancel: None,
+        }
+    }
+
+    pub fn choice(mut self, key: char, label: &str, action: Action) -> Self {
+        self.choices.push(Choice {
+            key,
+            label: label.to_string(),
+            action,
+        });
+        self
+    }
+
+    pub fn on_cancel(mut self, action: Action) -> Self {
+        self.cancel = Some(action);
+        self
+    }
+
+    pub fn choose(&self, key: char) -> Option<&Action> {
+        self.choices
+            .iter()
+            .find(|choice| choice.key == key)
+            .map(|choice| &choice.action)
+    }
+
+    /// the message followed by every choice, e.g. `file changed: [r]eload, [k]eep`
+    pub fn text(&self) -> String {
+        let choices = self
+            .choices
+            .iter()
+            .map(|choice| match choice.label.find(choice.key) {
+                Some(i) => format!(
+                    "{}[{}]{}",
+                    &choice.label[..i],
+                    choice.key,
+                    &choice.label[i + choice.key.len_utf8()..]
+                ),
+                None => format!("[{}] {}", choice.key, choice.label),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}: {}", self.message, choices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt() -> Prompt {
+        Prompt::new("file changed")
+            .choice('r', "reload", Action::ReloadBuffer)
+            .choice('k', "keep", Action::KeepBuffer)
+            .choice('d', "merge", Action::MergeBuffer)
+            .on_cancel(Action::KeepBuffer)
+    }
+
+    #[test]
+    fn test_choose() {
+        let prompt = prompt();
+
+        assert!(matches!(prompt.choose('r'), Some(Action::ReloadBuffer)));
+        assert!(matches!(prompt.choose('d'), Some(Action::MergeBuffer)));
+        assert!(prompt.choose('x').is_none());
+        assert!(matches!(prompt.cancel, Some(Action::KeepBuffer)));
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(prompt().text(), "file changed: [r]eload, [k]eep, [d] merge");
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::clipboard::{ClipboardKind, ClipboardProvider, ClipboardSync};
+
+/// How the text of a register is put back into a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    Charwise,
+    // whole lines, always ending with a line break
+    Linewise,
+    // a rectangle, every line of the text is put on its own line at the same
+    // column
+    Blockwise,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl Register {
+    pub fn new(text: impl Into<String>, kind: RegisterKind) -> Self {
+        Self {
+            text: text.into(),
+            kind,
+        }
+    }
+
+    pub fn charwise(text: impl Into<String>) -> Self {
+        Self::new(text, RegisterKind::Charwise)
+    }
+
+    pub fn linewise(text: impl Into<String>) -> Self {
+        let mut text = text.into();
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        Self::new(text, RegisterKind::Linewise)
+    }
+
+    pub fn blockwise(lines: &[String]) -> Self {
+        Self::new(lines.join("\n"), RegisterKind::Blockwise)
+    }
+
+    // appending to a register keeps it linewise if either side was
+    fn append(&mut self, other: Register) {
+        match (self.kind, other.kind) {
+            (RegisterKind::Charwise, RegisterKind::Charwise) => self.text.push_str(&other.text),
+            (RegisterKind::Blockwise, _) | (_, RegisterKind::Blockwise) => {
+                self.text.push('\n');
+                self.text.push_str(other.text.trim_end_matches('\n'));
+                self.kind = RegisterKind::Blockwise;
+            }
+            _ => {
+                if !self.text.ends_with('\n') {
+                    self.text.push('\n');
+                }
+                *self = Register::linewise(format!("{}{}", self.text, other.text));
+            }
+        }
+    }
+}
+
+pub const UNNAMED: char = '"';
+pub const SMALL_DELETE: char = '-';
+pub const BLACK_HOLE: char = '_';
+pub const LAST_INSERT: char = '.';
+pub const FILE_NAME: char = '%';
+pub const LAST_COMMAND: char = ':';
+pub const LAST_SEARCH: char = '/';
+
+const READ_ONLY: [char; 4] = [LAST_INSERT, FILE_NAME, LAST_COMMAND, LAST_SEARCH];
+
+/// Every register of the editor, following vim semantics:
+///
+/// - `"` holds the last yanked or deleted text
+/// - `0` holds the last yank, `1`-`9` the history of deletes spanning lines
+/// - `-` holds the last delete within a line
+/// - `a`-`z` are written on request, their uppercase name appends to them
+/// - `_` discards everything written to it
+/// - `.`, `%`, `:` and `/` are kept up to date by the editor and can only be
+///   read
+/// - `+` and `*` are the system clipboard and primary selection
+#[derive(Debug, Default)]
+pub struct Registers {
+    unnamed: Option<Register>,
+    yank: Option<Register>,
+    deletes: VecDeque<Register>,
+    small_delete: Option<Register>,
+    named: HashMap<char, Register>,
+    read_only: HashMap<char, String>,
+    clipboard: Option<Box<dyn ClipboardProvider>>,
+    // what was last written to each clipboard register, used when the
+    // provider can't read the clipboard and to remember the register kind
+    clipboard_cache: HashMap<char, Register>,
+    sync: Option<ClipboardSync>,
+}
+
+impl Registers {
+    pub fn with_clipboard(
+        clipboard: Box<dyn ClipboardProvider>,
+        sync: Option<ClipboardSync>,
+    ) -> Self {
+        Self {
+            clipboard: Some(clipboard),
+            sync,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric()
+            || [UNNAMED, SMALL_DELETE, BLACK_HOLE, '+', '*'].contains(&name)
+            || READ_ONLY.contains(&name)
+    }
+
+    /// stores yanked text in `name`, or in `0` when no register was given
+    pub fn yank(&mut self, name: Option<char>, register: Register) -> anyhow::Result<()> {
+        match name {
+            Some(BLACK_HOLE) => return Ok(()),
+            None | Some(UNNAMED) => {
+                self.yank = Some(register.clone());
+                self.sync_clipboard(&register);
+            }
+            Some(name) => self.write_named(name, register.clone())?,
+        }
+        self.unnamed = Some(register);
+        Ok(())
+    }
+
+    // mirrors unnamed writes into the clipboard, failing to do so shouldn't
+    // make the yank or delete itself fail
+    fn sync_clipboard(&mut self, register: &Register) {
+        if let Some(sync) = self.sync {
+            if let Err(err) = self.write_clipboard(sync.register(), register.clone()) {
+                tracing::error!("{err:#}");
+            }
+        }
+    }
+
+    fn write_clipboard(&mut self, name: char, register: Register) -> anyhow::Result<()> {
+        let kind = ClipboardKind::from_register(name).unwrap();
+        if let Some(clipboard) = &mut self.clipboard {
+            clipboard.set(kind, &register.text)?;
+        }
+        self.clipboard_cache.insert(name, register);
+        Ok(())
+    }
+
+    fn read_clipboard(&mut self, name: char) -> Option<Register> {
+        let kind = ClipboardKind::from_register(name)?;
+        let cached = self.clipboard_cache.get(&name).cloned();
+        let text = match self.clipboard.as_mut().map(|clipboard| clipboard.get(kind)) {
+            Some(Ok(text)) => text,
+            Some(Err(err)) => {
+                tracing::error!("{err:#}");
+                None
+            }
+            None => None,
+        };
+        match (text, cached) {
+            (Some(text), Some(cached)) if cached.text == text => Some(cached),
+            (Some(text), _) if text.ends_with('\n') => Some(Register::linewise(text)),
+            (Some(text), _) => Some(Register::charwise(text)),
+            (None, cached) => cached,
+        }
+    }
+
+    /// stores deleted text in `name`, or when no register was given, in the
+    /// numbered history if it spans lines and in `-` otherwise
+    pub fn delete(&mut self, name: Option<char>, register: Register) -> anyhow::Result<()> {
+        match name {
+            Some(BLACK_HOLE) => return Ok(()),
+            None | Some(UNNAMED) => {
+                if register.kind == RegisterKind::Charwise && !register.text.contains('\n') {
+                    self.small_delete = Some(register.clone());
+                } else {
+                    self.deletes.push_front(register.clone());
+                    self.deletes.truncate(9);
+                }
+                self.sync_clipboard(&register);
+            }
+            Some(name) => self.write_named(name, register.clone())?,
+        }
+        self.unnamed = Some(register);
+        Ok(())
+    }
+
+    fn write_named(&mut self, name: char, register: Register) -> anyhow::Result<()> {
+        if READ_ONLY.contains(&name) {
+            anyhow::bail!("register {name} is read only");
+        }
+        match name {
+            'a'..='z' => _ = self.named.insert(name, register),
+            'A'..='Z' => {
+                let name = name.to_ascii_lowercase();
+                match self.named.get_mut(&name) {
+                    Some(named) => named.append(register),
+                    None => _ = self.named.insert(name, register),
+                }
+            }
+            '0' => self.yank = Some(register),
+            '1'..='9' => {
+                let index = name as usize - '1' as usize;
+                while self.deletes.len() <= index {
+                    self.deletes.push_back(Register::charwise(""));
+                }
+                self.deletes[index] = register;
+            }
+            SMALL_DELETE => self.small_delete = Some(register),
+            '+' | '*' => self.write_clipboard(name, register)?,
+            _ => anyhow::bail!("invalid register: {name}"),
+        }
+        Ok(())
+    }
+
+    /// stores the keys of a recorded macro in `name`, unlike a yank this
+    /// leaves the unnamed register alone
+    pub fn record(&mut self, name: char, keys: impl Into<String>) -> anyhow::Result<()> {
+        self.write_named(name, Register::charwise(keys))
+    }
+
+    /// updates one of the read only registers
+    pub fn set_read_only(&mut self, name: char, text: impl Into<String>) {
+        debug_assert!(READ_ONLY.contains(&name));
+        self.read_only.insert(name, text.into());
+    }
+
+    /// content of `name`, or of the unnamed register when no name is given
+    pub fn get(&mut self, name: Option<char>) -> Option<Register> {
+        match name.unwrap_or(UNNAMED) {
+            // the clipboard may have changed outside of the editor since
+            UNNAMED if self.sync.is_some() => {
+                let name = self.sync.unwrap().register();
+                self.read_clipboard(name).or_else(|| self.unnamed.clone())
+            }
+            UNNAMED => self.unnamed.clone(),
+            name @ ('+' | '*') => self.read_clipboard(name),
+            BLACK_HOLE => None,
+            SMALL_DELETE => self.small_delete.clone(),
+            '0' => self.yank.clone(),
+            name @ '1'..='9' => self.deletes.get(name as usize - '1' as usize).cloned(),
+            name if name.is_ascii_alphabetic() => {
+                self.named.get(&name.to_ascii_lowercase()).cloned()
+            }
+            name => self.read_only.get(&name).cloned().map(Register::charwise),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yank_and_delete_fill_unnamed() {
+        let mut registers = Registers::default();
+
+        registers.yank(None, Register::charwise("yanked")).unwrap();
+        registers.delete(None, Register::charwise("word")).unwrap();
+
+        assert_eq!(registers.get(None), Some(Register::charwise("word")));
+        assert_eq!(registers.get(Some('0')), Some(Register::charwise("yanked")));
+        assert_eq!(registers.get(Some('-')), Some(Register::charwise("word")));
+        assert_eq!(registers.get(Some('1')), None);
+    }
+
+    #[test]
+    fn test_numbered_deletes_shift() {
+        let mut registers = Registers::default();
+
+        for i in 0..10 {
+            registers
+                .delete(None, Register::linewise(format!("line {i}")))
+                .unwrap();
+        }
+
+        assert_eq!(registers.get(Some('1')), Some(Register::linewise("line 9")));
+        assert_eq!(registers.get(Some('9')), Some(Register::linewise("line 1")));
+    }
+
+    #[test]
+    fn test_uppercase_appends() {
+        let mut registers = Registers::default();
+
+        registers
+            .yank(Some('a'), Register::charwise("one"))
+            .unwrap();
+        registers
+            .yank(Some('A'), Register::charwise(" two"))
+            .unwrap();
+        assert_eq!(
+            registers.get(Some('a')),
+            Some(Register::charwise("one two"))
+        );
+
+        registers
+            .yank(Some('A'), Register::linewise("three"))
+            .unwrap();
+        assert_eq!(
+            registers.get(Some('A')),
+            Some(Register::linewise("one two\nthree\n"))
+        );
+    }
+
+    #[test]
+    fn test_black_hole_and_read_only() {
+        let mut registers = Registers::default();
+        registers.yank(None, Register::charwise("kept")).unwrap();
+
+        registers
+            .delete(Some('_'), Register::charwise("gone"))
+            .unwrap();
+        assert_eq!(registers.get(None), Some(Register::charwise("kept")));
+        assert_eq!(registers.get(Some('_')), None);
+
+        registers.set_read_only(LAST_COMMAND, "w");
+        assert_eq!(registers.get(Some(':')), Some(Register::charwise("w")));
+        assert!(registers.yank(Some(':'), Register::charwise("x")).is_err());
+    }
+
+    #[test]
+    fn test_macros_share_named_registers() {
+        let mut registers = Registers::default();
+        registers.yank(None, Register::charwise("kept")).unwrap();
+
+        registers.record('q', "dw").unwrap();
+        registers.record('Q', "j<Esc>").unwrap();
+
+        assert_eq!(
+            registers.get(Some('q')),
+            Some(Register::charwise("dwj<Esc>"))
+        );
+        assert_eq!(registers.get(None), Some(Register::charwise("kept")));
+        assert!(registers.record('.', "x").is_err());
+    }
+
+    #[derive(Debug, Default)]
+    struct FakeClipboard {
+        text: Option<String>,
+    }
+
+    impl ClipboardProvider for FakeClipboard {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn set(&mut self, _: ClipboardKind, text: &str) -> anyhow::Result<()> {
+            self.text = Some(text.to_string());
+            Ok(())
+        }
+
+        fn get(&mut self, _: ClipboardKind) -> anyhow::Result<Option<String>> {
+            Ok(self.text.clone())
+        }
+    }
+
+    #[test]
+    fn test_clipboard_registers() {
+        let mut registers = Registers::with_clipboard(Box::<FakeClipboard>::default(), None);
+
+        registers.yank(None, Register::charwise("local")).unwrap();
+        assert_eq!(registers.get(Some('+')), None);
+
+        registers
+            .yank(Some('+'), Register::linewise("line"))
+            .unwrap();
+        assert_eq!(registers.get(Some('+')), Some(Register::linewise("line")));
+        assert_eq!(registers.get(None), Some(Register::linewise("line")));
+    }
+
+    #[test]
+    fn test_unnamed_mirrors_clipboard() {
+        let mut registers = Registers::with_clipboard(
+            Box::<FakeClipboard>::default(),
+            Some(ClipboardSync::UnnamedPlus),
+        );
+
+        registers.delete(None, Register::charwise("word")).unwrap();
+
+        assert_eq!(registers.get(Some('+')), Some(Register::charwise("word")));
+        assert_eq!(registers.get(None), Some(Register::charwise("word")));
+    }
+}
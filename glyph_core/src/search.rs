@@ -0,0 +1,219 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::Buffer;
+
+// char ranges of the matches in a text, shared with whoever asked for them
+type Matches = Rc<[Range<usize>]>;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+impl Direction {
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
+}
+
+/// A pattern typed after `/` or `?`, in rust regex syntax with a few vim
+/// additions:
+///
+/// - a leading `\v` is accepted and ignored, the syntax is already "very magic"
+/// - `\<` and `\>` match at word boundaries
+/// - `\c` and `\C` make the search ignore or respect case, otherwise it is
+///   smartcase: case is only respected when the pattern has an uppercase char
+#[derive(Debug, Clone)]
+pub struct Search {
+    pub pattern: String,
+    regex: Regex,
+    // the matches in the last text searched, by its stamp, so `n` and the
+    // match count don't search the whole buffer again until it changes
+    found: RefCell<Option<(usize, Matches)>>,
+}
+
+impl Search {
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        let mut translated = String::new();
+        let mut ignore_case = None;
+        let mut has_uppercase = false;
+        let mut chars = pattern.strip_prefix("\\v").unwrap_or(pattern).chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('c') => ignore_case = Some(true),
+                    Some('C') => ignore_case = Some(false),
+                    Some('<' | '>') => translated.push_str("\\b"),
+                    Some(escaped) => {
+                        translated.push('\\');
+                        translated.push(escaped);
+                    }
+                    None => translated.push('\\'),
+                },
+                c => {
+                    has_uppercase |= c.is_uppercase();
+                    translated.push(c);
+                }
+            }
+        }
+        let regex = RegexBuilder::new(&translated)
+            .case_insensitive(ignore_case.unwrap_or(!has_uppercase))
+            .multi_line(true)
+            .build()
+            .map_err(|err| anyhow::anyhow!("invalid pattern {pattern}: {err}"))?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+            found: RefCell::default(),
+        })
+    }
+
+    /// searches `word` as a whole word, like `*` and `#`
+    pub fn word(word: &str) -> anyhow::Result<Self> {
+        Self::new(&format!("\\<{}\\>", regex::escape(word)))
+    }
+
+    pub(crate) fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    /// char ranges of every match in `text`
+    pub fn matches(&self, text: &str) -> Vec<Range<usize>> {
+        let mut chars = 0;
+        let mut bytes = 0;
+        let mut char_offset = |byte: usize| {
+            chars += text[bytes..byte].chars().count();
+            bytes = byte;
+            chars
+        };
+        self.regex
+            .find_iter(text)
+            .map(|found| {
+                let start = char_offset(found.start());
+                start..char_offset(found.end())
+            })
+            .collect()
+    }
+
+    /// char ranges of every match in the buffer, only searched again once
+    /// its text changed
+    pub fn buffer_matches(&self, buffer: &Buffer) -> Matches {
+        let mut found = self.found.borrow_mut();
+        if let Some((stamp, matches)) = &*found {
+            if *stamp == buffer.stamp() {
+                return matches.clone();
+            }
+        }
+        let matches: Matches = self.matches(&buffer.to_string()).into();
+        *found = Some((buffer.stamp(), matches.clone()));
+        matches
+    }
+
+    /// start of the first match after `from`, or before it going backward,
+    /// wrapping around the end of the buffer, along with whether it wrapped
+    pub fn find(
+        &self,
+        buffer: &Buffer,
+        from: usize,
+        direction: Direction,
+    ) -> Option<(usize, bool)> {
+        let matches = self.buffer_matches(buffer);
+        let after = matches.partition_point(|found| found.start <= from);
+        let before = matches.partition_point(|found| found.start < from);
+        let found = match direction {
+            Direction::Forward => matches.get(after),
+            Direction::Backward => before.checked_sub(1).map(|i| &matches[i]),
+        };
+        match (found, direction) {
+            (Some(found), _) => Some((found.start, false)),
+            (None, Direction::Forward) => matches.first().map(|found| (found.start, true)),
+            (None, Direction::Backward) => matches.last().map(|found| (found.start, true)),
+        }
+    }
+
+    /// the number of the match under `pos`, or of the last one before it, and
+    /// how many matches there are
+    pub fn count(&self, buffer: &Buffer, pos: usize) -> (usize, usize) {
+        let matches = self.buffer_matches(buffer);
+        let current = matches.partition_point(|found| found.start <= pos);
+        (current, matches.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> Vec<(usize, usize)> {
+        let search = Search::new(pattern).unwrap();
+        search
+            .matches(text)
+            .into_iter()
+            .map(|found| (found.start, found.end))
+            .collect()
+    }
+
+    #[test]
+    fn test_smartcase() {
+        let text = "Foo foo FOO";
+
+        assert_eq!(Search::new("foo").unwrap().matches(text).len(), 3);
+        assert_eq!(matches("Foo", text), [(0, 3)]);
+        assert_eq!(Search::new("Foo\\c").unwrap().matches(text).len(), 3);
+        assert_eq!(matches("\\Cfoo", text), [(4, 7)]);
+    }
+
+    #[test]
+    fn test_vim_syntax() {
+        let text = "cat concat cats";
+
+        assert_eq!(matches("\\<cat\\>", text), [(0, 3)]);
+        assert_eq!(matches("\\vcat(s)?$", text), [(11, 15)]);
+        assert_eq!(matches("a+", "ä aa"), [(2, 4)]);
+        assert!(Search::new("(").is_err());
+        let word = Search::word("a.b").unwrap();
+        assert_eq!(word.matches("axb a.b").len(), 1);
+        assert_eq!(word.matches("axb a.b")[0], 4..7);
+    }
+
+    #[test]
+    fn test_find_wraps_around() {
+        let text = Buffer::from_string(1, "one two\none\n");
+        let search = Search::new("one").unwrap();
+
+        assert_eq!(search.find(&text, 0, Direction::Forward), Some((8, false)));
+        assert_eq!(search.find(&text, 8, Direction::Forward), Some((0, true)));
+        assert_eq!(search.find(&text, 0, Direction::Backward), Some((8, true)));
+        assert_eq!(search.count(&text, 8), (2, 2));
+        assert_eq!(
+            Search::new("three")
+                .unwrap()
+                .find(&text, 0, Direction::Forward),
+            None
+        );
+    }
+
+    #[test]
+    fn test_search_again_once_the_buffer_changed() {
+        let mut buffer = Buffer::from_string(1, "ne ne\n");
+        let search = Search::new("one").unwrap();
+        assert_eq!(search.count(&buffer, 0), (0, 0));
+
+        buffer.insert_char('o', 3);
+
+        assert_eq!(search.count(&buffer, 0), (0, 1));
+        assert_eq!(
+            search.find(&buffer, 0, Direction::Forward),
+            Some((3, false))
+        );
+    }
+}
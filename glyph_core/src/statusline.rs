@@ -8,6 +8,7 @@ pub trait Statusline<'a> {
     fn new(area: Rect, theme: &'a Theme) -> Self;
     fn resize(area: Rect);
     fn render(&mut self, update: StatuslineUpdate) -> anyhow::Result<()>;
+    fn set_theme(&mut self, theme: &'static Theme);
 }
 
 #[derive(Debug)]
@@ -15,6 +16,9 @@ pub struct StatuslineUpdate {
     pub mode: Mode,
     pub cursor_pos: Position,
     pub buffer: Rc<RefCell<Buffer>>,
+    // number of the match under the cursor and how many there are, shown
+    // after a search
+    pub search_count: Option<(usize, usize)>,
 }
 
 impl StatuslineUpdate {
@@ -23,6 +27,12 @@ impl StatuslineUpdate {
             mode,
             cursor_pos,
             buffer,
+            search_count: None,
         }
     }
+
+    pub fn search_count(mut self, search_count: Option<(usize, usize)>) -> Self {
+        self.search_count = search_count;
+        self
+    }
 }
@@ -0,0 +1,338 @@
+use std::ops::{Range, RangeInclusive};
+
+use regex::Captures;
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::transaction::Change;
+use crate::buffer::Buffer;
+use crate::ex::LineRange;
+use crate::search::Search;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    // every match of a line instead of the first one
+    pub global: bool,
+    pub confirm: bool,
+    pub ignore_case: bool,
+    pub match_case: bool,
+    // counts the matches without replacing them
+    pub count_only: bool,
+}
+
+/// `:[range]s/pattern/replacement/flags`, any char that is not a letter, a
+/// digit or a space can delimit the parts instead of `/`.
+///
+/// The replacement supports `\1`-`\9` and `$1`, `${name}` for named groups,
+/// `&` and `\0` for the whole match, `\n` for a line break and the `\u`,
+/// `\l`, `\U`, `\L` and `\E` case modifiers.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Substitute {
+    pub range: LineRange,
+    pub pattern: String,
+    pub replacement: String,
+    pub flags: Flags,
+}
+
+/// A match of the pattern and the text it is replaced with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub range: Range<usize>,
+    pub text: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Confirm {
+    Yes,
+    No,
+    All,
+    Quit,
+    // replaces this match and stops
+    Last,
+}
+
+impl Substitute {
+    /// parses what follows `s`, e.g. `/foo/bar/g`
+    pub fn parse(range: LineRange, args: &str) -> anyhow::Result<Self> {
+        let mut chars = args.chars();
+        let delimiter = chars
+            .next()
+            .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && *c != '\\')
+            .ok_or_else(|| anyhow::anyhow!("expected /pattern/replacement/: {args}"))?;
+        let rest = chars.as_str();
+        let (pattern, rest) = split_delimited(rest, delimiter);
+        let (replacement, rest) = split_delimited(rest.unwrap_or_default(), delimiter);
+
+        let mut flags = Flags::default();
+        for flag in rest.unwrap_or_default().trim().chars() {
+            match flag {
+                'g' => flags.global = true,
+                'c' => flags.confirm = true,
+                'i' => flags.ignore_case = true,
+                'I' => flags.match_case = true,
+                'n' => flags.count_only = true,
+                flag => anyhow::bail!("invalid flag: {flag}"),
+            }
+        }
+        Ok(Self {
+            range,
+            pattern,
+            replacement,
+            flags,
+        })
+    }
+
+    /// the search for the pattern, an empty pattern reuses the last search
+    pub fn search(&self, last: Option<&Search>) -> anyhow::Result<Search> {
+        let pattern = match (self.pattern.as_str(), last) {
+            ("", Some(last)) => last.pattern.as_str(),
+            ("", None) => anyhow::bail!("no previous search"),
+            (pattern, _) => pattern,
+        };
+        match (self.flags.ignore_case, self.flags.match_case) {
+            (true, _) => Search::new(&format!("\\c{pattern}")),
+            (_, true) => Search::new(&format!("\\C{pattern}")),
+            _ => Search::new(pattern),
+        }
+    }
+
+    /// every match starting on `lines`, only the first one of each line
+    /// unless the `g` flag is set
+    pub fn replacements(
+        &self,
+        search: &Search,
+        buffer: &Buffer,
+        lines: RangeInclusive<usize>,
+    ) -> Vec<Replacement> {
+        let text = &buffer.buffer;
+        let start = text.line_to_char(*lines.start());
+        let end = text.line_to_char((*lines.end() + 1).min(text.len_lines()));
+        let content = text.slice(start..end).to_string();
+
+        let mut replacements: Vec<Replacement> = vec![];
+        let mut chars = 0;
+        let mut bytes = 0;
+        for captures in search.regex().captures_iter(&content) {
+            let found = captures.get(0).unwrap();
+            // the end of the text right after its last line break is part of
+            // the next line
+            if found.start() == content.len() && content.ends_with('\n') {
+                break;
+            }
+            chars += content[bytes..found.start()].chars().count();
+            bytes = found.start();
+            let match_start = start + chars;
+            let line = text.char_to_line(match_start);
+            if !self.flags.global && replacements.last().is_some_and(|last| last.line == line) {
+                continue;
+            }
+            replacements.push(Replacement {
+                range: match_start..match_start + found.as_str().chars().count(),
+                text: expand(&self.replacement, &captures),
+                line,
+            });
+        }
+        replacements
+    }
+}
+
+pub fn changes(replacements: &[Replacement]) -> Vec<Change> {
+    replacements
+        .iter()
+        .map(|replacement| {
+            let text = Some(replacement.text.clone());
+            (replacement.range.start, replacement.range.end, text)
+        })
+        .collect()
+}
+
+/// where the text of each replacement ends up once they are all applied
+pub fn applied(replacements: &[Replacement]) -> Vec<Range<usize>> {
+    let mut shift = 0isize;
+    replacements
+        .iter()
+        .map(|replacement| {
+            let start = replacement.range.start.saturating_add_signed(shift);
+            let len = replacement.text.chars().count();
+            shift += len as isize - replacement.range.len() as isize;
+            start..start + len
+        })
+        .collect()
+}
+
+// the text up to the first unescaped `delimiter`, with escaped delimiters
+// unescaped, and what follows it if it was found
+fn split_delimited(text: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut result = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == delimiter => return (result, Some(&text[i + c.len_utf8()..])),
+            '\\' => match chars.next() {
+                Some((_, c)) if c == delimiter => result.push(c),
+                Some((_, c)) => {
+                    result.push('\\');
+                    result.push(c);
+                }
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    (result, None)
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Upper,
+    Lower,
+}
+
+fn expand(replacement: &str, captures: &Captures) -> String {
+    let mut result = String::new();
+    let mut case = None;
+    let mut next_case = None;
+    let mut push = |text: &str, case: Option<Case>, next_case: &mut Option<Case>| {
+        for c in text.chars() {
+            let c = match next_case.take().or(case) {
+                Some(Case::Upper) => c.to_uppercase().collect::<String>(),
+                Some(Case::Lower) => c.to_lowercase().collect::<String>(),
+                None => c.to_string(),
+            };
+            result.push_str(&c);
+        }
+    };
+    let group = |name: &str| {
+        let found = match name.parse::<usize>() {
+            Ok(i) => captures.get(i),
+            Err(_) => captures.name(name),
+        };
+        found.map_or("", |found| found.as_str())
+    };
+
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => push(group(&digit.to_string()), case, &mut next_case),
+                Some('n' | 'r') => push("\n", case, &mut next_case),
+                Some('t') => push("\t", case, &mut next_case),
+                Some('u') => next_case = Some(Case::Upper),
+                Some('l') => next_case = Some(Case::Lower),
+                Some('U') => case = Some(Case::Upper),
+                Some('L') => case = Some(Case::Lower),
+                Some('E' | 'e') => case = None,
+                Some(c) => push(&c.to_string(), case, &mut next_case),
+                None => push("\\", case, &mut next_case),
+            },
+            '$' => match chars.peek() {
+                Some('$') => {
+                    chars.next();
+                    push("$", case, &mut next_case);
+                }
+                Some(digit) if digit.is_ascii_digit() => {
+                    let digit = chars.next().unwrap();
+                    push(group(&digit.to_string()), case, &mut next_case);
+                }
+                Some('{') => {
+                    chars.next();
+                    let name = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                    push(group(&name), case, &mut next_case);
+                }
+                _ => push("$", case, &mut next_case),
+            },
+            '&' => push(group("0"), case, &mut next_case),
+            c => push(&c.to_string(), case, &mut next_case),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ex::Address;
+
+    fn substitute(text: &str, command: &str) -> String {
+        let buffer = Buffer::from_string(1, text);
+        let range = LineRange {
+            start: Address::Line(1),
+            end: Address::Last,
+        };
+        let substitute = Substitute::parse(range, command).unwrap();
+        let search = substitute.search(None).unwrap();
+        let last = buffer.buffer.len_lines() - 1;
+        let mut result = text.to_string();
+        for replacement in substitute
+            .replacements(&search, &buffer, 0..=last)
+            .iter()
+            .rev()
+        {
+            let start = result
+                .char_indices()
+                .nth(replacement.range.start)
+                .map_or(result.len(), |(i, _)| i);
+            let end = result
+                .char_indices()
+                .nth(replacement.range.end)
+                .map_or(result.len(), |(i, _)| i);
+            result.replace_range(start..end, &replacement.text);
+        }
+        result
+    }
+
+    #[test]
+    fn test_parse() {
+        let range = LineRange::current;
+        let substitute = Substitute::parse(range(), "#a\\#b#c\\/d#gc").unwrap();
+
+        assert_eq!(substitute.pattern, "a#b");
+        assert_eq!(substitute.replacement, "c\\/d");
+        assert!(substitute.flags.global && substitute.flags.confirm);
+        assert_eq!(Substitute::parse(range(), "/a").unwrap().replacement, "");
+        assert!(Substitute::parse(range(), "/a/b/x").is_err());
+        assert!(Substitute::parse(range(), "a/b/").is_err());
+    }
+
+    #[test]
+    fn test_replace_first_or_every_match() {
+        assert_eq!(substitute("aa\naa\n", "/a/b/"), "ba\nba\n");
+        assert_eq!(substitute("aa\naa\n", "/a/b/g"), "bb\nbb\n");
+        assert_eq!(substitute("one\ntwo\n", "/$/;/"), "one;\ntwo;\n");
+        assert_eq!(substitute("one\ntwo\n", "/\\n//"), "onetwo");
+        assert_eq!(substitute("AbC\n", "/B/x/i"), "AxC\n");
+    }
+
+    #[test]
+    fn test_expand_groups_and_case() {
+        assert_eq!(
+            substitute("john smith\n", "/(\\w+) (\\w+)/\\2, $1/"),
+            "smith, john\n"
+        );
+        assert_eq!(substitute("john smith\n", "/\\w+/\\u&/g"), "John Smith\n");
+        assert_eq!(
+            substitute("john smith\n", "/(\\w+) /\\U\\1\\E-/"),
+            "JOHN-smith\n"
+        );
+        assert_eq!(substitute("a-b\n", "/(?<x>a)-/${x}\\n/"), "a\nb\n");
+        assert_eq!(substitute("price\n", "/price/$$5/"), "$5\n");
+    }
+
+    #[test]
+    fn test_applied_ranges() {
+        let replacements = [
+            Replacement {
+                range: 0..1,
+                text: "abc".into(),
+                line: 0,
+            },
+            Replacement {
+                range: 4..6,
+                text: String::new(),
+                line: 0,
+            },
+        ];
+
+        assert_eq!(applied(&replacements), [0..3, 6..6]);
+    }
+}
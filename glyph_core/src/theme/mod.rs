@@ -50,6 +50,21 @@ impl Theme {
     pub fn light() -> anyhow::Result<Self> {
         loader::ThemeLoader::default_light()
     }
+
+    /// style of a scope like `ui.error`, falling back to its parent scopes
+    /// and then to the default style
+    pub fn token(&self, scope: &str) -> Style {
+        let mut scope = scope;
+        loop {
+            if let Some(style) = self.tokens.get(scope) {
+                return *style;
+            }
+            match scope.rsplit_once('.') {
+                Some((parent, _)) => scope = parent,
+                None => return self.style,
+            }
+        }
+    }
 }
 
 impl Default for Theme {
@@ -258,6 +273,36 @@ impl Default for Theme {
                 bold: None,
             },
         );
+        tokens.insert(
+            "ui.error".to_string(),
+            Style {
+                fg: hex_to_rgb(Some("#e46876".to_string())).unwrap(),
+                bg: None,
+                italic: None,
+                underline: None,
+                bold: None,
+            },
+        );
+        tokens.insert(
+            "ui.selection".to_string(),
+            Style {
+                fg: None,
+                bg: hex_to_rgb(Some("#3a3f4b".to_string())).unwrap(),
+                italic: None,
+                underline: None,
+                bold: None,
+            },
+        );
+        tokens.insert(
+            "ui.search".to_string(),
+            Style {
+                fg: hex_to_rgb(Some("#1f1f28".to_string())).unwrap(),
+                bg: hex_to_rgb(Some("#c4a96b".to_string())).unwrap(),
+                italic: None,
+                underline: None,
+                bold: None,
+            },
+        );
 
         Self {
             name: "glyph-dark".to_string(),
@@ -326,3 +371,24 @@ impl Default for Gutter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_falls_back_to_parent_scope() {
+        let theme = Theme::default();
+
+        assert_eq!(
+            theme.token("function.method"),
+            theme.tokens["function.method"]
+        );
+        assert_eq!(
+            theme.token("function.method.call"),
+            theme.tokens["function.method"]
+        );
+        assert_eq!(theme.token("ui.error"), theme.tokens["ui.error"]);
+        assert_eq!(theme.token("nonexistent.scope"), theme.style);
+    }
+}
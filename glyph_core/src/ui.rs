@@ -1,6 +1,8 @@
 use crate::buffer::Buffer;
 use crate::cursor::Cursor;
 use crate::editor::{Cell, Mode, Position, Rect};
+use crate::gutter::Gutter;
+use crate::theme::Theme;
 
 pub trait Scrollable: Renderable {
     fn maybe_scroll(&mut self, cursor: &Cursor) {
@@ -42,4 +44,8 @@ pub trait Renderable {
     fn get_area(&self) -> &Rect;
     fn get_scroll(&self) -> &Position;
     fn set_scroll(&mut self, scroll: Position);
+    // the text starts `width` columns to the right of the gutter's left edge
+    fn set_gutter(&mut self, gutter: Box<dyn Gutter>, width: usize);
+    // everything is drawn again in the colors of the new theme
+    fn set_theme(&mut self, theme: &'static Theme);
 }
@@ -1,22 +1,47 @@
 use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::{Range, RangeInclusive};
 use std::rc::Rc;
 
+use crate::buffer::marks;
+use crate::buffer::text_object::{self, TextObject};
+use crate::buffer::transaction::{Assoc, Change, ChangeSet, Operation, Transaction};
 use crate::buffer::Buffer;
-use crate::cursor::Cursor;
-use crate::editor::{Action, Cell, Mode, Position, Rect};
+use crate::config::LineNumbers;
+use crate::cursor::{Cursor, MotionKind};
+use crate::editor::{Action, Cell, Mode, Operator, Position, Rect};
+use crate::gutter;
 use crate::highlight::Highlight;
-use crate::theme::Theme;
+use crate::jump_list::{Jump, JumpList};
+use crate::options::Options;
+use crate::register::{Register, RegisterKind, Registers};
+use crate::search::{Direction, Search};
+use crate::theme::{Style, Theme};
 use crate::ui::Scrollable;
 
 pub struct Window<'a> {
     pub id: usize,
     pub cursor: Cursor,
+    // the other cursors, edits and motions apply to each of them as well
+    cursors: Vec<Cursor>,
     highlight: Highlight<'a>,
     view: Box<dyn Scrollable + 'a>,
     pub buffer: Option<Rc<RefCell<Buffer>>>,
     // Currently, `layers[0]` is the buffer layer and `layers[1]` is the popups layer
     pub size: Rect,
     theme: &'a Theme,
+    // anchor, cursor and mode of the last selection, for `gv`
+    last_selection: Option<(usize, usize, Mode)>,
+    pub jumps: JumpList,
+    // whose matches are highlighted
+    search: Option<Search>,
+    // char ranges highlighted on top of the search, e.g. the replacements of
+    // a `:s` being typed
+    highlights: Vec<Range<usize>>,
+    // the window-local options, `apply_options` shows their changes
+    pub options: Options,
+    // columns taken by the gutter the view was given last
+    gutter_width: usize,
 }
 
 impl<'a> Window<'a> {
@@ -32,15 +57,56 @@ impl<'a> Window<'a> {
             buffer,
             highlight: Highlight::new(theme),
             cursor: Cursor::default(),
+            cursors: vec![],
             view,
             size,
             theme,
+            last_selection: None,
+            jumps: JumpList::default(),
+            search: None,
+            highlights: vec![],
+            options: Options::default(),
+            gutter_width: 0,
         }
     }
 
+    /// swaps the gutter for the one the options ask for, without line
+    /// numbers there's no gutter at all
+    pub fn apply_options(&mut self, mode: &Mode) -> anyhow::Result<()> {
+        self.set_gutter();
+        self.render(mode)
+    }
+
+    fn set_gutter(&mut self) {
+        let line_numbers = self.options.line_numbers();
+        let width = self.gutter_width();
+        let empty_line_char = self.options.char("emptylinechar");
+        let gutter = gutter::line_gutter(line_numbers, empty_line_char, width);
+        self.view.set_gutter(gutter, width);
+        self.gutter_width = width;
+    }
+
+    // numberwidth is the least the gutter takes, it grows to fit the number of
+    // the last line but never past the window
+    fn gutter_width(&self) -> usize {
+        if let LineNumbers::None = self.options.line_numbers() {
+            return 0;
+        }
+        let lines = self.buffer.as_ref().map_or(1, |b| b.borrow().marker.len());
+        let digits = lines.to_string().len();
+        let width = self.options.number("numberwidth").max(digits + 1);
+        width.min(self.size.width)
+    }
+
+    pub fn set_theme(&mut self, theme: &'static Theme, mode: &Mode) -> anyhow::Result<()> {
+        self.theme = theme;
+        self.highlight.set_theme(theme);
+        self.view.set_theme(theme);
+        self.render(mode)
+    }
+
     pub fn initialize(&mut self, mode: &Mode) -> anyhow::Result<()> {
-        self.render(mode)?;
-        Ok(())
+        self.apply_options(mode)
     }
 
     pub fn resize(&mut self, new_size: Rect, mode: &Mode) -> anyhow::Result<()> {
@@ -51,32 +117,926 @@ impl<'a> Window<'a> {
     }
 
     pub fn handle_action(&mut self, action: &Action, mode: &Mode) -> anyhow::Result<()> {
+        if !self.cursors.is_empty() && is_cursor_edit(action) {
+            self.edit_at_cursors(action)?;
+            return self.render(mode);
+        }
         let col = self.cursor.col;
         let row = self.cursor.row;
-        let mark = {
+        let position = self.cursor.absolute_position;
+        let prev_line = {
             let buffer = self.buffer.as_mut().unwrap().borrow_mut();
-            let mark = buffer.marker.get_by_cursor(self.cursor.absolute_position);
-            mark.unwrap()
+            buffer.marker.get_by_line(row)
         };
 
         {
             let mut buffer = self.buffer.as_mut().unwrap().borrow_mut();
-            buffer.handle_action(action, self.cursor.absolute_position)?;
-            self.cursor.handle_action(action, &mut buffer, mode);
+            let travel = match action {
+                Action::Undo => Some(buffer.undo()),
+                Action::Redo => Some(buffer.redo()),
+                Action::Earlier(jump) => Some(buffer.earlier(*jump)),
+                Action::Later(jump) => Some(buffer.later(*jump)),
+                _ => None,
+            };
+            // undoing an edit made at several cursors leaves only the main one
+            if travel.is_some() {
+                self.cursors.clear();
+            }
+            match travel {
+                Some(Some(position)) => self.cursor.move_to(position, &buffer),
+                Some(None) => (),
+                None => {
+                    buffer.handle_action(action, self.cursor.absolute_position)?;
+                    self.cursor.handle_action(action, &mut buffer, mode);
+                    for cursor in &mut self.cursors {
+                        cursor.handle_action(action, &mut buffer, mode);
+                    }
+                }
+            }
         }
+        self.merge_cursors();
 
-        if let Action::DeletePreviousChar = action {
+        if let (Action::DeletePreviousChar, Some(mark)) = (action, prev_line) {
             if let (0, 1..) = (col, row) {
                 self.cursor.col = mark.size.saturating_sub(1);
                 self.cursor.absolute_position = mark.start + mark.size.saturating_sub(1);
             }
         };
+        // remembered once the jump is done so `''` can still read the previous one
+        if action.is_jump() && self.cursor.absolute_position != position {
+            self.push_jump(position);
+        }
 
         self.render(mode)?;
         Ok(())
     }
 
+    /// puts or inserts the content of a register, `name` is the register
+    /// selected with `"x`, if any
+    pub fn handle_register_action(
+        &mut self,
+        action: &Action,
+        registers: &mut Registers,
+        name: Option<char>,
+        mode: &Mode,
+    ) -> anyhow::Result<()> {
+        if !self.cursors.is_empty() {
+            self.put_at_cursors(action, registers, name, mode)?;
+            return self.render(mode);
+        }
+        {
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            let pos = self.cursor.absolute_position;
+            match action {
+                Action::PasteBelow | Action::PasteAbove => {
+                    let register = registers.get(name).ok_or_else(|| {
+                        anyhow::anyhow!("nothing in register {}", name.unwrap_or('"'))
+                    })?;
+                    let cursor = buffer.put(&register, pos, matches!(action, Action::PasteBelow));
+                    self.cursor.move_to(cursor, &buffer);
+                }
+                Action::InsertRegister(name) => {
+                    let register = registers
+                        .get(Some(*name))
+                        .ok_or_else(|| anyhow::anyhow!("nothing in register {name}"))?;
+                    let transaction = Transaction::insert(&buffer.buffer, pos, &register.text)?
+                        .with_cursor(pos + register.text.chars().count());
+                    let cursor = buffer.apply(&transaction)?.unwrap_or(pos);
+                    self.cursor.move_to(cursor, &buffer);
+                }
+                _ => (),
+            }
+            self.cursor.clamp(&buffer, mode);
+        }
+        self.render(mode)
+    }
+
+    /// applies `operator` to the text between the cursor and where `motion`
+    /// repeated `count` times takes it, from every cursor when there are more
+    pub fn apply_operator(
+        &mut self,
+        operator: Operator,
+        motion: &Action,
+        count: usize,
+        registers: &mut Registers,
+        name: Option<char>,
+        mode: &Mode,
+    ) -> anyhow::Result<()> {
+        let ranges = {
+            let buffer = self.get_buffer();
+            let mut buffer = buffer.borrow_mut();
+            std::iter::once(&self.cursor)
+                .chain(&self.cursors)
+                .map(|cursor| operator_range(cursor, operator, motion, count, &mut buffer))
+                .collect::<Option<Vec<_>>>()
+        };
+        match ranges.as_deref() {
+            None => Ok(()),
+            Some([(from, to, kind)]) => {
+                self.operate(operator, *from, *to, *kind, registers, name, mode)
+            }
+            Some(ranges) => self.operate_at_cursors(operator, ranges, registers, name, mode),
+        }
+    }
+
+    /// applies `operator` to the selection of visual `mode`, ending it
+    pub fn apply_visual_operator(
+        &mut self,
+        operator: Operator,
+        registers: &mut Registers,
+        name: Option<char>,
+        mode: &Mode,
+    ) -> anyhow::Result<()> {
+        let Some((from, to, kind)) = self.cursor.selection(mode) else {
+            return Ok(());
+        };
+        self.forget_selection(mode);
+        self.operate(operator, from, to, kind, registers, name, &Mode::Normal)
+    }
+
+    /// runs an ex line command such as `:d` or `:m`, the cursor ends up on the
+    /// first non blank char of the last line it touched
+    pub fn line_command(
+        &mut self,
+        action: &Action,
+        registers: &mut Registers,
+        mode: &Mode,
+    ) -> anyhow::Result<()> {
+        let current = self.cursor.row;
+        let (operator, range, name) = match action {
+            Action::DeleteLines(range, name) => (Operator::Delete, range, *name),
+            Action::YankLines(range, name) => (Operator::Yank, range, *name),
+            action => return self.edit_lines(action, current, mode),
+        };
+        let (from, to) = {
+            let buffer = self.buffer.as_ref().unwrap().borrow();
+            let lines = range.resolve(&buffer, current)?;
+            let from = buffer.line_col_to_char(*lines.start(), 0);
+            (from, buffer.line_col_to_char(*lines.end(), 0))
+        };
+        self.operate(
+            operator,
+            from,
+            to,
+            RegisterKind::Linewise,
+            registers,
+            name,
+            mode,
+        )
+    }
+
+    fn edit_lines(&mut self, action: &Action, current: usize, mode: &Mode) -> anyhow::Result<()> {
+        {
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            let line = match action {
+                Action::MoveLines(range, address) => {
+                    let lines = range.resolve(&buffer, current)?;
+                    let below = address.below(&buffer, current)?;
+                    buffer.move_lines(*lines.start(), *lines.end(), below)?
+                }
+                Action::CopyLines(range, address) => {
+                    let lines = range.resolve(&buffer, current)?;
+                    let below = address.below(&buffer, current)?;
+                    buffer.copy_lines(*lines.start(), *lines.end(), below)
+                }
+                Action::JoinLines(range, spaces) => {
+                    let lines = range.resolve(&buffer, current)?;
+                    buffer.join_lines(*lines.start(), *lines.end(), *spaces);
+                    *lines.start()
+                }
+                Action::IndentLines {
+                    range,
+                    levels,
+                    dedent,
+                } => {
+                    let lines = range.resolve(&buffer, current)?;
+                    for _ in 0..*levels {
+                        buffer.indent(*lines.start(), *lines.end(), *dedent);
+                    }
+                    *lines.end()
+                }
+                Action::SortLines(range, sort) => {
+                    let lines = range.resolve(&buffer, current)?;
+                    let text = lines.clone().map(|line| buffer.line_text(line)).collect();
+                    let sorted = sort.sort(text)?;
+                    buffer.replace_lines(*lines.start(), *lines.end(), &sorted);
+                    *lines.start() + sorted.len().saturating_sub(1)
+                }
+                _ => return Ok(()),
+            };
+            let pos = buffer.first_non_blank(line);
+            self.cursor.move_to(pos, &buffer);
+            self.cursor.clamp(&buffer, mode);
+        }
+        self.render(mode)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn operate(
+        &mut self,
+        operator: Operator,
+        from: usize,
+        to: usize,
+        kind: RegisterKind,
+        registers: &mut Registers,
+        name: Option<char>,
+        mode: &Mode,
+    ) -> anyhow::Result<()> {
+        {
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            let (start_line, _) = buffer.char_to_line_col(from);
+            let (end_line, _) = buffer.char_to_line_col(match kind {
+                RegisterKind::Charwise if to > from => to - 1,
+                _ => to,
+            });
+            let first_line_start = buffer.line_col_to_char(start_line, 0);
+            // blocks start at their top left corner, wherever the selection began
+            let start = match kind {
+                RegisterKind::Blockwise => buffer.selection(from, to, kind)[0].start,
+                _ => from.min(to),
+            };
+
+            match operator {
+                Operator::Yank => {
+                    registers.yank(name, buffer.yank(from, to, kind))?;
+                    let ranges = buffer.selection(from, to, kind);
+                    if let (Some(first), Some(last)) = (ranges.first(), ranges.last()) {
+                        buffer.marks.set(marks::CHANGE_START, first.start);
+                        let end = last.end.saturating_sub(1).max(first.start);
+                        buffer.marks.set(marks::CHANGE_END, end);
+                    }
+                    self.cursor.move_to(start, &buffer);
+                }
+                Operator::Delete => {
+                    let (register, cursor) = buffer.delete(from, to, kind);
+                    registers.delete(name, register)?;
+                    self.cursor.move_to(cursor, &buffer);
+                }
+                // changing lines keeps an empty line to insert into
+                Operator::Change if kind == RegisterKind::Linewise => {
+                    let register = buffer.yank(from, to, kind);
+                    let end = buffer.line_col_to_char(end_line, usize::MAX);
+                    let (_, cursor) = buffer.delete(first_line_start, end, RegisterKind::Charwise);
+                    registers.delete(name, register)?;
+                    self.cursor.move_to(cursor, &buffer);
+                }
+                Operator::Change => {
+                    let (register, cursor) = buffer.delete(from, to, kind);
+                    registers.delete(name, register)?;
+                    self.cursor.move_to(cursor, &buffer);
+                }
+                Operator::Lowercase | Operator::Uppercase => {
+                    let ranges = buffer.selection(from, to, kind);
+                    buffer.transform(ranges, |text| match operator {
+                        Operator::Lowercase => text.to_lowercase(),
+                        _ => text.to_uppercase(),
+                    });
+                    self.cursor.move_to(start, &buffer);
+                }
+                Operator::Indent | Operator::Dedent => {
+                    buffer.indent(start_line, end_line, operator == Operator::Dedent);
+                    let start = buffer.first_non_blank(start_line);
+                    self.cursor.move_to(start, &buffer);
+                }
+                Operator::Format => {
+                    buffer.reindent(start_line, end_line);
+                    let start = buffer.first_non_blank(start_line);
+                    self.cursor.move_to(start, &buffer);
+                }
+            }
+            if operator != Operator::Change {
+                self.cursor.clamp(&buffer, mode);
+            }
+        }
+        self.render(mode)
+    }
+
+    // `operate` at every cursor with the range its motion covers, as a single
+    // change, the register gets the text of the main cursor's range
+    #[allow(clippy::single_range_in_vec_init)]
+    fn operate_at_cursors(
+        &mut self,
+        operator: Operator,
+        ranges: &[(usize, usize, RegisterKind)],
+        registers: &mut Registers,
+        name: Option<char>,
+        mode: &Mode,
+    ) -> anyhow::Result<()> {
+        {
+            let buffer = self.get_buffer();
+            let mut buffer = buffer.borrow_mut();
+            let (from, to, kind) = ranges[0];
+            let register = buffer.yank(from, to, kind);
+            let lines = ranges
+                .iter()
+                .map(|&(from, to, kind)| {
+                    let (start_line, _) = buffer.char_to_line_col(from);
+                    let (end_line, _) = buffer.char_to_line_col(match kind {
+                        RegisterKind::Charwise if to > from => to - 1,
+                        _ => to,
+                    });
+                    start_line..=end_line
+                })
+                .collect::<Vec<_>>();
+            let selections = ranges
+                .iter()
+                .zip(&lines)
+                .map(|(&(from, to, kind), lines)| match (operator, kind) {
+                    // changing lines keeps an empty line to insert into
+                    (Operator::Change, RegisterKind::Linewise) => {
+                        let start = buffer.line_col_to_char(*lines.start(), 0);
+                        vec![start..buffer.line_col_to_char(*lines.end(), usize::MAX)]
+                    }
+                    _ => buffer.selection(from, to, kind),
+                })
+                .collect::<Vec<_>>();
+            let starts = selections.iter().map(|ranges| ranges[0].start);
+
+            let positions = match operator {
+                Operator::Yank => {
+                    registers.yank(name, register)?;
+                    starts.collect()
+                }
+                Operator::Delete | Operator::Change => {
+                    let changes = selections
+                        .iter()
+                        .flatten()
+                        .map(|range| (range.start, range.end, None))
+                        .collect();
+                    let pos = self.cursor.absolute_position;
+                    let changes = buffer.change(merge(changes), pos)?;
+                    registers.delete(name, register)?;
+                    starts
+                        .map(|start| changes.map_pos(start, Assoc::Before))
+                        .collect()
+                }
+                Operator::Lowercase | Operator::Uppercase => {
+                    let changes = selections
+                        .iter()
+                        .flatten()
+                        .map(|range| (range.start, range.end, None))
+                        .collect();
+                    let ranges = merge(changes)
+                        .into_iter()
+                        .map(|(start, end, _)| start..end)
+                        .collect();
+                    buffer.transform(ranges, |text| match operator {
+                        Operator::Lowercase => text.to_lowercase(),
+                        _ => text.to_uppercase(),
+                    });
+                    starts.collect()
+                }
+                Operator::Indent | Operator::Dedent | Operator::Format => {
+                    // a line is only indented once however many cursors are on it
+                    let touched = lines.iter().cloned().flatten().collect::<BTreeSet<_>>();
+                    for line in touched {
+                        match operator {
+                            Operator::Format => buffer.reindent(line, line),
+                            _ => buffer.indent(line, line, operator == Operator::Dedent),
+                        }
+                    }
+                    lines
+                        .iter()
+                        .map(|lines| buffer.first_non_blank(*lines.start()))
+                        .collect()
+                }
+            };
+            self.place_cursors(positions, &buffer);
+            if operator != Operator::Change {
+                self.clamp_cursors(&buffer, mode);
+            }
+        }
+        self.render(mode)
+    }
+
+    // makes `action` at every cursor as a single change, each cursor ends up
+    // past its own edit
+    fn edit_at_cursors(&mut self, action: &Action) -> anyhow::Result<()> {
+        let buffer = self.get_buffer();
+        let mut buffer = buffer.borrow_mut();
+        let len = buffer.buffer.len_chars();
+        let newline = || Some("\n".to_string());
+        // the change each cursor makes and the side of it the cursor goes to
+        let edits = self
+            .positions()
+            .into_iter()
+            .map(|pos| {
+                let pos = pos.min(len);
+                let (line, _) = buffer.char_to_line_col(pos);
+                match action {
+                    Action::InsertChar(c) => ((pos, pos, Some(c.to_string())), Assoc::After),
+                    Action::InsertLine => ((pos, pos, newline()), Assoc::After),
+                    Action::DeletePreviousChar => {
+                        ((pos.saturating_sub(1), pos, None), Assoc::After)
+                    }
+                    Action::InsertLineBelow => {
+                        let end = buffer.line_col_to_char(line, usize::MAX);
+                        ((end, end, newline()), Assoc::After)
+                    }
+                    _ => {
+                        let start = buffer.line_col_to_char(line, 0);
+                        ((start, start, newline()), Assoc::Before)
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let changes = edits.iter().map(|(change, _)| change.clone()).collect();
+        let changes = buffer.change(merge(changes), self.cursor.absolute_position)?;
+        let positions = edits
+            .iter()
+            .map(|((start, _, _), assoc)| changes.map_pos(*start, *assoc))
+            .collect();
+        self.place_cursors(positions, &buffer);
+        Ok(())
+    }
+
+    // puts or inserts a register at every cursor as a single change
+    fn put_at_cursors(
+        &mut self,
+        action: &Action,
+        registers: &mut Registers,
+        name: Option<char>,
+        mode: &Mode,
+    ) -> anyhow::Result<()> {
+        let buffer = self.get_buffer();
+        let mut buffer = buffer.borrow_mut();
+        let (register, after) = match action {
+            Action::PasteBelow | Action::PasteAbove => {
+                let register = registers.get(name).ok_or_else(|| {
+                    anyhow::anyhow!("nothing in register {}", name.unwrap_or('"'))
+                })?;
+                (register, matches!(action, Action::PasteBelow))
+            }
+            Action::InsertRegister(name) => {
+                let register = registers
+                    .get(Some(*name))
+                    .ok_or_else(|| anyhow::anyhow!("nothing in register {name}"))?;
+                (Register::charwise(register.text), false)
+            }
+            _ => return Ok(()),
+        };
+        if register.kind == RegisterKind::Blockwise {
+            anyhow::bail!("can't put a block at several cursors");
+        }
+        // where each put starts and where its cursor goes from there
+        let mut puts = vec![];
+        let mut changes = vec![];
+        for pos in self.positions() {
+            let (put, cursor) = match action {
+                Action::InsertRegister(_) => {
+                    let end = pos + register.text.chars().count();
+                    (vec![(pos, pos, Some(register.text.clone()))], end)
+                }
+                _ => buffer.put_changes(&register, pos, after),
+            };
+            puts.push((put[0].0, cursor - put[0].0));
+            changes.extend(put);
+        }
+        let changes = buffer.change(merge(changes), self.cursor.absolute_position)?;
+        let positions = puts
+            .into_iter()
+            .map(|(at, offset)| changes.map_pos(at, Assoc::Before) + offset)
+            .collect();
+        self.place_cursors(positions, &buffer);
+        self.clamp_cursors(&buffer, mode);
+        Ok(())
+    }
+
+    // the position of the main cursor, then those of the others
+    fn positions(&self) -> Vec<usize> {
+        std::iter::once(&self.cursor)
+            .chain(&self.cursors)
+            .map(|cursor| cursor.absolute_position)
+            .collect()
+    }
+
+    // moves the main cursor to the first of `positions` and the others to the
+    // rest of them
+    fn place_cursors(&mut self, positions: Vec<usize>, buffer: &Buffer) {
+        let mut positions = positions.into_iter();
+        if let Some(pos) = positions.next() {
+            self.cursor.move_to(pos, buffer);
+        }
+        self.cursors = positions
+            .map(|pos| {
+                let mut cursor = Cursor::default();
+                cursor.move_to(pos, buffer);
+                cursor
+            })
+            .collect();
+        self.merge_cursors();
+    }
+
+    fn clamp_cursors(&mut self, buffer: &Buffer, mode: &Mode) {
+        self.cursor.clamp(buffer, mode);
+        for cursor in &mut self.cursors {
+            cursor.clamp(buffer, mode);
+        }
+        self.merge_cursors();
+    }
+
+    // cursors that ended up on the same char become one
+    fn merge_cursors(&mut self) {
+        let mut taken = HashSet::from([self.cursor.absolute_position]);
+        self.cursors
+            .retain(|cursor| taken.insert(cursor.absolute_position));
+        self.cursors.sort_by_key(|cursor| cursor.absolute_position);
+    }
+
+    /// the cursors besides the main one
+    pub fn cursors(&self) -> &[Cursor] {
+        &self.cursors
+    }
+
+    /// leaves only the main cursor
+    pub fn clear_cursors(&mut self, mode: &Mode) -> anyhow::Result<()> {
+        self.cursors.clear();
+        self.render(mode)
+    }
+
+    /// adds a cursor at the next match of `search` without one, as far into it
+    /// as the cursor is into the match it's on, and makes it the main cursor,
+    /// returns whether there was such a match
+    pub fn add_cursor_at_next_match(
+        &mut self,
+        search: &Search,
+        mode: &Mode,
+    ) -> anyhow::Result<bool> {
+        self.search = Some(search.clone());
+        let target = {
+            let buffer = self.buffer.as_ref().unwrap().borrow();
+            let pos = self.cursor.absolute_position;
+            let matches = search.buffer_matches(&buffer);
+            let offset = matches
+                .iter()
+                .find(|found| found.contains(&pos))
+                .map_or(0, |found| pos - found.start);
+            let taken = self.positions();
+            let (after, before): (Vec<_>, Vec<_>) = matches
+                .iter()
+                .map(|found| found.start + offset.min(found.len().saturating_sub(1)))
+                .filter(|target| !taken.contains(target))
+                .partition(|target| *target > pos);
+            // wrapping around the end of the buffer
+            after.into_iter().chain(before).next()
+        };
+        let Some(target) = target else {
+            self.render(mode)?;
+            return Ok(false);
+        };
+        self.cursors.push(self.cursor.clone());
+        self.jump_to(target, mode)?;
+        self.merge_cursors();
+        Ok(true)
+    }
+
+    /// ends the selection of visual block `mode` with a cursor on each of its
+    /// lines, at the column of the cursor or the end of shorter lines
+    pub fn add_block_cursors(&mut self, mode: &Mode) -> anyhow::Result<()> {
+        let Some((first, last, _, _)) = self.selected_block(mode) else {
+            return Ok(());
+        };
+        self.forget_selection(mode);
+        {
+            let buffer = self.get_buffer();
+            let buffer = buffer.borrow();
+            let (row, col) = (self.cursor.row, self.cursor.col);
+            let others = (first..=last)
+                .filter(|line| *line != row)
+                .map(|line| buffer.line_col_to_char(line, col));
+            let positions = std::iter::once(self.cursor.absolute_position)
+                .chain(others)
+                .collect();
+            self.place_cursors(positions, &buffer);
+            self.clamp_cursors(&buffer, &Mode::Normal);
+        }
+        self.render(&Mode::Normal)
+    }
+
+    /// ends the selection of visual `mode` with a cursor at the start of each
+    /// match of `search` inside it, the first one being the main cursor,
+    /// returns whether anything matched
+    pub fn add_cursors_at_matches(&mut self, search: &Search, mode: &Mode) -> anyhow::Result<bool> {
+        let Some((from, to, kind)) = self.cursor.selection(mode) else {
+            return Ok(false);
+        };
+        let buffer = self.get_buffer();
+        let starts = {
+            let buffer = buffer.borrow();
+            let matches = search.buffer_matches(&buffer);
+            // matches are sorted, only those from the start of each range on
+            // are looked at
+            buffer
+                .selection(from, to, kind)
+                .into_iter()
+                .flat_map(|range| {
+                    let first = matches.partition_point(|found| found.start < range.start);
+                    matches[first..]
+                        .iter()
+                        .take_while(|found| found.end <= range.end)
+                        .map(|found| found.start)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        if starts.is_empty() {
+            return Ok(false);
+        }
+        self.forget_selection(mode);
+        self.search = Some(search.clone());
+        {
+            let buffer = buffer.borrow();
+            self.place_cursors(starts, &buffer);
+            self.clamp_cursors(&buffer, &Mode::Normal);
+        }
+        self.render(&Mode::Normal)?;
+        Ok(true)
+    }
+
+    /// starts selecting from the cursor, switching between visual modes keeps
+    /// the selection
+    pub fn start_selection(&mut self, mode: &Mode) -> anyhow::Result<()> {
+        // only the main cursor selects
+        self.cursors.clear();
+        if self.cursor.anchor.is_none() {
+            self.cursor.anchor = Some(self.cursor.absolute_position);
+        }
+        self.render(mode)
+    }
+
+    /// ends the selection of visual `mode`, remembering it for `gv`
+    pub fn end_selection(&mut self, mode: &Mode) -> anyhow::Result<()> {
+        self.forget_selection(mode);
+        self.render(&Mode::Normal)
+    }
+
+    fn forget_selection(&mut self, mode: &Mode) {
+        if let Some(anchor) = self.cursor.anchor.take() {
+            let pos = self.cursor.absolute_position;
+            self.last_selection = Some((anchor, pos, mode.clone()));
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            buffer.marks.set(marks::VISUAL_START, anchor.min(pos));
+            buffer.marks.set(marks::VISUAL_END, anchor.max(pos));
+        }
+    }
+
+    /// selects again what was last selected, returning its visual mode
+    pub fn reselect(&mut self) -> anyhow::Result<Option<Mode>> {
+        let Some((anchor, pos, mode)) = self.last_selection.clone() else {
+            return Ok(None);
+        };
+        {
+            let buffer = self.buffer.as_ref().unwrap().borrow();
+            self.cursor.move_to(pos, &buffer);
+            self.cursor.clamp(&buffer, &mode);
+            self.cursor.anchor = Some(anchor.min(buffer.buffer.len_chars()));
+        }
+        self.render(&mode)?;
+        Ok(Some(mode))
+    }
+
+    /// moves the cursor to the other end of the selection
+    pub fn swap_selection_ends(&mut self, mode: &Mode) -> anyhow::Result<()> {
+        let Some(anchor) = self.cursor.anchor else {
+            return Ok(());
+        };
+        {
+            let buffer = self.buffer.as_ref().unwrap().borrow();
+            self.cursor.anchor = Some(self.cursor.absolute_position);
+            self.cursor.move_to(anchor, &buffer);
+        }
+        self.render(mode)
+    }
+
+    /// selects the text object around the cursor, returning the visual mode
+    /// that fits it
+    pub fn select_text_object(
+        &mut self,
+        object: TextObject,
+        around: bool,
+        count: usize,
+        mode: &Mode,
+    ) -> anyhow::Result<Mode> {
+        let mode = {
+            let buffer = self.buffer.as_ref().unwrap().borrow();
+            let pos = self.cursor.absolute_position;
+            let (range, mode) = match text_object::select(&buffer, pos, object, around, count) {
+                Some((range, MotionKind::Linewise)) => (range.start..range.end, Mode::VisualLine),
+                Some((range, _)) if !range.is_empty() => (range.start..range.end - 1, Mode::Visual),
+                _ => return Ok(mode.clone()),
+            };
+            self.cursor.anchor = Some(range.start);
+            self.cursor.move_to(range.end, &buffer);
+            mode
+        };
+        self.render(&mode)?;
+        Ok(mode)
+    }
+
+    /// first and last line, and first and last column, of the selection of
+    /// visual block `mode`
+    pub fn selected_block(&self, mode: &Mode) -> Option<(usize, usize, usize, usize)> {
+        let (from, to, RegisterKind::Blockwise) = self.cursor.selection(mode)? else {
+            return None;
+        };
+        let buffer = self.buffer.as_ref().unwrap().borrow();
+        let (from_line, from_col) = buffer.char_to_line_col(from);
+        let (to_line, to_col) = buffer.char_to_line_col(to);
+        Some((
+            from_line.min(to_line),
+            from_line.max(to_line),
+            from_col.min(to_col),
+            from_col.max(to_col),
+        ))
+    }
+
+    /// ends the selection moving the cursor where `I` or `A` start inserting,
+    /// for a block this returns the lines below the cursor and the column the
+    /// inserted text is replicated at
+    pub fn start_visual_insert(
+        &mut self,
+        append: bool,
+        mode: &Mode,
+    ) -> anyhow::Result<Option<(RangeInclusive<usize>, usize)>> {
+        let block = self.selected_block(mode);
+        let Some((from, to, kind)) = self.cursor.selection(mode) else {
+            return Ok(None);
+        };
+        self.forget_selection(mode);
+        let replicate = {
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            match block {
+                Some((first, last, left, right)) => {
+                    let col = if append { right + 1 } else { left };
+                    // appending past the end of a short line pads it first
+                    buffer.insert_at_column(first..=first, col, "", true);
+                    let pos = buffer.line_col_to_char(first, col);
+                    self.cursor.move_to(pos, &buffer);
+                    Some((first + 1..=last, col))
+                }
+                None => {
+                    let (first, _) = buffer.char_to_line_col(from.min(to));
+                    let (last, _) = buffer.char_to_line_col(from.max(to));
+                    let pos = match (append, kind) {
+                        (true, RegisterKind::Linewise) => buffer.line_col_to_char(last, usize::MAX),
+                        (false, RegisterKind::Linewise) => buffer.line_col_to_char(first, 0),
+                        (true, _) => from.max(to),
+                        (false, _) => from.min(to),
+                    };
+                    self.cursor.move_to(pos, &buffer);
+                    None
+                }
+            }
+        };
+        self.render(&Mode::Insert)?;
+        Ok(replicate)
+    }
+
+    /// shows another buffer on this window, moving the cursor to its start
+    pub fn set_buffer(&mut self, buffer: Rc<RefCell<Buffer>>, mode: &Mode) -> anyhow::Result<()> {
+        self.buffer = Some(buffer);
+        self.cursor = Cursor::default();
+        self.cursors.clear();
+        self.render(mode)
+    }
+
+    /// remembers `pos` of the current buffer in the jump list and as the `` ` ``
+    /// mark, for jumps that start from there
+    pub fn push_jump(&mut self, pos: usize) {
+        let jump = {
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            buffer.marks.set(marks::LAST_JUMP, pos);
+            Jump {
+                buffer: buffer.id,
+                anchor: buffer.marks.anchor(pos),
+            }
+        };
+        self.jumps.push(jump);
+    }
+
+    /// the buffer id and position of the previous entry of the jump list
+    pub fn jump_back(&mut self) -> Option<(usize, usize)> {
+        let from = {
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            Jump {
+                buffer: buffer.id,
+                anchor: buffer.marks.anchor(self.cursor.absolute_position),
+            }
+        };
+        let jump = self.jumps.back(from)?;
+        Some((jump.buffer, jump.anchor.get()))
+    }
+
+    /// the buffer id and position of the next entry of the jump list
+    pub fn jump_forward(&mut self) -> Option<(usize, usize)> {
+        let jump = self.jumps.forward()?;
+        Some((jump.buffer, jump.anchor.get()))
+    }
+
+    /// highlights the matches of `search`, or none
+    pub fn set_search(&mut self, search: Option<Search>, mode: &Mode) -> anyhow::Result<()> {
+        self.search = search;
+        self.render(mode)
+    }
+
+    /// highlights the text in `ranges` in place of the search
+    pub fn set_highlights(&mut self, ranges: Vec<Range<usize>>, mode: &Mode) -> anyhow::Result<()> {
+        self.highlights = ranges;
+        self.render(mode)
+    }
+
+    /// shows `changes` in the buffer without making them, highlighting the
+    /// text they insert, or stops showing them
+    pub fn preview(&mut self, changes: Option<ChangeSet>, mode: &Mode) -> anyhow::Result<()> {
+        let mut highlights = vec![];
+        {
+            let mut buffer = self.buffer.as_ref().unwrap().borrow_mut();
+            buffer.end_preview();
+            if let Some(changes) = changes {
+                let mut pos = 0;
+                for op in changes.operations() {
+                    match op {
+                        Operation::Retain(n) => pos += n,
+                        Operation::Insert(text) => {
+                            let len = text.chars().count();
+                            highlights.push(pos..pos + len);
+                            pos += len;
+                        }
+                        Operation::Delete(_) => (),
+                    }
+                }
+                buffer.preview(&changes);
+            }
+            self.cursor.clamp(&buffer, mode);
+        }
+        self.set_highlights(highlights, mode)
+    }
+
+    /// jumps to the next match of `search` in `direction` and highlights every
+    /// match, returns whether the search wrapped around the buffer or `None`
+    /// when nothing matches, or nothing does before the end without `wrap`
+    pub fn find(
+        &mut self,
+        search: &Search,
+        direction: Direction,
+        wrap: bool,
+        mode: &Mode,
+    ) -> anyhow::Result<Option<bool>> {
+        self.search = Some(search.clone());
+        let pos = self.cursor.absolute_position;
+        let found = search
+            .find(&self.get_buffer().borrow(), pos, direction)
+            .filter(|(_, wrapped)| wrap || !wrapped);
+        let Some((target, wrapped)) = found else {
+            self.render(mode)?;
+            return Ok(None);
+        };
+        self.push_jump(pos);
+        self.jump_to(target, mode)?;
+        Ok(Some(wrapped))
+    }
+
+    /// the word under the cursor, for `*` and `#`
+    pub fn word_under_cursor(&self) -> Option<String> {
+        let buffer = self.buffer.as_ref().unwrap().borrow();
+        let pos = self.cursor.absolute_position;
+        let (range, _) = text_object::select(&buffer, pos, TextObject::Word, false, 1)?;
+        let word = buffer.buffer.slice(range).to_string();
+        let is_word = word.chars().all(|c| c.is_alphanumeric() || c == '_');
+        (is_word && !word.is_empty()).then_some(word)
+    }
+
+    /// moves the cursor to `pos` of the current buffer
+    pub fn jump_to(&mut self, pos: usize, mode: &Mode) -> anyhow::Result<()> {
+        {
+            let buffer = self.buffer.as_ref().unwrap().borrow();
+            self.cursor.move_to(pos, &buffer);
+            self.cursor.clamp(&buffer, mode);
+        }
+        self.render(mode)
+    }
+
+    /// keeps the cursor on the same text after the buffer was changed by
+    /// something other than this window
+    pub fn buffer_changed(&mut self, changes: &ChangeSet, mode: &Mode) -> anyhow::Result<()> {
+        {
+            let buffer = self.get_buffer();
+            let buffer = buffer.borrow();
+            let positions = self
+                .positions()
+                .into_iter()
+                .map(|pos| changes.map_pos(pos, Assoc::Before))
+                .collect();
+            self.place_cursors(positions, &buffer);
+        }
+        self.render(mode)
+    }
+
     fn render(&mut self, mode: &Mode) -> anyhow::Result<()> {
+        // the buffer or the window may have outgrown the gutter
+        if self.gutter_width() != self.gutter_width {
+            self.set_gutter();
+        }
         self.view.maybe_scroll(&self.cursor);
         let cells = self.get_highlight();
         self.view.render(
@@ -90,7 +1050,6 @@ impl<'a> Window<'a> {
 
     fn get_highlight(&mut self) -> Vec<Cell> {
         let mut result: Vec<Cell> = Vec::new();
-        let mut current_byte_index = 0;
         let scroll = self.view.get_scroll();
         let buffer = self
             .buffer
@@ -101,10 +1060,10 @@ impl<'a> Window<'a> {
         let colors = self.highlight.colors(&buffer);
         let style = self.theme.style;
 
-        for c in buffer.chars() {
+        for (i, c) in buffer.chars().enumerate() {
             let cell = match colors
                 .iter()
-                .find(|token| current_byte_index >= token.start && current_byte_index < token.end)
+                .find(|token| i >= token.start && i < token.end)
             {
                 Some(token) => Cell {
                     c,
@@ -113,7 +1072,45 @@ impl<'a> Window<'a> {
                 None => Cell { c, style },
             };
             result.push(cell);
-            current_byte_index += c.len_utf8();
+        }
+        let start = {
+            let buffer = self.buffer.as_ref().unwrap().borrow();
+            buffer
+                .buffer
+                .line_to_char(scroll.row.min(buffer.buffer.len_lines()))
+        };
+        let highlights = match (&self.search, self.highlights.is_empty()) {
+            (_, false) => self
+                .highlights
+                .iter()
+                .map(|range| range.start.saturating_sub(start)..range.end.saturating_sub(start))
+                .map(|range| range.start.min(result.len())..range.end.min(result.len()))
+                .collect(),
+            (Some(search), true) => search.matches(&buffer),
+            (None, true) => vec![],
+        };
+        if !highlights.is_empty() {
+            let highlight = self.theme.token("ui.search");
+            for range in highlights {
+                for cell in &mut result[range] {
+                    cell.style = Style {
+                        fg: highlight.fg.or(cell.style.fg),
+                        bg: highlight.bg.or(cell.style.bg),
+                        ..cell.style
+                    };
+                }
+            }
+        }
+        // the other cursors look like a selected char
+        let selection = self.theme.token("ui.selection");
+        for cursor in &self.cursors {
+            let cell = cursor
+                .absolute_position
+                .checked_sub(start)
+                .and_then(|i| result.get_mut(i));
+            if let Some(cell) = cell {
+                cell.style.bg = selection.bg.or(cell.style.bg);
+            }
         }
 
         result
@@ -127,3 +1124,541 @@ impl<'a> Window<'a> {
         self.buffer.as_ref().unwrap().clone()
     }
 }
+
+// the text between `cursor` and where `motion` repeated `count` times takes
+// it, as the bounds and kind `operate` takes
+fn operator_range(
+    cursor: &Cursor,
+    operator: Operator,
+    motion: &Action,
+    count: usize,
+    buffer: &mut Buffer,
+) -> Option<(usize, usize, RegisterKind)> {
+    let pos = cursor.absolute_position;
+    match motion {
+        Action::Inner(object) | Action::Around(object) => {
+            let around = matches!(motion, Action::Around(_));
+            let (range, kind) = text_object::select(buffer, pos, *object, around, count)?;
+            let kind = match kind {
+                MotionKind::Linewise => RegisterKind::Linewise,
+                _ => RegisterKind::Charwise,
+            };
+            Some((range.start, range.end, kind))
+        }
+        motion => {
+            let (target, kind) = cursor.motion(motion, buffer, count)?;
+            let (from, mut to) = (pos.min(target), pos.max(target));
+            let (start_line, _) = buffer.char_to_line_col(from);
+            let (end_line, end_col) = buffer.char_to_line_col(to);
+            let start_line_end = buffer.line_col_to_char(start_line, usize::MAX);
+            let kind = match kind {
+                MotionKind::Linewise => RegisterKind::Linewise,
+                MotionKind::Inclusive => {
+                    to = (to + 1).min(buffer.line_col_to_char(end_line, usize::MAX));
+                    RegisterKind::Charwise
+                }
+                // the last word of a line doesn't take the next line with it
+                MotionKind::Exclusive
+                    if matches!(motion, Action::NextWord) && end_line > start_line =>
+                {
+                    to = start_line_end.max(from);
+                    RegisterKind::Charwise
+                }
+                // neither does a motion that ends at the start of another line
+                MotionKind::Exclusive if end_col == 0 && end_line > start_line => {
+                    to = start_line_end.max(from);
+                    RegisterKind::Charwise
+                }
+                MotionKind::Exclusive => RegisterKind::Charwise,
+            };
+            // `cw` only changes the word, not the spaces after it
+            if operator == Operator::Change && matches!(motion, Action::NextWord) {
+                while to > from && buffer.buffer.char(to - 1).is_whitespace() {
+                    to -= 1;
+                }
+            }
+            Some((from, to, kind))
+        }
+    }
+}
+
+// the edits made at every cursor when there are several
+fn is_cursor_edit(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::InsertChar(_)
+            | Action::InsertLine
+            | Action::DeletePreviousChar
+            | Action::InsertLineBelow
+            | Action::InsertLineAbove
+    )
+}
+
+// sorts the changes made at several cursors, dropping those that overlap an
+// earlier one, such as the same line opened twice by two cursors on it
+fn merge(mut changes: Vec<Change>) -> Vec<Change> {
+    changes.sort_by_key(|(start, end, _)| (*start, *end));
+    changes.dedup_by(|next, prev| next.0 < prev.1 || (next.0, next.1) == (prev.0, prev.1));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gutter::Gutter;
+    use crate::ui::Renderable;
+
+    #[derive(Default)]
+    struct NullView {
+        area: Rect,
+        scroll: Position,
+    }
+
+    impl Renderable for NullView {
+        fn render(&mut self, _: &[Cell], _: &Buffer, _: &Cursor, _: &Mode) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn resize(&mut self, new_area: Rect) {
+            self.area = new_area;
+        }
+        fn get_area(&self) -> &Rect {
+            &self.area
+        }
+        fn get_scroll(&self) -> &Position {
+            &self.scroll
+        }
+        fn set_scroll(&mut self, scroll: Position) {
+            self.scroll = scroll;
+        }
+        fn set_gutter(&mut self, _gutter: Box<dyn Gutter>, _width: usize) {}
+        fn set_theme(&mut self, _theme: &'static Theme) {}
+    }
+
+    impl Scrollable for NullView {}
+
+    fn window<'a>(theme: &'a Theme, text: &str) -> Window<'a> {
+        let buffer = Rc::new(RefCell::new(Buffer::from_string(1, text)));
+        let size = Rect::new(0, 0, 80, 24);
+        let view = NullView {
+            area: size.clone(),
+            ..Default::default()
+        };
+        Window::new(1, Some(buffer), theme, size, Box::new(view))
+    }
+
+    fn operate(window: &mut Window, operator: Operator, motion: Action, count: usize) -> Registers {
+        let mut registers = Registers::default();
+        window
+            .apply_operator(
+                operator,
+                &motion,
+                count,
+                &mut registers,
+                None,
+                &Mode::Normal,
+            )
+            .unwrap();
+        registers
+    }
+
+    fn text(window: &Window) -> String {
+        window.get_buffer().borrow().to_string()
+    }
+
+    #[test]
+    fn test_delete_words_with_count() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "one two three\nfour");
+
+        let mut registers = operate(&mut window, Operator::Delete, Action::NextWord, 2);
+
+        assert_eq!(text(&window), "three\nfour");
+        assert_eq!(registers.get(None).unwrap().text, "one two ");
+    }
+
+    #[test]
+    fn test_delete_word_stops_at_line_end() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "one\n  two");
+
+        operate(&mut window, Operator::Delete, Action::NextWord, 1);
+
+        assert_eq!(text(&window), "\n  two");
+    }
+
+    #[test]
+    fn test_change_word_keeps_spaces() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "one two");
+
+        operate(&mut window, Operator::Change, Action::NextWord, 1);
+
+        assert_eq!(text(&window), " two");
+        assert_eq!(window.cursor.absolute_position, 0);
+    }
+
+    #[test]
+    fn test_linewise_operators() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "a\nb\nc\n");
+
+        let mut registers = operate(&mut window, Operator::Delete, Action::MoveDown, 1);
+        assert_eq!(text(&window), "c\n");
+        assert_eq!(registers.get(None).unwrap().text, "a\nb\n");
+
+        operate(&mut window, Operator::Indent, Action::CurrentLine, 1);
+        assert_eq!(text(&window), "    c\n");
+
+        operate(&mut window, Operator::Uppercase, Action::CurrentLine, 1);
+        assert_eq!(text(&window), "    C\n");
+    }
+
+    #[test]
+    fn test_delete_until_line_end_is_inclusive() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "abc\nd");
+        window.cursor.move_to(1, &window.get_buffer().borrow());
+
+        operate(&mut window, Operator::Delete, Action::MoveToLineEnd, 1);
+
+        assert_eq!(text(&window), "a\nd");
+        assert_eq!(window.cursor.absolute_position, 0);
+    }
+
+    #[test]
+    fn test_text_objects() {
+        let theme = Theme::default();
+        let mut call = window(&theme, "call(foo, bar(baz))\n");
+        call.cursor.move_to(6, &call.get_buffer().borrow());
+
+        operate(
+            &mut call,
+            Operator::Change,
+            Action::Inner(TextObject::Bracket('(')),
+            1,
+        );
+        assert_eq!(text(&call), "call()\n");
+        assert_eq!(call.cursor.absolute_position, 5);
+
+        let mut block = window(&theme, "fn f() {\n    a;\n    b;\n}\n");
+        block.cursor.move_to(14, &block.get_buffer().borrow());
+
+        let mut registers = operate(
+            &mut block,
+            Operator::Delete,
+            Action::Inner(TextObject::Bracket('{')),
+            1,
+        );
+        assert_eq!(text(&block), "fn f() {\n}\n");
+        assert_eq!(registers.get(None).unwrap().kind, RegisterKind::Linewise);
+    }
+
+    fn select(window: &mut Window, from: usize, to: usize, mode: &Mode) {
+        window.cursor.move_to(from, &window.get_buffer().borrow());
+        window.start_selection(mode).unwrap();
+        window.cursor.move_to(to, &window.get_buffer().borrow());
+    }
+
+    #[test]
+    fn test_visual_operators() {
+        let theme = Theme::default();
+        let mut registers = Registers::default();
+
+        let mut chars = window(&theme, "one two three\n");
+        select(&mut chars, 6, 4, &Mode::Visual);
+        chars
+            .apply_visual_operator(Operator::Delete, &mut registers, None, &Mode::Visual)
+            .unwrap();
+        assert_eq!(text(&chars), "one  three\n");
+        assert_eq!(registers.get(None).unwrap().text, "two");
+        assert_eq!(chars.cursor.anchor, None);
+
+        let mut lines = window(&theme, "a\nb\nc\n");
+        select(&mut lines, 4, 2, &Mode::VisualLine);
+        lines
+            .apply_visual_operator(Operator::Yank, &mut registers, None, &Mode::VisualLine)
+            .unwrap();
+        assert_eq!(registers.get(None).unwrap().text, "b\nc\n");
+        assert_eq!(lines.cursor.absolute_position, 2);
+
+        let mut block = window(&theme, "abcd\nefgh\nijkl\n");
+        select(&mut block, 2, 11, &Mode::VisualBlock);
+        block
+            .apply_visual_operator(Operator::Delete, &mut registers, None, &Mode::VisualBlock)
+            .unwrap();
+        assert_eq!(text(&block), "ad\neh\nil\n");
+    }
+
+    #[test]
+    fn test_reselect_and_swap_ends() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "one two three\n");
+        select(&mut window, 4, 6, &Mode::Visual);
+
+        window.swap_selection_ends(&Mode::Visual).unwrap();
+        assert_eq!(window.cursor.absolute_position, 4);
+        assert_eq!(
+            window.cursor.selection(&Mode::Visual),
+            Some((4, 7, RegisterKind::Charwise))
+        );
+
+        window.end_selection(&Mode::Visual).unwrap();
+        window.cursor.move_to(0, &window.get_buffer().borrow());
+        assert_eq!(window.reselect().unwrap(), Some(Mode::Visual));
+        assert_eq!(
+            window.cursor.selection(&Mode::Visual),
+            Some((4, 7, RegisterKind::Charwise))
+        );
+    }
+
+    #[test]
+    fn test_select_text_objects() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "f(a, b)\n\nnext\n");
+        window.cursor.move_to(3, &window.get_buffer().borrow());
+        window.start_selection(&Mode::Visual).unwrap();
+
+        let mode = window
+            .select_text_object(TextObject::Bracket('('), false, 1, &Mode::Visual)
+            .unwrap();
+        assert_eq!(mode, Mode::Visual);
+        assert_eq!(
+            window.cursor.selection(&mode),
+            Some((2, 6, RegisterKind::Charwise))
+        );
+
+        let mode = window
+            .select_text_object(TextObject::Paragraph, true, 1, &mode)
+            .unwrap();
+        assert_eq!(mode, Mode::VisualLine);
+        assert_eq!(
+            window.cursor.selection(&mode),
+            Some((0, 8, RegisterKind::Linewise))
+        );
+    }
+
+    #[test]
+    fn test_block_append_pads_short_lines() {
+        let theme = Theme::default();
+        let mut window = window(&theme, "ab\nabcd\n");
+        select(&mut window, 0, 4, &Mode::VisualBlock);
+
+        let (lines, col) = window
+            .start_visual_insert(true, &Mode::VisualBlock)
+            .unwrap()
+            .unwrap();
+        assert_eq!((lines.clone(), col), (1..=1, 2));
+        assert_eq!(window.cursor.absolute_position, 2);
+
+        window
+            .get_buffer()
+            .borrow_mut()
+            .insert_at_column(lines, col, "X", true);
+        assert_eq!(text(&window), "ab\nabXcd\n");
+    }
+
+    #[test]
+    fn test_marks_and_jumps() {
+        let theme = Theme::default();
+        let mut win = window(&theme, "one\n  two\nthree\nfour\n");
+        win.jump_to(6, &Mode::Normal).unwrap();
+        win.get_buffer().borrow_mut().marks.set('a', 6);
+
+        // the mark follows its text when a line is added above it
+        win.jump_to(0, &Mode::Normal).unwrap();
+        win.handle_action(&Action::InsertLineAbove, &Mode::Normal)
+            .unwrap();
+        win.handle_action(&Action::MoveToBottom, &Mode::Normal)
+            .unwrap();
+        let bottom = win.cursor.absolute_position;
+        win.handle_action(&Action::GoToMarkLine('a'), &Mode::Normal)
+            .unwrap();
+        assert_eq!(win.cursor.absolute_position, 7);
+        assert_eq!(win.get_buffer().borrow().marks.get('\''), Some(bottom));
+
+        assert_eq!(win.jump_back(), Some((1, bottom)));
+        assert_eq!(win.jump_back(), Some((1, 0)));
+        assert_eq!(win.jump_forward(), Some((1, bottom)));
+
+        win.jump_to(0, &Mode::Normal).unwrap();
+        operate(&mut win, Operator::Delete, Action::GoToMarkLine('a'), 1);
+        assert_eq!(text(&win), "three\nfour\n");
+    }
+
+    #[test]
+    fn test_search_jumps_and_highlights() {
+        let theme = Theme::default();
+        let mut win = window(
+            &theme,
+            "foo bar
+bar foo
+",
+        );
+        let search = Search::new("foo").unwrap();
+
+        assert_eq!(
+            win.find(&search, Direction::Forward, true, &Mode::Normal)
+                .unwrap(),
+            Some(false)
+        );
+        assert_eq!(win.cursor.absolute_position, 12);
+        assert_eq!(
+            win.find(&search, Direction::Forward, true, &Mode::Normal)
+                .unwrap(),
+            Some(true)
+        );
+        assert_eq!(win.cursor.absolute_position, 0);
+        assert_eq!(win.jump_back(), Some((1, 12)));
+
+        let missing = Search::new("baz").unwrap();
+        assert_eq!(
+            win.find(&missing, Direction::Forward, true, &Mode::Normal)
+                .unwrap(),
+            None
+        );
+        win.jump_to(4, &Mode::Normal).unwrap();
+        assert_eq!(win.word_under_cursor().as_deref(), Some("bar"));
+    }
+
+    #[test]
+    fn test_line_commands() {
+        let theme = Theme::default();
+        let mut win = window(&theme, "c\n  a\nb\n");
+        let mut registers = Registers::default();
+        let mut run = |win: &mut Window, command: &str| {
+            let action = crate::ex::parse(command).unwrap();
+            win.line_command(&action, &mut registers, &Mode::Normal)
+        };
+
+        run(&mut win, "1m$").unwrap();
+        assert_eq!(text(&win), "  a\nb\nc\n");
+        assert_eq!(win.cursor.row, 2);
+        run(&mut win, "1t0").unwrap();
+        assert_eq!(text(&win), "  a\n  a\nb\nc\n");
+        assert_eq!(win.cursor.absolute_position, 2);
+        run(&mut win, "2,3d").unwrap();
+        assert_eq!(text(&win), "  a\nc\n");
+        run(&mut win, "%j").unwrap();
+        assert_eq!(text(&win), "  a c\n");
+        run(&mut win, "<").unwrap();
+        assert_eq!(text(&win), "a c\n");
+        assert!(run(&mut win, "5d").is_err());
+
+        let mut win = window(&theme, "b\na\nb\n");
+        run(&mut win, "sort u").unwrap();
+        assert_eq!(text(&win), "a\nb\n");
+    }
+
+    fn positions(window: &Window) -> Vec<usize> {
+        window.positions()
+    }
+
+    #[test]
+    fn test_edit_at_cursors() {
+        let theme = Theme::default();
+        let mut win = window(&theme, "foo\nfoo\nbar foo\n");
+        let search = Search::word("foo").unwrap();
+
+        assert!(win
+            .add_cursor_at_next_match(&search, &Mode::Normal)
+            .unwrap());
+        assert!(win
+            .add_cursor_at_next_match(&search, &Mode::Normal)
+            .unwrap());
+        assert!(!win
+            .add_cursor_at_next_match(&search, &Mode::Normal)
+            .unwrap());
+        assert_eq!(positions(&win), [12, 0, 4]);
+
+        for action in [Action::InsertChar('x'), Action::InsertChar('y')] {
+            win.handle_action(&action, &Mode::Insert).unwrap();
+        }
+        win.handle_action(&Action::DeletePreviousChar, &Mode::Insert)
+            .unwrap();
+        assert_eq!(text(&win), "xfoo\nxfoo\nbar xfoo\n");
+        assert_eq!(positions(&win), [15, 1, 6]);
+
+        win.handle_action(&Action::MoveToLineEnd, &Mode::Normal)
+            .unwrap();
+        assert_eq!(positions(&win), [17, 3, 8]);
+
+        // the whole edit is a single undo step
+        win.get_buffer().borrow_mut().commit();
+        win.handle_action(&Action::Undo, &Mode::Normal).unwrap();
+        assert_eq!(text(&win), "foo\nfoo\nbar foo\n");
+        assert!(win.cursors().is_empty());
+    }
+
+    #[test]
+    fn test_operators_at_cursors() {
+        let theme = Theme::default();
+        let mut win = window(&theme, "one two\none two\n");
+        select(&mut win, 0, 8, &Mode::VisualBlock);
+        win.add_block_cursors(&Mode::VisualBlock).unwrap();
+        assert_eq!(positions(&win), [8, 0]);
+
+        let mut registers = operate(&mut win, Operator::Delete, Action::NextWord, 1);
+        assert_eq!(text(&win), "two\ntwo\n");
+        assert_eq!(registers.get(None).unwrap().text, "one ");
+        assert_eq!(positions(&win), [4, 0]);
+
+        win.handle_register_action(&Action::PasteAbove, &mut registers, None, &Mode::Normal)
+            .unwrap();
+        assert_eq!(text(&win), "one two\none two\n");
+        assert_eq!(positions(&win), [11, 3]);
+
+        win.handle_action(&Action::MoveToLineStart, &Mode::Normal)
+            .unwrap();
+        win.handle_action(&Action::InsertLineBelow, &Mode::Normal)
+            .unwrap();
+        assert_eq!(text(&win), "one two\n\none two\n\n");
+        assert_eq!(positions(&win), [17, 8]);
+    }
+
+    #[test]
+    fn test_cursors_at_matches_in_selection() {
+        let theme = Theme::default();
+        let mut win = window(&theme, "a1 b2\nc3 d4\ne5\n");
+        let search = Search::new("[0-9]").unwrap();
+        select(&mut win, 3, 8, &Mode::Visual);
+
+        assert!(win.add_cursors_at_matches(&search, &Mode::Visual).unwrap());
+        assert_eq!(positions(&win), [4, 7]);
+        assert_eq!(win.cursor.anchor, None);
+
+        operate(&mut win, Operator::Uppercase, Action::MoveLeft, 1);
+        win.handle_action(&Action::InsertChar('-'), &Mode::Insert)
+            .unwrap();
+        assert_eq!(text(&win), "a1 -B2\n-C3 d4\ne5\n");
+        win.clear_cursors(&Mode::Normal).unwrap();
+        assert!(win.cursors().is_empty());
+
+        // both cursors on a line only open one line above it
+        let mut win = window(&theme, "a1 b2\n");
+        select(&mut win, 0, 4, &Mode::Visual);
+        assert!(win.add_cursors_at_matches(&search, &Mode::Visual).unwrap());
+        win.handle_action(&Action::InsertLineAbove, &Mode::Normal)
+            .unwrap();
+        assert_eq!(text(&win), "\na1 b2\n");
+        assert!(win.cursors().is_empty());
+    }
+
+    #[test]
+    fn test_gutter_fits_line_numbers_and_window() {
+        let theme = Theme::default();
+        let mut win = window(&theme, &"line\n".repeat(150));
+        let (mut global, mut buffer) = (Options::default(), Options::default());
+        let mut scopes = crate::options::Scopes {
+            global: &mut global,
+            window: &mut win.options,
+            buffer: &mut buffer,
+        };
+        let setting = crate::options::Setting::parse("nuw=3").unwrap();
+        scopes.apply(&setting, true, &Options::default()).unwrap();
+
+        win.apply_options(&Mode::Normal).unwrap();
+        assert_eq!(win.gutter_width, 4);
+        win.resize(Rect::new(0, 0, 2, 24), &Mode::Normal).unwrap();
+        assert_eq!(win.gutter_width, 2);
+    }
+}
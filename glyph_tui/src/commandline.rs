@@ -1,14 +1,150 @@
-use glyph_core::commandline::Commandline;
+use std::io::{stdout, Stdout};
+
+use crossterm::{
+    cursor,
+    style::{self, Print},
+    QueueableCommand,
+};
+use glyph_core::commandline::{Commandline, Message, MessageKind};
 use glyph_core::editor::Rect;
+use glyph_core::theme::{Style, Theme};
+
+// between the items of the wildmenu
+const SEPARATOR: &str = "  ";
 
-#[derive(Debug, Default)]
-pub struct TuiCommandline {}
+#[derive(Debug)]
+pub struct TuiCommandline<'a> {
+    area: Rect,
+    theme: &'a Theme,
+    message: Option<Message>,
+    // the prompt and what was typed after it, and the chars before the cursor
+    input: Option<(String, usize)>,
+    // drawn over the statusline, which is drawn again before the commandline
+    wildmenu: Option<(Vec<String>, Option<usize>)>,
+    stdout: Stdout,
+}
 
-impl Commandline for TuiCommandline {
-    fn new(area: Rect) -> Self {
-        Self {}
+impl<'a> Commandline<'a> for TuiCommandline<'a> {
+    fn new(area: Rect, theme: &'a Theme) -> Self {
+        Self {
+            area,
+            theme,
+            message: None,
+            input: None,
+            wildmenu: None,
+            stdout: stdout(),
+        }
     }
+
     fn render(&mut self) -> anyhow::Result<()> {
+        let (text, style) = match (&self.input, &self.message) {
+            (Some((input, _)), _) => (input.as_str(), self.theme.style),
+            (None, Some(message)) => {
+                let style = match message.kind {
+                    MessageKind::Info => self.theme.style,
+                    MessageKind::Error => self.theme.token("ui.error"),
+                };
+                (message.text.as_str(), style)
+            }
+            (None, None) => ("", self.theme.style),
+        };
+        let text = text
+            .chars()
+            .filter(|c| !c.is_control())
+            .chain(std::iter::repeat(' '))
+            .take(self.area.width)
+            .collect::<String>();
+
+        self.stdout
+            .queue(cursor::MoveTo(self.area.col as u16, self.area.row as u16))?;
+        self.print(&text, style)?;
+        if self.area.row > 0 {
+            self.render_wildmenu()?;
+        }
+        Ok(())
+    }
+
+    fn set_message(&mut self, message: Message) {
+        self.message = Some(message);
+    }
+
+    fn clear_message(&mut self) {
+        self.message = None;
+    }
+
+    fn set_input(&mut self, prompt: char, text: &str, cursor: usize) {
+        self.input = Some((format!("{prompt}{text}"), cursor + 1));
+    }
+
+    fn clear_input(&mut self) {
+        self.input = None;
+    }
+
+    fn input_cursor(&self) -> Option<(u16, u16)> {
+        let (_, cursor) = self.input.as_ref()?;
+        let col = (self.area.col + cursor).min(self.area.col + self.area.width - 1);
+        Some((col as u16, self.area.row as u16))
+    }
+
+    fn set_wildmenu(&mut self, items: &[String], selected: Option<usize>) {
+        self.wildmenu = Some((items.to_vec(), selected));
+    }
+
+    fn clear_wildmenu(&mut self) {
+        self.wildmenu = None;
+    }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+    }
+}
+
+impl TuiCommandline<'_> {
+    // the items from the first one that leaves room for the selected one, as
+    // many as fit on the row above the commandline
+    fn render_wildmenu(&mut self) -> anyhow::Result<()> {
+        let Some((items, selected)) = self.wildmenu.clone() else {
+            return Ok(());
+        };
+        let width = |item: &String| item.chars().count() + SEPARATOR.len();
+        let mut first = 0;
+        if let Some(selected) = selected {
+            while first < selected
+                && items[first..=selected].iter().map(width).sum::<usize>() > self.area.width
+            {
+                first += 1;
+            }
+        }
+
+        let style = self.theme.statusline.inner;
+        let row = self.area.row as u16 - 1;
+        self.stdout
+            .queue(cursor::MoveTo(self.area.col as u16, row))?;
+        let mut used = 0;
+        for (i, item) in items.iter().enumerate().skip(first) {
+            if used + width(item) > self.area.width {
+                break;
+            }
+            let item_style = match Some(i) == selected {
+                true => self.theme.token("ui.selection"),
+                false => style,
+            };
+            self.print(item, item_style)?;
+            self.print(SEPARATOR, style)?;
+            used += width(item);
+        }
+        self.print(&" ".repeat(self.area.width - used), style)?;
+        Ok(())
+    }
+
+    fn print(&mut self, text: &str, style: Style) -> anyhow::Result<()> {
+        self.stdout.queue(style::SetBackgroundColor(
+            style.bg.or(self.theme.style.bg).unwrap(),
+        ))?;
+        self.stdout.queue(style::SetForegroundColor(
+            style.fg.or(self.theme.style.fg).unwrap(),
+        ))?;
+        self.stdout.queue(Print(text))?;
         Ok(())
     }
 }
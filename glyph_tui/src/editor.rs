@@ -1,31 +1,54 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{stdout, Stdout, Write};
+use std::ops::RangeInclusive;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use glyph_core::buffer::diff;
+use glyph_core::buffer::file::{self, DiskStatus};
+use glyph_core::buffer::marks::{self, Anchor, Marks};
+use glyph_core::buffer::swap::Swap;
+use glyph_core::buffer::transaction::{Assoc, ChangeSet, Transaction};
+use glyph_core::buffer::undo;
 use glyph_core::buffer::Buffer;
-use glyph_core::commandline::Commandline;
-use glyph_core::config::Config;
-use glyph_core::editor::{Action, KeyAction, Mode, Rect, Size};
+use glyph_core::clipboard;
+use glyph_core::commandline::complete::{self, Sources, Wildmenu};
+use glyph_core::commandline::history::{Browsing, Histories};
+use glyph_core::commandline::input::Input;
+use glyph_core::commandline::{Commandline, Message};
+use glyph_core::config::{Config, EditorBackground, UndoConfig};
+use glyph_core::editor::{Action, KeyAction, Mode, Operator, Rect, Size};
 use glyph_core::event_handler::EventHandler;
+use glyph_core::ex::LineRange;
+use glyph_core::keys;
 use glyph_core::lsp::{IncomingMessage, LspClient};
+use glyph_core::options::{Options, Scope, Scopes};
+use glyph_core::prompt::Prompt;
+use glyph_core::register::{self, Registers};
+use glyph_core::search::{Direction, Search};
 use glyph_core::statusline::{Statusline, StatuslineUpdate};
+use glyph_core::substitute::{self, Confirm, Replacement, Substitute};
 use glyph_core::tab::Tab;
 use glyph_core::theme::Theme;
 use glyph_core::window::Window;
 
 use crossterm::cursor;
-use crossterm::event::EventStream;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent};
 use crossterm::{terminal, QueueableCommand};
 use futures::{future::FutureExt, StreamExt};
 
 use crate::view::TuiView;
 
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+// how many edits are journaled at most before the swap file is written
+const SWAP_EDITS: usize = 200;
+
 pub struct TuiEditor<'a, S, C, E>
 where
     S: Statusline<'a>,
-    C: Commandline,
+    C: Commandline<'a>,
     E: EventHandler,
 {
     event_handler: E,
@@ -35,6 +58,60 @@ where
     statusline: S,
     commandline: C,
     mode: Mode,
+    command: Input,
+    // the histories of `:` and of `/` and `?`, the walk through one of them
+    // with `Up` and `Down`, and the completions `Tab` walks
+    histories: Histories,
+    browsing: Option<Browsing>,
+    wildmenu: Option<Wildmenu>,
+    prompt: Option<Prompt>,
+    registers: Registers,
+    // register selected with `"x` for the next yank, delete or put
+    pending_register: Option<char>,
+    // text typed during the current insert session, for the `.` register
+    inserted: String,
+    block_insert: Option<BlockInsert>,
+    // how many more times the key that entered insert mode and what is typed
+    // are replayed when leaving it
+    insert_repeat: Option<(usize, Vec<Action>)>,
+    // the last change made from normal mode, replayed by `.`, and the one
+    // being recorded while its insert session lasts
+    last_change: Option<Change>,
+    pending_change: Option<Change>,
+    // keys of the macros and `:normal` commands being played, fed to the
+    // event handler as if typed
+    macro_queue: VecDeque<Queued>,
+    last_macro: Option<char>,
+    // set by failing motions and operators, aborting a running macro
+    action_failed: bool,
+    // set once `:q` succeeded
+    quit: bool,
+    // the last search, what `n` searches and the direction it goes in
+    search: Option<Search>,
+    search_direction: Direction,
+    // direction and start of the search being typed, the cursor moves to its
+    // first match as it is typed and goes back to the start when cancelled
+    incremental_search: Option<(Direction, usize)>,
+    search_count: Option<(usize, usize)>,
+    // the matches of a `:s` with the `c` flag waiting for confirmation
+    confirmation: Option<Confirmation>,
+    // buffers opened with `:e` persist their undo history like the first one
+    undo: UndoConfig,
+    // the global values of the options, new windows and buffers start with
+    // them, and the values the config set, which `:set option&` goes back to
+    options: Options,
+    config_options: Options,
+    // what `:colorscheme` loads the theme for, and the themes it loaded
+    background: EditorBackground,
+    themes: HashMap<String, &'static Theme>,
+    last_disk_check: Instant,
+    // the buffer that changed on disk the prompt is about, the one of the
+    // window when none
+    disk_prompt: Option<usize>,
+    last_swap_write: Instant,
+    // the scratch buffer showing the diff of a swap file, dropped once the
+    // prompt about the swap file is answered
+    swap_diff: Option<usize>,
     tabs: HashMap<usize, Tab>,
     windows: HashMap<usize, Window<'a>>,
     buffers: HashMap<usize, Rc<RefCell<Buffer>>>,
@@ -43,6 +120,60 @@ where
     active_buffer: usize,
 }
 
+// where the text typed after `I`, `A` or `c` on a block is replicated once
+// insert mode is left
+struct BlockInsert {
+    lines: RangeInclusive<usize>,
+    col: usize,
+    // pads lines too short to reach `col` instead of skipping them
+    pad: bool,
+}
+
+// the matches of a `:s` with the `c` flag, they are all replaced at once,
+// as a single change, when no match is left to confirm
+struct Confirmation {
+    current: Option<Replacement>,
+    // the next match is last
+    pending: Vec<Replacement>,
+    accepted: Vec<Replacement>,
+}
+
+// what is played from the macro queue, `:normal` on a range of lines types
+// its keys from the start of each of them, anchored along with the start of
+// the next line so the lines deleted or joined on a previous one are skipped
+#[derive(Debug, Clone)]
+enum Queued {
+    Key(Event),
+    Line(Anchor, Anchor),
+}
+
+/// A command that changed the buffer, as the actions it was made of and the
+/// register it used.
+#[derive(Debug, Clone)]
+struct Change {
+    actions: Vec<Action>,
+    register: Option<char>,
+}
+
+impl Change {
+    // the count of an operator or repeated action is replaced, anything else
+    // is replayed `count` times
+    fn with_count(&self, count: usize) -> Change {
+        let mut actions = self.actions.clone();
+        match actions.as_mut_slice() {
+            [Action::OperatorMotion { count: old, .. }, ..]
+            | [Action::Repeat(old, _), ..]
+            | [Action::RepeatActions(old, _), ..] => *old = count,
+            [action] => *action = Action::Repeat(count, Box::new(action.clone())),
+            _ => actions = (0..count).flat_map(|_| self.actions.clone()).collect(),
+        }
+        Change {
+            actions,
+            register: self.register,
+        }
+    }
+}
+
 pub struct EditorSetup<'a> {
     pub size: Size,
     pub config: &'a Config,
@@ -54,7 +185,7 @@ pub struct EditorSetup<'a> {
 impl<'a, S, C, E> TuiEditor<'a, S, C, E>
 where
     S: Statusline<'a>,
-    C: Commandline,
+    C: Commandline<'a>,
     E: EventHandler,
 {
     pub fn new(
@@ -67,6 +198,42 @@ where
             event_handler,
             lsp: setup.lsp,
             mode: Mode::Normal,
+            command: Input::default(),
+            histories: Histories::read(&Histories::path()).unwrap_or_else(|err| {
+                tracing::error!("{err:#}");
+                Histories::default()
+            }),
+            browsing: None,
+            wildmenu: None,
+            prompt: None,
+            registers: Registers::with_clipboard(
+                clipboard::provider(setup.config.clipboard_provider, Box::new(stdout())),
+                setup.config.clipboard,
+            ),
+            pending_register: None,
+            inserted: String::new(),
+            block_insert: None,
+            insert_repeat: None,
+            last_change: None,
+            pending_change: None,
+            macro_queue: VecDeque::new(),
+            last_macro: None,
+            action_failed: false,
+            search: None,
+            search_direction: Direction::Forward,
+            incremental_search: None,
+            search_count: None,
+            confirmation: None,
+            quit: false,
+            undo: setup.config.undo.clone(),
+            options: Options::from_config(setup.config),
+            config_options: Options::from_config(setup.config),
+            background: setup.config.background.clone(),
+            themes: HashMap::new(),
+            last_disk_check: Instant::now(),
+            disk_prompt: None,
+            last_swap_write: Instant::now(),
+            swap_diff: None,
             stdout: stdout(),
             size: setup.size,
             statusline,
@@ -80,16 +247,22 @@ where
         };
 
         let buffer_id = 1;
-        let buffer = Rc::new(RefCell::new(Buffer::new(buffer_id, setup.file_name)?));
+        let mut buffer = Buffer::new(buffer_id, setup.file_name)?;
+        buffer.options = editor.options.scope(Scope::Buffer);
+        if setup.config.undo.persistent {
+            load_undo(&mut buffer, &setup.config.undo);
+        }
+        let buffer = Rc::new(RefCell::new(buffer));
         let mut window_size: Rect = editor.size.into();
         window_size.height -= 2;
-        let window = Window::new(
+        let mut window = Window::new(
             1,
             Some(buffer.clone()),
             setup.theme,
             window_size.clone(),
-            Box::new(TuiView::new(window_size, setup.config, setup.theme)),
+            Box::new(TuiView::new(window_size, setup.theme)),
         );
+        window.options = editor.options.scope(Scope::Window);
         let tab = Tab::new(1);
         editor.tabs.insert(tab.id, tab);
         editor.windows.insert(window.id, window);
@@ -128,6 +301,7 @@ where
     pub async fn start(&mut self) -> anyhow::Result<()> {
         self.initialize()?;
         self.lsp.initialize().await?;
+        self.check_swap()?;
 
         let mut stream = EventStream::new();
         loop {
@@ -139,16 +313,19 @@ where
                     if let Some(message) = self.lsp.try_read_message().await? {
                         self.handle_lsp_message(message)?;
                     }
+                    if self.prompt.is_none() && self.last_disk_check.elapsed() >= DISK_CHECK_INTERVAL {
+                        self.last_disk_check = Instant::now();
+                        self.check_disk().await?;
+                    }
+                    if self.last_swap_write.elapsed() >= SWAP_INTERVAL {
+                        self.last_swap_write = Instant::now();
+                        self.write_swaps(0)?;
+                    }
                 }
                 maybe_event = event => {
                     if let Some(Ok(event)) = maybe_event {
-                        if let Some(action) = self.event_handler.poll(&event, &self.mode) {
-                            match action {
-                                KeyAction::Simple(Action::Quit) => {
-                                    break;
-                                }
-                                _ => self.handle_action(action).await?,
-                            }
+                        if self.handle_event(&event, false).await? || self.play_macros().await? {
+                            break;
                         }
                     };
                 }
@@ -158,49 +335,1249 @@ where
         Ok(())
     }
 
+    // returns whether the editor should quit
+    async fn handle_event(&mut self, event: &Event, replayed: bool) -> anyhow::Result<bool> {
+        if let (Some(_), Event::Key(key)) = (&self.prompt, event) {
+            self.handle_prompt(key).await?;
+            return Ok(self.quit);
+        }
+        let action = match replayed {
+            true => self.event_handler.poll_replayed(event, &self.mode),
+            false => self.event_handler.poll(event, &self.mode),
+        };
+        if let Some(action) = action {
+            self.handle_action(action).await?;
+        }
+        Ok(self.quit)
+    }
+
+    // replays the keys of the macros queued by `@` through the same pipeline
+    // as typed keys, the first failing action aborts every one of them, or
+    // only the keys of the current line for `:normal` on a range
+    async fn play_macros(&mut self) -> anyhow::Result<bool> {
+        while let Some(queued) = self.macro_queue.pop_front() {
+            let event = match queued {
+                Queued::Key(event) => event,
+                Queued::Line(start, next) => {
+                    let window = self.windows.get_mut(&self.active_window).unwrap();
+                    let buffer = window.get_buffer();
+                    if collapsed(&buffer.borrow(), start.get(), next.get()) {
+                        self.skip_line_keys();
+                    } else {
+                        window.jump_to(start.get(), &self.mode)?;
+                    }
+                    continue;
+                }
+            };
+            if self.handle_event(&event, true).await? {
+                return Ok(true);
+            }
+            if self.action_failed {
+                self.skip_line_keys();
+            }
+        }
+        Ok(false)
+    }
+
+    // drops the queued keys up to the next line of a `:normal` on a range, or
+    // every key without one
+    fn skip_line_keys(&mut self) {
+        let next_line = self
+            .macro_queue
+            .iter()
+            .position(|queued| matches!(queued, Queued::Line(..)));
+        match next_line {
+            Some(next_line) => _ = self.macro_queue.drain(..next_line),
+            None => self.macro_queue.clear(),
+        }
+    }
+
     async fn handle_action(&mut self, action: KeyAction) -> anyhow::Result<()> {
+        self.action_failed = false;
+        self.search_count = None;
         let mut actions = Vec::new();
         flatten_actions(&mut actions, action);
+        let recorded = actions.clone();
+        let mode_before = self.mode.clone();
+        let register_before = self.pending_register;
+        let mut actions = VecDeque::from(actions);
 
         let window = self.windows.get_mut(&self.active_window).unwrap();
-        for action in actions {
+        let mut keep_register = false;
+        let mut keep_browsing = false;
+        let mut keep_wildmenu = false;
+        let mut command_typed = false;
+        while let Some(action) = actions.pop_front() {
             match action {
+                Action::InsertCommand(c) => {
+                    self.command.insert(c.encode_utf8(&mut [0; 4]));
+                    command_typed = true;
+                }
+                Action::EditCommand(edit) if matches!(self.mode, Mode::Command | Mode::Search) => {
+                    self.command.edit(edit);
+                    command_typed = true;
+                }
+                Action::OlderHistory | Action::NewerHistory
+                    if matches!(self.mode, Mode::Command | Mode::Search) =>
+                {
+                    let history = match self.mode {
+                        Mode::Search => &self.histories.searches,
+                        _ => &self.histories.commands,
+                    };
+                    let browsing = self
+                        .browsing
+                        .get_or_insert_with(|| Browsing::new(self.command.text()));
+                    let older = matches!(action, Action::OlderHistory);
+                    if let Some(text) = browsing.browse(history, older) {
+                        self.command.set(&text);
+                        command_typed = true;
+                    }
+                    keep_browsing = true;
+                }
+                Action::CompleteCommand | Action::CompletePrevious
+                    if matches!(self.mode, Mode::Command) =>
+                {
+                    let forward = matches!(action, Action::CompleteCommand);
+                    match &mut self.wildmenu {
+                        Some(wildmenu) => {
+                            let start = wildmenu.start();
+                            self.command.complete(start, wildmenu.select(forward));
+                        }
+                        None => {
+                            let buffers = self
+                                .buffers
+                                .values()
+                                .map(|buffer| buffer.borrow().file_name.clone())
+                                .filter(|name| !name.is_empty())
+                                .collect::<Vec<_>>();
+                            let themes = Config::themes_path();
+                            let sources = Sources {
+                                buffers: &buffers,
+                                themes: &themes,
+                            };
+                            let completion =
+                                complete::complete(self.command.before_cursor(), &sources);
+                            self.wildmenu = completion.and_then(|completion| {
+                                complete_command(&mut self.command, completion, forward)
+                            });
+                        }
+                    }
+                    keep_wildmenu = true;
+                    command_typed = true;
+                }
+                Action::SelectRegister(name) if Registers::is_valid(name) => {
+                    self.pending_register = Some(name);
+                    keep_register = true;
+                }
+                Action::SelectRegister(name) => self
+                    .commandline
+                    .set_message(Message::error(format!("invalid register: {name}"))),
+                Action::InsertRegister(name)
+                    if matches!(self.mode, Mode::Command | Mode::Search) =>
+                {
+                    if let Some(register) = self.registers.get(Some(name)) {
+                        self.command.insert(register.text.trim_end_matches('\n'));
+                    }
+                    command_typed = true;
+                }
+                Action::EnterMode(Mode::Search) => {
+                    actions.push_front(Action::Search(Direction::Forward))
+                }
+                Action::Search(direction) => {
+                    self.mode = Mode::Search;
+                    self.command.clear();
+                    self.incremental_search = Some((direction, window.cursor.absolute_position));
+                    command_typed = true;
+                    self.stdout.queue(cursor::SetCursorStyle::SteadyBar)?;
+                }
+                Action::SearchWord(direction) => {
+                    let search = window.word_under_cursor().map(|word| Search::word(&word));
+                    match search {
+                        Some(Ok(search)) => {
+                            self.registers
+                                .set_read_only(register::LAST_SEARCH, search.pattern.clone());
+                            self.search = Some(search);
+                            self.search_direction = direction;
+                            actions.push_front(Action::FindNext);
+                        }
+                        Some(Err(err)) => self
+                            .commandline
+                            .set_message(Message::error(format!("{err:#}"))),
+                        None => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error("no word under the cursor"));
+                        }
+                    }
+                }
+                Action::FindNext | Action::FindPrevious => {
+                    let Some(search) = &self.search else {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error("no previous search"));
+                        continue;
+                    };
+                    let direction = match action {
+                        Action::FindNext => self.search_direction,
+                        _ => self.search_direction.reverse(),
+                    };
+                    let wrap = self.options.flag("wrapscan");
+                    match window.find(search, direction, wrap, &self.mode)? {
+                        Some(wrapped) => {
+                            let buffer = window.get_buffer();
+                            let pos = window.cursor.absolute_position;
+                            self.search_count = Some(search.count(&buffer.borrow(), pos));
+                            match (wrapped, direction) {
+                                (true, Direction::Forward) => self.commandline.set_message(
+                                    Message::info("search hit BOTTOM, continuing at TOP"),
+                                ),
+                                (true, Direction::Backward) => self.commandline.set_message(
+                                    Message::info("search hit TOP, continuing at BOTTOM"),
+                                ),
+                                (false, _) => self.commandline.clear_message(),
+                            }
+                        }
+                        None if !wrap => {
+                            self.action_failed = true;
+                            let end = match direction {
+                                Direction::Forward => "BOTTOM",
+                                Direction::Backward => "TOP",
+                            };
+                            self.commandline.set_message(Message::error(format!(
+                                "search hit {end} without match for: {}",
+                                search.pattern
+                            )));
+                        }
+                        None => {
+                            self.action_failed = true;
+                            self.commandline.set_message(Message::error(format!(
+                                "pattern not found: {}",
+                                search.pattern
+                            )));
+                        }
+                    }
+                }
+                Action::AddCursorAtNextMatch => {
+                    let Some(word) = window.word_under_cursor() else {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error("no word under the cursor"));
+                        continue;
+                    };
+                    let search = match Search::word(&word) {
+                        Ok(search) => search,
+                        Err(err) => {
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                            continue;
+                        }
+                    };
+                    // adding the next cursors reuses the matches found for the first
+                    let search = match self.search.take() {
+                        Some(last) if last.pattern == search.pattern => last,
+                        _ => search,
+                    };
+                    self.registers
+                        .set_read_only(register::LAST_SEARCH, search.pattern.clone());
+                    if !window.add_cursor_at_next_match(&search, &self.mode)? {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error(format!("no other match for: {word}")));
+                    }
+                    self.search = Some(search);
+                }
+                Action::AddCursorsToSelection if self.mode == Mode::VisualBlock => {
+                    window.add_block_cursors(&self.mode)?;
+                    self.mode = Mode::Normal;
+                }
+                Action::AddCursorsToSelection if self.mode.is_visual() => {
+                    let Some(search) = &self.search else {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error("no previous search"));
+                        continue;
+                    };
+                    match window.add_cursors_at_matches(search, &self.mode)? {
+                        true => self.mode = Mode::Normal,
+                        false => {
+                            self.action_failed = true;
+                            self.commandline.set_message(Message::error(format!(
+                                "pattern not found in the selection: {}",
+                                search.pattern
+                            )));
+                        }
+                    }
+                }
+                Action::ClearCursors => window.clear_cursors(&self.mode)?,
+                Action::ClearSearch => window.set_search(None, &self.mode)?,
+                Action::Substitute(substitute) => {
+                    window.preview(None, &self.mode)?;
+                    let replacements = substitutions(&substitute, window, self.search.as_ref());
+                    let (search, replacements) = match replacements {
+                        Ok((search, replacements)) if !replacements.is_empty() => {
+                            (search, replacements)
+                        }
+                        Ok(_) => {
+                            self.action_failed = true;
+                            self.commandline.set_message(Message::error(format!(
+                                "pattern not found: {}",
+                                substitute.pattern
+                            )));
+                            continue;
+                        }
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                            continue;
+                        }
+                    };
+                    // like in vim, `n` goes on searching the pattern
+                    self.registers
+                        .set_read_only(register::LAST_SEARCH, search.pattern.clone());
+                    self.search = Some(search);
+                    if substitute.flags.count_only {
+                        self.commandline.set_message(Message::info(count_message(
+                            &replacements,
+                            ("match", "matches"),
+                        )));
+                    } else if substitute.flags.confirm {
+                        self.confirmation = Some(Confirmation {
+                            current: None,
+                            pending: replacements.into_iter().rev().collect(),
+                            accepted: vec![],
+                        });
+                        actions.push_front(Action::ConfirmSubstitute(Confirm::No));
+                    } else {
+                        replace(window, &replacements, &self.mode)?;
+                        self.commandline.set_message(Message::info(count_message(
+                            &replacements,
+                            ("substitution", "substitutions"),
+                        )));
+                    }
+                }
+                Action::ConfirmSubstitute(answer) => {
+                    let Some(mut confirmation) = self.confirmation.take() else {
+                        continue;
+                    };
+                    let current = confirmation.current.take();
+                    match answer {
+                        Confirm::Yes => confirmation.accepted.extend(current),
+                        Confirm::No => (),
+                        Confirm::All => {
+                            confirmation.accepted.extend(current);
+                            let rest = confirmation.pending.drain(..).rev();
+                            confirmation.accepted.extend(rest);
+                        }
+                        Confirm::Quit => confirmation.pending.clear(),
+                        Confirm::Last => {
+                            confirmation.accepted.extend(current);
+                            confirmation.pending.clear();
+                        }
+                    }
+                    match confirmation.pending.pop() {
+                        Some(next) => {
+                            window.jump_to(next.range.start, &self.mode)?;
+                            window.set_highlights(vec![next.range.clone()], &self.mode)?;
+                            let prompt = Prompt::new(format!("replace with {}", next.text))
+                                .choice('y', "yes", Action::ConfirmSubstitute(Confirm::Yes))
+                                .choice('n', "no", Action::ConfirmSubstitute(Confirm::No))
+                                .choice('a', "all", Action::ConfirmSubstitute(Confirm::All))
+                                .choice('q', "quit", Action::ConfirmSubstitute(Confirm::Quit))
+                                .choice('l', "last", Action::ConfirmSubstitute(Confirm::Last))
+                                .on_cancel(Action::ConfirmSubstitute(Confirm::Quit));
+                            self.commandline.set_message(Message::info(prompt.text()));
+                            self.prompt = Some(prompt);
+                            confirmation.current = Some(next);
+                            self.confirmation = Some(confirmation);
+                        }
+                        None => {
+                            window.set_highlights(vec![], &self.mode)?;
+                            let replaced = confirmation.accepted;
+                            replace(window, &replaced, &self.mode)?;
+                            self.commandline.set_message(Message::info(count_message(
+                                &replaced,
+                                ("substitution", "substitutions"),
+                            )));
+                        }
+                    }
+                }
+                Action::StartRecording(name) if Registers::is_valid(name) => self
+                    .commandline
+                    .set_message(Message::info(format!("recording @{name}"))),
+                Action::StartRecording(name) => self
+                    .commandline
+                    .set_message(Message::error(format!("invalid register: {name}"))),
+                Action::StoreMacro(name, keys) => {
+                    self.commandline.clear_message();
+                    if let Err(err) = self.registers.record(name, keys) {
+                        self.commandline
+                            .set_message(Message::error(format!("{err:#}")));
+                    }
+                }
+                Action::PlayMacro(name) => {
+                    let name = match name {
+                        '@' => self.last_macro,
+                        name => Some(name),
+                    };
+                    let keys = match name {
+                        // `@:` repeats the last command line
+                        Some(register::LAST_COMMAND) => self
+                            .registers
+                            .get(name)
+                            .map(|register| format!(":{}<CR>", register.text)),
+                        Some(name) => self.registers.get(Some(name)).map(|r| r.text),
+                        None => None,
+                    };
+                    match keys {
+                        Some(keys) => {
+                            self.last_macro = name;
+                            keys::decode(&keys).into_iter().rev().for_each(|key| {
+                                self.macro_queue.push_front(Queued::Key(Event::Key(key)))
+                            });
+                        }
+                        None => {
+                            self.action_failed = true;
+                            self.commandline.set_message(Message::error(format!(
+                                "nothing to play in register {}",
+                                name.unwrap_or('@')
+                            )));
+                        }
+                    }
+                }
+                Action::SetMark(name) if Marks::is_valid(name) => {
+                    // global marks live in a single buffer
+                    if name.is_ascii_uppercase() {
+                        self.buffers
+                            .values()
+                            .for_each(|buffer| _ = buffer.borrow_mut().marks.remove(name));
+                    }
+                    let pos = window.cursor.absolute_position;
+                    window.get_buffer().borrow_mut().marks.set(name, pos);
+                }
+                Action::SetMark(name) => self
+                    .commandline
+                    .set_message(Message::error(format!("invalid mark: {name}"))),
+                Action::GoToMark(name) | Action::GoToMarkLine(name) => {
+                    let current = window.get_buffer();
+                    let holder = match current.borrow().marks.get(name) {
+                        Some(_) => Some(current.clone()),
+                        None if name.is_ascii_uppercase() => self
+                            .buffers
+                            .values()
+                            .find(|buffer| buffer.borrow().marks.get(name).is_some())
+                            .cloned(),
+                        None => None,
+                    };
+                    match holder {
+                        Some(buffer) if Rc::ptr_eq(&buffer, &current) => {
+                            window.handle_action(&action, &self.mode)?
+                        }
+                        Some(buffer) => {
+                            let linewise = matches!(action, Action::GoToMarkLine(_));
+                            let pos = buffer.borrow().mark(name, linewise).unwrap_or(0);
+                            window.push_jump(window.cursor.absolute_position);
+                            self.active_buffer = buffer.borrow().id;
+                            window.set_buffer(buffer, &self.mode)?;
+                            window.jump_to(pos, &self.mode)?;
+                        }
+                        None => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("mark not set: {name}")));
+                        }
+                    }
+                }
+                Action::JumpBack | Action::JumpForward => {
+                    let jump = match action {
+                        Action::JumpBack => window.jump_back(),
+                        _ => window.jump_forward(),
+                    };
+                    let Some((id, pos)) = jump else {
+                        self.action_failed = true;
+                        continue;
+                    };
+                    if id != window.get_buffer().borrow().id {
+                        let Some(buffer) = self.buffers.get(&id).cloned() else {
+                            continue;
+                        };
+                        self.active_buffer = id;
+                        window.set_buffer(buffer, &self.mode)?;
+                    }
+                    window.jump_to(pos, &self.mode)?;
+                }
+                Action::EditFile(file_name) => {
+                    let path = file::resolve_path(&file_name).ok();
+                    let open = self
+                        .buffers
+                        .values()
+                        .find(|buffer| path.is_some() && buffer.borrow().path == path)
+                        .cloned();
+                    let buffer = match open {
+                        Some(buffer) => buffer,
+                        None => {
+                            let id = self.buffers.keys().max().unwrap() + 1;
+                            match Buffer::new(id, Some(file_name.clone())) {
+                                Ok(mut buffer) => {
+                                    buffer.options = self.options.scope(Scope::Buffer);
+                                    if self.undo.persistent {
+                                        load_undo(&mut buffer, &self.undo);
+                                    }
+                                    let buffer = Rc::new(RefCell::new(buffer));
+                                    self.buffers.insert(id, buffer.clone());
+                                    if let Some(prompt) = swap_check(&buffer) {
+                                        self.commandline.set_message(Message::info(prompt.text()));
+                                        self.prompt = Some(prompt);
+                                    }
+                                    buffer
+                                }
+                                Err(err) => {
+                                    self.action_failed = true;
+                                    self.commandline.set_message(Message::error(format!(
+                                        "failed to open {file_name}: {err}"
+                                    )));
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    if !Rc::ptr_eq(&buffer, &window.get_buffer()) {
+                        window.push_jump(window.cursor.absolute_position);
+                        self.active_buffer = buffer.borrow().id;
+                        window.set_buffer(buffer, &self.mode)?;
+                    }
+                }
+                Action::SwitchBuffer(name) => match find_buffer(&self.buffers, &name) {
+                    Ok(buffer) => {
+                        if !Rc::ptr_eq(&buffer, &window.get_buffer()) {
+                            window.push_jump(window.cursor.absolute_position);
+                            self.active_buffer = buffer.borrow().id;
+                            window.set_buffer(buffer, &self.mode)?;
+                        }
+                    }
+                    Err(err) => {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error(format!("{err:#}")));
+                    }
+                },
+                Action::RepeatChange => {
+                    if let Some(change) = &self.last_change {
+                        self.pending_register = self.pending_register.or(change.register);
+                        change
+                            .actions
+                            .iter()
+                            .rev()
+                            .for_each(|a| actions.push_front(a.clone()));
+                    }
+                }
+                Action::Repeat(count, action) if matches!(*action, Action::RepeatChange) => {
+                    if let Some(change) = &self.last_change {
+                        let change = change.with_count(count);
+                        self.pending_register = self.pending_register.or(change.register);
+                        change
+                            .actions
+                            .iter()
+                            .rev()
+                            .for_each(|a| actions.push_front(a.clone()));
+                        self.last_change = Some(change);
+                    }
+                }
+                Action::Repeat(count, action) => match action.as_operator() {
+                    Some((operator, motion)) => actions.push_front(Action::OperatorMotion {
+                        operator,
+                        motion: Box::new(motion),
+                        count,
+                    }),
+                    // one at a time, the rest of the count waits behind it
+                    None => {
+                        if count > 1 {
+                            actions.push_front(Action::Repeat(count - 1, action.clone()));
+                        }
+                        actions.push_front(*action);
+                    }
+                },
+                Action::RepeatActions(count, repeated) => {
+                    let inserts = repeated
+                        .iter()
+                        .any(|action| matches!(action, Action::EnterMode(Mode::Insert)));
+                    match inserts {
+                        true => self.insert_repeat = Some((count, repeated.clone())),
+                        false if count > 1 => {
+                            actions.push_front(Action::RepeatActions(count - 1, repeated.clone()))
+                        }
+                        false => (),
+                    }
+                    repeated
+                        .into_iter()
+                        .rev()
+                        .for_each(|action| actions.push_front(action));
+                }
+                action if action.as_operator().is_some() => {
+                    let (operator, motion) = action.as_operator().unwrap();
+                    actions.push_front(Action::OperatorMotion {
+                        operator,
+                        motion: Box::new(motion),
+                        count: 1,
+                    });
+                }
+                Action::Operator(operator) if self.mode.is_visual() => {
+                    let file_name = window.get_buffer().borrow().file_name.clone();
+                    self.registers.set_read_only(register::FILE_NAME, file_name);
+                    let name = self.pending_register.take();
+                    let block = window.selected_block(&self.mode);
+                    let mode = std::mem::replace(&mut self.mode, Mode::Normal);
+                    let result =
+                        window.apply_visual_operator(operator, &mut self.registers, name, &mode);
+                    match result {
+                        Ok(_) if operator == Operator::Change => {
+                            if let Some((first, last, left, _)) = block {
+                                self.block_insert = Some(BlockInsert {
+                                    lines: first + 1..=last,
+                                    col: left,
+                                    pad: false,
+                                });
+                            }
+                            actions.push_front(Action::EnterMode(Mode::Insert))
+                        }
+                        Ok(_) => (),
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::Inner(object) | Action::Around(object) if self.mode.is_visual() => {
+                    let around = matches!(action, Action::Around(_));
+                    self.mode = window.select_text_object(object, around, 1, &self.mode)?;
+                }
+                Action::VisualInsert | Action::VisualAppend if self.mode.is_visual() => {
+                    let append = matches!(action, Action::VisualAppend);
+                    let mode = std::mem::replace(&mut self.mode, Mode::Normal);
+                    if let Some((lines, col)) = window.start_visual_insert(append, &mode)? {
+                        self.block_insert = Some(BlockInsert {
+                            lines,
+                            col,
+                            pad: append,
+                        });
+                    }
+                    actions.push_front(Action::EnterMode(Mode::Insert));
+                }
+                Action::SwapSelectionEnds => window.swap_selection_ends(&self.mode)?,
+                Action::Reselect => {
+                    if let Some(mode) = window.reselect()? {
+                        self.mode = mode;
+                    }
+                }
+                Action::OperatorMotion {
+                    operator,
+                    motion,
+                    count,
+                } => {
+                    let file_name = window.get_buffer().borrow().file_name.clone();
+                    self.registers.set_read_only(register::FILE_NAME, file_name);
+                    let name = self.pending_register.take();
+                    let result = window.apply_operator(
+                        operator,
+                        &motion,
+                        count,
+                        &mut self.registers,
+                        name,
+                        &self.mode,
+                    );
+                    match result {
+                        Ok(_) if operator == Operator::Change => {
+                            actions.push_front(Action::EnterMode(Mode::Insert))
+                        }
+                        Ok(_) => (),
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::PasteBelow | Action::PasteAbove | Action::InsertRegister(_) => {
+                    let file_name = window.get_buffer().borrow().file_name.clone();
+                    self.registers.set_read_only(register::FILE_NAME, file_name);
+                    if let Action::InsertRegister(name) = action {
+                        if let Some(register) = self.registers.get(Some(name)) {
+                            self.inserted.push_str(&register.text);
+                        }
+                    }
+                    let name = self.pending_register.take();
+                    if let Err(err) = window.handle_register_action(
+                        &action,
+                        &mut self.registers,
+                        name,
+                        &self.mode,
+                    ) {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error(format!("{err:#}")));
+                    }
+                }
+                // like in vim, deleting past the prompt leaves the mode
+                Action::DeletePreviousChar
+                    if matches!(self.mode, Mode::Command | Mode::Search)
+                        && self.command.is_empty() =>
+                {
+                    actions.push_front(Action::EnterMode(Mode::Normal));
+                }
+                Action::DeletePreviousChar if matches!(self.mode, Mode::Command | Mode::Search) => {
+                    self.command.delete_back();
+                    command_typed = true;
+                }
+                Action::ExecuteCommand if matches!(self.mode, Mode::Search) => {
+                    self.mode = Mode::Normal;
+                    self.stdout.queue(cursor::SetCursorStyle::SteadyBlock)?;
+                    self.commandline.clear_input();
+                    self.commandline.clear_message();
+                    let pos = window.cursor.absolute_position;
+                    let (direction, start) = self
+                        .incremental_search
+                        .take()
+                        .unwrap_or((self.search_direction, pos));
+                    // `n` searches from where the search was started
+                    window.jump_to(start, &self.mode)?;
+                    let pattern = self.command.text().to_string();
+                    self.command.clear();
+                    self.histories.searches.add(&pattern);
+                    write_histories(&self.histories);
+                    // an empty pattern searches the last one again
+                    let search = match pattern.is_empty() {
+                        true => self
+                            .search
+                            .clone()
+                            .ok_or_else(|| anyhow::anyhow!("no previous search")),
+                        false => Search::new(&pattern),
+                    };
+                    match search {
+                        Ok(search) => {
+                            self.registers
+                                .set_read_only(register::LAST_SEARCH, search.pattern.clone());
+                            self.search = Some(search);
+                            self.search_direction = direction;
+                            actions.push_front(Action::FindNext);
+                        }
+                        Err(err) => {
+                            self.action_failed = true;
+                            window.set_search(self.search.clone(), &self.mode)?;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::ExecuteCommand => {
+                    window.preview(None, &Mode::Normal)?;
+                    self.commandline.clear_input();
+                    self.mode = Mode::Normal;
+                    self.stdout.queue(cursor::SetCursorStyle::SteadyBlock)?;
+                    self.histories.commands.add(self.command.text());
+                    write_histories(&self.histories);
+                    match glyph_core::ex::parse(self.command.text()) {
+                        Ok(action) => {
+                            self.registers.set_read_only(
+                                register::LAST_COMMAND,
+                                self.command.text().to_string(),
+                            );
+                            actions.push_front(action);
+                        }
+                        Err(err) => {
+                            tracing::error!("failed to parse command: {err}");
+                            self.commandline
+                                .set_message(Message::error(err.to_string()));
+                        }
+                    }
+                    self.command.clear();
+                }
                 Action::MoveToLineStart => window.handle_action(&action, &self.mode)?,
                 Action::MoveToLineEnd => window.handle_action(&action, &self.mode)?,
-                Action::DeletePreviousChar => window.handle_action(&action, &self.mode)?,
-                Action::DeleteCurrentChar => window.handle_action(&action, &self.mode)?,
-                Action::NextWord => window.handle_action(&action, &self.mode)?,
-                Action::MoveLeft => window.handle_action(&action, &self.mode)?,
-                Action::MoveDown => window.handle_action(&action, &self.mode)?,
-                Action::MoveUp => window.handle_action(&action, &self.mode)?,
-                Action::MoveRight => window.handle_action(&action, &self.mode)?,
+                Action::DeletePreviousChar => {
+                    if matches!(self.mode, Mode::Insert) {
+                        self.inserted.pop();
+                    }
+                    window.handle_action(&action, &self.mode)?
+                }
+                Action::NextWord
+                | Action::PreviousWord
+                | Action::MoveLeft
+                | Action::MoveDown
+                | Action::MoveUp
+                | Action::MoveRight => {
+                    // like in vim, a motion that can't move fails
+                    let position = window.cursor.absolute_position;
+                    window.handle_action(&action, &self.mode)?;
+                    if window.cursor.absolute_position == position {
+                        self.action_failed = true;
+                    }
+                }
                 Action::MoveToTop => window.handle_action(&action, &self.mode)?,
-                Action::SaveBuffer => window.handle_action(&action, &self.mode)?,
+                Action::GoToLine(_) => window.handle_action(&action, &self.mode)?,
+                Action::DeleteLines(..)
+                | Action::YankLines(..)
+                | Action::MoveLines(..)
+                | Action::CopyLines(..)
+                | Action::JoinLines(..)
+                | Action::IndentLines { .. }
+                | Action::SortLines(..) => {
+                    if let Err(err) = window.line_command(&action, &mut self.registers, &self.mode)
+                    {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error(format!("{err:#}")));
+                    }
+                }
+                Action::Normal(range, keys) => {
+                    let lines = match range {
+                        Some(range) => {
+                            let buffer = window.get_buffer();
+                            let mut buffer = buffer.borrow_mut();
+                            match range.resolve(&buffer, window.cursor.row) {
+                                Ok(lines) => anchor_lines(&mut buffer, lines),
+                                Err(err) => {
+                                    self.action_failed = true;
+                                    self.commandline
+                                        .set_message(Message::error(format!("{err:#}")));
+                                    continue;
+                                }
+                            }
+                        }
+                        None => vec![],
+                    };
+                    for queued in normal_keys(&keys, lines).into_iter().rev() {
+                        self.macro_queue.push_front(queued);
+                    }
+                }
+                Action::Global {
+                    range,
+                    pattern,
+                    invert,
+                    command,
+                } => {
+                    let global = Search::new(&pattern).and_then(|search| {
+                        let lines = global_lines(window, &range, &search, invert)?;
+                        Ok((search, lines, glyph_core::ex::parse(&command)?))
+                    });
+                    let (search, lines, action) = match global {
+                        Ok(global) => global,
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                            continue;
+                        }
+                    };
+                    self.registers
+                        .set_read_only(register::LAST_SEARCH, search.pattern.clone());
+                    self.search = Some(search);
+                    let result = match action {
+                        Action::Normal(_, keys) => {
+                            let buffer = window.get_buffer();
+                            let lines = anchor_lines(&mut buffer.borrow_mut(), lines);
+                            for queued in normal_keys(&keys, lines).into_iter().rev() {
+                                self.macro_queue.push_front(queued);
+                            }
+                            Ok(())
+                        }
+                        action => run_global(
+                            window,
+                            &mut self.registers,
+                            &lines,
+                            &action,
+                            self.search.as_ref(),
+                            &self.mode,
+                        ),
+                    };
+                    if let Err(err) = result {
+                        self.action_failed = true;
+                        self.commandline
+                            .set_message(Message::error(format!("{err:#}")));
+                    }
+                }
+                Action::SaveBuffer | Action::ForceSaveBuffer => {
+                    match window.handle_action(&action, &self.mode) {
+                        Ok(_) => {
+                            let buffer = window.get_buffer();
+                            let buffer = buffer.borrow();
+                            self.commandline.set_message(Message::info(format!(
+                                "\"{}\" {}L written",
+                                buffer.file_name,
+                                buffer.marker.len()
+                            )));
+                        }
+                        Err(err) => {
+                            tracing::error!("failed to save buffer: {err:#}");
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::WriteFile { path, force } => {
+                    let buffer = window.get_buffer();
+                    let result = buffer.borrow_mut().write_to(&path, force);
+                    match result {
+                        Ok(()) => self.commandline.set_message(Message::info(format!(
+                            "\"{path}\" {}L written",
+                            buffer.borrow().marker.len()
+                        ))),
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::SaveAs { path, force } => {
+                    let buffer = window.get_buffer();
+                    let result = buffer.borrow_mut().save_as(&path, force);
+                    match result {
+                        Ok(()) => {
+                            let mut buffer = buffer.borrow_mut();
+                            if self.undo.persistent {
+                                load_undo(&mut buffer, &self.undo);
+                            }
+                            self.registers
+                                .set_read_only(register::FILE_NAME, buffer.file_name.clone());
+                            self.commandline.set_message(Message::info(format!(
+                                "\"{path}\" {}L written",
+                                buffer.marker.len()
+                            )));
+                        }
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::ReadFile(path, address) => {
+                    let buffer = window.get_buffer();
+                    let result = {
+                        let mut buffer = buffer.borrow_mut();
+                        let line = buffer.buffer.char_to_line(window.cursor.absolute_position);
+                        address
+                            .line(&buffer, line)
+                            .and_then(|line| buffer.read_file(&path, line))
+                    };
+                    match result {
+                        Ok(pos) => window.jump_to(pos, &self.mode)?,
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::Quit => {
+                    let modified = self
+                        .buffers
+                        .values()
+                        .find(|buffer| buffer.borrow().is_modified());
+                    match modified {
+                        // a failed write already said why
+                        Some(_) if self.action_failed => (),
+                        Some(buffer) => {
+                            self.action_failed = true;
+                            let buffer = buffer.borrow();
+                            let name = match buffer.file_name.as_str() {
+                                "" => "[No Name]",
+                                name => name,
+                            };
+                            self.commandline.set_message(Message::error(format!(
+                                "no write since last change for \"{name}\" (add ! to override)"
+                            )));
+                        }
+                        None => actions.push_front(Action::ForceQuit),
+                    }
+                }
+                Action::ForceQuit => {
+                    self.buffers
+                        .values()
+                        .for_each(|b| b.borrow_mut().remove_swap());
+                    self.quit = true;
+                    break;
+                }
+                Action::WriteQuit(force) | Action::Exit(force) => {
+                    let modified = window.get_buffer().borrow().is_modified();
+                    actions.push_front(Action::Quit);
+                    if modified || matches!(action, Action::WriteQuit(_)) {
+                        actions.push_front(match force {
+                            true => Action::ForceSaveBuffer,
+                            false => Action::SaveBuffer,
+                        });
+                    }
+                }
+                Action::ReloadBuffer => {
+                    let buffer = disk_buffer(&self.buffers, self.disk_prompt.take(), window);
+                    let result = buffer.borrow_mut().reload();
+                    match result {
+                        Ok(changes) => {
+                            if Rc::ptr_eq(&buffer, &window.get_buffer()) {
+                                window.buffer_changed(&changes, &self.mode)?;
+                            }
+                            self.commandline.set_message(Message::info(format!(
+                                "\"{}\" reloaded",
+                                buffer.borrow().file_name
+                            )));
+                        }
+                        Err(err) => self
+                            .commandline
+                            .set_message(Message::error(format!("{err:#}"))),
+                    }
+                }
+                Action::MergeBuffer => {
+                    let buffer = disk_buffer(&self.buffers, self.disk_prompt.take(), window);
+                    let result = buffer.borrow_mut().merge_disk();
+                    let shown = Rc::ptr_eq(&buffer, &window.get_buffer());
+                    match result {
+                        Ok((changes, 0)) => {
+                            if shown {
+                                window.buffer_changed(&changes, &self.mode)?;
+                            }
+                            self.commandline
+                                .set_message(Message::info("merged changes from disk"));
+                        }
+                        Ok((changes, conflicts)) => {
+                            if shown {
+                                window.buffer_changed(&changes, &self.mode)?;
+                            }
+                            self.commandline.set_message(Message::error(format!(
+                                "merged changes from disk with {conflicts} conflicts"
+                            )));
+                        }
+                        Err(err) => self
+                            .commandline
+                            .set_message(Message::error(format!("{err:#}"))),
+                    }
+                }
+                Action::RecoverSwap => {
+                    let buffer = self.buffers.get(&self.active_buffer).unwrap().clone();
+                    window.set_buffer(buffer.clone(), &self.mode)?;
+                    if let Some(id) = self.swap_diff.take() {
+                        self.buffers.remove(&id);
+                    }
+                    let result = buffer.borrow_mut().recover_swap();
+                    match result {
+                        Ok(changes) => {
+                            window.buffer_changed(&changes, &self.mode)?;
+                            self.commandline.set_message(Message::info(
+                                "recovered unsaved changes, save the file to keep them",
+                            ));
+                        }
+                        Err(err) => self
+                            .commandline
+                            .set_message(Message::error(format!("{err:#}"))),
+                    }
+                }
+                Action::DiscardSwap => {
+                    let buffer = self.buffers.get(&self.active_buffer).unwrap().clone();
+                    window.set_buffer(buffer.clone(), &self.mode)?;
+                    if let Some(id) = self.swap_diff.take() {
+                        self.buffers.remove(&id);
+                    }
+                    buffer.borrow_mut().remove_swap();
+                }
+                Action::DiffSwap => {
+                    let buffer = self.buffers.get(&self.active_buffer).unwrap().clone();
+                    let Some(swap) = buffer.borrow().find_swap() else {
+                        continue;
+                    };
+                    let diff = diff::unified(
+                        &buffer.borrow().to_string(),
+                        &swap.content,
+                        &buffer.borrow().file_name,
+                        "swap",
+                    );
+                    let id = self.buffers.keys().max().unwrap() + 1;
+                    let scratch = Rc::new(RefCell::new(Buffer::from_string(id, &diff)));
+                    scratch.borrow_mut().file_name = "[swap diff]".into();
+                    self.buffers.insert(id, scratch.clone());
+                    self.swap_diff = Some(id);
+                    window.set_buffer(scratch, &self.mode)?;
+
+                    let prompt = swap_prompt(&buffer.borrow().file_name, &swap)
+                        .choices
+                        .into_iter()
+                        .filter(|choice| !matches!(choice.action, Action::DiffSwap))
+                        .fold(Prompt::new("after reviewing the diff"), |prompt, choice| {
+                            prompt.choice(choice.key, &choice.label, choice.action)
+                        });
+                    self.commandline.set_message(Message::info(prompt.text()));
+                    self.prompt = Some(prompt);
+                }
+                Action::KeepBuffer => {
+                    let buffer = disk_buffer(&self.buffers, self.disk_prompt.take(), window);
+                    let result = buffer.borrow_mut().keep_buffer();
+                    if let Err(err) = result {
+                        self.commandline
+                            .set_message(Message::error(format!("{err:#}")));
+                    }
+                }
+                Action::Set(settings, local) => {
+                    let buffer = window.get_buffer();
+                    let mut buffer = buffer.borrow_mut();
+                    let mut scopes = Scopes {
+                        global: &mut self.options,
+                        window: &mut window.options,
+                        buffer: &mut buffer.options,
+                    };
+                    let mut shown = vec![];
+                    for setting in &settings {
+                        match scopes.apply(setting, local, &self.config_options) {
+                            Ok(Some(value)) => shown.push(value),
+                            Ok(None) => (),
+                            Err(err) => {
+                                self.action_failed = true;
+                                self.commandline
+                                    .set_message(Message::error(format!("{err:#}")));
+                                break;
+                            }
+                        }
+                    }
+                    drop(buffer);
+                    window.apply_options(&self.mode)?;
+                    if !shown.is_empty() && !self.action_failed {
+                        self.commandline
+                            .set_message(Message::info(shown.join("  ")));
+                    }
+                }
+                Action::ColorScheme(name) => {
+                    match load_colorscheme(&mut self.themes, &self.background, &name) {
+                        Ok(theme) => {
+                            window.set_theme(theme, &self.mode)?;
+                            self.statusline.set_theme(theme);
+                            self.commandline.set_theme(theme);
+                        }
+                        Err(err) => {
+                            self.action_failed = true;
+                            self.commandline
+                                .set_message(Message::error(format!("{err:#}")));
+                        }
+                    }
+                }
+                Action::SetFileFormat(_) => window.handle_action(&action, &self.mode)?,
+                Action::SetFileEncoding(_) => window.handle_action(&action, &self.mode)?,
                 Action::MoveToBottom => window.handle_action(&action, &self.mode)?,
-                Action::InsertLine => window.handle_action(&action, &self.mode)?,
+                Action::InsertLine => {
+                    self.inserted.push('\n');
+                    window.handle_action(&action, &self.mode)?
+                }
                 Action::InsertLineBelow => window.handle_action(&action, &self.mode)?,
                 Action::InsertLineAbove => window.handle_action(&action, &self.mode)?,
-                Action::InsertChar(_) => window.handle_action(&action, &self.mode)?,
+                Action::InsertChar(c) => {
+                    self.inserted.push(c);
+                    window.handle_action(&action, &self.mode)?
+                }
+                Action::Undo => window.handle_action(&action, &self.mode)?,
+                Action::Redo => window.handle_action(&action, &self.mode)?,
+                Action::Earlier(_) => window.handle_action(&action, &self.mode)?,
+                Action::Later(_) => window.handle_action(&action, &self.mode)?,
                 Action::EnterMode(Mode::Insert) => {
                     self.mode = Mode::Insert;
+                    self.inserted.clear();
                     self.stdout.queue(cursor::SetCursorStyle::SteadyBar)?;
                 }
+                Action::EnterMode(mode) if mode.is_visual() => {
+                    // the key of the current visual mode leaves it
+                    if self.mode == mode {
+                        actions.push_front(Action::EnterMode(Mode::Normal));
+                    } else {
+                        self.mode = mode;
+                        window.start_selection(&self.mode)?;
+                    }
+                }
                 Action::EnterMode(Mode::Normal) => {
+                    // one more time after the other, the rest of the count
+                    // waits for the next time insert mode is left
+                    if let (Mode::Insert, Some((count, repeated))) =
+                        (&self.mode, self.insert_repeat.take())
+                    {
+                        if count > 1 {
+                            let typed = self.inserted.chars().map(|c| match c {
+                                '\n' => Action::InsertLine,
+                                c => Action::InsertChar(c),
+                            });
+                            let replay = repeated
+                                .iter()
+                                .cloned()
+                                .chain(typed)
+                                .chain([Action::EnterMode(Mode::Normal)])
+                                .collect::<Vec<_>>();
+                            replay
+                                .into_iter()
+                                .rev()
+                                .for_each(|action| actions.push_front(action));
+                            self.insert_repeat = Some((count - 1, repeated));
+                        }
+                    }
+                    if matches!(self.mode, Mode::Insert) {
+                        self.registers
+                            .set_read_only(register::LAST_INSERT, self.inserted.clone());
+                        let pos = window.cursor.absolute_position;
+                        window
+                            .get_buffer()
+                            .borrow_mut()
+                            .marks
+                            .set(marks::LAST_INSERT, pos);
+                    }
+                    if let Some(block) = self.block_insert.take() {
+                        if !self.inserted.is_empty() && !self.inserted.contains('\n') {
+                            let buffer = window.get_buffer();
+                            let changes = buffer.borrow_mut().insert_at_column(
+                                block.lines,
+                                block.col,
+                                &self.inserted,
+                                block.pad,
+                            );
+                            window.buffer_changed(&changes, &Mode::Normal)?;
+                        }
+                    }
+                    if self.mode.is_visual() {
+                        window.end_selection(&self.mode)?;
+                    }
+                    if matches!(self.mode, Mode::Command) {
+                        window.preview(None, &Mode::Normal)?;
+                        self.command.clear();
+                        self.commandline.clear_input();
+                    }
+                    // a cancelled search leaves the cursor where it was
+                    if let Some((_, start)) = self.incremental_search.take() {
+                        self.command.clear();
+                        self.commandline.clear_input();
+                        window.jump_to(start, &Mode::Normal)?;
+                        window.set_search(self.search.clone(), &Mode::Normal)?;
+                    }
                     self.mode = Mode::Normal;
                     // self.maybe_leave_command_mode()?;
                     self.stdout.queue(cursor::SetCursorStyle::SteadyBlock)?;
                 }
                 Action::EnterMode(Mode::Command) => {
+                    self.command.clear();
+                    // `:` on a selection acts on its lines
+                    if self.mode.is_visual() {
+                        window.end_selection(&self.mode)?;
+                        self.command.insert("'<,'>");
+                    }
                     self.mode = Mode::Command;
+                    self.commandline.clear_message();
+                    self.commandline
+                        .set_input(':', self.command.text(), self.command.cursor());
                     // self.enter_command_mode()?;
                     self.stdout.queue(cursor::SetCursorStyle::SteadyBar)?;
                 }
                 Action::Hover => {
-                    let buffer = self.buffers.get(&self.active_buffer).unwrap();
-                    let file_name = buffer.borrow().file_name.clone();
-                    let row = window.cursor.row;
-                    let col = window.cursor.col;
-                    self.lsp.request_hover(&file_name, row, col).await?;
+                    let (file_name, position) = {
+                        let buffer = self.buffers.get(&self.active_buffer).unwrap().borrow();
+                        let position = window.cursor.absolute_position;
+                        (
+                            buffer.file_name.clone(),
+                            self.lsp.position(&buffer, position),
+                        )
+                    };
+                    self.lsp.request_hover(&file_name, position).await?;
                 }
                 Action::Resize(cols, rows) => {
                     self.size = (cols, rows).into();
@@ -217,20 +1594,216 @@ where
                 _ => (),
             };
         }
+        if !keep_register {
+            self.pending_register = None;
+        }
+        if !keep_browsing {
+            self.browsing = None;
+        }
+        if !keep_wildmenu {
+            self.wildmenu = None;
+        }
+        match &self.wildmenu {
+            Some(wildmenu) => self
+                .commandline
+                .set_wildmenu(wildmenu.candidates(), wildmenu.selected()),
+            None => self.commandline.clear_wildmenu(),
+        }
+        if let (true, Some((direction, start))) = (command_typed, self.incremental_search) {
+            let prompt = match direction {
+                Direction::Forward => '/',
+                Direction::Backward => '?',
+            };
+            self.commandline
+                .set_input(prompt, self.command.text(), self.command.cursor());
+            let search = Search::new(self.command.text())
+                .ok()
+                .filter(|_| !self.command.is_empty());
+            let found = search
+                .as_ref()
+                .and_then(|search| search.find(&window.get_buffer().borrow(), start, direction));
+            window.jump_to(found.map_or(start, |(pos, _)| pos), &self.mode)?;
+            window.set_search(search, &self.mode)?;
+        }
+        // the substitution being typed is shown in the buffer
+        if let (true, Mode::Command) = (command_typed, &self.mode) {
+            self.commandline
+                .set_input(':', self.command.text(), self.command.cursor());
+            let changes = match glyph_core::ex::parse(self.command.text()) {
+                Ok(Action::Substitute(substitute)) if !substitute.flags.count_only => {
+                    window.preview(None, &self.mode)?;
+                    substitutions(&substitute, window, self.search.as_ref())
+                        .ok()
+                        .and_then(|(_, replacements)| {
+                            let buffer = window.get_buffer();
+                            let buffer = buffer.borrow();
+                            ChangeSet::from_changes(
+                                buffer.buffer.len_chars(),
+                                substitute::changes(&replacements),
+                            )
+                            .ok()
+                        })
+                }
+                _ => None,
+            };
+            window.preview(changes, &self.mode)?;
+        }
+        let cursor_pos = window.get_cursor_readable_position();
+        let buffer = self.buffers.get(&self.active_buffer).unwrap().clone();
+        let changed = buffer.borrow().has_pending_changes();
+        self.record_change(recorded, &mode_before, register_before, changed);
+        // everything done within a single insert session is undone at once
+        if !matches!(self.mode, Mode::Insert) {
+            buffer.borrow_mut().commit();
+        }
+        self.write_swaps(SWAP_EDITS)?;
+
         self.stdout
             .queue(cursor::SavePosition)?
             .queue(cursor::Hide)?;
-        let cursor_pos = window.get_cursor_readable_position();
-        let buffer = self.buffers.get(&self.active_buffer).unwrap();
-        self.statusline.render(StatuslineUpdate::new(
-            self.mode.clone(),
-            cursor_pos.clone(),
-            buffer.clone(),
-        ))?;
+        self.statusline.render(
+            StatuslineUpdate::new(self.mode.clone(), cursor_pos.clone(), buffer.clone())
+                .search_count(self.search_count),
+        )?;
+        self.commandline.render()?;
+        self.stdout.queue(cursor::RestorePosition)?;
+        // the cursor follows what is typed on the commandline
+        if let Some((col, row)) = self.commandline.input_cursor() {
+            self.stdout.queue(cursor::MoveTo(col, row))?;
+        }
+        self.stdout.queue(cursor::Show)?.flush()?;
+        Ok(())
+    }
+
+    // keeps what was just done from normal mode as the last change when it
+    // changed the buffer, commands entering insert mode are completed by
+    // every action of the insert session
+    fn record_change(
+        &mut self,
+        actions: Vec<Action>,
+        mode: &Mode,
+        register: Option<char>,
+        changed: bool,
+    ) {
+        let replayed = actions.iter().any(|action| match action {
+            Action::Repeat(_, action) => matches!(**action, Action::RepeatChange),
+            action => matches!(action, Action::RepeatChange),
+        });
+        match mode {
+            _ if replayed => (),
+            Mode::Insert => {
+                if let Some(change) = &mut self.pending_change {
+                    change.actions.extend(actions);
+                }
+                if !matches!(self.mode, Mode::Insert) {
+                    self.last_change = self.pending_change.take().or(self.last_change.take());
+                }
+            }
+            Mode::Normal if matches!(self.mode, Mode::Insert) => {
+                self.pending_change = Some(Change { actions, register });
+            }
+            Mode::Normal if changed => self.last_change = Some(Change { actions, register }),
+            _ => (),
+        }
+    }
+
+    async fn handle_prompt(&mut self, key: &KeyEvent) -> anyhow::Result<()> {
+        let Some(prompt) = &self.prompt else {
+            return Ok(());
+        };
+        let action = match key.code {
+            KeyCode::Char(c) => prompt.choose(c).cloned(),
+            KeyCode::Esc => prompt.cancel.clone(),
+            _ => None,
+        };
+        if let Some(action) = action {
+            self.prompt = None;
+            self.commandline.clear_message();
+            self.handle_action(KeyAction::Simple(action)).await?;
+        }
+        Ok(())
+    }
+
+    // polls the file of every buffer, the active one first, clean buffers are
+    // reloaded and the first modified one that changed asks what to do
+    async fn check_disk(&mut self) -> anyhow::Result<()> {
+        let mut ids = self.buffers.keys().copied().collect::<Vec<_>>();
+        ids.sort_by_key(|id| (*id != self.active_buffer, *id));
+        for id in ids {
+            let buffer = self.buffers.get(&id).unwrap().clone();
+            let status = buffer.borrow_mut().check_disk();
+            let (file_name, modified) = {
+                let buffer = buffer.borrow();
+                (buffer.file_name.clone(), buffer.is_modified())
+            };
+            match status {
+                DiskStatus::Unchanged => (),
+                // clean buffers have nothing to lose
+                DiskStatus::Changed if !modified => {
+                    self.disk_prompt = Some(id);
+                    self.handle_action(KeyAction::Simple(Action::ReloadBuffer))
+                        .await?;
+                }
+                DiskStatus::Changed => {
+                    let prompt = Prompt::new(format!("\"{file_name}\" changed on disk"))
+                        .choice('r', "reload", Action::ReloadBuffer)
+                        .choice('k', "keep", Action::KeepBuffer)
+                        .choice('m', "merge", Action::MergeBuffer)
+                        .on_cancel(Action::KeepBuffer);
+                    self.commandline.set_message(Message::info(prompt.text()));
+                    self.disk_prompt = Some(id);
+                    self.prompt = Some(prompt);
+                    return self.render_commandline();
+                }
+                DiskStatus::Deleted => {
+                    self.commandline.set_message(Message::error(format!(
+                        "\"{file_name}\" was deleted from disk"
+                    )));
+                    self.render_commandline()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_swap(&mut self) -> anyhow::Result<()> {
+        let buffer = self.buffers.get(&self.active_buffer).unwrap().clone();
+        let Some(prompt) = swap_check(&buffer) else {
+            return Ok(());
+        };
+        self.commandline.set_message(Message::info(prompt.text()));
+        self.prompt = Some(prompt);
+        self.render_commandline()
+    }
+
+    /// writes the swap file of every buffer with at least `min_edits` edits
+    /// that were not journaled yet
+    fn write_swaps(&mut self, min_edits: usize) -> anyhow::Result<()> {
+        for buffer in self.buffers.values() {
+            let mut buffer = buffer.borrow_mut();
+            if buffer.swap_edits() == 0 || buffer.swap_edits() < min_edits {
+                continue;
+            }
+            if let Err(err) = buffer.write_swap() {
+                tracing::error!("{err:#}");
+                self.commandline
+                    .set_message(Message::error(format!("{err:#}")));
+            }
+        }
+        Ok(())
+    }
+
+    fn render_commandline(&mut self) -> anyhow::Result<()> {
         self.stdout
-            .queue(cursor::RestorePosition)?
-            .queue(cursor::Show)?
-            .flush()?;
+            .queue(cursor::SavePosition)?
+            .queue(cursor::Hide)?;
+        self.commandline.render()?;
+        self.stdout.queue(cursor::RestorePosition)?;
+        // the cursor follows what is typed on the commandline
+        if let Some((col, row)) = self.commandline.input_cursor() {
+            self.stdout.queue(cursor::MoveTo(col, row))?;
+        }
+        self.stdout.queue(cursor::Show)?.flush()?;
         Ok(())
     }
 
@@ -242,6 +1815,319 @@ where
     }
 }
 
+// the search of `substitute` and what it replaces in the buffer of `window`
+fn substitutions(
+    substitute: &Substitute,
+    window: &Window,
+    last_search: Option<&Search>,
+) -> anyhow::Result<(Search, Vec<Replacement>)> {
+    let search = substitute.search(last_search)?;
+    let buffer = window.get_buffer();
+    let buffer = buffer.borrow();
+    let line = buffer.buffer.char_to_line(window.cursor.absolute_position);
+    let lines = substitute.range.resolve(&buffer, line)?;
+    let replacements = substitute.replacements(&search, &buffer, lines);
+    Ok((search, replacements))
+}
+
+// makes every replacement as a single change, leaving the cursor on the last
+// replaced line like vim
+fn replace(window: &mut Window, replacements: &[Replacement], mode: &Mode) -> anyhow::Result<()> {
+    let Some(last) = substitute::applied(replacements).pop() else {
+        return Ok(());
+    };
+    let buffer = window.get_buffer();
+    let pos = {
+        let mut buffer = buffer.borrow_mut();
+        let transaction = Transaction::change(&buffer.buffer, substitute::changes(replacements))?;
+        buffer.apply(&transaction)?;
+        let line = buffer.buffer.char_to_line(last.start);
+        buffer.first_non_blank(line)
+    };
+    window.jump_to(pos, mode)
+}
+
+// completes the word before the cursor to its single candidate, or to where
+// its candidates differ, or to the first one when they don't extend it, the
+// wildmenu is left to walk several candidates
+fn complete_command(
+    input: &mut Input,
+    completion: complete::Completion,
+    forward: bool,
+) -> Option<Wildmenu> {
+    let start = completion.start;
+    if let [candidate] = completion.candidates.as_slice() {
+        input.complete(start, candidate);
+        return None;
+    }
+    if completion.candidates.is_empty() {
+        return None;
+    }
+    let common = complete::common_prefix(&completion.candidates).to_string();
+    let typed = input.before_cursor().len() - start;
+    let mut wildmenu = Wildmenu::new(completion);
+    match common.len() > typed {
+        true => input.complete(start, &common),
+        false => input.complete(start, wildmenu.select(forward)),
+    }
+    Some(wildmenu)
+}
+
+// the buffer a prompt about a change on disk is for, `id` when it's still
+// open and the one of `window` otherwise
+fn disk_buffer(
+    buffers: &HashMap<usize, Rc<RefCell<Buffer>>>,
+    id: Option<usize>,
+    window: &Window,
+) -> Rc<RefCell<Buffer>> {
+    id.and_then(|id| buffers.get(&id).cloned())
+        .unwrap_or_else(|| window.get_buffer())
+}
+
+// the open buffer numbered `name`, named it, or the only one whose name
+// contains it
+fn find_buffer(
+    buffers: &HashMap<usize, Rc<RefCell<Buffer>>>,
+    name: &str,
+) -> anyhow::Result<Rc<RefCell<Buffer>>> {
+    if let Some(buffer) = name.parse().ok().and_then(|id| buffers.get(&id)) {
+        return Ok(buffer.clone());
+    }
+    if let Some(buffer) = buffers.values().find(|b| b.borrow().file_name == name) {
+        return Ok(buffer.clone());
+    }
+    let matching = buffers
+        .values()
+        .filter(|buffer| buffer.borrow().file_name.contains(name))
+        .collect::<Vec<_>>();
+    match matching.as_slice() {
+        [buffer] => Ok((*buffer).clone()),
+        [] => Err(anyhow::anyhow!("no matching buffer for {name}")),
+        _ => Err(anyhow::anyhow!("more than one match for {name}")),
+    }
+}
+
+// the theme file `name` of the themes directory, unlike the one of the
+// config a missing file is an error rather than the default theme
+fn load_colorscheme(
+    loaded: &mut HashMap<String, &'static Theme>,
+    background: &EditorBackground,
+    name: &str,
+) -> anyhow::Result<&'static Theme> {
+    if let Some(theme) = loaded.get(name) {
+        return Ok(theme);
+    }
+    let themes = Config::themes_path();
+    if !themes.join(name).is_file() {
+        anyhow::bail!("theme not found: {name}");
+    }
+    let theme = glyph_core::load_theme(background, name, themes)?;
+    // the window, statusline and commandline hold on to it until they quit,
+    // leaking each theme once is simpler than sharing them
+    let theme = Box::leak(Box::new(theme));
+    loaded.insert(name.to_string(), theme);
+    Ok(theme)
+}
+
+// the histories are saved as soon as they change, failing to is only logged
+fn write_histories(histories: &Histories) {
+    if let Err(err) = histories.write(&Histories::path()) {
+        tracing::error!("{err:#}");
+    }
+}
+
+// the keys of `:normal`, typed from the start of each of `lines` or from
+// the cursor without any, whatever they leave pending is cancelled
+fn normal_keys(keys: &str, lines: Vec<(Anchor, Anchor)>) -> Vec<Queued> {
+    let keys = keys::decode(keys)
+        .into_iter()
+        .chain([KeyEvent::from(KeyCode::Esc)])
+        .map(|key| Queued::Key(Event::Key(key)))
+        .collect::<Vec<_>>();
+    match lines.is_empty() {
+        true => keys,
+        false => lines
+            .into_iter()
+            .flat_map(|(start, next)| {
+                std::iter::once(Queued::Line(start, next)).chain(keys.clone())
+            })
+            .collect(),
+    }
+}
+
+// anchors the start of each of `lines` and of the line after it
+fn anchor_lines(
+    buffer: &mut Buffer,
+    lines: impl IntoIterator<Item = usize>,
+) -> Vec<(Anchor, Anchor)> {
+    lines
+        .into_iter()
+        .map(|line| {
+            let start = buffer.buffer.line_to_char(line);
+            let next = buffer.buffer.line_to_char(line + 1);
+            (buffer.marks.anchor(start), buffer.marks.anchor(next))
+        })
+        .collect()
+}
+
+// whether the line from `start` to `next` was deleted, or joined to another
+// one, by what ran on a previous line of `:g` or `:normal`
+fn collapsed(buffer: &Buffer, start: usize, next: usize) -> bool {
+    next <= start || (start > 0 && buffer.buffer.get_char(start - 1) != Some('\n'))
+}
+
+// the lines of `range` that `search` matches, or doesn't when `invert` is set
+fn global_lines(
+    window: &Window,
+    range: &LineRange,
+    search: &Search,
+    invert: bool,
+) -> anyhow::Result<Vec<usize>> {
+    let buffer = window.get_buffer();
+    let buffer = buffer.borrow();
+    let lines = range.resolve(&buffer, window.cursor.row)?;
+    let matching = lines
+        .filter(|line| search.matches(&buffer.line_text(*line)).is_empty() == invert)
+        .collect::<Vec<_>>();
+    if matching.is_empty() {
+        anyhow::bail!("pattern not found: {}", search.pattern);
+    }
+    Ok(matching)
+}
+
+// the start of each line `:g` runs on and of the line after it, in order,
+// they follow the edits of the command themselves rather than as anchors,
+// which the buffer would map one by one on every edit
+struct GlobalLines {
+    // the positions, less `shift` which applies to all of them
+    positions: Vec<isize>,
+    shift: isize,
+}
+
+impl GlobalLines {
+    fn new(buffer: &Buffer, lines: &[usize]) -> Self {
+        let positions = lines
+            .iter()
+            .flat_map(|line| [*line, line + 1])
+            .map(|line| buffer.buffer.line_to_char(line) as isize)
+            .collect();
+        Self {
+            positions,
+            shift: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len() / 2
+    }
+
+    // the start of line `i` and of the line after it
+    fn line(&self, i: usize) -> (usize, usize) {
+        let pos = |i: usize| (self.positions[i] + self.shift) as usize;
+        (pos(2 * i), pos(2 * i + 1))
+    }
+
+    // follows `changes` with the lines from `from` on, the positions past the
+    // last edit all move by the same amount so only those before are mapped
+    fn map(&mut self, changes: &ChangeSet, from: usize) {
+        let Some(end) = changes.last_change_end() else {
+            return;
+        };
+        let shift = self.shift;
+        self.shift += changes.len_after() as isize - changes.len() as isize;
+        for i in 2 * from..self.positions.len() {
+            let pos = (self.positions[i] + shift) as usize;
+            if pos >= end {
+                break;
+            }
+            self.positions[i] = changes.map_pos(pos, Assoc::After) as isize - self.shift;
+        }
+    }
+}
+
+// runs the command of `:g` on each of `lines` left in the buffer, they are
+// all undone at once
+fn run_global(
+    window: &mut Window,
+    registers: &mut Registers,
+    lines: &[usize],
+    action: &Action,
+    last_search: Option<&Search>,
+    mode: &Mode,
+) -> anyhow::Result<()> {
+    let buffer = window.get_buffer();
+    let mut lines = GlobalLines::new(&buffer.borrow(), lines);
+    buffer.borrow_mut().record_changes(true);
+    let result = (0..lines.len()).try_for_each(|i| {
+        let (start, next) = lines.line(i);
+        if collapsed(&buffer.borrow(), start, next) {
+            return Ok(());
+        }
+        window.jump_to(start, mode)?;
+        match action {
+            Action::Substitute(substitute) if substitute.flags.confirm => {
+                anyhow::bail!("cannot confirm substitutions within :g")
+            }
+            Action::Substitute(substitute) => {
+                let (_, replacements) = substitutions(substitute, window, last_search)?;
+                replace(window, &replacements, mode)?;
+            }
+            Action::DeleteLines(..)
+            | Action::YankLines(..)
+            | Action::MoveLines(..)
+            | Action::CopyLines(..)
+            | Action::JoinLines(..)
+            | Action::IndentLines { .. }
+            | Action::SortLines(..) => window.line_command(action, registers, mode)?,
+            _ => anyhow::bail!("command not supported within :g"),
+        }
+        for changes in buffer.borrow_mut().take_changes() {
+            lines.map(&changes, i + 1);
+        }
+        Ok(())
+    });
+    buffer.borrow_mut().record_changes(false);
+    result
+}
+
+// e.g. `3 substitutions on 2 lines`
+fn count_message(replacements: &[Replacement], what: (&str, &str)) -> String {
+    let lines = replacements
+        .iter()
+        .map(|replacement| replacement.line)
+        .collect::<HashSet<_>>()
+        .len();
+    let count = replacements.len();
+    let what = if count == 1 { what.0 } else { what.1 };
+    let line = if lines == 1 { "line" } else { "lines" };
+    format!("{count} {what} on {lines} {line}")
+}
+
+// the prompt for the swap file `buffer` was left with, unless there's none or
+// it holds nothing that was lost, e.g. the editor was killed right after a save
+fn swap_check(buffer: &RefCell<Buffer>) -> Option<Prompt> {
+    let swap = buffer.borrow().find_swap()?;
+    if swap.content == buffer.borrow().to_string() && !swap.is_running() {
+        buffer.borrow_mut().remove_swap();
+        return None;
+    }
+    Some(swap_prompt(&buffer.borrow().file_name, &swap))
+}
+
+fn swap_prompt(file_name: &str, swap: &Swap) -> Prompt {
+    let running = match swap.is_running() {
+        true => " (still running)",
+        false => "",
+    };
+    Prompt::new(format!(
+        "found unsaved changes to \"{file_name}\" from process {}{running}",
+        swap.pid
+    ))
+    .choice('r', "recover", Action::RecoverSwap)
+    .choice('d', "discard", Action::DiscardSwap)
+    .choice('f', "diff", Action::DiffSwap)
+}
+
 fn flatten_actions(actions: &mut Vec<Action>, action: KeyAction) {
     match action {
         KeyAction::Multiple(a) => actions.extend(a),
@@ -252,3 +2138,147 @@ fn flatten_actions(actions: &mut Vec<Action>, action: KeyAction) {
         }
     };
 }
+
+/// prunes the undo directory and restores the persisted history of `buffer`,
+/// failing to do so only loses the history so errors are just logged
+fn load_undo(buffer: &mut Buffer, config: &UndoConfig) {
+    let dir = undo::undo_dir();
+    let max_size = config.max_size_mb * 1024 * 1024;
+    let max_age = Duration::from_secs(config.max_age_days * 24 * 60 * 60);
+    if let Err(err) = undo::prune(&dir, max_size, max_age) {
+        tracing::error!("failed to prune {dir:?}: {err}");
+    }
+    let Some(path) = buffer.path.clone() else {
+        return;
+    };
+    if let Err(err) = buffer.load_undo(undo::undo_path(&dir, &path)) {
+        tracing::error!("{err:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glyph_core::register::RegisterKind;
+
+    #[test]
+    fn test_complete_command() {
+        let completion = |start, candidates: &[&str]| complete::Completion {
+            start,
+            candidates: candidates.iter().map(|c| c.to_string()).collect(),
+        };
+        let mut input = Input::default();
+
+        input.set("set rel");
+        assert!(complete_command(&mut input, completion(4, &["relativenumber"]), true).is_none());
+        assert_eq!(input.text(), "set relativenumber");
+        input.set("se nonu");
+        let wildmenu = complete_command(
+            &mut input,
+            completion(3, &["nonumber", "nonumberwidth"]),
+            true,
+        );
+        assert_eq!(input.text(), "se nonumber");
+        assert_eq!(wildmenu.unwrap().selected(), None);
+        input.set("so");
+        let wildmenu = complete_command(&mut input, completion(0, &["sort", "saveas"]), false);
+        assert_eq!(input.text(), "saveas");
+        assert_eq!(wildmenu.unwrap().selected(), Some(1));
+        assert!(complete_command(&mut input, completion(0, &[]), true).is_none());
+    }
+
+    #[test]
+    fn test_global_lines_follow_edits() {
+        let mut buffer = Buffer::from_string(1, "a\nb\nc\nd\ne\n");
+        let mut lines = GlobalLines::new(&buffer, &[0, 2, 3, 4]);
+        buffer.record_changes(true);
+        let run = |buffer: &mut Buffer, lines: &mut GlobalLines, i: usize| {
+            for changes in buffer.take_changes() {
+                lines.map(&changes, i + 1);
+            }
+        };
+
+        // `:d` on the first line
+        buffer.delete(0, 0, RegisterKind::Linewise);
+        run(&mut buffer, &mut lines, 0);
+        assert_eq!(lines.line(1), (2, 4));
+        // `:j` on the second one takes the third with it
+        buffer.join_lines(1, 2, true);
+        run(&mut buffer, &mut lines, 1);
+        let (start, next) = lines.line(2);
+        assert!(collapsed(&buffer, start, next));
+        assert_eq!(lines.line(3), (6, 8));
+        // `:m0` on the last one
+        buffer.move_lines(2, 2, 0).unwrap();
+        run(&mut buffer, &mut lines, 3);
+        assert_eq!(buffer.to_string(), "e\nb\nc d\n");
+        assert_eq!(buffer.take_changes(), vec![]);
+    }
+
+    #[test]
+    fn test_find_buffer() {
+        let buffers = [(1, "src/main.rs"), (2, "src/lib.rs"), (3, "lib")]
+            .into_iter()
+            .map(|(id, name)| {
+                let mut buffer = Buffer::from_string(id, "");
+                buffer.file_name = name.to_string();
+                (id, Rc::new(RefCell::new(buffer)))
+            })
+            .collect::<HashMap<_, _>>();
+        let id = |name| find_buffer(&buffers, name).map(|buffer| buffer.borrow().id);
+
+        assert_eq!(id("2").unwrap(), 2);
+        assert_eq!(id("main").unwrap(), 1);
+        assert_eq!(id("lib").unwrap(), 3);
+        assert!(id("src").is_err());
+        assert!(id("none").is_err());
+    }
+
+    #[test]
+    fn test_change_with_count() {
+        let operator = Change {
+            actions: vec![Action::OperatorMotion {
+                operator: Operator::Delete,
+                motion: Box::new(Action::NextWord),
+                count: 2,
+            }],
+            register: Some('a'),
+        };
+        let Action::OperatorMotion { count, .. } = &operator.with_count(5).actions[0] else {
+            panic!("expected an operator");
+        };
+        assert_eq!(*count, 5);
+
+        let paste = Change {
+            actions: vec![Action::PasteBelow],
+            register: None,
+        };
+        assert!(matches!(
+            paste.with_count(3).actions.as_slice(),
+            [Action::Repeat(3, _)]
+        ));
+
+        let insert = Change {
+            actions: vec![
+                Action::EnterMode(Mode::Insert),
+                Action::InsertChar('x'),
+                Action::EnterMode(Mode::Normal),
+            ],
+            register: None,
+        };
+        assert_eq!(insert.with_count(2).actions.len(), 6);
+
+        let counted_insert = Change {
+            actions: vec![
+                Action::RepeatActions(3, vec![Action::InsertLineBelow]),
+                Action::InsertChar('x'),
+                Action::EnterMode(Mode::Normal),
+            ],
+            register: None,
+        };
+        assert!(matches!(
+            counted_insert.with_count(2).actions.as_slice(),
+            [Action::RepeatActions(2, _), _, _]
+        ));
+    }
+}
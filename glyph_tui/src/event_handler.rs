@@ -1,86 +1,290 @@
 use std::collections::HashMap;
 
 use glyph_core::config::Config;
-use glyph_core::editor::{Action, KeyAction, Mode};
+use glyph_core::editor::{Action, KeyAction, Mode, Operator};
 use glyph_core::event_handler::EventHandler;
+use glyph_core::keys;
+use glyph_core::register::Registers;
 
 use crossterm::event::Event;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
+// counts typed past it are cut to it, repeating an action more times than
+// that is never meant
+const MAX_COUNT: usize = 99_999;
+
 #[derive(Debug)]
 pub struct TuiEventHandler<'a> {
-    action_being_composed: Option<String>,
+    // keys typed so far of a mapping that takes more than one key
+    pending_keys: Vec<String>,
+    count: Option<usize>,
+    // operator waiting for a motion, alongside the key that applies it to
+    // whole lines when typed again and the count typed before it
+    operator: Option<(Operator, String, usize)>,
+    awaiting: Option<Awaiting>,
+    // register being recorded into and the keys typed since
+    recording: Option<(char, String)>,
+    replaying: bool,
     config: &'a Config,
 }
 
+// what the next key names a register or a mark for
+#[derive(Debug)]
+enum Awaiting {
+    Register,
+    Recording,
+    // the count typed before `@`
+    Macro(usize),
+    SetMark,
+    // the operator going to the mark applies to, with its count
+    Mark {
+        linewise: bool,
+        operator: Option<(Operator, usize)>,
+    },
+}
+
 impl EventHandler for TuiEventHandler<'_> {
     fn poll(&mut self, event: &Event, mode: &Mode) -> Option<KeyAction> {
-        if let Some(action) = self.action_being_composed.take() {
-            self.action_being_composed = Some(action);
-            match event {
-                Event::Key(KeyEvent { code, .. }) => {
-                    let action = self.action_being_composed.clone().unwrap();
-                    let action = self.config.keys.normal.get(&action).unwrap();
-                    let key = match code {
-                        KeyCode::Char(c) => *c,
-                        _ => ' ',
-                    };
+        if let Event::Resize(cols, rows) = event {
+            return Some(KeyAction::Simple(Action::Resize(*cols, *rows)));
+        }
+        if let (Some((_, keys)), Event::Key(key), false) =
+            (&mut self.recording, event, self.replaying)
+        {
+            keys.extend(keys::encode(key));
+        }
 
-                    match action {
-                        KeyAction::Complex(complex) => {
-                            let action = complex.get(key.to_string().as_str());
-                            if let Some(action) = action {
-                                self.action_being_composed = None;
-                                return Some(action.clone());
-                            }
-                            self.action_being_composed = None;
-                        }
-                        _ => {
-                            self.action_being_composed = None;
-                        }
+        if let Some(awaiting) = self.awaiting.take() {
+            let Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) = event
+            else {
+                return None;
+            };
+            let action = match (awaiting, mode) {
+                (Awaiting::Register, Mode::Insert | Mode::Command | Mode::Search) => {
+                    Action::InsertRegister(*c)
+                }
+                (Awaiting::Register, _) => Action::SelectRegister(*c),
+                (Awaiting::Recording, _) => {
+                    if Registers::is_valid(*c) {
+                        self.recording = Some((*c, String::new()));
                     }
+                    Action::StartRecording(*c)
                 }
-                _ => {
-                    self.action_being_composed = None;
+                (Awaiting::Macro(count), _) if count > 1 => {
+                    Action::Repeat(count, Box::new(Action::PlayMacro(*c)))
                 }
-            }
-        }
-
-        if let Event::Resize(cols, rows) = event {
-            return Some(KeyAction::Simple(Action::Resize(*cols, *rows)));
+                (Awaiting::Macro(_), _) => Action::PlayMacro(*c),
+                (Awaiting::SetMark, _) => Action::SetMark(*c),
+                (Awaiting::Mark { linewise, operator }, _) => {
+                    let motion = match linewise {
+                        true => Action::GoToMarkLine(*c),
+                        false => Action::GoToMark(*c),
+                    };
+                    match operator {
+                        Some((operator, count)) => Action::OperatorMotion {
+                            operator,
+                            motion: Box::new(motion),
+                            count,
+                        },
+                        None => motion,
+                    }
+                }
+            };
+            return Some(KeyAction::Simple(action));
         }
 
-        match mode {
-            Mode::Normal => self.handle_normal_event(event),
+        let action = match mode {
+            Mode::Normal | Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                self.handle_normal_event(event, mode)
+            }
             Mode::Insert => self.handle_insert_event(event),
             Mode::Command => self.handle_command_event(event),
             Mode::Search => self.handle_search_event(event),
+        };
+        let Some(KeyAction::Simple(simple)) = &action else {
+            return action;
+        };
+        let (count, simple) = match simple {
+            Action::Repeat(count, action) => (*count, &**action),
+            action => (1, action),
+        };
+        match simple {
+            Action::AwaitRegister => self.awaiting = Some(Awaiting::Register),
+            Action::AwaitMacro => self.awaiting = Some(Awaiting::Macro(count)),
+            Action::AwaitSetMark => self.awaiting = Some(Awaiting::SetMark),
+            Action::AwaitMark | Action::AwaitMarkLine => {
+                self.awaiting = Some(Awaiting::Mark {
+                    linewise: matches!(simple, Action::AwaitMarkLine),
+                    operator: None,
+                })
+            }
+            Action::OperatorMotion {
+                operator,
+                motion,
+                count,
+            } if matches!(**motion, Action::AwaitMark | Action::AwaitMarkLine) => {
+                self.awaiting = Some(Awaiting::Mark {
+                    linewise: matches!(**motion, Action::AwaitMarkLine),
+                    operator: Some((*operator, *count)),
+                })
+            }
+            Action::ToggleRecording => match self.recording.take() {
+                // the `q` that stops recording isn't part of the macro
+                Some((name, mut keys)) => {
+                    if !self.replaying {
+                        keys.pop();
+                    }
+                    return Some(KeyAction::Simple(Action::StoreMacro(name, keys)));
+                }
+                None => self.awaiting = Some(Awaiting::Recording),
+            },
+            _ => return action,
         }
+        None
+    }
+
+    fn poll_replayed(&mut self, event: &Event, mode: &Mode) -> Option<KeyAction> {
+        self.replaying = true;
+        let action = self.poll(event, mode);
+        self.replaying = false;
+        action
     }
 }
 
 impl<'a> TuiEventHandler<'a> {
     pub fn new(config: &'a Config) -> Self {
         Self {
-            action_being_composed: None,
+            pending_keys: vec![],
+            count: None,
+            operator: None,
+            awaiting: None,
+            recording: None,
+            replaying: false,
             config,
         }
     }
 
-    pub fn handle_normal_event(&mut self, event: &Event) -> Option<KeyAction> {
-        let (key, action) = self.map_event_to_key_action(&self.config.keys.normal, event);
-        if let Some(action) = action {
-            match action {
-                KeyAction::Simple(_) => return Some(action),
-                KeyAction::Multiple(_) => return Some(action),
-                KeyAction::Complex(_) => {
-                    self.action_being_composed = key;
-                    return None;
-                }
+    /// normal mode keys follow vim's grammar: an optional count, then either
+    /// an action, which the count repeats, or an operator followed by another
+    /// optional count and the motion it applies to
+    pub fn handle_normal_event(&mut self, event: &Event, mode: &Mode) -> Option<KeyAction> {
+        let key = Self::key_name(event)?;
+        // a pending command is cancelled first, then the extra cursors
+        if key == "Esc" {
+            let pending =
+                !self.pending_keys.is_empty() || self.count.is_some() || self.operator.is_some();
+            self.reset();
+            return match mode.is_visual() {
+                true => Some(KeyAction::Simple(Action::EnterMode(Mode::Normal))),
+                false if pending => None,
+                false => Some(KeyAction::Simple(Action::ClearCursors)),
             };
+        }
+
+        if self.pending_keys.is_empty() {
+            let digit = key.parse::<usize>().ok().filter(|_| key.len() == 1);
+            if let Some(digit) = digit.filter(|d| *d > 0 || self.count.is_some()) {
+                let count = self.count.unwrap_or(0).saturating_mul(10) + digit;
+                self.count = Some(count.min(MAX_COUNT));
+                return None;
+            }
+            if let Some((operator, line_key, operator_count)) = &self.operator {
+                if key == *line_key {
+                    let action = Action::OperatorMotion {
+                        operator: *operator,
+                        motion: Box::new(Action::CurrentLine),
+                        count: Self::multiply(*operator_count, self.count),
+                    };
+                    self.reset();
+                    return Some(KeyAction::Simple(action));
+                }
+            }
+        }
+
+        self.pending_keys.push(key);
+        // visual mode keys fall back to text objects and then to normal mode
+        let keys = &self.config.keys;
+        let mappings = match (mode.is_visual(), &self.operator) {
+            (true, _) => vec![&keys.visual, &keys.operator, &keys.normal],
+            (false, Some(_)) => vec![&keys.operator, &keys.normal],
+            (false, None) => vec![&keys.normal],
         };
-        None
+        let action = mappings
+            .into_iter()
+            .find_map(|mappings| Self::lookup(mappings, &self.pending_keys))
+            .cloned();
+        if let Some(KeyAction::Complex(_)) = action {
+            return None;
+        }
+        let keys = std::mem::take(&mut self.pending_keys);
+        let count = self.count.take();
+        match (action, self.operator.take()) {
+            // operators apply to the selection right away
+            (Some(KeyAction::Simple(Action::Operator(operator))), None) if mode.is_visual() => {
+                Some(KeyAction::Simple(Action::Operator(operator)))
+            }
+            (Some(KeyAction::Simple(Action::Operator(operator))), None) => {
+                let line_key = keys.last().cloned().unwrap_or_default();
+                self.operator = Some((operator, line_key, count.unwrap_or(1)));
+                None
+            }
+            (Some(KeyAction::Simple(motion)), Some((operator, _, operator_count))) => {
+                Some(KeyAction::Simple(Action::OperatorMotion {
+                    operator,
+                    motion: Box::new(motion),
+                    count: Self::multiply(operator_count, count),
+                }))
+            }
+            // only single actions can be operated on
+            (_, Some(_)) => None,
+            (Some(KeyAction::Simple(action)), None) => match count {
+                Some(count) if count > 1 => {
+                    Some(KeyAction::Simple(Action::Repeat(count, Box::new(action))))
+                }
+                _ => Some(KeyAction::Simple(action)),
+            },
+            (Some(KeyAction::Multiple(actions)), None) => match count {
+                Some(count) if count > 1 => {
+                    Some(KeyAction::Simple(Action::RepeatActions(count, actions)))
+                }
+                _ => Some(KeyAction::Multiple(actions)),
+            },
+            (action, None) => action,
+        }
     }
+
+    // the counts typed before an operator and before its motion
+    fn multiply(operator_count: usize, count: Option<usize>) -> usize {
+        operator_count
+            .saturating_mul(count.unwrap_or(1))
+            .min(MAX_COUNT)
+    }
+
+    fn reset(&mut self) {
+        self.pending_keys.clear();
+        self.count = None;
+        self.operator = None;
+    }
+
+    // walks the nested mappings following `keys`
+    fn lookup<'m>(
+        mappings: &'m HashMap<String, KeyAction>,
+        keys: &[String],
+    ) -> Option<&'m KeyAction> {
+        let (first, rest) = keys.split_first()?;
+        let mut action = mappings.get(first)?;
+        for key in rest {
+            match action {
+                KeyAction::Complex(mappings) => action = mappings.get(key)?,
+                _ => return None,
+            }
+        }
+        Some(action)
+    }
+
     pub fn handle_insert_event(&self, event: &Event) -> Option<KeyAction> {
         let (_, action) = self.map_event_to_key_action(&self.config.keys.insert, event);
         if let Some(action) = action {
@@ -118,8 +322,18 @@ impl<'a> TuiEventHandler<'a> {
         }
     }
 
-    pub fn handle_search_event(&self, _event: &Event) -> Option<KeyAction> {
-        None
+    pub fn handle_search_event(&self, event: &Event) -> Option<KeyAction> {
+        let (_, action) = self.map_event_to_key_action(&self.config.keys.search, event);
+        if action.is_some() {
+            return action;
+        }
+        match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) => Some(KeyAction::Simple(Action::InsertCommand(*c))),
+            _ => None,
+        }
     }
 
     pub fn map_event_to_key_action(
@@ -127,25 +341,245 @@ impl<'a> TuiEventHandler<'a> {
         mappings: &HashMap<String, KeyAction>,
         event: &Event,
     ) -> (Option<String>, Option<KeyAction>) {
-        match event {
-            Event::Key(KeyEvent {
-                code, modifiers, ..
-            }) => {
-                let key = match code {
-                    KeyCode::Char(c) => format!("{c}"),
-                    _ => format!("{code:?}"),
-                };
-
-                let key = match *modifiers {
-                    KeyModifiers::ALT => format!("A-{key}"),
-                    KeyModifiers::CONTROL => format!("C-{key}"),
-                    KeyModifiers::SHIFT => format!("S-{key}"),
-                    _ => key,
-                };
-
-                (Some(key.clone()), mappings.get(&key).cloned())
+        match Self::key_name(event) {
+            Some(key) => {
+                let action = mappings.get(&key).cloned();
+                (Some(key), action)
             }
-            _ => (None, None),
+            None => (None, None),
         }
     }
+
+    fn key_name(event: &Event) -> Option<String> {
+        let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        else {
+            return None;
+        };
+        let key = match code {
+            KeyCode::Char(c) => format!("{c}"),
+            _ => format!("{code:?}"),
+        };
+
+        let key = match *modifiers {
+            KeyModifiers::ALT => format!("A-{key}"),
+            KeyModifiers::CONTROL => format!("C-{key}"),
+            KeyModifiers::SHIFT => format!("S-{key}"),
+            _ => key,
+        };
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glyph_core::buffer::text_object::TextObject;
+
+    fn config() -> Config {
+        toml::from_str(include_str!("../../config/glyph.toml")).unwrap()
+    }
+
+    fn keys(handler: &mut TuiEventHandler, keys: &str) -> Option<KeyAction> {
+        let mut action = None;
+        for c in keys.chars() {
+            let event = Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            action = handler.poll(&event, &Mode::Normal);
+        }
+        action
+    }
+
+    fn operator_motion(action: Option<KeyAction>) -> (Operator, Action, usize) {
+        match action {
+            Some(KeyAction::Simple(Action::OperatorMotion {
+                operator,
+                motion,
+                count,
+            })) => (operator, *motion, count),
+            action => panic!("expected an operator, got {action:?}"),
+        }
+    }
+
+    #[test]
+    fn test_operator_with_counts() {
+        let config = config();
+        let mut handler = TuiEventHandler::new(&config);
+
+        let (operator, motion, count) = operator_motion(keys(&mut handler, "3dw"));
+        assert_eq!(operator, Operator::Delete);
+        assert!(matches!(motion, Action::NextWord));
+        assert_eq!(count, 3);
+
+        let (_, motion, count) = operator_motion(keys(&mut handler, "2d3j"));
+        assert!(matches!(motion, Action::MoveDown));
+        assert_eq!(count, 6);
+
+        let (operator, motion, count) = operator_motion(keys(&mut handler, "c$"));
+        assert_eq!(operator, Operator::Change);
+        assert!(matches!(motion, Action::MoveToLineEnd));
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_doubled_operator_covers_lines() {
+        let config = config();
+        let mut handler = TuiEventHandler::new(&config);
+
+        let (operator, motion, count) = operator_motion(keys(&mut handler, "2yy"));
+        assert_eq!(operator, Operator::Yank);
+        assert!(matches!(motion, Action::CurrentLine));
+        assert_eq!(count, 2);
+
+        let (operator, motion, _) = operator_motion(keys(&mut handler, "guu"));
+        assert_eq!(operator, Operator::Lowercase);
+        assert!(matches!(motion, Action::CurrentLine));
+    }
+
+    #[test]
+    fn test_counts_repeat_actions() {
+        let config = config();
+        let mut handler = TuiEventHandler::new(&config);
+
+        assert!(matches!(
+            keys(&mut handler, "10j"),
+            Some(KeyAction::Simple(Action::Repeat(10, _)))
+        ));
+        assert!(matches!(
+            keys(&mut handler, "0"),
+            Some(KeyAction::Simple(Action::MoveToLineStart))
+        ));
+        assert!(matches!(
+            keys(&mut handler, "999999999j"),
+            Some(KeyAction::Simple(Action::Repeat(MAX_COUNT, _)))
+        ));
+        let (_, _, count) = operator_motion(keys(&mut handler, "99999d99999j"));
+        assert_eq!(count, MAX_COUNT);
+        // keys running several actions keep their count too
+        let Some(KeyAction::Simple(Action::RepeatActions(3, actions))) = keys(&mut handler, "3o")
+        else {
+            panic!("expected the actions of `o` repeated");
+        };
+        assert!(matches!(
+            actions.as_slice(),
+            [Action::InsertLineBelow, Action::EnterMode(Mode::Insert)]
+        ));
+        assert!(matches!(
+            keys(&mut handler, "1o"),
+            Some(KeyAction::Multiple(_))
+        ));
+        assert!(matches!(
+            keys(&mut handler, "j"),
+            Some(KeyAction::Simple(Action::MoveDown))
+        ));
+    }
+
+    #[test]
+    fn test_text_objects_after_operator() {
+        let config = config();
+        let mut handler = TuiEventHandler::new(&config);
+
+        let (operator, motion, _) = operator_motion(keys(&mut handler, "ci("));
+        assert_eq!(operator, Operator::Change);
+        assert!(matches!(motion, Action::Inner(TextObject::Bracket('('))));
+
+        let (_, motion, count) = operator_motion(keys(&mut handler, "d2aw"));
+        assert!(matches!(motion, Action::Around(TextObject::Word)));
+        assert_eq!(count, 2);
+
+        // without an operator `i` still enters insert mode
+        assert!(matches!(
+            keys(&mut handler, "i"),
+            Some(KeyAction::Simple(Action::EnterMode(Mode::Insert)))
+        ));
+    }
+
+    #[test]
+    fn test_visual_mode_keys() {
+        let config = config();
+        let mut handler = TuiEventHandler::new(&config);
+        let poll = |handler: &mut TuiEventHandler, c: char| {
+            let event = Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+            handler.poll(&event, &Mode::Visual)
+        };
+
+        assert!(matches!(
+            poll(&mut handler, 'd'),
+            Some(KeyAction::Simple(Action::Operator(Operator::Delete)))
+        ));
+        assert!(poll(&mut handler, 'i').is_none());
+        assert!(matches!(
+            poll(&mut handler, 'w'),
+            Some(KeyAction::Simple(Action::Inner(TextObject::Word)))
+        ));
+        assert!(matches!(
+            poll(&mut handler, 'o'),
+            Some(KeyAction::Simple(Action::SwapSelectionEnds))
+        ));
+
+        let esc = Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(matches!(
+            handler.poll(&esc, &Mode::Visual),
+            Some(KeyAction::Simple(Action::EnterMode(Mode::Normal)))
+        ));
+        // in normal mode it cancels what is pending before the extra cursors
+        assert!(keys(&mut handler, "d").is_none());
+        assert!(handler.poll(&esc, &Mode::Normal).is_none());
+        assert!(matches!(
+            handler.poll(&esc, &Mode::Normal),
+            Some(KeyAction::Simple(Action::ClearCursors))
+        ));
+    }
+
+    #[test]
+    fn test_macro_recording() {
+        let config = config();
+        let mut handler = TuiEventHandler::new(&config);
+
+        assert!(matches!(
+            keys(&mut handler, "qa"),
+            Some(KeyAction::Simple(Action::StartRecording('a')))
+        ));
+        keys(&mut handler, "2dw");
+        let esc = Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        handler.poll(&esc, &Mode::Normal);
+        // keys replayed from another macro aren't recorded twice
+        let j = Event::Key(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE));
+        handler.poll_replayed(&j, &Mode::Normal);
+        match keys(&mut handler, "q") {
+            Some(KeyAction::Simple(Action::StoreMacro('a', keys))) => {
+                assert_eq!(keys, "2dw<Esc>")
+            }
+            action => panic!("expected a macro, got {action:?}"),
+        }
+
+        assert!(keys(&mut handler, "3@").is_none());
+        assert!(matches!(
+            keys(&mut handler, "a"),
+            Some(KeyAction::Simple(Action::Repeat(3, action))) if matches!(*action, Action::PlayMacro('a'))
+        ));
+        assert!(matches!(
+            keys(&mut handler, "@@"),
+            Some(KeyAction::Simple(Action::PlayMacro('@')))
+        ));
+    }
+
+    #[test]
+    fn test_marks() {
+        let config = config();
+        let mut handler = TuiEventHandler::new(&config);
+
+        assert!(matches!(
+            keys(&mut handler, "ma"),
+            Some(KeyAction::Simple(Action::SetMark('a')))
+        ));
+        assert!(matches!(
+            keys(&mut handler, "`B"),
+            Some(KeyAction::Simple(Action::GoToMark('B')))
+        ));
+        let (operator, motion, count) = operator_motion(keys(&mut handler, "2d'a"));
+        assert_eq!(operator, Operator::Delete);
+        assert!(matches!(motion, Action::GoToMarkLine('a')));
+        assert_eq!(count, 2);
+    }
 }
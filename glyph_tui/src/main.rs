@@ -7,9 +7,10 @@ mod statusline;
 mod view;
 
 use commandline::TuiCommandline;
+use glyph_core::buffer::Buffer;
 use glyph_core::commandline::Commandline;
 use glyph_core::config::Config;
-use glyph_core::editor::Size;
+use glyph_core::editor::{Action, Size};
 use glyph_core::lsp::LspClient;
 use glyph_core::statusline::Statusline;
 
@@ -32,14 +33,23 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
 
-    let file_name = std::env::args().nth(1);
+    let mut args = std::env::args().skip(1);
+    let file_name = match args.next() {
+        Some(flag) if flag == "--recover" => {
+            let file_name = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("usage: gly --recover <file>"))?;
+            return recover(file_name);
+        }
+        file_name => file_name,
+    };
     let lsp = LspClient::start().await?;
     let config = glyph_core::load_config()?;
     let theme = glyph_core::load_theme(&config.background, &config.theme, Config::themes_path())?;
     let event_handler = TuiEventHandler::new(&config);
     let size: Size = crossterm::terminal::size()?.into();
     let statusline = TuiStatusline::new(Rect::new(0, size.height - 2, size.width, 1), &theme);
-    let commandline = TuiCommandline::new(Rect::new(0, size.height - 1, size.width, 1));
+    let commandline = TuiCommandline::new(Rect::new(0, size.height - 1, size.width, 1), &theme);
     let editor_setup = EditorSetup {
         config: &config,
         theme: &theme,
@@ -52,3 +62,16 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// writes the content of the swap file of `file_name` back to it without
+/// starting the editor
+fn recover(file_name: String) -> anyhow::Result<()> {
+    let mut buffer = Buffer::new(0, Some(file_name.clone()))?;
+    if buffer.find_swap().is_none() {
+        anyhow::bail!("no swap file found for {file_name}");
+    }
+    buffer.recover_swap()?;
+    buffer.handle_action(&Action::ForceSaveBuffer, 0)?;
+    println!("recovered {file_name}");
+    Ok(())
+}
@@ -115,7 +115,7 @@
 //
 //impl<'a> HoverPopup<'a> {
 //    pub fn new(col: usize, row: usize, theme: &'a Theme, content: String) -> Self {
-//        let buffer = Buffer::from_string(0, &content, 0);
+//        let buffer = Buffer::from_string(0, &content);
 //        let area = HoverPopup::calculate_area(&buffer, col, row);
 //        Self {
 //            theme,
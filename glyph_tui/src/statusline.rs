@@ -56,6 +56,10 @@ impl<'a> Statusline<'a> for TuiStatusline<'a> {
         }
         Ok(())
     }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+    }
 }
 
 impl TuiStatusline<'_> {
@@ -66,6 +70,13 @@ impl TuiStatusline<'_> {
         let Position { col, row } = update.cursor_pos;
         let file_name = buffer.file_name.clone();
 
+        let file_format = match update.search_count {
+            Some((current, total)) => format!(
+                "[{current}/{total}] {} | {} ",
+                buffer.encoding, buffer.line_ending
+            ),
+            None => format!("{} | {} ", buffer.encoding, buffer.line_ending),
+        };
         let cursor = format!("{}:{} ", row, col);
         let percentage = match row {
             1 => "TOP ".into(),
@@ -79,7 +90,8 @@ impl TuiStatusline<'_> {
         let mode = format!(" {}", mode);
 
         let padding = " ".repeat(
-            self.area.width - mode.len() - file_name.len() - cursor.len() - percentage.len(),
+            (self.area.width - mode.len() - file_name.len() - cursor.len() - percentage.len())
+                .saturating_sub(file_format.len()),
         );
         self.view
             .set_text(0, 0, &mode, &self.theme.statusline.inner);
@@ -112,5 +124,12 @@ impl TuiStatusline<'_> {
             &percentage,
             &self.theme.statusline.inner,
         );
+
+        self.view.set_text(
+            (self.area.width - cursor.len() - percentage.len()).saturating_sub(file_format.len()),
+            0,
+            &file_format,
+            &self.theme.statusline.inner,
+        );
     }
 }
@@ -1,14 +1,12 @@
 use std::io::{stdout, Stdout};
+use std::ops::Range;
 
 use glyph_core::buffer::Buffer;
-use glyph_core::config::{Config, LineNumbers};
 use glyph_core::cursor::Cursor;
 use glyph_core::editor::{Cell, Mode, Position, Rect};
-use glyph_core::gutter::absolute_line_gutter::AbsoluteLineGutter;
 use glyph_core::gutter::noop_line_gutter::NoopLineGutter;
-use glyph_core::gutter::relative_line_gutter::RelativeLineGutter;
 use glyph_core::gutter::Gutter;
-use glyph_core::theme::Theme;
+use glyph_core::theme::{Style, Theme};
 use glyph_core::ui::{Renderable, Scrollable};
 
 use crossterm::{cursor, style, QueueableCommand};
@@ -18,40 +16,23 @@ use crate::diff::Viewport;
 pub struct TuiView<'a> {
     stdout: Stdout,
     area: Rect,
-    config: &'a Config,
     theme: &'a Theme,
     scroll: Position,
     diff: Viewport,
+    // swapped as the options of the window change
     gutter: Box<dyn Gutter>,
+    gutter_width: usize,
 }
 
 impl<'a> TuiView<'a> {
-    pub fn new(area: Rect, config: &'a Config, theme: &'a Theme) -> Self {
-        let gutter: Box<dyn Gutter> = match config.line_numbers {
-            LineNumbers::Absolute => Box::new(AbsoluteLineGutter::new(
-                config.empty_line_char,
-                config.gutter_width,
-            )),
-            LineNumbers::Relative => Box::new(RelativeLineGutter::new(
-                config.empty_line_char,
-                config.gutter_width,
-                config.line_numbers.clone(),
-            )),
-            LineNumbers::RelativeNumbered => Box::new(RelativeLineGutter::new(
-                config.empty_line_char,
-                config.gutter_width,
-                config.line_numbers.clone(),
-            )),
-            LineNumbers::None => Box::<NoopLineGutter>::default(),
-        };
-
+    pub fn new(area: Rect, theme: &'a Theme) -> Self {
         Self {
             stdout: stdout(),
             diff: Viewport::default(),
             area,
-            config,
             scroll: Position::default(),
-            gutter,
+            gutter: Box::<NoopLineGutter>::default(),
+            gutter_width: 0,
             theme,
         }
     }
@@ -70,14 +51,16 @@ impl<'a> TuiView<'a> {
     }
 
     fn draw_cursor(&mut self, mode: &Mode, buffer: &Buffer, cursor: &Cursor) -> anyhow::Result<()> {
-        let offset = self.config.gutter_width;
+        let offset = self.gutter_width;
         let scroll = &self.scroll;
 
         let col = {
             let mut col = 0;
             if let Some(mark) = buffer.marker.get_by_line(cursor.row + 1) {
                 col = match mode {
-                    Mode::Normal => cursor.col.min(mark.size.saturating_sub(2)),
+                    Mode::Normal | Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                        cursor.col.min(mark.size.saturating_sub(2))
+                    }
                     _ => cursor.col.min(mark.size.saturating_sub(1)),
                 };
             }
@@ -102,22 +85,33 @@ impl<'a> TuiView<'a> {
         Ok(())
     }
 
-    fn draw(&self, view: &mut Viewport, cells: &[Cell]) {
+    // `selection` holds char ranges of the buffer, `offset` is the char the
+    // first cell stands for
+    fn draw(&self, view: &mut Viewport, cells: &[Cell], selection: &[Range<usize>], offset: usize) {
+        let selected = self.theme.token("ui.selection");
         let mut row = 0;
-        let mut col = self.config.gutter_width;
-        for cell in cells {
+        let mut col = self.gutter_width;
+        for (i, cell) in cells.iter().enumerate() {
             if col >= self.scroll.col && col - self.scroll.col < self.area.width {
+                let style = match selection.iter().any(|range| range.contains(&(offset + i))) {
+                    true => Style {
+                        fg: selected.fg.or(cell.style.fg),
+                        bg: selected.bg.or(cell.style.bg),
+                        ..cell.style
+                    },
+                    false => cell.style,
+                };
                 // we print a space when the char is a newline so the background gets printed
                 match cell.c {
-                    '\n' => view.set_cell(col - self.scroll.col, row, ' ', &cell.style),
-                    _ => view.set_cell(col - self.scroll.col, row, cell.c, &cell.style),
+                    '\n' => view.set_cell(col - self.scroll.col, row, ' ', &style),
+                    _ => view.set_cell(col - self.scroll.col, row, cell.c, &style),
                 };
                 col += 1;
             }
 
             if cell.c == '\n' {
                 row += 1;
-                col = self.config.gutter_width;
+                col = self.gutter_width;
             }
         }
     }
@@ -137,7 +131,14 @@ impl Renderable for TuiView<'_> {
         let last_diff = self.diff.clone();
         let mut diff = Viewport::new(self.area.width, self.area.height);
         self.hide_cursor()?;
-        self.draw(&mut diff, cells);
+        let selection = cursor
+            .selection(mode)
+            .map(|(from, to, kind)| buffer.selection(from, to, kind))
+            .unwrap_or_default();
+        let offset = buffer
+            .buffer
+            .line_to_char(self.scroll.row.min(buffer.buffer.len_lines()));
+        self.draw(&mut diff, cells, &selection, offset);
         self.draw_sidebar(buffer, cursor, &mut diff);
         let changes = diff.diff(&last_diff);
 
@@ -186,4 +187,15 @@ impl Renderable for TuiView<'_> {
     fn set_scroll(&mut self, scroll: Position) {
         self.scroll = scroll;
     }
+
+    fn set_gutter(&mut self, gutter: Box<dyn Gutter>, width: usize) {
+        self.gutter = gutter;
+        self.gutter_width = width;
+    }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+        // cells left in the default style would keep the old colors
+        self.diff = Viewport::default();
+    }
 }